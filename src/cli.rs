@@ -1,76 +1,100 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
+use crate::config_file::ClearSetting;
+use crate::probe::Probe;
+use crate::service_manager::{self, FailureAction, LaunchProtection, PatternStream, ProcessPriorityClass, RestartPolicy, RestartWindow, RotationInterval, ServiceStartType, StopMethod};
 
 #[derive(Parser)]
 #[command(name = "rust-nssm")]
 #[command(about = "A Rust-based Windows service manager similar to NSSM")]
 #[command(version = "0.1.0")]
 pub struct Cli {
+    /// 批量命令在第一个失败的服务处停止（默认跳过失败的服务，最后汇总）
+    #[arg(long, global = true)]
+    pub fail_fast: bool,
+
+    /// list 和 status 命令的输出格式 (text/json/table)
+    #[arg(long, global = true, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// list 和 status 命令的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// 适合阅读的文本
+    Text,
+    /// JSON，便于脚本处理
+    Json,
+    /// 对齐的表格
+    Table,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// 安装服务
-    Install {
-        /// 服务名称
-        #[arg(short, long)]
-        name: Option<String>,
+    Install(InstallArgs),
 
-        /// 显示名称
-        #[arg(short = 'i', long)]
-        display_name: Option<String>,
+    /// 修改已安装服务的配置（参数与 install 相同，只更新指定的设置，新配置在服务下次启动时生效）
+    Edit(InstallArgs),
 
-        /// 服务描述
+    /// 卸载服务
+    Uninstall {
+        /// 服务名称
         #[arg(short, long)]
-        description: Option<String>,
+        name: String,
+    },
 
-        /// 可执行文件路径
+    /// 启动服务
+    Start {
+        /// 服务名称
         #[arg(short, long)]
-        executable: Option<PathBuf>,
-
-        /// 命令行参数
-        #[arg(short, long, num_args = 0..)]
-        args: Vec<String>,
+        name: String,
 
-        /// 工作目录
-        #[arg(short = 'w', long)]
-        working_directory: Option<PathBuf>,
+        /// 启动后确认服务真正可用：tcp://host:port、http://host[:port]/path，或其他任意命令（退出码0为成功）；
+        /// 确认失败时停止服务并以非零状态退出
+        #[arg(long, value_name = "PROBE")]
+        post_start_verify: Option<Probe>,
 
-        /// 标准输出重定向文件
-        #[arg(long)]
-        stdout: Option<PathBuf>,
+        /// 启动后确认的超时时间（秒），期间失败会持续重试
+        #[arg(long, value_name = "SECS", default_value_t = 30, requires = "post_start_verify")]
+        verify_timeout: u64,
 
-        /// 标准错误重定向文件
+        /// 等待服务进入运行状态后再返回，启动期间停止或超时以非零状态退出
         #[arg(long)]
-        stderr: Option<PathBuf>,
+        wait: bool,
 
-        /// 服务名称（位置参数）
-        #[arg(index = 1)]
-        service_name: Option<String>,
-
-        /// 可执行文件路径（位置参数）
-        #[arg(index = 2)]
-        service_executable: Option<PathBuf>,
+        /// 等待服务启动的最长时间（秒）
+        #[arg(long, value_name = "SECS", default_value_t = 60, requires = "wait")]
+        timeout: u64,
     },
 
-    /// 卸载服务
-    Uninstall {
+    /// 停止服务
+    Stop {
         /// 服务名称
         #[arg(short, long)]
         name: String,
+
+        /// 等待服务停止的最长时间（秒）
+        #[arg(long, value_name = "SECS", default_value_t = 60)]
+        timeout: u64,
+
+        /// 发送停止请求后立即返回，不等待服务停止
+        #[arg(long, conflicts_with = "timeout")]
+        no_wait: bool,
     },
 
-    /// 启动服务
-    Start {
+    /// 暂停服务：挂起子进程树的全部线程
+    Pause {
         /// 服务名称
         #[arg(short, long)]
         name: String,
     },
 
-    /// 停止服务
-    Stop {
+    /// 继续运行已暂停的服务
+    Continue {
         /// 服务名称
         #[arg(short, long)]
         name: String,
@@ -81,17 +105,189 @@ pub enum Commands {
         /// 服务名称
         #[arg(short, long)]
         name: String,
+
+        /// 等待服务停止的最长时间（秒），超时后不再启动服务；指定 --wait 时也用于等待服务启动
+        #[arg(long, value_name = "SECS", default_value_t = 60)]
+        timeout: u64,
+
+        /// 等待服务重新进入运行状态后再返回
+        #[arg(long)]
+        wait: bool,
+    },
+
+    /// 禁用服务（维护模式）：记住当前启动类型后设为禁用并停止服务
+    Disable {
+        /// 服务名称
+        #[arg(short, long)]
+        name: String,
+    },
+
+    /// 启用服务：恢复禁用前的启动类型
+    Enable {
+        /// 服务名称
+        #[arg(short, long)]
+        name: String,
+
+        /// 启用后立即启动服务
+        #[arg(long)]
+        start: bool,
+    },
+
+    /// 修改已安装服务的启动类型
+    SetStartType {
+        /// 服务名称
+        name: String,
+
+        /// 新的启动类型 (auto/manual/disabled/delayed-auto)
+        #[arg(value_enum)]
+        start_type: ServiceStartType,
+    },
+
+    /// 替换已安装服务的依赖列表
+    SetDependencies {
+        /// 服务名称
+        name: String,
+
+        /// 必须先于本服务启动的服务（以 + 开头表示服务组），省略时取消所有依赖
+        dependencies: Vec<String>,
     },
 
     /// 获取服务状态
     Status {
+        /// 服务名称
+        #[arg(short, long, required_unless_present = "all")]
+        name: Option<String>,
+
+        /// 以表格形式显示所有由 rust-nssm 管理的服务
+        #[arg(long, conflicts_with = "name")]
+        all: bool,
+
+        /// 以JSON格式输出（配合 --all 时输出整个表格），等同于 --output json
+        #[arg(long, conflicts_with = "history")]
+        json: bool,
+
+        /// 不输出任何内容，只通过退出码报告整体健康状况（配合 --all）
+        #[arg(long, requires = "all", conflicts_with = "json")]
+        quiet: bool,
+
+        /// 计算退出码时把启动中/停止中等过渡状态视为健康（配合 --all）
+        #[arg(long, requires = "all")]
+        pending_healthy: bool,
+
+        /// 显示子进程最近的退出记录
+        #[arg(long, conflicts_with = "all")]
+        history: bool,
+    },
+
+    /// 检测服务宿主本身是否响应（通过状态管道往返一次），无响应时以非零状态退出
+    Ping {
         /// 服务名称
         #[arg(short, long)]
         name: String,
+
+        /// 等待响应的超时时间（毫秒）
+        #[arg(long, value_name = "MS", default_value_t = 2000)]
+        timeout: u64,
+    },
+
+    /// 汇总显示服务崩溃或退出时将发生的一切：启动类型、SCM恢复操作、子进程重启与停止策略
+    Policy {
+        /// 服务名称
+        #[arg(short, long)]
+        name: String,
+
+        /// 以JSON格式输出
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// 通过服务的IPC管道向子进程的标准输入发送一行命令（需安装时指定 --ipc-pipe-name）
+    Send {
+        /// 服务名称
+        name: String,
+
+        /// 要发送的命令
+        message: String,
+    },
+
+    /// 列出服务的全部有效配置（含状态），并标明哪些是默认值，便于提交问题时附上
+    Dump {
+        /// 服务名称
+        name: String,
     },
 
     /// 列出所有服务
-    List,
+    List {
+        /// 以JSON格式输出（名称、显示名称和状态），等同于 --output json
+        #[arg(long)]
+        json: bool,
+
+        /// 只列出由 rust-nssm 安装的服务（逐个检查注册表，服务较多时较慢）
+        #[arg(long)]
+        managed: bool,
+    },
+
+    /// 向标准输出打印带注释的TOML配置文件模板，供 install --config 使用
+    Init {
+        /// 服务名称
+        name: String,
+    },
+
+    /// 把已安装服务的配置导出为 install --config 可以读取的TOML文件
+    Export {
+        /// 服务名称
+        name: String,
+
+        /// 输出文件，`-` 表示输出到标准输出
+        #[arg(default_value = "-")]
+        output: PathBuf,
+    },
+
+    /// 查看服务子进程的输出日志
+    Logs {
+        /// 服务名称
+        #[arg(short, long)]
+        name: String,
+
+        /// 查看标准错误日志（默认查看标准输出）
+        #[arg(long)]
+        stderr: bool,
+
+        /// 只显示该时间之后的输出：相对时长（如 10m、2h）或本地时间（如 2024-01-02T10:00）
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+
+        /// 持续输出新写入的内容
+        #[arg(short, long)]
+        follow: bool,
+
+        /// 直接从运行中的服务宿主实时接收子进程输出（包括标准输出和标准错误，无需配置日志文件）
+        #[arg(long, conflicts_with_all = ["stderr", "since", "follow"])]
+        live: bool,
+    },
+
+    /// 将由NSSM创建的服务迁移为由 rust-nssm 托管
+    ImportNssm {
+        /// 服务名称
+        #[arg(short, long)]
+        name: String,
+
+        /// 只逐项显示NSSM设置的转换结果，不修改服务（存在无法转换的设置时以非零状态退出）
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// 升级 rust-nssm：把新版本程序复制到当前程序旁（文件名带版本号），并让所有托管服务改用新程序
+    ///
+    /// 正在运行的服务宿主不受影响，重启后才切换到新程序；任一服务更新失败时撤销全部修改。
+    Upgrade {
+        /// 新版本 rust-nssm 程序的路径
+        new_binary: PathBuf,
+
+        /// 更新完成后逐个重启正在运行的托管服务
+        #[arg(long)]
+        restart: bool,
+    },
 
     /// 运行服务（用于Windows服务主机）
     Run {
@@ -99,4 +295,389 @@ pub enum Commands {
         #[arg(short, long)]
         name: String,
     },
-}
\ No newline at end of file
+}
+
+/// install 和 edit 命令共用的服务配置参数
+#[derive(Args)]
+pub struct InstallArgs {
+    /// 服务名称
+    #[arg(short, long)]
+    pub name: Option<String>,
+
+    /// 显示名称
+    #[arg(short = 'i', long)]
+    pub display_name: Option<String>,
+
+    /// 服务描述
+    #[arg(short, long)]
+    pub description: Option<String>,
+
+    /// 可执行文件路径
+    #[arg(short, long)]
+    pub executable: Option<PathBuf>,
+
+    /// 命令行参数
+    #[arg(short, long, num_args = 0..)]
+    pub args: Vec<String>,
+
+    /// 从TOML配置文件读取服务设置（可用 init 命令生成模板），命令行参数优先
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// 从文件读取命令行参数（每行一个参数，.json文件则为字符串数组），位于 --args 之前
+    #[arg(long)]
+    pub args_from_file: Option<PathBuf>,
+
+    /// 子进程的环境变量（KEY=VALUE，可重复指定；同名时以最后一个为准）
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = service_manager::parse_env_var)]
+    pub environment: Vec<(String, String)>,
+
+    /// 不继承宿主的环境变量，子进程只获得 --env 指定的变量
+    #[arg(long)]
+    pub env_clear: bool,
+
+    /// 继承宿主的环境变量（撤销 --env-clear）
+    #[arg(long, conflicts_with = "env_clear")]
+    pub no_env_clear: bool,
+
+    /// 删除原配置或配置文件中的环境变量（可重复，变量名不区分大小写）
+    #[arg(long, value_name = "KEY")]
+    pub unset_env: Vec<String>,
+
+    /// 清除原配置或配置文件中的设置，恢复为默认行为（可重复，如 --clear stdout --clear health-check）
+    #[arg(long, value_enum, value_name = "SETTING")]
+    pub clear: Vec<ClearSetting>,
+
+    /// dotenv 格式的环境变量文件（KEY=VALUE，每次启动子进程时重新读取）
+    #[arg(long, value_name = "PATH")]
+    pub env_file: Option<PathBuf>,
+
+    /// 工作目录
+    #[arg(short = 'w', long)]
+    pub working_directory: Option<PathBuf>,
+
+    /// 标准输出重定向文件
+    #[arg(long)]
+    pub stdout: Option<PathBuf>,
+
+    /// 标准错误重定向文件
+    #[arg(long)]
+    pub stderr: Option<PathBuf>,
+
+    /// 子进程启动后把其PID写入该文件，子进程退出后删除，供外部监控工具使用
+    #[arg(long, value_name = "PATH")]
+    pub pid_file: Option<PathBuf>,
+
+    /// 服务宿主诊断日志的完整路径（默认为日志目录中的 rust-nssm-<服务名>.log）
+    #[arg(long, value_name = "PATH")]
+    pub host_log_path: Option<PathBuf>,
+
+    /// 每隔指定秒数检查目标程序是否在磁盘上被替换
+    #[arg(long, value_name = "SECS")]
+    pub update_check: Option<u64>,
+
+    /// 检测到目标程序被替换后自动重启子进程（需配合 --update-check）
+    #[arg(long, requires = "update_check")]
+    pub restart_on_binary_change: bool,
+
+    /// 目标程序被替换后不自动重启子进程
+    #[arg(long, conflicts_with = "restart_on_binary_change")]
+    pub no_restart_on_binary_change: bool,
+
+    /// 服务启动保护级别（要求二进制文件具有相应签名，绝大多数场景保持 none）
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    pub launch_protection: Option<LaunchProtection>,
+
+    /// 服务启动类型（auto/manual/disabled/delayed-auto，默认auto）
+    #[arg(long, value_enum, value_name = "TYPE")]
+    pub start_type: Option<ServiceStartType>,
+
+    /// 延迟自动启动（在其他自动启动的服务之后启动，适合依赖网络的服务），只能用于自动启动
+    #[arg(long)]
+    pub delayed: bool,
+
+    /// 服务运行所用的账户：LocalSystem（默认）、LocalService、NetworkService，或 .\user、DOMAIN\user 形式的用户
+    #[arg(long, alias = "account", value_name = "USER")]
+    pub username: Option<String>,
+
+    /// 用户账户的密码（组托管服务账户不需要）
+    #[arg(long, value_name = "PASSWORD", requires = "username")]
+    pub password: Option<String>,
+
+    /// 必须先于本服务启动的服务 (可重复，以 + 开头表示服务组)
+    #[arg(long, visible_alias = "depends", value_name = "SERVICE")]
+    pub depends_on: Vec<String>,
+
+    /// 服务宿主失败（崩溃或放弃重启子进程）后由SCM在该秒数后重启服务
+    #[arg(long, value_name = "SECS", conflicts_with = "recovery_action")]
+    pub recovery_restart_delay: Option<u64>,
+
+    /// 依次对应第1、2、3次及以后失败的SCM恢复操作（可重复，最多3个）：
+    /// restart:SECS（或 restart:MSms）、run:PROGRAM [ARGS]、reboot 或 none
+    #[arg(long, visible_alias = "recovery", value_name = "ACTION")]
+    pub recovery_action: Vec<FailureAction>,
+
+    /// 多长时间（秒）没有失败后SCM将失败计数清零（默认86400）
+    #[arg(long, visible_alias = "recovery-reset", value_name = "SECS")]
+    pub recovery_reset_period: Option<u32>,
+
+    /// 恢复操作重启计算机前向用户广播的消息
+    #[arg(long, value_name = "TEXT")]
+    pub recovery_reboot_message: Option<String>,
+
+    /// 子进程树的I/O带宽上限（字节/秒，需要 Windows 10 / Server 2016 及以上）
+    #[arg(long, value_name = "BYTES_PER_SEC")]
+    pub io_rate_limit: Option<u64>,
+
+    /// 子进程树中每个进程的最大工作集（字节）
+    #[arg(long, value_name = "BYTES")]
+    pub max_working_set: Option<u64>,
+
+    /// 子进程的优先级类 (idle/below-normal/normal/above-normal/high/realtime)
+    #[arg(long, value_enum, value_name = "CLASS")]
+    pub priority: Option<ProcessPriorityClass>,
+
+    /// 子进程可运行的CPU（位掩码，如 0x0F 表示前4个逻辑处理器）
+    #[arg(long, value_name = "MASK", value_parser = service_manager::parse_affinity_mask)]
+    pub cpu_affinity: Option<usize>,
+
+    /// 输出文件超过该大小（字节）时，在下次启动子进程前轮转为 .1、.2 等历史文件；与按日期轮转同时指定时，写入时达到该大小即轮转
+    #[arg(long, alias = "rotate-bytes", value_name = "BYTES")]
+    pub max_log_size: Option<u64>,
+
+    /// 按日期轮转输出文件（daily/weekly）：写入带日期的文件，跨越日期边界时自动切换
+    #[arg(long, value_enum, value_name = "INTERVAL")]
+    pub rotate_interval: Option<RotationInterval>,
+
+    /// 每天本地时间零点轮转输出文件，等同于 --rotate-interval daily
+    #[arg(long, conflicts_with = "rotate_interval")]
+    pub rotate_daily: bool,
+
+    /// 为写入输出文件的每行加上 [YYYY-MM-DD HH:MM:SS] 时间戳前缀
+    #[arg(long)]
+    pub log_timestamps: bool,
+
+    /// 不为输出行加时间戳前缀
+    #[arg(long, conflicts_with = "log_timestamps")]
+    pub no_log_timestamps: bool,
+
+    /// 轮转时保留的历史日志文件数，按日期轮转时为保留的周期数（默认5）
+    #[arg(long, alias = "rotate-count", value_name = "N")]
+    pub max_log_files: Option<u32>,
+
+    /// 除 Administrators 外允许查询服务状态管道的组（组名或SID）
+    #[arg(long, value_name = "GROUP")]
+    pub pipe_allow_group: Option<String>,
+
+    /// 创建命名管道 \\.\pipe\<NAME> 并把子进程的标准输入接到宿主，通过 send 命令向子进程逐行发送命令
+    #[arg(long, value_name = "NAME")]
+    pub ipc_pipe_name: Option<String>,
+
+    /// 不把子进程的启动、停止、崩溃和重启事件写入Windows应用程序事件日志
+    #[arg(long)]
+    pub no_event_log: bool,
+
+    /// 重新启用事件日志（撤销 --no-event-log）
+    #[arg(long, conflicts_with = "no_event_log")]
+    pub event_log: bool,
+
+    /// 只在该时间段内自动重启子进程（本地时间，如 22:00-06:00），其余时间子进程退出后保持停止直至时间段开始
+    #[arg(long, value_name = "HH:MM-HH:MM")]
+    pub restart_window: Option<RestartWindow>,
+
+    /// 子进程退出后等待多少秒再重新启动（默认1秒，0 表示立即重启）
+    #[arg(long, value_name = "SECS")]
+    pub restart_delay: Option<u64>,
+
+    /// 停止服务时结束子进程的方式：console 先发送 CTRL_BREAK 再等待（默认），kill 直接结束（GUI程序）
+    #[arg(long, value_enum, value_name = "METHOD")]
+    pub stop_method: Option<StopMethod>,
+
+    /// 停止服务时向子进程发送 CTRL_BREAK 后等待其自行退出的秒数，超时后强制结束（默认10秒，0 表示直接结束）
+    #[arg(long, value_name = "SECS")]
+    pub stop_timeout: Option<u64>,
+
+    /// 子进程树在这段时间内（秒）几乎不使用CPU时视为卡死并重启（启动后60秒内不检测）
+    #[arg(long, value_name = "SECS")]
+    pub cpu_stall_timeout: Option<u64>,
+
+    /// 判定CPU停滞的使用率阈值（占一个CPU核心的百分比，默认0.5）
+    #[arg(long, value_name = "PERCENT", requires = "cpu_stall_timeout")]
+    pub cpu_stall_threshold: Option<f64>,
+
+    /// 定期连接 127.0.0.1 的该端口检查子进程是否仍在受理连接，连续失败达到阈值时结束并重启子进程
+    #[arg(long, value_name = "PORT", group = "health_check")]
+    pub health_check_port: Option<u16>,
+
+    /// 定期向该地址（http://host[:port]/path）发送 GET 请求检查子进程，状态码不符时视为失败
+    #[arg(long, value_name = "URL", group = "health_check")]
+    pub health_check_url: Option<String>,
+
+    /// HTTP健康检查期望的状态码（默认200）
+    #[arg(long, value_name = "CODE", requires = "health_check_url")]
+    pub health_check_status: Option<u16>,
+
+    /// HTTP健康检查的响应正文中必须包含的文本
+    #[arg(long, value_name = "TEXT", requires = "health_check_url")]
+    pub health_check_body: Option<String>,
+
+    /// HTTP健康检查单次请求的超时时间（毫秒，默认2000）
+    #[arg(long, value_name = "MS", requires = "health_check_url")]
+    pub health_check_timeout: Option<u64>,
+
+    /// 健康检查的间隔（秒，默认10）
+    #[arg(long, value_name = "SECS", requires = "health_check")]
+    pub health_check_interval: Option<u64>,
+
+    /// 连续失败多少次后重启子进程（默认3）
+    #[arg(long, value_name = "N", requires = "health_check")]
+    pub health_check_failures: Option<u32>,
+
+    /// 出现失败后连续成功多少次才清零失败计数（默认1）
+    #[arg(long, value_name = "N", requires = "health_check")]
+    pub health_check_successes: Option<u32>,
+
+    /// 子进程控制台的输入/输出代码页（如 65001 表示UTF-8），仅影响使用控制台代码页的程序
+    #[arg(long, value_name = "N")]
+    pub console_codepage: Option<u32>,
+
+    /// 以 DETACHED_PROCESS 启动子进程，不分配控制台（没有 conhost.exe）；
+    /// 子进程无法接收 Ctrl 事件，停止时直接结束进程
+    #[arg(long, conflicts_with = "console_codepage")]
+    pub detached: bool,
+
+    /// 为子进程分配控制台（撤销 --detached）
+    #[arg(long, conflicts_with = "detached")]
+    pub no_detached: bool,
+
+    /// 主程序连续启动失败达到上限后改为启动的备用程序（如降级模式程序或告警程序），而不是放弃
+    #[arg(long, value_name = "PATH")]
+    pub fallback_executable: Option<PathBuf>,
+
+    /// 备用程序的命令行参数
+    #[arg(long, num_args = 0.., requires = "fallback_executable")]
+    pub fallback_args: Vec<String>,
+
+    /// 运行备用程序期间每分钟检查一次主程序，主程序可以启动时切换回主程序
+    #[arg(long, requires = "fallback_executable")]
+    pub prefer_primary: bool,
+
+    /// 运行备用程序期间不再切换回主程序
+    #[arg(long, conflicts_with = "prefer_primary")]
+    pub no_prefer_primary: bool,
+
+    /// 时间窗口内子进程重启超过该次数时放弃并停止服务（报告服务特定退出码3，可触发SCM恢复操作）
+    #[arg(long, value_name = "N")]
+    pub max_restarts_per_window: Option<u32>,
+
+    /// 子进程连续重启（退出或启动失败）超过该次数时放弃并停止服务（报告服务特定退出码4），0 表示不限制
+    #[arg(long, value_name = "N")]
+    pub max_restarts: Option<u32>,
+
+    /// 子进程持续运行超过该时间（秒，默认60）后视为稳定，连续重启次数清零；
+    /// 指定后未稳定运行就退出的子进程按启动失败的退避策略延迟重启（类似NSSM的 AppThrottle）
+    #[arg(long, value_name = "SECS")]
+    pub stable_window: Option<u64>,
+
+    /// 子进程连续启动失败的次数上限（默认5），达到后切换到备用程序或放弃并停止服务，0 表示不限制
+    #[arg(long, value_name = "N")]
+    pub max_start_attempts: Option<u32>,
+
+    /// 启动失败重试等待时间的基数（秒，默认2）：第N次失败后等待 基数×2^N 秒
+    #[arg(long, value_name = "SECS")]
+    pub start_retry_delay: Option<u64>,
+
+    /// 启动失败重试等待时间的上限（秒，默认512）
+    #[arg(long, alias = "restart-delay-max", value_name = "SECS")]
+    pub start_retry_delay_max: Option<u64>,
+
+    /// 子进程退出后每次重启前额外等待 0 到该秒数之间的随机时间，错开同时崩溃的多个服务的重启（不用于启动失败的重试）
+    #[arg(long, value_name = "SECS")]
+    pub restart_jitter: Option<u64>,
+
+    /// 重启频率上限的时间窗口（秒，默认300）
+    #[arg(long, value_name = "SECS", requires = "max_restarts_per_window")]
+    pub restart_window_seconds: Option<u64>,
+
+    /// 子进程频繁崩溃重启时限制重启日志：超过阈值后只定期输出"仍在频繁重启"的摘要，直到恢复稳定
+    #[arg(long)]
+    pub quiet_restart_logging: bool,
+
+    /// 逐条记录每次重启（撤销 --quiet-restart-logging）
+    #[arg(long, conflicts_with = "quiet_restart_logging")]
+    pub no_quiet_restart_logging: bool,
+
+    /// 时间窗口内逐条记录的重启次数（默认5）
+    #[arg(long, value_name = "N", requires = "quiet_restart_logging")]
+    pub restart_log_burst: Option<u32>,
+
+    /// 重启日志限流的时间窗口，也是摘要的输出间隔（秒，默认60）
+    #[arg(long, value_name = "SECS", requires = "quiet_restart_logging")]
+    pub restart_log_window: Option<u64>,
+
+    /// 安装时运行一次的环境准备命令（如创建目录、授权），失败时撤销安装
+    #[arg(long, value_name = "CMD")]
+    pub provision_cmd: Option<String>,
+
+    /// 子进程输出中出现匹配该正则表达式的行时重启子进程（可多次指定），用于记录了致命错误却不退出的程序
+    #[arg(long, value_name = "REGEX")]
+    pub restart_on_pattern: Vec<String>,
+
+    /// 重启规则匹配的输出流
+    #[arg(long, value_enum, value_name = "STREAM", default_value = "stderr", requires = "restart_on_pattern")]
+    pub restart_pattern_stream: PatternStream,
+
+    /// 按退出码决定子进程退出后的操作（CODE=ACTION 或 default=ACTION，可重复）：
+    /// restart 重启子进程、stop 正常停止服务、stop-service 以失败状态停止服务
+    #[arg(long, value_name = "CODE=ACTION")]
+    pub on_exit: Vec<String>,
+
+    /// 常用退出码规则的简写：always 总是重启（默认）、on-failure 只在退出码非零时重启、
+    /// never 子进程退出后正常停止服务
+    #[arg(long, value_enum, value_name = "POLICY", conflicts_with = "on_exit")]
+    pub restart_policy: Option<RestartPolicy>,
+
+    /// 子进程以该退出码退出时不重启，而是正常停止服务（可重复，十进制或 0x 开头的十六进制）
+    #[arg(long, value_name = "CODE", value_parser = service_manager::parse_exit_code)]
+    pub no_restart_on: Vec<i32>,
+
+    /// 停止服务时等待整个进程树退出，超时后再强制结束残留进程
+    #[arg(long, alias = "graceful-stop-wait-for-children")]
+    pub wait_for_tree: bool,
+
+    /// 停止时不等待整个进程树退出
+    #[arg(long, conflicts_with = "wait_for_tree")]
+    pub no_wait_for_tree: bool,
+
+    /// 安装时及每次启动前校验目标程序的 Authenticode 签名（失败时仅警告）
+    #[arg(long)]
+    pub check_signature: bool,
+
+    /// 签名无效或无法校验时拒绝安装和启动（隐含 --check-signature）
+    #[arg(long)]
+    pub require_signature: bool,
+
+    /// 要求签名证书的SHA1指纹与之匹配（隐含 --check-signature）
+    #[arg(long, value_name = "THUMBPRINT")]
+    pub signer_thumbprint: Option<String>,
+
+    /// 不校验签名（同时撤销 --require-signature 和 --signer-thumbprint）
+    #[arg(long, conflicts_with_all = ["check_signature", "require_signature", "signer_thumbprint"])]
+    pub no_check_signature: bool,
+
+    /// 签名校验失败时只记录警告，不拒绝启动（撤销 --require-signature）
+    #[arg(long, conflicts_with = "require_signature")]
+    pub no_require_signature: bool,
+
+    /// 允许目标程序为 rust-nssm 自身（仅用于确实需要嵌套的场景）
+    #[arg(long)]
+    pub allow_self: bool,
+
+    /// 服务名称（位置参数）
+    #[arg(index = 1)]
+    pub service_name: Option<String>,
+
+    /// 可执行文件路径（位置参数）
+    #[arg(index = 2)]
+    pub service_executable: Option<PathBuf>,
+}
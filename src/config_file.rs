@@ -0,0 +1,811 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::service_manager::{
+    self, FailureAction, HealthCheckTarget, LaunchProtection, LogRotation, PatternStream, ProcessPriorityClass, RestartWindow,
+    RotationInterval, ServiceAccount, ServiceConfig, ServiceStartType, StopMethod,
+};
+
+/// `edit --clear` 可以清除的设置，名称与对应的命令行参数相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClearSetting {
+    /// 程序参数（--args）
+    Args,
+    /// 全部环境变量（--env）
+    Env,
+    EnvFile,
+    WorkingDirectory,
+    Stdout,
+    Stderr,
+    PidFile,
+    HostLogPath,
+    /// 服务账户，恢复为 LocalSystem（--username / --password）
+    Account,
+    /// 依赖的服务（--depends-on）
+    Dependencies,
+    /// SCM恢复操作（--recovery-action 及其重置时间、重启消息）
+    Recovery,
+    RestartDelay,
+    RestartJitter,
+    RestartWindow,
+    /// 时间窗口内的重启次数上限（--max-restarts-per-window 及 --restart-window-seconds）
+    MaxRestartsPerWindow,
+    MaxRestarts,
+    StableWindow,
+    MaxStartAttempts,
+    /// 启动失败重试等待时间（--start-retry-delay 及 --start-retry-delay-max）
+    StartRetryDelay,
+    /// 重启日志限流参数（--restart-log-burst 及 --restart-log-window）
+    RestartLog,
+    /// 按输出重启的规则（--restart-on-pattern 及 --restart-pattern-stream）
+    RestartPatterns,
+    OnExit,
+    StopTimeout,
+    Priority,
+    CpuAffinity,
+    IoRateLimit,
+    MaxWorkingSet,
+    ConsoleCodepage,
+    /// 输出文件轮转（--max-log-size、--rotate-interval 及 --max-log-files）
+    LogRotation,
+    UpdateCheck,
+    /// CPU停滞检测（--cpu-stall-timeout 及 --cpu-stall-threshold）
+    CpuStall,
+    /// 健康检查（全部 --health-check-* 参数）
+    HealthCheck,
+    /// 备用程序（--fallback-executable、--fallback-args 及 --prefer-primary）
+    Fallback,
+    SignerThumbprint,
+    PipeAllowGroup,
+    IpcPipeName,
+}
+
+/// `install --config` 读取、`export` 写出的TOML服务配置
+///
+/// 包含服务配置的全部设置，键名与命令行参数相同（使用下划线）。所有字段均可省略；命令行同时指定时以命令行为准。
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceConfigFile {
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    pub executable: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arguments: Vec<String>,
+    pub working_directory: Option<PathBuf>,
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+    pub pid_file: Option<PathBuf>,
+    pub host_log_path: Option<PathBuf>,
+    /// 子进程的环境变量
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    pub env_clear: Option<bool>,
+    pub env_file: Option<PathBuf>,
+    #[serde(default, deserialize_with = "value_enum", serialize_with = "value_enum_name")]
+    pub start_type: Option<ServiceStartType>,
+    #[serde(default, deserialize_with = "value_enum", serialize_with = "value_enum_name")]
+    pub launch_protection: Option<LaunchProtection>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    /// SCM恢复操作，格式与 `--recovery-action` 相同
+    #[serde(default, deserialize_with = "parsed_list", serialize_with = "display_list", skip_serializing_if = "Vec::is_empty")]
+    pub recovery_actions: Vec<FailureAction>,
+    pub recovery_reset_period: Option<u32>,
+    pub recovery_reboot_message: Option<String>,
+    pub restart_delay: Option<u64>,
+    pub restart_jitter: Option<u64>,
+    /// 允许自动重启的时间段，格式与 `--restart-window` 相同
+    #[serde(default, deserialize_with = "parsed", serialize_with = "display")]
+    pub restart_window: Option<RestartWindow>,
+    pub max_restarts_per_window: Option<u32>,
+    pub restart_window_seconds: Option<u64>,
+    pub max_restarts: Option<u32>,
+    pub stable_window: Option<u64>,
+    pub max_start_attempts: Option<u32>,
+    pub start_retry_delay: Option<u64>,
+    pub start_retry_delay_max: Option<u64>,
+    pub quiet_restart_logging: Option<bool>,
+    pub restart_log_burst: Option<u32>,
+    pub restart_log_window: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restart_patterns: Vec<String>,
+    #[serde(default, deserialize_with = "value_enum", serialize_with = "value_enum_name")]
+    pub restart_pattern_stream: Option<PatternStream>,
+    #[serde(default, deserialize_with = "value_enum", serialize_with = "value_enum_name")]
+    pub stop_method: Option<StopMethod>,
+    pub stop_timeout: Option<u64>,
+    #[serde(default, deserialize_with = "value_enum", serialize_with = "value_enum_name")]
+    pub priority: Option<ProcessPriorityClass>,
+    /// CPU位掩码，格式与 `--cpu-affinity` 相同
+    #[serde(default, deserialize_with = "affinity_mask", serialize_with = "hex_mask")]
+    pub cpu_affinity: Option<usize>,
+    pub io_rate_limit: Option<u64>,
+    pub max_working_set: Option<u64>,
+    pub console_codepage: Option<u32>,
+    pub detached: Option<bool>,
+    pub max_log_size: Option<u64>,
+    #[serde(default, deserialize_with = "value_enum", serialize_with = "value_enum_name")]
+    pub rotate_interval: Option<RotationInterval>,
+    pub max_log_files: Option<u32>,
+    pub log_timestamps: Option<bool>,
+    /// 按退出码决定的操作，格式与 `--on-exit` 相同
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_exit: Vec<String>,
+    pub wait_for_tree: Option<bool>,
+    pub update_check: Option<u64>,
+    pub restart_on_binary_change: Option<bool>,
+    pub cpu_stall_timeout: Option<u64>,
+    pub cpu_stall_threshold: Option<f64>,
+    pub health_check_port: Option<u16>,
+    pub health_check_url: Option<String>,
+    pub health_check_status: Option<u16>,
+    pub health_check_body: Option<String>,
+    pub health_check_timeout: Option<u64>,
+    pub health_check_interval: Option<u64>,
+    pub health_check_failures: Option<u32>,
+    pub health_check_successes: Option<u32>,
+    pub fallback_executable: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_arguments: Vec<String>,
+    pub prefer_primary: Option<bool>,
+    pub check_signature: Option<bool>,
+    pub require_signature: Option<bool>,
+    pub signer_thumbprint: Option<String>,
+    pub pipe_allow_group: Option<String>,
+    pub ipc_pipe_name: Option<String>,
+    /// 是否写入Windows应用程序事件日志（默认写入）
+    pub event_log: Option<bool>,
+    /// 安装时运行一次的环境准备命令
+    pub provision_cmd: Option<String>,
+}
+
+impl ServiceConfigFile {
+    /// 由已安装服务的配置生成，包含全部设置；密码无法读回，不会导出
+    pub fn from_service_config(config: &ServiceConfig) -> Self {
+        let (max_log_size, rotate_interval, max_log_files) = match config.log_rotation {
+            LogRotation::None => (None, None, None),
+            LogRotation::BySize { max_bytes, keep } => (Some(max_bytes), None, Some(keep)),
+            LogRotation::ByDate { interval, keep, max_bytes } => (max_bytes, Some(interval), Some(keep)),
+        };
+        let recovery = config.recovery_actions.as_ref();
+        let health_check = config.health_check.as_ref();
+        let (health_check_port, health_check_url, health_check_status, health_check_timeout, health_check_body) =
+            match health_check.map(|health_check| &health_check.target) {
+                Some(HealthCheckTarget::Tcp { port }) => (Some(*port), None, None, None, None),
+                Some(HealthCheckTarget::Http { url, expected_status, timeout_ms, body_contains }) => {
+                    (None, Some(url.clone()), Some(*expected_status), Some(*timeout_ms), body_contains.clone())
+                }
+                None => (None, None, None, None, None),
+            };
+
+        Self {
+            name: Some(config.name.clone()),
+            display_name: Some(config.display_name.clone()),
+            description: Some(config.description.clone()),
+            executable: Some(config.executable_path.clone()),
+            arguments: config.arguments.clone(),
+            working_directory: config.working_directory.clone(),
+            stdout: config.stdout_path.clone(),
+            stderr: config.stderr_path.clone(),
+            pid_file: config.pid_file.clone(),
+            host_log_path: config.host_log_path.clone(),
+            environment: config.environment.iter().cloned().collect(),
+            env_clear: config.env_clear.then_some(true),
+            env_file: config.env_file.clone(),
+            start_type: Some(config.start_type),
+            launch_protection: Some(config.launch_protection),
+            username: config.service_account.as_ref().map(ServiceAccount::display_name),
+            password: None,
+            dependencies: config.dependencies.clone(),
+            recovery_actions: recovery.map(|recovery| recovery.to_actions()).unwrap_or_default(),
+            recovery_reset_period: recovery.map(|recovery| recovery.reset_period_secs),
+            recovery_reboot_message: recovery.and_then(|recovery| recovery.reboot_message.clone()),
+            restart_delay: config.restart_delay,
+            restart_jitter: config.restart_jitter,
+            restart_window: config.restart_window,
+            max_restarts_per_window: config.max_restarts_per_window,
+            restart_window_seconds: config.restart_rate_window,
+            max_restarts: config.max_restarts,
+            stable_window: config.stable_window,
+            max_start_attempts: config.max_start_attempts,
+            start_retry_delay: config.start_retry_delay,
+            start_retry_delay_max: config.start_retry_delay_max,
+            quiet_restart_logging: config.quiet_restart_logging.then_some(true),
+            restart_log_burst: config.restart_log_burst,
+            restart_log_window: config.restart_log_window,
+            restart_patterns: config.restart_patterns.clone(),
+            restart_pattern_stream: (!config.restart_patterns.is_empty()).then_some(config.restart_pattern_stream),
+            stop_method: Some(config.stop_method),
+            stop_timeout: config.stop_timeout,
+            priority: config.priority_class,
+            cpu_affinity: config.cpu_affinity,
+            io_rate_limit: config.io_rate_limit,
+            max_working_set: config.max_working_set,
+            console_codepage: config.console_codepage,
+            detached: config.detached.then_some(true),
+            max_log_size,
+            rotate_interval,
+            max_log_files,
+            log_timestamps: config.log_timestamps.then_some(true),
+            on_exit: if config.exit_actions.is_default() { Vec::new() } else { config.exit_actions.to_rules() },
+            wait_for_tree: config.wait_for_tree.then_some(true),
+            update_check: config.update_check_interval,
+            restart_on_binary_change: config.restart_on_binary_change.then_some(true),
+            cpu_stall_timeout: config.cpu_stall_timeout,
+            cpu_stall_threshold: config.cpu_stall_threshold,
+            health_check_port,
+            health_check_url,
+            health_check_status,
+            health_check_body,
+            health_check_timeout,
+            health_check_interval: health_check.map(|health_check| health_check.interval_secs),
+            health_check_failures: health_check.map(|health_check| health_check.failure_threshold),
+            health_check_successes: health_check.map(|health_check| health_check.success_threshold),
+            fallback_executable: config.fallback_executable.clone(),
+            fallback_arguments: config.fallback_arguments.clone(),
+            prefer_primary: config.prefer_primary.then_some(true),
+            check_signature: config.check_signature.then_some(true),
+            require_signature: config.require_signature.then_some(true),
+            signer_thumbprint: config.signer_thumbprint.clone(),
+            pipe_allow_group: config.pipe_allow_group.clone(),
+            ipc_pipe_name: config.ipc_pipe_name.clone(),
+            event_log: Some(config.event_log_enabled),
+            provision_cmd: config.provision_command.clone(),
+        }
+    }
+
+    /// 把 `other` 中指定的设置叠加到当前配置之上，`other` 未提及的设置保持不变
+    ///
+    /// 列表整体替换，`environment` 按变量名覆盖；输出文件轮转方式、健康检查对象、备用程序、
+    /// 重启规则和账户在 `other` 指定其中任一项时整体替换。
+    pub fn overlay(&mut self, other: ServiceConfigFile) {
+        fn set<T>(value: &mut Option<T>, other: Option<T>) {
+            if other.is_some() {
+                *value = other;
+            }
+        }
+        fn set_list<T>(value: &mut Vec<T>, other: Vec<T>) {
+            if !other.is_empty() {
+                *value = other;
+            }
+        }
+
+        let ServiceConfigFile {
+            name,
+            display_name,
+            description,
+            executable,
+            arguments,
+            working_directory,
+            stdout,
+            stderr,
+            pid_file,
+            host_log_path,
+            environment,
+            env_clear,
+            env_file,
+            start_type,
+            launch_protection,
+            username,
+            password,
+            dependencies,
+            recovery_actions,
+            recovery_reset_period,
+            recovery_reboot_message,
+            restart_delay,
+            restart_jitter,
+            restart_window,
+            max_restarts_per_window,
+            restart_window_seconds,
+            max_restarts,
+            stable_window,
+            max_start_attempts,
+            start_retry_delay,
+            start_retry_delay_max,
+            quiet_restart_logging,
+            restart_log_burst,
+            restart_log_window,
+            restart_patterns,
+            restart_pattern_stream,
+            stop_method,
+            stop_timeout,
+            priority,
+            cpu_affinity,
+            io_rate_limit,
+            max_working_set,
+            console_codepage,
+            detached,
+            max_log_size,
+            rotate_interval,
+            max_log_files,
+            log_timestamps,
+            on_exit,
+            wait_for_tree,
+            update_check,
+            restart_on_binary_change,
+            cpu_stall_timeout,
+            cpu_stall_threshold,
+            health_check_port,
+            health_check_url,
+            health_check_status,
+            health_check_body,
+            health_check_timeout,
+            health_check_interval,
+            health_check_failures,
+            health_check_successes,
+            fallback_executable,
+            fallback_arguments,
+            prefer_primary,
+            check_signature,
+            require_signature,
+            signer_thumbprint,
+            pipe_allow_group,
+            ipc_pipe_name,
+            event_log,
+            provision_cmd,
+        } = other;
+
+        set(&mut self.name, name);
+        set(&mut self.display_name, display_name);
+        set(&mut self.description, description);
+        set(&mut self.executable, executable);
+        set_list(&mut self.arguments, arguments);
+        set(&mut self.working_directory, working_directory);
+        set(&mut self.stdout, stdout);
+        set(&mut self.stderr, stderr);
+        set(&mut self.pid_file, pid_file);
+        set(&mut self.host_log_path, host_log_path);
+        for (key, value) in environment {
+            self.environment.retain(|existing, _| !existing.eq_ignore_ascii_case(&key));
+            self.environment.insert(key, value);
+        }
+        set(&mut self.env_clear, env_clear);
+        set(&mut self.env_file, env_file);
+        set(&mut self.start_type, start_type);
+        set(&mut self.launch_protection, launch_protection);
+        // 指定账户时密码随账户一起替换
+        if username.is_some() {
+            self.username = username;
+            self.password = password;
+        } else {
+            set(&mut self.password, password);
+        }
+        set_list(&mut self.dependencies, dependencies);
+        set_list(&mut self.recovery_actions, recovery_actions);
+        set(&mut self.recovery_reset_period, recovery_reset_period);
+        set(&mut self.recovery_reboot_message, recovery_reboot_message);
+        set(&mut self.restart_delay, restart_delay);
+        set(&mut self.restart_jitter, restart_jitter);
+        set(&mut self.restart_window, restart_window);
+        set(&mut self.max_restarts_per_window, max_restarts_per_window);
+        set(&mut self.restart_window_seconds, restart_window_seconds);
+        set(&mut self.max_restarts, max_restarts);
+        set(&mut self.stable_window, stable_window);
+        set(&mut self.max_start_attempts, max_start_attempts);
+        set(&mut self.start_retry_delay, start_retry_delay);
+        set(&mut self.start_retry_delay_max, start_retry_delay_max);
+        set(&mut self.quiet_restart_logging, quiet_restart_logging);
+        set(&mut self.restart_log_burst, restart_log_burst);
+        set(&mut self.restart_log_window, restart_log_window);
+        if restart_patterns.is_empty() {
+            set(&mut self.restart_pattern_stream, restart_pattern_stream);
+        } else {
+            self.restart_patterns = restart_patterns;
+            self.restart_pattern_stream = restart_pattern_stream;
+        }
+        set(&mut self.stop_method, stop_method);
+        set(&mut self.stop_timeout, stop_timeout);
+        set(&mut self.priority, priority);
+        set(&mut self.cpu_affinity, cpu_affinity);
+        set(&mut self.io_rate_limit, io_rate_limit);
+        set(&mut self.max_working_set, max_working_set);
+        set(&mut self.console_codepage, console_codepage);
+        set(&mut self.detached, detached);
+        if max_log_size.is_some() || rotate_interval.is_some() {
+            self.max_log_size = max_log_size;
+            self.rotate_interval = rotate_interval;
+        }
+        set(&mut self.max_log_files, max_log_files);
+        set(&mut self.log_timestamps, log_timestamps);
+        set_list(&mut self.on_exit, on_exit);
+        set(&mut self.wait_for_tree, wait_for_tree);
+        set(&mut self.update_check, update_check);
+        set(&mut self.restart_on_binary_change, restart_on_binary_change);
+        set(&mut self.cpu_stall_timeout, cpu_stall_timeout);
+        set(&mut self.cpu_stall_threshold, cpu_stall_threshold);
+        if health_check_port.is_some() || health_check_url.is_some() {
+            self.health_check_port = health_check_port;
+            self.health_check_url = health_check_url;
+        }
+        set(&mut self.health_check_status, health_check_status);
+        set(&mut self.health_check_body, health_check_body);
+        set(&mut self.health_check_timeout, health_check_timeout);
+        set(&mut self.health_check_interval, health_check_interval);
+        set(&mut self.health_check_failures, health_check_failures);
+        set(&mut self.health_check_successes, health_check_successes);
+        if fallback_executable.is_some() {
+            self.fallback_executable = fallback_executable;
+            self.fallback_arguments = fallback_arguments;
+        } else {
+            set_list(&mut self.fallback_arguments, fallback_arguments);
+        }
+        set(&mut self.prefer_primary, prefer_primary);
+        set(&mut self.check_signature, check_signature);
+        set(&mut self.require_signature, require_signature);
+        set(&mut self.signer_thumbprint, signer_thumbprint);
+        set(&mut self.pipe_allow_group, pipe_allow_group);
+        set(&mut self.ipc_pipe_name, ipc_pipe_name);
+        set(&mut self.event_log, event_log);
+        set(&mut self.provision_cmd, provision_cmd);
+    }
+
+    /// 清除一项设置，恢复为未设置时的默认行为
+    pub fn clear(&mut self, setting: ClearSetting) {
+        match setting {
+            ClearSetting::Args => self.arguments.clear(),
+            ClearSetting::Env => self.environment.clear(),
+            ClearSetting::EnvFile => self.env_file = None,
+            ClearSetting::WorkingDirectory => self.working_directory = None,
+            ClearSetting::Stdout => self.stdout = None,
+            ClearSetting::Stderr => self.stderr = None,
+            ClearSetting::PidFile => self.pid_file = None,
+            ClearSetting::HostLogPath => self.host_log_path = None,
+            ClearSetting::Account => {
+                self.username = None;
+                self.password = None;
+            }
+            ClearSetting::Dependencies => self.dependencies.clear(),
+            ClearSetting::Recovery => {
+                self.recovery_actions.clear();
+                self.recovery_reset_period = None;
+                self.recovery_reboot_message = None;
+            }
+            ClearSetting::RestartDelay => self.restart_delay = None,
+            ClearSetting::RestartJitter => self.restart_jitter = None,
+            ClearSetting::RestartWindow => self.restart_window = None,
+            ClearSetting::MaxRestartsPerWindow => {
+                self.max_restarts_per_window = None;
+                self.restart_window_seconds = None;
+            }
+            ClearSetting::MaxRestarts => self.max_restarts = None,
+            ClearSetting::StableWindow => self.stable_window = None,
+            ClearSetting::MaxStartAttempts => self.max_start_attempts = None,
+            ClearSetting::StartRetryDelay => {
+                self.start_retry_delay = None;
+                self.start_retry_delay_max = None;
+            }
+            ClearSetting::RestartLog => {
+                self.restart_log_burst = None;
+                self.restart_log_window = None;
+            }
+            ClearSetting::RestartPatterns => {
+                self.restart_patterns.clear();
+                self.restart_pattern_stream = None;
+            }
+            ClearSetting::OnExit => self.on_exit.clear(),
+            ClearSetting::StopTimeout => self.stop_timeout = None,
+            ClearSetting::Priority => self.priority = None,
+            ClearSetting::CpuAffinity => self.cpu_affinity = None,
+            ClearSetting::IoRateLimit => self.io_rate_limit = None,
+            ClearSetting::MaxWorkingSet => self.max_working_set = None,
+            ClearSetting::ConsoleCodepage => self.console_codepage = None,
+            ClearSetting::LogRotation => {
+                self.max_log_size = None;
+                self.rotate_interval = None;
+                self.max_log_files = None;
+            }
+            ClearSetting::UpdateCheck => self.update_check = None,
+            ClearSetting::CpuStall => {
+                self.cpu_stall_timeout = None;
+                self.cpu_stall_threshold = None;
+            }
+            ClearSetting::HealthCheck => {
+                self.health_check_port = None;
+                self.health_check_url = None;
+                self.health_check_status = None;
+                self.health_check_body = None;
+                self.health_check_timeout = None;
+                self.health_check_interval = None;
+                self.health_check_failures = None;
+                self.health_check_successes = None;
+            }
+            ClearSetting::Fallback => {
+                self.fallback_executable = None;
+                self.fallback_arguments.clear();
+                self.prefer_primary = None;
+            }
+            ClearSetting::SignerThumbprint => self.signer_thumbprint = None,
+            ClearSetting::PipeAllowGroup => self.pipe_allow_group = None,
+            ClearSetting::IpcPipeName => self.ipc_pipe_name = None,
+        }
+    }
+
+    /// 序列化为 `install --config` 可以读取的TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).context("Failed to serialize service config")
+    }
+}
+
+/// 读取并解析配置文件
+pub fn load(path: &Path) -> Result<ServiceConfigFile> {
+    let text = std::fs::read_to_string(path)
+        .context(format!("Failed to read config file: {}", path.display()))?;
+    parse(&text).context(format!("Invalid config file: {}", path.display()))
+}
+
+fn parse(text: &str) -> Result<ServiceConfigFile> {
+    Ok(toml::from_str(text)?)
+}
+
+/// 按命令行中的名称解析枚举值（如 `delayed-auto`）
+fn value_enum<'de, D: Deserializer<'de>, T: ValueEnum>(deserializer: D) -> Result<Option<T>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| T::from_str(&value, true).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// 逐项按命令行格式解析字符串数组
+fn parsed_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = anyhow::Error>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|value| value.parse().map_err(|e| serde::de::Error::custom(format!("{:#}", e))))
+        .collect()
+}
+
+/// 按命令行格式解析单个字符串值
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = anyhow::Error>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| value.parse().map_err(|e| serde::de::Error::custom(format!("{:#}", e))))
+        .transpose()
+}
+
+/// 按命令行中的名称写出枚举值，与 `value_enum` 对应
+fn value_enum_name<S: Serializer, T: ValueEnum>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value.as_ref().and_then(ValueEnum::to_possible_value) {
+        Some(value) => serializer.serialize_str(value.get_name()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// 按命令行格式写出，与 `parsed` 对应
+fn display<S: Serializer, T: std::fmt::Display>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// 逐项按命令行格式写出，与 `parsed_list` 对应
+fn display_list<S: Serializer, T: std::fmt::Display>(values: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(ToString::to_string))
+}
+
+fn hex_mask<S: Serializer>(mask: &Option<usize>, serializer: S) -> Result<S::Ok, S::Error> {
+    match mask {
+        Some(mask) => serializer.serialize_str(&format!("{:#x}", mask)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn affinity_mask<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| service_manager::parse_affinity_mask(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// 生成带注释的配置文件模板，可选项均以注释形式给出默认值
+pub fn template(name: &str) -> String {
+    TEMPLATE.replace("{name}", name)
+}
+
+const TEMPLATE: &str = r#"# rust-nssm 服务配置文件
+# 安装: rust-nssm install --config {name}.toml
+# 命令行参数会覆盖本文件中的同名设置
+
+name = "{name}"
+# display_name = "{name}"
+# description = "Service managed by rust-nssm: {name}"
+
+# 要运行的程序及其参数
+executable = 'C:\path\to\program.exe'
+arguments = []
+# working_directory = 'C:\path\to'
+
+# 输出重定向
+# stdout = 'C:\logs\{name}.out.log'
+# stderr = 'C:\logs\{name}.err.log'
+# max_log_size = 10485760      # 按大小轮转（字节），与 rotate_interval 同时指定时以先达到者为准
+# rotate_interval = "daily"    # 按日期轮转: daily / weekly
+# max_log_files = 5
+# log_timestamps = false
+# pid_file = 'C:\run\{name}.pid'
+# host_log_path = 'C:\logs\rust-nssm-{name}.log'
+# event_log = true             # 把子进程的启动、停止和崩溃写入应用程序事件日志
+
+# 服务设置
+# start_type = "auto"          # auto / manual / disabled / delayed-auto
+# username = 'NT AUTHORITY\LocalService'
+# password = ""
+# dependencies = ["Tcpip"]
+# launch_protection = "none"   # none / windows / windows-light / antimalware-light
+# pipe_allow_group = "Users"
+# ipc_pipe_name = "{name}-ipc"
+# provision_cmd = 'mkdir C:\data\{name}'   # 仅安装时运行一次
+
+# 子进程
+# env_clear = false
+# env_file = 'C:\path\to\.env'
+# priority = "normal"          # idle / below-normal / normal / above-normal / high / realtime
+# cpu_affinity = "0x0F"
+# restart_delay = 1
+# restart_jitter = 0           # 重启前额外等待的随机秒数上限
+# stop_method = "console"      # console / kill
+# stop_timeout = 10
+# wait_for_tree = false
+# on_exit = ["0=stop", "default=restart"]
+# io_rate_limit = 10485760     # 字节/秒
+# max_working_set = 536870912  # 字节
+# console_codepage = 65001
+# detached = false
+
+# 重启控制
+# restart_window = "22:00-06:00"
+# max_restarts_per_window = 5
+# restart_window_seconds = 300
+# max_restarts = 0             # 0 表示不限制
+# stable_window = 60
+# max_start_attempts = 5
+# start_retry_delay = 2
+# start_retry_delay_max = 512
+# quiet_restart_logging = false
+# restart_log_burst = 5
+# restart_log_window = 60
+# restart_patterns = ["FATAL"]
+# restart_pattern_stream = "stderr"   # stderr / stdout / both
+
+# 监控
+# update_check = 60            # 检查目标程序是否被替换的间隔（秒）
+# restart_on_binary_change = false
+# cpu_stall_timeout = 300
+# cpu_stall_threshold = 0.5
+# health_check_port = 8080     # 或 health_check_url = "http://127.0.0.1:8080/health"
+# health_check_status = 200
+# health_check_body = "ok"
+# health_check_timeout = 2000  # 毫秒
+# health_check_interval = 10
+# health_check_failures = 3
+# health_check_successes = 1
+
+# 主程序连续启动失败后改为启动的备用程序
+# fallback_executable = 'C:\path\to\fallback.exe'
+# fallback_arguments = []
+# prefer_primary = false
+
+# Authenticode 签名校验
+# check_signature = false
+# require_signature = false
+# signer_thumbprint = "0123456789ABCDEF0123456789ABCDEF01234567"
+
+# 服务宿主失败后的SCM恢复操作（依次对应第1、2、3次及以后失败）
+# recovery_actions = ["restart:10", "restart:60", "none"]
+# recovery_reset_period = 86400
+# recovery_reboot_message = "Restarting to recover {name}"
+
+# 子进程的环境变量
+[environment]
+# RUST_LOG = "info"
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_file() {
+        let config = parse(
+            r#"
+            name = "web"
+            executable = 'C:\app\web.exe'
+            arguments = ["--port", "8080"]
+            start_type = "delayed-auto"
+            dependencies = ["Tcpip"]
+            recovery_actions = ["restart:10", "none"]
+            cpu_affinity = "0x3"
+            log_timestamps = true
+            restart_window = "22:00-06:00"
+            restart_pattern_stream = "both"
+            health_check_url = "http://127.0.0.1:8080/health"
+            event_log = false
+
+            [environment]
+            RUST_LOG = "info"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.name.as_deref(), Some("web"));
+        assert_eq!(config.executable, Some(PathBuf::from(r"C:\app\web.exe")));
+        assert_eq!(config.arguments, ["--port", "8080"]);
+        assert_eq!(config.start_type, Some(ServiceStartType::AutoDelayedStart));
+        assert_eq!(config.recovery_actions.len(), 2);
+        assert_eq!(config.cpu_affinity, Some(0x3));
+        assert_eq!(config.log_timestamps, Some(true));
+        assert_eq!(config.environment.get("RUST_LOG").map(String::as_str), Some("info"));
+        assert_eq!(config.stop_method, None);
+        assert_eq!(config.restart_window, Some("22:00-06:00".parse().unwrap()));
+        assert_eq!(config.restart_pattern_stream, Some(PatternStream::Both));
+        assert_eq!(config.health_check_url.as_deref(), Some("http://127.0.0.1:8080/health"));
+        assert_eq!(config.event_log, Some(false));
+        assert_eq!(config.detached, None);
+
+        assert!(parse("start_type = \"sometimes\"").is_err());
+        assert!(parse("restart_window = \"22:00\"").is_err());
+        assert!(parse("unknown_key = 1").is_err());
+    }
+
+    #[test]
+    fn test_export_roundtrip() {
+        let config = ServiceConfig {
+            name: "web".to_string(),
+            display_name: "Web".to_string(),
+            description: "Web server".to_string(),
+            executable_path: PathBuf::from(r"C:\app\web.exe"),
+            arguments: vec!["--port".to_string(), "8080".to_string()],
+            environment: vec![("RUST_LOG".to_string(), "info".to_string())],
+            start_type: ServiceStartType::AutoDelayedStart,
+            service_account: Some(ServiceAccount::LocalService),
+            dependencies: vec!["Tcpip".to_string()],
+            recovery_actions: Some(service_manager::RecoveryConfig::restart_after(
+                std::time::Duration::from_secs(10),
+                3600,
+            )),
+            cpu_affinity: Some(0x3),
+            log_rotation: LogRotation::ByDate { interval: RotationInterval::Weekly, keep: 4, max_bytes: Some(1048576) },
+            log_timestamps: true,
+            ..Default::default()
+        };
+
+        let text = ServiceConfigFile::from_service_config(&config).to_toml().unwrap();
+        let file = parse(&text).unwrap();
+        assert_eq!(file.name.as_deref(), Some("web"));
+        assert_eq!(file.executable, Some(config.executable_path.clone()));
+        assert_eq!(file.arguments, config.arguments);
+        assert_eq!(file.environment.get("RUST_LOG").map(String::as_str), Some("info"));
+        assert_eq!(file.start_type, Some(ServiceStartType::AutoDelayedStart));
+        assert_eq!(file.username.as_deref(), Some("NT AUTHORITY\\LocalService"));
+        assert_eq!(file.dependencies, ["Tcpip"]);
+        assert_eq!(file.recovery_actions, [FailureAction::RestartService(std::time::Duration::from_secs(10))]);
+        assert_eq!(file.recovery_reset_period, Some(3600));
+        assert_eq!(file.stop_method, Some(StopMethod::Console));
+        assert_eq!(file.cpu_affinity, Some(0x3));
+        assert_eq!(file.rotate_interval, Some(RotationInterval::Weekly));
+        assert_eq!(file.max_log_size, Some(1048576));
+        assert_eq!(file.max_log_files, Some(4));
+        assert_eq!(file.log_timestamps, Some(true));
+        assert!(file.on_exit.is_empty());
+        assert!(file.password.is_none());
+    }
+
+    #[test]
+    fn test_template_parses() {
+        let config = parse(&template("demo")).unwrap();
+        assert_eq!(config.name.as_deref(), Some("demo"));
+        assert!(config.executable.is_some());
+        assert!(config.environment.is_empty());
+    }
+}
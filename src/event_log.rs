@@ -0,0 +1,94 @@
+use log::warn;
+use windows_sys::Win32::Foundation::GetLastError;
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, EventSourceHandle, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+    EVENTLOG_INFORMATION_TYPE, REPORT_EVENT_TYPE,
+};
+
+/// 子进程已启动
+pub const EVENT_CHILD_STARTED: u32 = 1000;
+/// 子进程已停止（服务停止或子进程正常退出）
+pub const EVENT_CHILD_STOPPED: u32 = 1001;
+/// 子进程以非零退出码退出
+pub const EVENT_CHILD_CRASHED: u32 = 1002;
+/// 准备重新启动子进程
+pub const EVENT_RESTART_ATTEMPT: u32 = 1003;
+
+/// 写入Windows应用程序事件日志的事件源（以服务名称为来源）
+///
+/// 未启用或注册失败时所有写入都被忽略，不影响服务运行。
+pub struct EventLog {
+    handle: EventSourceHandle,
+}
+
+impl EventLog {
+    /// 打开服务的事件源，`enabled` 为 `false` 时返回不写入任何事件的实例
+    pub fn open(service_name: &str, enabled: bool) -> Self {
+        if !enabled {
+            return Self { handle: 0 };
+        }
+
+        let source_w: Vec<u16> = service_name.encode_utf16().chain(std::iter::once(0)).collect();
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), source_w.as_ptr()) };
+        if handle == 0 {
+            warn!("Failed to register event source '{}': error {}", service_name, unsafe { GetLastError() });
+        }
+        Self { handle }
+    }
+
+    /// 写入一条信息事件
+    pub fn info(&self, event_id: u32, message: &str) {
+        self.report(EVENTLOG_INFORMATION_TYPE, event_id, message);
+    }
+
+    /// 写入一条错误事件
+    pub fn error(&self, event_id: u32, message: &str) {
+        self.report(EVENTLOG_ERROR_TYPE, event_id, message);
+    }
+
+    fn report(&self, event_type: REPORT_EVENT_TYPE, event_id: u32, message: &str) {
+        if self.handle == 0 {
+            return;
+        }
+
+        let message_w: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+        let strings = [message_w.as_ptr()];
+        let result = unsafe {
+            ReportEventW(
+                self.handle,
+                event_type,
+                0,
+                event_id,
+                std::ptr::null_mut(),
+                strings.len() as u16,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            )
+        };
+        if result == 0 {
+            warn!("Failed to write event {} to the event log: error {}", event_id, unsafe { GetLastError() });
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        if self.handle != 0 {
+            unsafe { DeregisterEventSource(self.handle) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_event_log_ignores_events() {
+        let event_log = EventLog::open("rust-nssm-test", false);
+        assert_eq!(event_log.handle, 0);
+        event_log.info(EVENT_CHILD_STARTED, "Started child process");
+        event_log.error(EVENT_CHILD_CRASHED, "Child process crashed");
+    }
+}
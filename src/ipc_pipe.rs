@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use std::process::ChildStdin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use windows_sys::Win32::Foundation::{GetLastError, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE};
+use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+use windows_sys::Win32::System::Pipes::{ConnectNamedPipe, DisconnectNamedPipe};
+
+use crate::status_pipe;
+
+/// 单条命令的最大长度（字节），超过时拒绝转发
+const MAX_COMMAND_LEN: u64 = 64 * 1024;
+
+/// IPC管道的完整名称
+pub fn pipe_name(ipc_pipe_name: &str) -> String {
+    format!("\\\\.\\pipe\\{}", ipc_pipe_name)
+}
+
+/// 向子进程的标准输入写入一条命令（以换行结尾）
+pub fn send_command(pipe: HANDLE, cmd: &str) -> Result<()> {
+    // 句柄仍归调用者所有，不能在这里关闭
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_handle(pipe as _) });
+    file.write_all(format_command(cmd).as_bytes())
+        .and_then(|_| file.flush())
+        .context("Failed to write command to child process stdin")
+}
+
+/// 去掉命令末尾的换行后补上一个换行，保证每条命令恰好占一行
+fn format_command(cmd: &str) -> String {
+    format!("{}\n", cmd.trim_end_matches(['\r', '\n']))
+}
+
+/// 当前子进程的标准输入，子进程每次启动时替换
+///
+/// IPC管道收到的命令经此转发给当前子进程；子进程退出后写入失败，直至下一个子进程启动。
+#[derive(Default)]
+pub struct ChildInput {
+    stdin: Mutex<Option<ChildStdin>>,
+}
+
+impl ChildInput {
+    /// 记录新启动的子进程的标准输入
+    pub fn attach(&self, stdin: ChildStdin) {
+        if let Ok(mut current) = self.stdin.lock() {
+            *current = Some(stdin);
+        }
+    }
+
+    /// 向当前子进程发送一条命令
+    pub fn send(&self, cmd: &str) -> Result<()> {
+        let current = self.stdin.lock()
+            .map_err(|_| anyhow::anyhow!("Child process stdin is unavailable"))?;
+        let stdin = current.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No child process is running"))?;
+        send_command(stdin.as_raw_handle() as HANDLE, cmd)
+    }
+}
+
+/// 在后台线程中启动IPC管道服务端，每个连接发送一行命令，转发给子进程后回复结果
+///
+/// 回复为 `OK` 或 `ERROR: <原因>`。访问控制与状态管道相同，只有管理员可以发送命令。
+/// 每个连接在独立的线程中处理，迟迟不发送命令的客户端不会阻塞其他客户端。管道名已被占用时返回错误。
+pub fn spawn_server(ipc_pipe_name: &str, input: Arc<ChildInput>, allow_group: Option<&str>) -> Result<()> {
+    status_pipe::spawn_pipe_server(&pipe_name(ipc_pipe_name), allow_group, PIPE_ACCESS_DUPLEX, move |pipe| {
+        let connected = unsafe { ConnectNamedPipe(pipe, std::ptr::null_mut()) } != 0
+            || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+        if !connected {
+            drop(unsafe { File::from_raw_handle(pipe as _) });
+            return;
+        }
+
+        let input = input.clone();
+        std::thread::spawn(move || serve_client(pipe, &input));
+    })
+}
+
+/// 从已连接的客户端读取一行命令并转发给子进程
+fn serve_client(pipe: HANDLE, input: &ChildInput) {
+    // 管道句柄交由 File 管理，离开作用域时关闭
+    let file = unsafe { File::from_raw_handle(pipe as _) };
+
+    let mut cmd = String::new();
+    let reply = match BufReader::new((&file).take(MAX_COMMAND_LEN)).read_line(&mut cmd) {
+        Ok(_) if !cmd.ends_with('\n') => "ERROR: command too long or not terminated by a newline".to_string(),
+        Ok(_) => match input.send(&cmd) {
+            Ok(()) => {
+                info!("Forwarded IPC command to child process: {}", cmd.trim_end());
+                "OK".to_string()
+            }
+            Err(e) => format!("ERROR: {:#}", e),
+        },
+        Err(e) => format!("ERROR: {}", e),
+    };
+
+    if let Err(e) = (&file).write_all(format_command(&reply).as_bytes()).and_then(|_| (&file).flush()) {
+        warn!("Failed to reply on IPC pipe: {}", e);
+    }
+
+    unsafe { DisconnectNamedPipe(file.as_raw_handle() as _) };
+}
+
+/// 作为客户端连接服务的IPC管道，发送一条命令并等待服务宿主的回复
+pub fn send(ipc_pipe_name: &str, message: &str, timeout: Duration) -> Result<()> {
+    let name = pipe_name(ipc_pipe_name);
+    let deadline = Instant::now() + timeout;
+
+    // 所有管道实例都忙时短暂重试
+    let mut pipe = loop {
+        match File::options().read(true).write(true).open(&name) {
+            Ok(file) => break file,
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) && Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Err(e) => return Err(e).context(format!("Failed to open IPC pipe {} (is the service running?)", name)),
+        }
+    };
+
+    pipe.write_all(format_command(message).as_bytes())
+        .context("Failed to write IPC pipe")?;
+
+    let mut reply = String::new();
+    BufReader::new(pipe).read_line(&mut reply)
+        .context("Failed to read IPC pipe reply")?;
+
+    match reply.trim_end() {
+        "OK" => Ok(()),
+        "" => Err(anyhow::anyhow!("Service host closed the IPC pipe without replying")),
+        reply => Err(anyhow::anyhow!("{}", reply.strip_prefix("ERROR: ").unwrap_or(reply))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipe_name() {
+        assert_eq!(pipe_name("my-app-control"), "\\\\.\\pipe\\my-app-control");
+    }
+
+    #[test]
+    fn test_format_command() {
+        assert_eq!(format_command("reload"), "reload\n");
+        assert_eq!(format_command("reload\r\n"), "reload\n");
+        assert_eq!(format_command(""), "\n");
+    }
+
+    #[test]
+    fn test_send_without_child_fails() {
+        assert!(ChildInput::default().send("reload").is_err());
+    }
+
+    #[test]
+    fn test_stalled_client_does_not_block_others() {
+        let name = format!("rust-nssm-ipc-test-{}", std::process::id());
+        spawn_server(&name, Arc::new(ChildInput::default()), None).unwrap();
+
+        // 第一个客户端连接后一直不发送命令
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let _stalled = loop {
+            match File::options().read(true).write(true).open(pipe_name(&name)) {
+                Ok(file) => break file,
+                Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+                Err(e) => panic!("Failed to connect to IPC pipe: {}", e),
+            }
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(send(&name, "reload", Duration::from_secs(5)));
+        });
+        let result = rx.recv_timeout(Duration::from_secs(10))
+            .expect("second client was blocked by the stalled one");
+        assert!(result.unwrap_err().to_string().contains("No child process is running"));
+    }
+}
@@ -0,0 +1,411 @@
+use anyhow::{Context, Result};
+use log::{error, warn};
+use regex::RegexSet;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use windows_sys::Win32::Foundation::{GetLastError, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE};
+use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_OUTBOUND;
+use windows_sys::Win32::System::IO::CancelSynchronousIo;
+use windows_sys::Win32::System::Pipes::{ConnectNamedPipe, DisconnectNamedPipe};
+
+use crate::status_pipe;
+
+/// 每个订阅者最多积压的输出块数，超过后断开该订阅者
+const SUBSCRIBER_BUFFER: usize = 256;
+
+// 读取子进程输出的缓冲区大小
+const READ_CHUNK_SIZE: usize = 8192;
+
+/// 等待匹配的输出块数上限，积压过多时丢弃新的输出块而不阻塞输出转发
+const MATCH_QUEUE_LEN: usize = 64;
+
+// 不含换行的超长输出按此长度截断为一行进行匹配
+const MAX_MATCH_LINE: usize = 64 * 1024;
+
+/// 子进程输出的分发中心：把每块输出复制给所有已连接的实时日志客户端
+///
+/// 发送从不阻塞子进程输出的读取；订阅者积压过多时直接断开，并取消其转发线程中阻塞的写入。
+#[derive(Default)]
+pub struct OutputHub {
+    subscribers: Mutex<Vec<Subscriber>>,
+    // 已断开但转发线程尚未退出的订阅者，每次发送时重新取消其写入
+    cancelling: Mutex<Vec<Arc<SubscriberWriter>>>,
+}
+
+impl OutputHub {
+    /// 注册一个新的订阅者，返回接收端和用于登记转发线程的句柄
+    pub fn subscribe(&self) -> (Receiver<Arc<[u8]>>, Arc<SubscriberWriter>) {
+        let (tx, rx) = sync_channel(SUBSCRIBER_BUFFER);
+        let writer = Arc::new(SubscriberWriter::default());
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(Subscriber { sender: tx, writer: writer.clone() });
+        }
+        (rx, writer)
+    }
+
+    /// 向所有订阅者发送一块输出
+    pub fn publish(&self, chunk: &[u8]) {
+        // 取消时转发线程可能还未进入写入，重复取消直到线程退出
+        if let Ok(mut cancelling) = self.cancelling.lock() {
+            cancelling.retain(|writer| writer.cancel());
+        }
+
+        let Ok(mut subscribers) = self.subscribers.lock() else {
+            return;
+        };
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let chunk: Arc<[u8]> = Arc::from(chunk);
+        subscribers.retain(|subscriber| match subscriber.sender.try_send(chunk.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                warn!("Disconnecting live output client that fell too far behind");
+                if subscriber.writer.cancel() {
+                    if let Ok(mut cancelling) = self.cancelling.lock() {
+                        cancelling.push(subscriber.writer.clone());
+                    }
+                }
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+/// 一个实时日志客户端
+struct Subscriber {
+    sender: SyncSender<Arc<[u8]>>,
+    writer: Arc<SubscriberWriter>,
+}
+
+/// 订阅者的转发线程：订阅者被断开时，转发线程可能正阻塞在向不再读取的客户端写入
+#[derive(Default)]
+pub struct SubscriberWriter {
+    cancelled: AtomicBool,
+    thread: OnceLock<JoinHandle<()>>,
+}
+
+impl SubscriberWriter {
+    /// 登记转发线程
+    pub fn attach(&self, thread: JoinHandle<()>) {
+        let _ = self.thread.set(thread);
+    }
+
+    /// 请求转发线程停止并取消其阻塞中的写入，线程可能仍在运行时返回 `true`
+    fn cancel(&self) -> bool {
+        self.cancelled.store(true, Ordering::SeqCst);
+        match self.thread.get() {
+            Some(thread) if thread.is_finished() => false,
+            Some(thread) => {
+                unsafe { CancelSynchronousIo(thread.as_raw_handle() as HANDLE) };
+                true
+            }
+            // 线程尚未登记，启动后会先检查取消标志
+            None => true,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// 在后台线程中逐行匹配子进程的一路输出，任一行匹配重启规则时置位共享标志
+///
+/// 每个子进程使用新的标志，同一子进程连续输出多条匹配的行只会触发一次重启。
+pub struct LineMatcher {
+    queue: SyncSender<MatchChunk>,
+    // 上次提交以来有输出块被丢弃时为 `Some`，值表示最后丢弃的块是否停在一行中间
+    dropped: Cell<Option<bool>>,
+}
+
+/// 提交给匹配线程的输出块
+struct MatchChunk {
+    /// 此前有输出块因积压被丢弃时为 `Some`，值表示丢弃处是否停在一行中间
+    dropped: Option<bool>,
+    data: Arc<[u8]>,
+}
+
+impl LineMatcher {
+    pub fn spawn(patterns: Arc<RegexSet>, matched: Arc<AtomicBool>) -> Self {
+        let (queue, chunks) = sync_channel::<MatchChunk>(MATCH_QUEUE_LEN);
+
+        std::thread::spawn(move || match_lines(chunks, &patterns, &matched));
+
+        Self { queue, dropped: Cell::new(None) }
+    }
+
+    /// 提交一块输出等待匹配，从不阻塞
+    ///
+    /// 匹配线程积压时丢弃该块，并让匹配线程跳过被截断的行。
+    pub fn feed(&self, chunk: &[u8]) {
+        let submitted = MatchChunk { dropped: self.dropped.get(), data: Arc::from(chunk) };
+        match self.queue.try_send(submitted) {
+            Ok(()) => self.dropped.set(None),
+            Err(_) => self.dropped.set(Some(!chunk.ends_with(b"\n"))),
+        }
+    }
+}
+
+/// 逐行匹配提交的输出块，直到发送端关闭
+fn match_lines(chunks: impl IntoIterator<Item = MatchChunk>, patterns: &RegexSet, matched: &AtomicBool) {
+    let mut line = Vec::new();
+    let mut skip_line = false;
+    for chunk in chunks {
+        if matched.load(Ordering::Relaxed) {
+            continue;
+        }
+        // 丢弃的输出截断了当前行，拼接出的残缺行不参与匹配
+        if let Some(mid_line) = chunk.dropped {
+            line.clear();
+            skip_line = mid_line;
+        }
+        let data = skip_rest_of_line(&mut skip_line, &chunk.data);
+        for_each_line(&mut line, data, |text| {
+            if !matched.load(Ordering::Relaxed) && patterns.is_match(text) {
+                warn!("Child output matched a restart pattern: {}", text);
+                matched.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+/// 需要跳过当前行时返回本块中下一行开始之后的部分，本块没有换行时返回空切片
+fn skip_rest_of_line<'a>(skip_line: &mut bool, chunk: &'a [u8]) -> &'a [u8] {
+    if !*skip_line {
+        return chunk;
+    }
+    match chunk.iter().position(|&byte| byte == b'\n') {
+        Some(end) => {
+            *skip_line = false;
+            &chunk[end + 1..]
+        }
+        None => &[],
+    }
+}
+
+/// 把一块输出追加到未完成的行中，对每个完整的行调用 `f`
+fn for_each_line(line: &mut Vec<u8>, chunk: &[u8], mut f: impl FnMut(&str)) {
+    for &byte in chunk {
+        if byte != b'\n' {
+            line.push(byte);
+            if line.len() < MAX_MATCH_LINE {
+                continue;
+            }
+        }
+        let text = String::from_utf8_lossy(line);
+        f(text.trim_end_matches('\r'));
+        line.clear();
+    }
+}
+
+/// 在后台线程中读取子进程的一路输出，写入日志文件（如有）、分发给实时日志客户端并提交重启规则匹配
+pub fn spawn_copier(
+    mut source: impl Read + Send + 'static,
+    mut sink: Option<Box<dyn Write + Send>>,
+    hub: Arc<OutputHub>,
+    matcher: Option<LineMatcher>,
+) {
+    std::thread::spawn(move || {
+        let mut buffer = vec![0u8; READ_CHUNK_SIZE];
+        loop {
+            let read = match source.read(&mut buffer) {
+                Ok(0) | Err(_) => return,
+                Ok(read) => read,
+            };
+
+            if let Some(file) = sink.as_mut() {
+                if let Err(e) = file.write_all(&buffer[..read]) {
+                    error!("Failed to write child output to log file: {}", e);
+                    sink = None;
+                }
+            }
+
+            hub.publish(&buffer[..read]);
+
+            if let Some(matcher) = &matcher {
+                matcher.feed(&buffer[..read]);
+            }
+        }
+    });
+}
+
+/// 实时输出管道名称
+pub fn pipe_name(service_name: &str) -> String {
+    format!("{}-output", status_pipe::pipe_name(service_name))
+}
+
+/// 在后台线程中启动实时输出管道服务端，每个连接的客户端持续接收子进程输出
+///
+/// 访问控制与状态管道相同，管道名已被占用时返回错误。
+pub fn spawn_server(service_name: &str, hub: Arc<OutputHub>, allow_group: Option<&str>) -> Result<()> {
+    status_pipe::spawn_pipe_server(&pipe_name(service_name), allow_group, PIPE_ACCESS_OUTBOUND, move |pipe| {
+        let connected = unsafe { ConnectNamedPipe(pipe, std::ptr::null_mut()) } != 0
+            || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+        if !connected {
+            drop(unsafe { File::from_raw_handle(pipe as _) });
+            return;
+        }
+
+        let (subscription, writer) = hub.subscribe();
+        let serving = writer.clone();
+        writer.attach(std::thread::spawn(move || serve_subscriber(pipe, subscription, &serving)));
+    })
+}
+
+/// 向一个客户端转发输出，直至客户端断开或因积压过多被取消订阅
+fn serve_subscriber(pipe: HANDLE, subscription: Receiver<Arc<[u8]>>, writer: &SubscriberWriter) {
+    // 管道句柄交由 File 管理，离开作用域时关闭
+    let mut file = unsafe { File::from_raw_handle(pipe as _) };
+
+    for chunk in subscription {
+        // 被取消订阅后不再转发积压的输出；阻塞中的写入由 CancelSynchronousIo 中断并返回错误
+        if writer.is_cancelled() || file.write_all(&chunk).is_err() {
+            break;
+        }
+    }
+
+    unsafe { DisconnectNamedPipe(file.as_raw_handle() as _) };
+}
+
+/// 连接服务宿主的实时输出管道，把子进程输出持续写到标准输出
+pub fn follow(service_name: &str, timeout: Duration) -> Result<()> {
+    let name = pipe_name(service_name);
+    let deadline = Instant::now() + timeout;
+
+    let mut pipe = loop {
+        match File::open(&name) {
+            Ok(file) => break file,
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) && Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Err(e) => {
+                return Err(e).context(format!(
+                    "Failed to connect to live output of service '{}' (is it running?)",
+                    service_name
+                ))
+            }
+        }
+    };
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let mut buffer = vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        let read = pipe.read(&mut buffer).unwrap_or(0);
+        if read == 0 {
+            eprintln!("Live output stream closed");
+            return Ok(());
+        }
+        out.write_all(&buffer[..read])?;
+        out.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_subscriber_is_dropped() {
+        let hub = OutputHub::default();
+        let (fast, _) = hub.subscribe();
+        let (slow, slow_writer) = hub.subscribe();
+
+        for _ in 0..SUBSCRIBER_BUFFER {
+            hub.publish(b"line\n");
+            fast.recv().unwrap();
+        }
+        assert_eq!(hub.subscribers.lock().unwrap().len(), 2);
+
+        // 慢订阅者的缓冲区已满，再发送一块即被断开，快订阅者不受影响
+        hub.publish(b"line\n");
+        assert_eq!(hub.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(&*fast.recv().unwrap(), b"line\n");
+        assert_eq!(slow.iter().count(), SUBSCRIBER_BUFFER);
+        assert!(slow_writer.is_cancelled());
+    }
+
+    #[test]
+    fn test_for_each_line() {
+        let mut line = Vec::new();
+        let mut lines = Vec::new();
+        for_each_line(&mut line, b"first\r\nsec", |text| lines.push(text.to_string()));
+        for_each_line(&mut line, b"ond\n", |text| lines.push(text.to_string()));
+        assert_eq!(lines, ["first", "second"]);
+        assert!(line.is_empty());
+    }
+
+    #[test]
+    fn test_skip_rest_of_line() {
+        let mut skip_line = true;
+        assert_eq!(skip_rest_of_line(&mut skip_line, b"AL partial"), b"");
+        assert!(skip_line);
+        assert_eq!(skip_rest_of_line(&mut skip_line, b" line\nnext\n"), b"next\n");
+        assert!(!skip_line);
+        assert_eq!(skip_rest_of_line(&mut skip_line, b"kept\n"), b"kept\n");
+    }
+
+    #[test]
+    fn test_line_matcher_skips_truncated_line() {
+        let (queue, chunks) = sync_channel(2);
+        let matcher = LineMatcher { queue, dropped: Cell::new(None) };
+
+        // 队列已满时 "connection lost\n" 被丢弃，"ERROR: FAT" 与 "AL" 之间缺了一段，不能拼成 "FATAL"
+        matcher.feed(b"INFO ready\n");
+        matcher.feed(b"ERROR: FAT");
+        matcher.feed(b" connection lost\n");
+        let mut received: Vec<MatchChunk> = chunks.try_iter().collect();
+        matcher.feed(b"AL database unreachable\n");
+        received.extend(chunks.try_iter());
+        assert_eq!(received.last().unwrap().dropped, Some(false));
+
+        let patterns = RegexSet::new(["FATAL"]).unwrap();
+        let matched = AtomicBool::new(false);
+        match_lines(received, &patterns, &matched);
+        assert!(!matched.load(Ordering::Relaxed));
+
+        // 丢弃处停在一行中间时跳过该行余下的内容，从下一行继续匹配
+        let chunks = vec![
+            MatchChunk { dropped: None, data: Arc::from(&b"ERROR: FAT"[..]) },
+            MatchChunk { dropped: Some(true), data: Arc::from(&b"AL tail\nFATAL next\n"[..]) },
+        ];
+        match_lines(chunks, &patterns, &matched);
+        assert!(matched.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_line_matcher_sets_flag() {
+        let patterns = Arc::new(RegexSet::new(["FATAL", "out of memory"]).unwrap());
+        let matched = Arc::new(AtomicBool::new(false));
+        let matcher = LineMatcher::spawn(patterns, matched.clone());
+
+        matcher.feed(b"INFO ready\n");
+        matcher.feed(b"ERROR: FAT");
+        matcher.feed(b"AL database unreachable\n");
+        drop(matcher);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !matched.load(Ordering::Relaxed) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(matched.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_disconnected_subscriber_is_removed() {
+        let hub = OutputHub::default();
+        drop(hub.subscribe().0);
+        hub.publish(b"line\n");
+        assert!(hub.subscribers.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,470 @@
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate};
+use log::{info, warn};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::service_manager::{LogRotation, RotationInterval};
+
+/// 轮转日志时默认保留的历史文件数（按日期轮转时为周期数）
+pub const DEFAULT_MAX_LOG_FILES: u32 = 5;
+
+// 带日期的文件名中的日期格式
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// 行首时间戳的格式，`logs --since` 按此格式解析
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// 不含换行的超长输出按此长度截断为一行写入
+const MAX_LINE: usize = 64 * 1024;
+
+/// 指定宿主诊断日志目录的环境变量
+pub const LOG_DIR_ENV: &str = "RUST_NSSM_LOG_DIR";
+
+/// 宿主自身诊断日志的目录：`RUST_NSSM_LOG_DIR`，未设置时为 `%ProgramData%\rust-nssm`，都不可用时为程序所在目录
+pub fn host_log_dir() -> Option<PathBuf> {
+    host_log_dir_from(
+        std::env::var_os(LOG_DIR_ENV),
+        std::env::var_os("ProgramData"),
+        std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)),
+    )
+}
+
+pub(crate) fn host_log_dir_from(
+    override_dir: Option<OsString>,
+    program_data: Option<OsString>,
+    exe_dir: Option<PathBuf>,
+) -> Option<PathBuf> {
+    let non_empty = |dir: Option<OsString>| dir.filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    non_empty(override_dir)
+        .or_else(|| non_empty(program_data).map(|dir| dir.join("rust-nssm")))
+        .or(exe_dir)
+}
+
+/// 以追加方式打开宿主诊断日志目录中的 `file_name`，目录不存在时先创建
+pub fn open_host_log(file_name: &str) -> io::Result<File> {
+    let dir = host_log_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No directory available for host logs"))?;
+    open_host_log_at(&dir.join(file_name))
+}
+
+/// 以追加方式打开指定路径的宿主诊断日志，所在目录不存在时先创建
+pub fn open_host_log_at(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    open_append(path)
+}
+
+/// 打开子进程输出文件
+///
+/// 按大小轮转时，文件达到上限则先轮转再打开；按日期轮转时返回的写入器在跨越日期边界时自动切换文件，
+/// 同时指定了大小上限时，同一周期内达到上限也会轮转。
+pub fn open(path: &Path, stream: &str, rotation: LogRotation) -> Result<Box<dyn Write + Send>> {
+    let file: io::Result<Box<dyn Write + Send>> = match rotation {
+        LogRotation::None => open_append(path).map(|file| Box::new(file) as _),
+        LogRotation::BySize { max_bytes, keep } => {
+            if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() >= max_bytes) {
+                if let Err(e) = rotate_log_file(path, keep) {
+                    warn!("Failed to rotate {} file {:?}: {}", stream, path, e);
+                }
+            }
+            open_append(path).map(|file| Box::new(file) as _)
+        }
+        LogRotation::ByDate { interval, keep, max_bytes } => {
+            DatedLogFile::open(path, interval, keep, max_bytes, Local::now().date_naive()).map(|file| Box::new(file) as _)
+        }
+    };
+    file.context(format!("Failed to open {} file: {:?}", stream, path))
+}
+
+/// 当前正在写入的输出文件：按日期轮转时为当前周期的带日期文件，否则为配置的文件本身
+pub fn current_path(path: &Path, rotation: LogRotation) -> PathBuf {
+    match rotation {
+        LogRotation::ByDate { interval, .. } => dated_path(path, interval.period_start(Local::now().date_naive())),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// 以追加方式打开文件
+fn open_append(path: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// 轮转日志文件：`service.log` → `service.log.1`，已有的 `.1` → `.2`，依此类推，只保留 `max_files` 个历史文件
+///
+/// 文件以重命名方式轮转，上一个子进程的输出线程仍持有旧文件句柄时，其最后写入的内容会进入 `.1` 而不会丢失。
+fn rotate_log_file(path: &Path, max_files: u32) -> Result<()> {
+    let rotated = |index: u32| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    };
+
+    // 删除超出保留数量的历史文件（包括调小保留数量前留下的文件）
+    let mut index = max_files.max(1);
+    while rotated(index).exists() {
+        std::fs::remove_file(rotated(index)).context(format!("Failed to delete {:?}", rotated(index)))?;
+        index += 1;
+    }
+
+    if max_files == 0 {
+        return std::fs::remove_file(path).context(format!("Failed to delete {:?}", path));
+    }
+
+    for index in (1..max_files).rev() {
+        if rotated(index).exists() {
+            std::fs::rename(rotated(index), rotated(index + 1))
+                .context(format!("Failed to rename {:?}", rotated(index)))?;
+        }
+    }
+
+    std::fs::rename(path, rotated(1)).context(format!("Failed to rename {:?}", path))?;
+    info!("Rotated log file {:?}", path);
+    Ok(())
+}
+
+/// 带日期的文件名：`service_stdout.log` → `service_stdout_2024-01-15.log`
+pub fn dated_path(path: &Path, date: NaiveDate) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, date.format(DATE_FORMAT), extension.to_string_lossy()),
+        None => format!("{}_{}", stem, date.format(DATE_FORMAT)),
+    };
+    path.with_file_name(name)
+}
+
+/// 从带日期的文件名中取出日期，不是 `path` 对应的带日期文件时返回 `None`
+fn parse_dated_name(path: &Path, name: &str) -> Option<NaiveDate> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+    let date = name.strip_prefix(&format!("{}_", stem))?.strip_suffix(extension.as_str())?;
+    NaiveDate::parse_from_str(date, DATE_FORMAT).ok()
+}
+
+/// 删除早于最近 `keep` 个周期的带日期文件（包括同一周期内按大小轮转出的 `.1`、`.2` 等历史文件）
+fn remove_expired(path: &Path, interval: RotationInterval, keep: u32, period: NaiveDate) {
+    let cutoff = period - chrono::Duration::days(interval.days() * i64::from(keep));
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let name = match name.rsplit_once('.') {
+            Some((base, index)) if !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) => base,
+            _ => &name,
+        };
+        let expired = parse_dated_name(path, name).is_some_and(|date| date < cutoff);
+        if expired {
+            match std::fs::remove_file(entry.path()) {
+                Ok(()) => info!("Deleted expired log file {:?}", entry.path()),
+                Err(e) => warn!("Failed to delete expired log file {:?}: {}", entry.path(), e),
+            }
+        }
+    }
+}
+
+/// 按日期轮转的输出文件：每次写入前检查日期，进入新周期时切换到新的带日期文件并清理过期文件；
+/// 指定了 `max_bytes` 时还检查当前文件大小，达到上限时轮转为 `.1`、`.2` 等历史文件
+///
+/// 输出经由宿主的转发线程写入，切换文件不需要重启子进程。
+pub struct DatedLogFile {
+    path: PathBuf,
+    interval: RotationInterval,
+    keep: u32,
+    max_bytes: Option<u64>,
+    period: NaiveDate,
+    file: File,
+    size: u64,
+}
+
+impl DatedLogFile {
+    fn open(
+        path: &Path,
+        interval: RotationInterval,
+        keep: u32,
+        max_bytes: Option<u64>,
+        today: NaiveDate,
+    ) -> io::Result<Self> {
+        let period = interval.period_start(today);
+        let file = open_append(&dated_path(path, period))?;
+        let size = file.metadata()?.len();
+        remove_expired(path, interval, keep, period);
+        Ok(Self { path: path.to_path_buf(), interval, keep, max_bytes, period, file, size })
+    }
+
+    /// 在 `today` 写入：先按日期、再按大小检查是否需要切换文件
+    fn write_on(&mut self, buf: &[u8], today: NaiveDate) -> io::Result<usize> {
+        self.roll_over(today)?;
+        self.roll_over_size()?;
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    /// `today` 进入新周期时切换文件
+    fn roll_over(&mut self, today: NaiveDate) -> io::Result<()> {
+        let period = self.interval.period_start(today);
+        if period == self.period {
+            return Ok(());
+        }
+
+        let path = dated_path(&self.path, period);
+        self.file = open_append(&path)?;
+        self.size = self.file.metadata()?.len();
+        self.period = period;
+        info!("Switched log file to {:?}", path);
+        remove_expired(&self.path, self.interval, self.keep, period);
+        Ok(())
+    }
+
+    /// 当前文件达到大小上限时轮转为 `.1`、`.2` 等历史文件并重新打开
+    fn roll_over_size(&mut self) -> io::Result<()> {
+        if self.max_bytes.is_none_or(|max_bytes| self.size < max_bytes) {
+            return Ok(());
+        }
+
+        let path = dated_path(&self.path, self.period);
+        if let Err(e) = rotate_log_file(&path, self.keep) {
+            // 轮转失败时继续写入原文件，再写入 `max_bytes` 后重试，避免每次写入都记录警告
+            warn!("Failed to rotate log file {:?}: {}", path, e);
+            self.size = 0;
+            return Ok(());
+        }
+        self.file = open_append(&path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for DatedLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_on(buf, Local::now().date_naive())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// 为每行输出加上 `[YYYY-MM-DD HH:MM:SS] ` 前缀的写入器
+///
+/// 跨越多次写入的不完整行先缓存，收到换行后才加上前缀整行写入，因此一行只有一个时间戳；
+/// 写入器被释放时（子进程的输出结束）写出剩余的不完整行。
+pub struct TimestampWriter<W: Write> {
+    inner: W,
+    line: Vec<u8>,
+}
+
+impl<W: Write> TimestampWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, line: Vec::new() }
+    }
+
+    /// 加上当前时间戳写出缓存的行
+    fn write_line(&mut self) -> io::Result<()> {
+        let prefix = format!("[{}] ", Local::now().format(TIMESTAMP_FORMAT));
+        let mut line = prefix.into_bytes();
+        line.append(&mut self.line);
+        self.inner.write_all(&line)
+    }
+}
+
+impl<W: Write> Write for TimestampWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.line.push(byte);
+            if byte == b'\n' || self.line.len() >= MAX_LINE {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for TimestampWriter<W> {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            self.line.push(b'\n');
+            let _ = self.write_line();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, DATE_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn test_host_log_dir_from() {
+        let exe_dir = Some(PathBuf::from("C:\\Tools"));
+        assert_eq!(
+            host_log_dir_from(Some("D:\\logs".into()), Some("C:\\ProgramData".into()), exe_dir.clone()),
+            Some(PathBuf::from("D:\\logs"))
+        );
+        assert_eq!(
+            host_log_dir_from(Some("".into()), Some("C:\\ProgramData".into()), exe_dir.clone()),
+            Some(PathBuf::from("C:\\ProgramData").join("rust-nssm"))
+        );
+        assert_eq!(host_log_dir_from(None, None, exe_dir.clone()), exe_dir);
+    }
+
+    #[test]
+    fn test_rotate_log_file() {
+        let dir = test_dir("rust_nssm_rotate_log_test");
+        let path = dir.join("service.log");
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).ok();
+
+        for content in ["first", "second", "third"] {
+            std::fs::write(&path, content).unwrap();
+            rotate_log_file(&path, 2).unwrap();
+        }
+        assert_eq!(read("service.log"), None);
+        assert_eq!(read("service.log.1").as_deref(), Some("third"));
+        assert_eq!(read("service.log.2").as_deref(), Some("second"));
+        assert_eq!(read("service.log.3"), None);
+
+        // 调小保留数量后多余的历史文件被删除
+        std::fs::write(&path, "fourth").unwrap();
+        rotate_log_file(&path, 1).unwrap();
+        assert_eq!(read("service.log.1").as_deref(), Some("fourth"));
+        assert_eq!(read("service.log.2"), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_dated_path() {
+        let dir = Path::new("logs");
+        let path = &dir.join("service_stdout.log");
+        assert_eq!(dated_path(path, date("2024-01-15")), dir.join("service_stdout_2024-01-15.log"));
+        assert_eq!(parse_dated_name(path, "service_stdout_2024-01-15.log"), Some(date("2024-01-15")));
+        assert_eq!(parse_dated_name(path, "service_stdout.log"), None);
+        assert_eq!(parse_dated_name(path, "other_2024-01-15.log"), None);
+
+        // 2024-01-17 是周三，按周轮转时归入从周一 2024-01-15 开始的周期
+        assert_eq!(RotationInterval::Weekly.period_start(date("2024-01-17")), date("2024-01-15"));
+        assert_eq!(RotationInterval::Daily.period_start(date("2024-01-17")), date("2024-01-17"));
+
+        // 跨年时按周轮转的周期从上一年的周一开始
+        assert_eq!(RotationInterval::Weekly.period_start(date("2025-01-01")), date("2024-12-30"));
+        assert_eq!(dated_path(path, date("2024-12-30")), dir.join("service_stdout_2024-12-30.log"));
+    }
+
+    #[test]
+    fn test_timestamp_writer() {
+        let mut output = Vec::new();
+        {
+            let mut writer = TimestampWriter::new(&mut output);
+            writer.write_all(b"first\nsec").unwrap();
+            writer.write_all(b"ond\r\n").unwrap();
+            writer.write_all(b"\nunterminated").unwrap();
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        for (line, text) in lines.iter().zip(["first", "second", "", "unterminated"]) {
+            // 每行恰好一个时间戳前缀：`[YYYY-MM-DD HH:MM:SS] `
+            let (timestamp, rest) = line.split_at(22);
+            assert!(chrono::NaiveDateTime::parse_from_str(&timestamp[1..20], TIMESTAMP_FORMAT).is_ok(), "{}", line);
+            assert_eq!(&timestamp[20..], "] ");
+            assert_eq!(rest, text);
+        }
+    }
+
+    #[test]
+    fn test_dated_log_file_rolls_over() {
+        let dir = test_dir("rust_nssm_dated_log_test");
+        let path = dir.join("service.log");
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).ok();
+
+        let mut file = DatedLogFile::open(&path, RotationInterval::Daily, 1, None, date("2024-01-15")).unwrap();
+        file.file.write_all(b"monday\n").unwrap();
+
+        // 同一天内不切换
+        file.roll_over(date("2024-01-15")).unwrap();
+        file.file.write_all(b"still monday\n").unwrap();
+
+        file.roll_over(date("2024-01-16")).unwrap();
+        file.file.write_all(b"tuesday\n").unwrap();
+        assert_eq!(read("service_2024-01-15.log").as_deref(), Some("monday\nstill monday\n"));
+        assert_eq!(read("service_2024-01-16.log").as_deref(), Some("tuesday\n"));
+
+        // 跳过若干天后只保留最近1个周期之内的文件
+        file.roll_over(date("2024-01-17")).unwrap();
+        assert_eq!(read("service_2024-01-15.log"), None);
+        assert!(read("service_2024-01-16.log").is_some());
+
+        // 跨月、跨年的日期边界同样切换
+        file.roll_over(date("2024-12-31")).unwrap();
+        file.file.write_all(b"new year's eve\n").unwrap();
+        file.roll_over(date("2025-01-01")).unwrap();
+        file.file.write_all(b"new year\n").unwrap();
+        assert_eq!(read("service_2024-12-31.log").as_deref(), Some("new year's eve\n"));
+        assert_eq!(read("service_2025-01-01.log").as_deref(), Some("new year\n"));
+        drop(file);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_dated_log_file_rolls_over_by_size_and_date() {
+        let dir = test_dir("rust_nssm_dated_size_log_test");
+        let path = dir.join("service.log");
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).ok();
+
+        let mut file = DatedLogFile::open(&path, RotationInterval::Daily, 2, Some(10), date("2024-01-15")).unwrap();
+        file.write_on(b"0123456789", date("2024-01-15")).unwrap();
+
+        // 同一天内先达到大小上限：轮转为 `.1`
+        file.write_on(b"more\n", date("2024-01-15")).unwrap();
+        assert_eq!(read("service_2024-01-15.log.1").as_deref(), Some("0123456789"));
+        assert_eq!(read("service_2024-01-15.log").as_deref(), Some("more\n"));
+
+        // 未达到大小上限时先跨越日期边界：切换到新的带日期文件
+        file.write_on(b"tuesday\n", date("2024-01-16")).unwrap();
+        assert_eq!(read("service_2024-01-15.log").as_deref(), Some("more\n"));
+        assert_eq!(read("service_2024-01-16.log").as_deref(), Some("tuesday\n"));
+        assert_eq!(read("service_2024-01-16.log.1"), None);
+
+        // 新文件重新计算大小
+        file.write_on(b"12", date("2024-01-16")).unwrap();
+        file.write_on(b"again\n", date("2024-01-16")).unwrap();
+        assert_eq!(read("service_2024-01-16.log.1").as_deref(), Some("tuesday\n12"));
+        assert_eq!(read("service_2024-01-16.log").as_deref(), Some("again\n"));
+
+        // 过期周期的带日期文件及其按大小轮转的历史文件一起删除
+        file.write_on(b"thursday\n", date("2024-01-18")).unwrap();
+        assert_eq!(read("service_2024-01-15.log"), None);
+        assert_eq!(read("service_2024-01-15.log.1"), None);
+        assert!(read("service_2024-01-16.log.1").is_some());
+        assert_eq!(read("service_2024-01-18.log").as_deref(), Some("thursday\n"));
+        drop(file);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::log_file;
+use crate::service_manager::{self, LogRotation, RotationInterval};
+
+// --follow 模式下检查新输出的间隔
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 显示服务的子进程输出日志
+///
+/// 指定 `since` 时只显示该时间之后写入的内容：带时间戳前缀的行按行内时间过滤，
+/// 否则按文件修改时间判断整个文件是否需要显示。
+pub fn show_logs(service_name: &str, stderr: bool, since: Option<DateTime<Local>>, follow: bool) -> Result<()> {
+    let value_name = if stderr { "StderrPath" } else { "StdoutPath" };
+    let path = service_manager::read_parameter_string(service_name, value_name)
+        .map(PathBuf::from)
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Service '{}' has no {} log configured",
+                service_name,
+                if stderr { "stderr" } else { "stdout" }
+            )
+        })?;
+
+    // 按日期轮转时显示当前周期的带日期文件
+    let path = match service_manager::read_parameter_string(service_name, "LogRotation")
+        .ok()
+        .and_then(|interval| <RotationInterval as clap::ValueEnum>::from_str(interval.trim(), true).ok())
+    {
+        Some(interval) => log_file::current_path(&path, LogRotation::ByDate { interval, keep: log_file::DEFAULT_MAX_LOG_FILES, max_bytes: None }),
+        None => path,
+    };
+
+    let file = File::open(&path).context(format!("Failed to open log file {:?}", path))?;
+    let mut reader = BufReader::new(file);
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+
+    match since {
+        Some(since) => print_since(&mut reader, &path, since, &mut out)?,
+        None => print_lines(&mut reader, &mut out, |_| true)?,
+    }
+
+    if follow {
+        out.flush()?;
+        follow_file(reader, &path, &mut out)?;
+    }
+
+    Ok(())
+}
+
+/// 输出 `since` 之后写入的内容
+fn print_since(reader: &mut BufReader<File>, path: &Path, since: DateTime<Local>, out: &mut impl Write) -> Result<()> {
+    let first_timestamp = first_line_timestamp(reader)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    match first_timestamp {
+        Some(first) => {
+            if first >= since {
+                eprintln!(
+                    "Requested time is before the earliest entry ({}), showing the whole log",
+                    first.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+
+            // 无时间戳的行（如多行输出的后续行）沿用上一行的判断结果
+            let mut include = false;
+            print_lines(reader, out, |line| {
+                if let Some(timestamp) = line_timestamp(line) {
+                    include = timestamp >= since;
+                }
+                include
+            })
+        }
+        None => {
+            // 日志行没有时间戳，只能根据文件修改时间判断
+            let modified: DateTime<Local> = std::fs::metadata(path)?.modified()?.into();
+            if modified < since {
+                reader.seek(SeekFrom::End(0))?;
+                return Ok(());
+            }
+
+            eprintln!("Log lines carry no timestamps; showing the whole file (last written {})", modified.format("%Y-%m-%d %H:%M:%S"));
+            print_lines(reader, out, |_| true)
+        }
+    }
+}
+
+/// 读取第一条非空行的时间戳
+fn first_line_timestamp(reader: &mut BufReader<File>) -> Result<Option<DateTime<Local>>> {
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(None);
+        }
+
+        let line = String::from_utf8_lossy(&buffer);
+        if !line.trim().is_empty() {
+            return Ok(line_timestamp(&line));
+        }
+    }
+}
+
+/// 逐行输出到文件末尾，`filter` 决定每行是否输出
+fn print_lines(reader: &mut BufReader<File>, out: &mut impl Write, mut filter: impl FnMut(&str) -> bool) -> Result<()> {
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(());
+        }
+
+        let line = String::from_utf8_lossy(&buffer);
+        if filter(&line) {
+            out.write_all(line.as_bytes())?;
+        }
+    }
+}
+
+/// 持续输出新写入的内容，文件被截断时从头开始
+fn follow_file(mut reader: BufReader<File>, path: &Path, out: &mut impl Write) -> Result<()> {
+    let mut position = reader.stream_position()?;
+    let mut pending = Vec::new();
+
+    loop {
+        let len = std::fs::metadata(path)?.len();
+        if len < position {
+            reader.seek(SeekFrom::Start(0))?;
+            position = 0;
+            pending.clear();
+        }
+
+        // 只输出完整的行，未写完的行留到下次
+        let read = reader.read_until(b'\n', &mut pending)?;
+        if read > 0 {
+            position += read as u64;
+            if pending.ends_with(b"\n") {
+                out.write_all(String::from_utf8_lossy(&pending).as_bytes())?;
+                out.flush()?;
+                pending.clear();
+            }
+            continue;
+        }
+
+        std::thread::sleep(FOLLOW_POLL_INTERVAL);
+    }
+}
+
+/// 解析行首的 `[YYYY-MM-DD HH:MM:SS(.fff)]` 时间戳前缀
+fn line_timestamp(line: &str) -> Option<DateTime<Local>> {
+    let rest = line.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    let naive = NaiveDateTime::parse_from_str(&rest[..end], "%Y-%m-%d %H:%M:%S%.f").ok()?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// 解析 `--since` 参数：相对时长（如 `30s`、`10m`、`2h`、`1d`）或本地时间（如 `2024-01-02T10:00`）
+pub fn parse_since(value: &str) -> Result<DateTime<Local>> {
+    parse_since_at(value, Local::now())
+}
+
+fn parse_since_at(value: &str, now: DateTime<Local>) -> Result<DateTime<Local>> {
+    let value = value.trim();
+
+    if let Some(duration) = parse_relative(value) {
+        return Ok(now - duration);
+    }
+
+    const FORMATS: [&str; 4] = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"];
+    let naive = FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| anyhow::anyhow!("Invalid --since value '{}', expected e.g. 10m, 2h or 2024-01-02T10:00", value))?;
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| anyhow::anyhow!("Time '{}' does not exist in the local time zone", value))
+}
+
+/// 解析相对时长
+fn parse_relative(value: &str) -> Option<chrono::Duration> {
+    let unit_pos = value.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = value[..unit_pos].parse().ok()?;
+    match &value[unit_pos..] {
+        "s" => Some(chrono::Duration::seconds(amount)),
+        "m" => Some(chrono::Duration::minutes(amount)),
+        "h" => Some(chrono::Duration::hours(amount)),
+        "d" => Some(chrono::Duration::days(amount)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        let now = Local.with_ymd_and_hms(2024, 1, 2, 12, 0, 0).unwrap();
+        assert_eq!(parse_since_at("10m", now).unwrap(), Local.with_ymd_and_hms(2024, 1, 2, 11, 50, 0).unwrap());
+        assert_eq!(parse_since_at("1d", now).unwrap(), Local.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap());
+        assert_eq!(parse_since_at("2024-01-02T10:00", now).unwrap(), Local.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap());
+        assert_eq!(parse_since_at("2024-01-02", now).unwrap(), Local.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+        assert!(parse_since_at("yesterday", now).is_err());
+        assert!(parse_since_at("10x", now).is_err());
+    }
+
+    #[test]
+    fn test_line_timestamp() {
+        assert_eq!(
+            line_timestamp("[2024-01-02 10:00:05.123] started\n"),
+            Some(Local.with_ymd_and_hms(2024, 1, 2, 10, 0, 5).unwrap() + chrono::Duration::milliseconds(123))
+        );
+        assert_eq!(line_timestamp("[2024-01-02 10:00:05] started"), Some(Local.with_ymd_and_hms(2024, 1, 2, 10, 0, 5).unwrap()));
+        assert_eq!(line_timestamp("plain output"), None);
+        assert_eq!(line_timestamp("[INFO] message"), None);
+    }
+
+    #[test]
+    fn test_print_since_filters_by_line_timestamp() {
+        let path = std::env::temp_dir().join("rust_nssm_logs_since_test.log");
+        std::fs::write(
+            &path,
+            "[2024-01-02 09:00:00] old\n  old detail\n[2024-01-02 10:30:00] new\n  new detail\n",
+        )
+        .unwrap();
+
+        let since = Local.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap();
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let mut out = Vec::new();
+        print_since(&mut reader, &path, since, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "[2024-01-02 10:30:00] new\n  new detail\n");
+        let _ = std::fs::remove_file(&path);
+    }
+}
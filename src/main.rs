@@ -1,13 +1,54 @@
 mod cli;
+mod config_file;
+mod event_log;
+mod ipc_pipe;
+mod live_output;
+mod log_file;
+mod logs;
+mod nssm;
+mod probe;
 mod service_host;
 mod service_manager;
+mod signature;
+mod status_pipe;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, InstallArgs, OutputFormat};
+use probe::Probe;
 use log::{info, error};
-use service_manager::{ServiceConfig, ServiceManager};
-use std::path::PathBuf;
+use serde::Serialize;
+use service_manager::{
+    format_exit_code, ExitAction, ExitActions, HealthCheckConfig, HealthCheckTarget, LogRotation,
+    ProcessPriorityClass, RecoveryActions, RecoveryConfig, RotationInterval, ServiceAccount, ServiceConfig, ServiceInfo,
+    ServiceManager, ServiceStartType, StopMethod,
+};
+use status_pipe::{ExitRecord, RuntimeStatus};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// `status --all` 查询单个服务状态管道的超时时间
+const STATUS_PIPE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// `status --all` 同时查询的状态管道数量上限
+const STATUS_QUERY_CONCURRENCY: usize = 8;
+
+/// `send` 在IPC管道所有实例都忙时的最长重试时间
+const IPC_PIPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `status --all` 发现不健康的服务时的退出码（1 留给命令本身执行失败）
+const EXIT_UNHEALTHY: i32 = 2;
+
+/// 需要管理员权限的命令在未提升权限时运行的退出码
+const EXIT_NOT_ELEVATED: i32 = 3;
+
+/// 批量命令同时进行的SCM/注册表操作数量上限（默认值）
+const DEFAULT_SCM_CONCURRENCY: usize = 4;
+
+/// 覆盖SCM操作并发上限的环境变量
+const SCM_CONCURRENCY_ENV: &str = "RUST_NSSM_SCM_CONCURRENCY";
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -19,46 +60,103 @@ async fn main() -> Result<()> {
 
     // 执行命令
     match cli.command {
-        Commands::Install {
-            name,
-            display_name,
-            description,
-            executable,
-            args,
-            working_directory,
-            stdout,
-            stderr,
-            service_name,
-            service_executable,
-        } => {
-            // 优先使用位置参数，如果不存在则使用命名参数
-            let final_name = service_name.or(name).ok_or_else(|| {
-                anyhow::anyhow!("服务名称是必需的，请使用位置参数或 --name/-n 参数")
-            })?;
-
-            let final_executable = service_executable.or(executable).ok_or_else(|| {
-                anyhow::anyhow!("可执行文件路径是必需的，请使用位置参数或 --executable/-e 参数")
-            })?;
-
-            install_service(final_name, display_name, description, final_executable, args, working_directory, stdout, stderr).await?;
+        Commands::Install(args) => {
+            require_elevation();
+            let allow_self = args.allow_self;
+            install_service(service_config_from_args(args, None)?, allow_self).await?;
+        }
+        Commands::Edit(args) => {
+            edit_service(args).await?;
         }
         Commands::Uninstall { name } => {
+            require_elevation();
             uninstall_service(name).await?;
         }
-        Commands::Start { name } => {
-            start_service(name).await?;
+        Commands::Start { name, post_start_verify, verify_timeout, wait, timeout } => {
+            require_elevation();
+            start_service(name.clone(), wait.then(|| Duration::from_secs(timeout))).await?;
+            if let Some(probe) = post_start_verify {
+                verify_started_service(name, probe, Duration::from_secs(verify_timeout)).await?;
+            }
+        }
+        Commands::Stop { name, timeout, no_wait } => {
+            require_elevation();
+            stop_service(name, (!no_wait).then(|| Duration::from_secs(timeout))).await?;
+        }
+        Commands::Pause { name } => {
+            pause_service(name).await?;
+        }
+        Commands::Continue { name } => {
+            continue_service(name).await?;
+        }
+        Commands::Restart { name, timeout, wait } => {
+            let timeout = Duration::from_secs(timeout);
+            restart_service(name, timeout, wait.then_some(timeout)).await?;
+        }
+        Commands::Disable { name } => {
+            disable_service(name).await?;
+        }
+        Commands::Enable { name, start } => {
+            enable_service(name, start).await?;
+        }
+        Commands::SetStartType { name, start_type } => {
+            set_start_type(name, start_type).await?;
         }
-        Commands::Stop { name } => {
-            stop_service(name).await?;
+        Commands::SetDependencies { name, dependencies } => {
+            set_dependencies(name, dependencies).await?;
         }
-        Commands::Restart { name } => {
-            restart_service(name).await?;
+        Commands::Status { name, all, json, quiet, pending_healthy, history } => {
+            if all {
+                let json = json || cli.output == OutputFormat::Json;
+                let unhealthy = get_all_service_status(json, quiet, pending_healthy, cli.fail_fast).await?;
+                if unhealthy > 0 {
+                    std::process::exit(EXIT_UNHEALTHY);
+                }
+            } else {
+                let name = name.ok_or_else(|| anyhow::anyhow!("服务名称是必需的，请使用 --name/-n 参数或 --all"))?;
+                if history {
+                    show_exit_history(&name)?;
+                } else {
+                    let output = if json { OutputFormat::Json } else { cli.output };
+                    get_service_status(name, output).await?;
+                }
+            }
         }
-        Commands::Status { name } => {
-            get_service_status(name).await?;
+        Commands::Ping { name, timeout } => {
+            ping_service(&name, Duration::from_millis(timeout))?;
         }
-        Commands::List => {
-            list_services().await?;
+        Commands::Policy { name, json } => {
+            show_policy(&name, json)?;
+        }
+        Commands::Send { name, message } => {
+            send_ipc_command(&name, &message)?;
+        }
+        Commands::Dump { name } => {
+            dump_service(&name)?;
+        }
+        Commands::List { json, managed } => {
+            list_services(if json { OutputFormat::Json } else { cli.output }, managed).await?;
+        }
+        Commands::Init { name } => {
+            service_manager::validate_service_name(&name)?;
+            print!("{}", config_file::template(&name));
+        }
+        Commands::Export { name, output } => {
+            export_service(name, output).await?;
+        }
+        Commands::Logs { name, stderr, since, follow, live } => {
+            if live {
+                live_output::follow(&name, STATUS_PIPE_TIMEOUT)?;
+            } else {
+                let since = since.as_deref().map(logs::parse_since).transpose()?;
+                logs::show_logs(&name, stderr, since, follow)?;
+            }
+        }
+        Commands::ImportNssm { name, dry_run } => {
+            import_nssm_service(name, dry_run).await?;
+        }
+        Commands::Upgrade { new_binary, restart } => {
+            upgrade_host_binary(&new_binary, restart, cli.fail_fast).await?;
         }
         Commands::Run { name } => {
             run_service_host(name).await?;
@@ -68,46 +166,680 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-/// 安装服务
-async fn install_service(
-    name: String,
-    display_name: Option<String>,
-    description: Option<String>,
-    executable: PathBuf,
-    args: Vec<String>,
-    working_directory: Option<PathBuf>,
-    stdout: Option<PathBuf>,
-    stderr: Option<PathBuf>,
-) -> Result<()> {
-    // 验证可执行文件是否存在
-    if !executable.exists() {
-        return Err(anyhow::anyhow!("Executable file does not exist: {:?}", executable));
+/// 未以管理员身份运行时给出明确提示并退出
+///
+/// 否则SCM操作会以含义不明的 "error 5"（拒绝访问）失败。
+fn require_elevation() {
+    if !service_manager::is_elevated() {
+        eprintln!("This command requires administrator privileges. Re-run from an elevated prompt.");
+        std::process::exit(EXIT_NOT_ELEVATED);
     }
+}
 
-    // 创建服务管理器
-    let service_manager = ServiceManager::new()
-        .context("Failed to create service manager")?;
+/// 由 install / edit 的命令行参数（及 --config 指定的配置文件）构造服务配置
+///
+/// 设置依次叠加：`base`（edit 时为已安装服务的配置）、配置文件、命令行，后者覆盖前者中的同名设置。
+fn service_config_from_args(args: InstallArgs, base: Option<&ServiceConfig>) -> Result<ServiceConfig> {
+    let InstallArgs {
+        name,
+        display_name,
+        description,
+        executable,
+        args,
+        config: config_path,
+        args_from_file,
+        environment,
+        env_clear,
+        no_env_clear,
+        unset_env,
+        clear,
+        env_file,
+        working_directory,
+        stdout,
+        stderr,
+        pid_file,
+        host_log_path,
+        update_check,
+        restart_on_binary_change,
+        no_restart_on_binary_change,
+        restart_window,
+        restart_delay,
+        stop_method,
+        stop_timeout,
+        launch_protection,
+        start_type,
+        delayed,
+        username,
+        password,
+        depends_on,
+        recovery_restart_delay,
+        recovery_action,
+        recovery_reset_period,
+        recovery_reboot_message,
+        io_rate_limit,
+        max_working_set,
+        priority,
+        cpu_affinity,
+        max_log_size,
+        rotate_interval,
+        rotate_daily,
+        max_log_files,
+        log_timestamps,
+        no_log_timestamps,
+        pipe_allow_group,
+        ipc_pipe_name,
+        no_event_log,
+        event_log,
+        cpu_stall_timeout,
+        cpu_stall_threshold,
+        health_check_port,
+        health_check_url,
+        health_check_status,
+        health_check_body,
+        health_check_timeout,
+        health_check_interval,
+        health_check_failures,
+        health_check_successes,
+        console_codepage,
+        detached,
+        no_detached,
+        fallback_executable,
+        fallback_args,
+        prefer_primary,
+        no_prefer_primary,
+        max_restarts_per_window,
+        max_restarts,
+        stable_window,
+        max_start_attempts,
+        start_retry_delay,
+        start_retry_delay_max,
+        restart_jitter,
+        restart_window_seconds,
+        quiet_restart_logging,
+        no_quiet_restart_logging,
+        restart_log_burst,
+        restart_log_window,
+        provision_cmd,
+        restart_on_pattern,
+        restart_pattern_stream,
+        on_exit,
+        restart_policy,
+        no_restart_on,
+        wait_for_tree,
+        no_wait_for_tree,
+        check_signature,
+        require_signature,
+        signer_thumbprint,
+        no_check_signature,
+        no_require_signature,
+        allow_self: _,
+        service_name,
+        service_executable,
+    } = args;
+
+    // 配置文件叠加在原配置之上，--clear / --unset-env 从两者中删除设置，命令行再覆盖其余的同名设置
+    let mut file = base.map(config_file::ServiceConfigFile::from_service_config).unwrap_or_default();
+    if let Some(path) = config_path {
+        file.overlay(config_file::load(&path)?);
+    }
+    for setting in clear {
+        file.clear(setting);
+    }
+    for key in &unset_env {
+        file.environment.retain(|name, _| !name.eq_ignore_ascii_case(key));
+    }
+    let name = name.or(file.name);
+    let executable = executable.or(file.executable);
+    let args = if args.is_empty() { file.arguments } else { args };
+    let environment: Vec<(String, String)> = file.environment.into_iter().chain(environment).collect();
+    let working_directory = working_directory.or(file.working_directory);
+    let stdout = stdout.or(file.stdout);
+    let stderr = stderr.or(file.stderr);
+    let pid_file = pid_file.or(file.pid_file);
+    let host_log_path = host_log_path.or(file.host_log_path);
+    let start_type = match (start_type.or(file.start_type).unwrap_or_default(), delayed) {
+        (ServiceStartType::AutoStart, true) => ServiceStartType::AutoDelayedStart,
+        (start_type, true) if start_type != ServiceStartType::AutoDelayedStart => {
+            return Err(anyhow::anyhow!("--delayed requires auto start, not --start-type {}", start_type.as_str()));
+        }
+        (start_type, _) => start_type,
+    };
+    let username = username.or(file.username);
+    let password = password.or(file.password);
+    let depends_on = if depends_on.is_empty() { file.dependencies } else { depends_on };
+    let recovery_action = if recovery_action.is_empty() && recovery_restart_delay.is_none() {
+        file.recovery_actions
+    } else {
+        recovery_action
+    };
+    let recovery_reset_period = recovery_reset_period
+        .or(file.recovery_reset_period)
+        .unwrap_or(service_manager::DEFAULT_RECOVERY_RESET_PERIOD);
+    let recovery_reboot_message = recovery_reboot_message.or(file.recovery_reboot_message);
+    let launch_protection = launch_protection.or(file.launch_protection);
+    let restart_delay = restart_delay.or(file.restart_delay);
+    let restart_jitter = restart_jitter.or(file.restart_jitter);
+    let restart_window = restart_window.or(file.restart_window);
+    let max_restarts_per_window = max_restarts_per_window.or(file.max_restarts_per_window);
+    let restart_window_seconds = restart_window_seconds.or(file.restart_window_seconds);
+    let max_restarts = max_restarts.or(file.max_restarts);
+    let stable_window = stable_window.or(file.stable_window);
+    let max_start_attempts = max_start_attempts.or(file.max_start_attempts);
+    let start_retry_delay = start_retry_delay.or(file.start_retry_delay);
+    let start_retry_delay_max = start_retry_delay_max.or(file.start_retry_delay_max);
+    let quiet_restart_logging = switch(quiet_restart_logging, no_quiet_restart_logging, file.quiet_restart_logging);
+    let restart_log_burst = restart_log_burst.or(file.restart_log_burst);
+    let restart_log_window = restart_log_window.or(file.restart_log_window);
+    // 命令行指定了重启规则时整体替换文件中的规则及其匹配的输出流
+    let (restart_on_pattern, restart_pattern_stream) = if restart_on_pattern.is_empty() {
+        (file.restart_patterns, file.restart_pattern_stream.unwrap_or_default())
+    } else {
+        (restart_on_pattern, restart_pattern_stream)
+    };
+    let stop_method = stop_method.or(file.stop_method).unwrap_or_default();
+    let stop_timeout = stop_timeout.or(file.stop_timeout);
+    let priority = priority.or(file.priority);
+    let cpu_affinity = cpu_affinity.or(file.cpu_affinity);
+    let io_rate_limit = io_rate_limit.or(file.io_rate_limit);
+    let max_working_set = max_working_set.or(file.max_working_set);
+    let console_codepage = console_codepage.or(file.console_codepage);
+    let detached = switch(detached, no_detached, file.detached);
+    // 命令行指定任一轮转方式时忽略文件中的轮转设置
+    let (max_log_size, rotate_interval) = if max_log_size.is_some() || rotate_interval.is_some() || rotate_daily {
+        (max_log_size, rotate_interval)
+    } else {
+        (file.max_log_size, file.rotate_interval)
+    };
+    let max_log_files = max_log_files.or(file.max_log_files);
+    let on_exit = if on_exit.is_empty() { file.on_exit } else { on_exit };
+    let env_clear = switch(env_clear, no_env_clear, file.env_clear);
+    let env_file = env_file.or(file.env_file);
+    let log_timestamps = switch(log_timestamps, no_log_timestamps, file.log_timestamps);
+    let wait_for_tree = switch(wait_for_tree, no_wait_for_tree, file.wait_for_tree);
+    let display_name = display_name.or(file.display_name);
+    let description = description.or(file.description);
+    let update_check = update_check.or(file.update_check);
+    let restart_on_binary_change =
+        switch(restart_on_binary_change, no_restart_on_binary_change, file.restart_on_binary_change);
+    let cpu_stall_timeout = cpu_stall_timeout.or(file.cpu_stall_timeout);
+    let cpu_stall_threshold = cpu_stall_threshold.or(file.cpu_stall_threshold);
+    // 命令行指定任一健康检查对象时忽略文件中的检查对象
+    let (health_check_port, health_check_url) = if health_check_port.is_some() || health_check_url.is_some() {
+        (health_check_port, health_check_url)
+    } else {
+        (file.health_check_port, file.health_check_url)
+    };
+    let health_check_status = health_check_status.or(file.health_check_status);
+    let health_check_body = health_check_body.or(file.health_check_body);
+    let health_check_timeout = health_check_timeout.or(file.health_check_timeout);
+    let health_check_interval = health_check_interval.or(file.health_check_interval);
+    let health_check_failures = health_check_failures.or(file.health_check_failures);
+    let health_check_successes = health_check_successes.or(file.health_check_successes);
+    // 命令行指定了备用程序时不沿用文件中备用程序的参数
+    let (fallback_executable, fallback_args) = match fallback_executable {
+        Some(executable) => (Some(executable), fallback_args),
+        None => (file.fallback_executable, file.fallback_arguments),
+    };
+    let prefer_primary = switch(prefer_primary, no_prefer_primary, file.prefer_primary);
+    // --no-check-signature 同时撤销隐含签名校验的 --require-signature 和 --signer-thumbprint
+    let check_signature = switch(check_signature, no_check_signature, file.check_signature);
+    let require_signature =
+        switch(require_signature, no_check_signature || no_require_signature, file.require_signature);
+    let signer_thumbprint = if no_check_signature { None } else { signer_thumbprint.or(file.signer_thumbprint) };
+    let pipe_allow_group = pipe_allow_group.or(file.pipe_allow_group);
+    let ipc_pipe_name = ipc_pipe_name.or(file.ipc_pipe_name);
+    let event_log_enabled = !no_event_log && (event_log || file.event_log.unwrap_or(true));
+    let provision_cmd = provision_cmd.or(file.provision_cmd);
+
+    // 优先使用位置参数，如果不存在则使用命名参数
+    let final_name = service_name.or(name).ok_or_else(|| {
+        anyhow::anyhow!("服务名称是必需的，请使用位置参数或 --name/-n 参数")
+    })?;
+
+    let final_executable = service_executable.or(executable).ok_or_else(|| {
+        anyhow::anyhow!("可执行文件路径是必需的，请使用位置参数或 --executable/-e 参数")
+    })?;
+
+    // 文件中的参数在前，命令行 --args 追加在后
+    let mut final_args = match args_from_file {
+        Some(path) => read_args_file(&path)?,
+        None => Vec::new(),
+    };
+    final_args.extend(args);
+
+    let recovery_actions = match recovery_restart_delay {
+        Some(secs) => Some(RecoveryConfig::restart_after(Duration::from_secs(secs), recovery_reset_period)),
+        None if !recovery_action.is_empty() => Some(RecoveryConfig::from_actions(
+            &recovery_action,
+            recovery_reset_period,
+            recovery_reboot_message,
+        )?),
+        None => None,
+    };
+
+    let mut exit_actions = match restart_policy {
+        Some(policy) => policy.exit_actions(),
+        None => ExitActions::parse_rules(&on_exit).context("Invalid --on-exit")?,
+    };
+    for code in no_restart_on {
+        exit_actions.codes.insert(code, ExitAction::Stop);
+    }
+
+    let health_target = match (health_check_port, health_check_url) {
+        (Some(port), _) => Some(HealthCheckTarget::Tcp { port }),
+        (None, Some(url)) => Some(HealthCheckTarget::Http {
+            url,
+            expected_status: health_check_status.unwrap_or(service_manager::DEFAULT_HEALTH_CHECK_STATUS),
+            timeout_ms: health_check_timeout.unwrap_or(service_manager::DEFAULT_HEALTH_CHECK_TIMEOUT_MS),
+            body_contains: health_check_body,
+        }),
+        (None, None) => None,
+    };
+    let health_check = health_target.map(|target| HealthCheckConfig {
+        target,
+        interval_secs: health_check_interval.unwrap_or(service_manager::DEFAULT_HEALTH_CHECK_INTERVAL),
+        failure_threshold: health_check_failures.unwrap_or(service_manager::DEFAULT_HEALTH_CHECK_FAILURES),
+        success_threshold: health_check_successes.unwrap_or(service_manager::DEFAULT_HEALTH_CHECK_SUCCESSES),
+    });
 
-    // 创建服务配置
-    let config = ServiceConfig {
-        name: name.clone(),
-        display_name: display_name.unwrap_or_else(|| name.clone()),
-        description: description.unwrap_or_else(|| format!("Service managed by rust-nssm: {}", name)),
-        executable_path: executable,
-        arguments: args,
+    let keep = max_log_files.unwrap_or(log_file::DEFAULT_MAX_LOG_FILES);
+    let rotate_interval = rotate_interval.or(rotate_daily.then_some(RotationInterval::Daily));
+    let log_rotation = match (max_log_size, rotate_interval) {
+        (max_bytes, Some(interval)) => LogRotation::ByDate { interval, keep, max_bytes },
+        (Some(max_bytes), None) => LogRotation::BySize { max_bytes, keep },
+        (None, None) if max_log_files.is_some() => {
+            return Err(anyhow::anyhow!("--max-log-files requires --max-log-size, --rotate-interval or --rotate-daily"));
+        }
+        (None, None) => LogRotation::None,
+    };
+
+    Ok(ServiceConfig {
+        display_name: display_name.unwrap_or_else(|| final_name.clone()),
+        description: description.unwrap_or_else(|| format!("Service managed by rust-nssm: {}", final_name)),
+        name: final_name,
+        executable_path: final_executable,
+        arguments: final_args,
+        environment: service_manager::dedup_environment(environment),
+        env_clear,
+        env_file,
         working_directory,
+        pid_file,
         stdout_path: stdout,
         stderr_path: stderr,
-    };
+        update_check_interval: update_check,
+        restart_on_binary_change,
+        restart_window,
+        restart_delay,
+        stop_method,
+        stop_timeout,
+        launch_protection: launch_protection.unwrap_or_default(),
+        recovery_actions,
+        start_type,
+        service_account: username.map(|username| ServiceAccount::from_username(&username, password)),
+        dependencies: depends_on,
+        pipe_allow_group,
+        ipc_pipe_name,
+        event_log_enabled,
+        host_log_path,
+        io_rate_limit,
+        max_working_set,
+        priority_class: priority,
+        cpu_affinity,
+        log_rotation,
+        log_timestamps,
+        cpu_stall_timeout,
+        cpu_stall_threshold,
+        health_check,
+        console_codepage,
+        detached,
+        fallback_executable,
+        fallback_arguments: fallback_args,
+        prefer_primary,
+        max_restarts_per_window,
+        max_restarts,
+        stable_window,
+        max_start_attempts,
+        start_retry_delay,
+        start_retry_delay_max,
+        restart_jitter,
+        restart_rate_window: restart_window_seconds,
+        quiet_restart_logging,
+        restart_log_burst,
+        restart_log_window,
+        provision_command: provision_cmd,
+        restart_patterns: restart_on_pattern,
+        restart_pattern_stream,
+        exit_actions,
+        wait_for_tree,
+        check_signature: check_signature || require_signature || signer_thumbprint.is_some(),
+        require_signature,
+        signer_thumbprint: signer_thumbprint.as_deref().map(signature::normalize_thumbprint),
+    })
+}
+
+/// 开关设置：命令行的 `--X` / `--no-X` 优先，否则沿用配置文件（及原配置）中的值，都未指定时关闭
+fn switch(on: bool, off: bool, file: Option<bool>) -> bool {
+    if on || off {
+        on
+    } else {
+        file.unwrap_or(false)
+    }
+}
+
+/// 安装服务
+async fn install_service(mut config: ServiceConfig, allow_self: bool) -> Result<()> {
+    validate_service_config(&mut config, allow_self)?;
+    check_account_password(&config)?;
+
+    // 创建服务管理器
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
 
     // 安装服务
     service_manager.install_service(&config)
-        .context(format!("Failed to install service '{}'", name))?;
+        .context(format!("Failed to install service '{}'", config.name))?;
+
+    // 运行一次性的环境准备命令，失败时撤销安装
+    if let Some(command) = &config.provision_command {
+        if let Err(e) = run_provision_command(command, config.working_directory.as_deref()) {
+            if let Err(rollback) = service_manager.uninstall_service(&config.name) {
+                error!("Failed to roll back service '{}': {}", config.name, rollback);
+            }
+            return Err(e.context(format!("Provisioning failed, service '{}' was not installed", config.name)));
+        }
+        service_manager::write_parameter_dword(&config.name, "Provisioned", 1)?;
+    }
+
+    println!("Service '{}' installed successfully!", config.name);
+    Ok(())
+}
+
+/// 修改已安装服务的配置，只更新指定的设置
+async fn edit_service(args: InstallArgs) -> Result<()> {
+    if args.provision_cmd.is_some() {
+        return Err(anyhow::anyhow!("--provision-cmd only runs at install time and cannot be used with edit"));
+    }
+    let allow_self = args.allow_self;
+    let name = match (&args.service_name, &args.name, &args.config) {
+        (Some(name), _, _) | (None, Some(name), _) => name.clone(),
+        (None, None, Some(path)) => config_file::load(path)?
+            .name
+            .ok_or_else(|| anyhow::anyhow!("服务名称是必需的，请使用位置参数或 --name/-n 参数"))?,
+        (None, None, None) => return Err(anyhow::anyhow!("服务名称是必需的，请使用位置参数或 --name/-n 参数")),
+    };
+
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    let existing = service_manager.get_service_config(&name)
+        .context(format!("Failed to read configuration of service '{}'", name))?;
+    let mut config = service_config_from_args(args, Some(&existing))?;
+    validate_service_config(&mut config, allow_self)?;
+
+    // 密码无法读回：账户未修改时保留SCM中原有的密码
+    if config.service_account != existing.service_account {
+        check_account_password(&config)?;
+    }
+
+    service_manager.update_service(&config)
+        .context(format!("Failed to update service '{}'", config.name))?;
+
+    println!("Service '{}' updated", config.name);
+    if service_manager.get_service_status(&config.name).is_ok_and(|status| status == 4) {
+        println!("Warning: service '{}' is running, restart it to apply the new configuration", config.name);
+    }
+    Ok(())
+}
 
-    println!("Service '{}' installed successfully!", name);
+/// 需要密码的用户账户必须提供密码
+fn check_account_password(config: &ServiceConfig) -> Result<()> {
+    if let Some(account @ ServiceAccount::User { password: None, .. }) = &config.service_account {
+        if account.requires_password() {
+            return Err(anyhow::anyhow!(
+                "--password is required for account '{}' (only LocalService, NetworkService, NT SERVICE\\ virtual accounts and group managed service accounts ending in '$' run without one)",
+                account.display_name()
+            ));
+        }
+    }
     Ok(())
 }
 
+/// 安装或修改服务前校验配置，必要时调整启动类型
+fn validate_service_config(config: &mut ServiceConfig, allow_self: bool) -> Result<()> {
+    service_manager::validate_service_name(&config.name)?;
+
+    let executable = &config.executable_path;
+
+    // 验证可执行文件是否存在
+    if !executable.exists() {
+        return Err(anyhow::anyhow!("Executable file does not exist: {:?}", executable));
+    }
+
+    // 防止服务递归启动 rust-nssm 自身
+    if !allow_self && service_manager::is_self_executable(executable)? {
+        return Err(anyhow::anyhow!(
+            "Target executable {:?} is rust-nssm itself, which would make the service host launch itself recursively. Use --allow-self if this is intended",
+            executable
+        ));
+    }
+
+    if let Some(fallback) = &config.fallback_executable {
+        if !fallback.exists() {
+            return Err(anyhow::anyhow!("Fallback executable does not exist: {:?}", fallback));
+        }
+        if !allow_self && service_manager::is_self_executable(fallback)? {
+            return Err(anyhow::anyhow!(
+                "Fallback executable {:?} is rust-nssm itself. Use --allow-self if this is intended",
+                fallback
+            ));
+        }
+    }
+
+    // 服务以 System32 为当前目录运行，相对路径在安装时转换为绝对路径
+    if let Some(env_file) = &config.env_file {
+        let env_file = std::env::current_dir()?.join(env_file);
+        service_manager::read_env_file(&env_file)?;
+        config.env_file = Some(env_file);
+    }
+    if let Some(pid_file) = &config.pid_file {
+        config.pid_file = Some(std::env::current_dir()?.join(pid_file));
+    }
+    if let Some(host_log_path) = &config.host_log_path {
+        config.host_log_path = Some(std::env::current_dir()?.join(host_log_path));
+    }
+
+    if config.update_check_interval == Some(0) {
+        return Err(anyhow::anyhow!("--update-check interval must be at least 1 second"));
+    }
+
+    if config.io_rate_limit == Some(0) || config.max_working_set == Some(0) {
+        return Err(anyhow::anyhow!("--io-rate-limit and --max-working-set must be greater than 0"));
+    }
+
+    // Realtime 需要 SeIncreaseBasePriorityPrivilege，否则系统静默改用 High
+    if config.priority_class == Some(ProcessPriorityClass::Realtime) {
+        if !service_manager::is_elevated() {
+            return Err(anyhow::anyhow!("--priority realtime requires running rust-nssm as administrator"));
+        }
+        if matches!(config.service_account, Some(ServiceAccount::LocalService | ServiceAccount::NetworkService)) {
+            return Err(anyhow::anyhow!(
+                "--priority realtime requires an account with SeIncreaseBasePriorityPrivilege, such as LocalSystem"
+            ));
+        }
+    }
+
+    if let Some(mask) = config.cpu_affinity {
+        if service_manager::system_affinity_mask().is_some_and(|system| mask & system == 0) {
+            return Err(anyhow::anyhow!("--cpu-affinity {:#x} does not include any CPU of this system", mask));
+        }
+    }
+
+    if matches!(
+        config.log_rotation,
+        LogRotation::BySize { max_bytes: 0, .. } | LogRotation::ByDate { max_bytes: Some(0), .. }
+    ) {
+        return Err(anyhow::anyhow!("--max-log-size must be greater than 0"));
+    }
+
+    if config.log_rotation != LogRotation::None && config.stdout_path.is_none() && config.stderr_path.is_none() {
+        return Err(anyhow::anyhow!("Log rotation requires --stdout or --stderr"));
+    }
+
+    if config.log_timestamps && config.stdout_path.is_none() && config.stderr_path.is_none() {
+        return Err(anyhow::anyhow!("--log-timestamps requires --stdout or --stderr"));
+    }
+
+    if config.cpu_stall_timeout == Some(0) {
+        return Err(anyhow::anyhow!("--cpu-stall-timeout must be at least 1 second"));
+    }
+
+    if config.cpu_stall_threshold.is_some_and(|threshold| !(threshold > 0.0 && threshold <= 100.0)) {
+        return Err(anyhow::anyhow!("--cpu-stall-threshold must be between 0 and 100"));
+    }
+
+    if let Some(health_check) = &config.health_check {
+        match &health_check.target {
+            HealthCheckTarget::Tcp { port: 0 } => {
+                return Err(anyhow::anyhow!("--health-check-port must not be 0"));
+            }
+            HealthCheckTarget::Http { url, timeout_ms, .. } => {
+                if !matches!(url.parse(), Ok(Probe::Http { .. })) {
+                    return Err(anyhow::anyhow!("--health-check-url must be in the form http://host[:port]/path"));
+                }
+                if *timeout_ms == 0 {
+                    return Err(anyhow::anyhow!("--health-check-timeout must be at least 1 millisecond"));
+                }
+            }
+            HealthCheckTarget::Tcp { .. } => {}
+        }
+        if health_check.interval_secs == 0 {
+            return Err(anyhow::anyhow!("--health-check-interval must be at least 1 second"));
+        }
+        if health_check.failure_threshold == 0 || health_check.success_threshold == 0 {
+            return Err(anyhow::anyhow!("--health-check-failures and --health-check-successes must be at least 1"));
+        }
+    }
+
+    if !config.restart_patterns.is_empty() {
+        regex::RegexSet::new(&config.restart_patterns).context("Invalid --restart-on-pattern")?;
+    }
+
+    if config.max_restarts_per_window == Some(0) || config.restart_rate_window == Some(0) {
+        return Err(anyhow::anyhow!("--max-restarts-per-window and --restart-window-seconds must be greater than 0"));
+    }
+
+    if config.start_retry_delay == Some(0) || config.start_retry_delay_max == Some(0) {
+        return Err(anyhow::anyhow!("--start-retry-delay and --start-retry-delay-max must be greater than 0"));
+    }
+
+    if config.restart_log_burst == Some(0) || config.restart_log_window == Some(0) {
+        return Err(anyhow::anyhow!("--restart-log-burst and --restart-log-window must be greater than 0"));
+    }
+
+    if let Some(codepage) = config.console_codepage {
+        if !service_manager::is_code_page_installed(codepage) {
+            return Err(anyhow::anyhow!("Code page {} is not installed on this system", codepage));
+        }
+    }
+
+    if let Some(thumbprint) = &config.signer_thumbprint {
+        if thumbprint.len() != 40 || !thumbprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!("--signer-thumbprint must be a 40-character SHA1 hex string"));
+        }
+    }
+
+    // 安装前先校验一次签名，尽早发现问题
+    signature::enforce(config)?;
+
+    // 开机时网络驱动器或可移动驱动器可能尚未就绪，改用延迟自动启动
+    if config.start_type == ServiceStartType::AutoStart && has_non_fixed_drive_path(config) {
+        eprintln!("Notice: switching the service to delayed auto-start so the drive has time to come online");
+        config.start_type = ServiceStartType::AutoDelayedStart;
+    }
+
+    Ok(())
+}
+
+/// 检查目标程序和工作目录是否位于网络驱动器或可移动驱动器等非固定驱动器上，逐个给出警告
+fn has_non_fixed_drive_path(config: &ServiceConfig) -> bool {
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_FIXED;
+
+    let paths = std::iter::once(config.executable_path.as_path()).chain(config.working_directory.as_deref());
+    let mut found = false;
+
+    for path in paths {
+        match service_manager::drive_type_of_path(path) {
+            Some(drive_type) if drive_type != DRIVE_FIXED => {
+                eprintln!(
+                    "Warning: {:?} is on a {} drive, which may not be available yet when the service auto-starts at boot",
+                    path,
+                    service_manager::drive_type_name(drive_type)
+                );
+                found = true;
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// 运行安装时的环境准备命令，输出原样显示，退出码非零视为失败
+fn run_provision_command(command: &str, working_directory: Option<&Path>) -> Result<()> {
+    use std::os::windows::process::CommandExt;
+
+    println!("Running provisioning command: {}", command);
+
+    // 按原样交给 cmd 解析，保留命令中的引号和重定向
+    let mut cmd = std::process::Command::new("cmd");
+    cmd.arg("/C").raw_arg(command);
+    if let Some(dir) = working_directory {
+        cmd.current_dir(dir);
+    }
+
+    let output = cmd.output().context("Failed to run provisioning command")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    print!("{}", stdout);
+    eprint!("{}", stderr);
+    info!("Provisioning command exited with {}", output.status);
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("Provisioning command exited with {}", output.status));
+    }
+    Ok(())
+}
+
+/// 从文件读取子进程参数
+fn read_args_file(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .context(format!("Failed to read arguments file: {:?}", path))?;
+    let content = content.trim_start_matches('\u{feff}');
+
+    let is_json = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+
+    if is_json {
+        serde_json::from_str::<Vec<String>>(content)
+            .context(format!("Arguments file must contain a JSON array of strings: {:?}", path))
+    } else {
+        Ok(parse_args_lines(content))
+    }
+}
+
+/// 解析按行书写的参数：每行一个参数，忽略空行和以 # 开头的注释行
+///
+/// 只去掉行尾的换行符，参数中有意保留的首尾空格原样传给子进程。
+fn parse_args_lines(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
 /// 卸载服务
 async fn uninstall_service(name: String) -> Result<()> {
     let service_manager = ServiceManager::new()
@@ -120,36 +852,189 @@ async fn uninstall_service(name: String) -> Result<()> {
     Ok(())
 }
 
-/// 启动服务
-async fn start_service(name: String) -> Result<()> {
+/// 启动服务，指定 `wait` 时等待服务进入运行状态
+async fn start_service(name: String, wait: Option<Duration>) -> Result<()> {
     let service_manager = ServiceManager::new()
         .context("Failed to create service manager")?;
 
-    service_manager.start_service(&name)
-        .context(format!("Failed to start service '{}'", name))?;
+    match wait {
+        Some(timeout) => service_manager.start_service_and_wait(&name, timeout),
+        None => service_manager.start_service(&name),
+    }
+    .context(format!("Failed to start service '{}'", name))?;
 
-    println!("Service '{}' started successfully!", name);
+    if wait.is_some() {
+        println!("Service '{}' is running.", name);
+    } else {
+        println!("Service '{}' started successfully!", name);
+    }
     Ok(())
 }
 
+/// 启动后确认服务真正可用，确认失败时停止服务并视为启动失败
+async fn verify_started_service(name: String, probe: Probe, timeout: Duration) -> Result<()> {
+    println!("Verifying service '{}' with '{}' (timeout {}s)...", name, probe, timeout.as_secs());
+    let result = tokio::task::spawn_blocking(move || probe::wait_until_healthy(&probe, timeout)).await?;
+
+    match result {
+        Ok(output) => {
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            println!("Service '{}' passed post-start verification", name);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Post-start verification failed: {:#}", e);
+            let service_manager = ServiceManager::new()
+                .context("Failed to create service manager")?;
+            if let Err(stop_error) = service_manager.stop_service(&name) {
+                error!("Failed to stop service '{}' after failed verification: {}", name, stop_error);
+            }
+            Err(e.context(format!("Service '{}' failed post-start verification and was stopped", name)))
+        }
+    }
+}
+
 /// 停止服务
-async fn stop_service(name: String) -> Result<()> {
+async fn stop_service(name: String, timeout: Option<Duration>) -> Result<()> {
     let service_manager = ServiceManager::new()
         .context("Failed to create service manager")?;
 
-    service_manager.stop_service(&name)
-        .context(format!("Failed to stop service '{}'", name))?;
+    match timeout {
+        Some(timeout) => {
+            service_manager.stop_service_and_wait(&name, timeout)
+                .context(format!("Failed to stop service '{}'", name))?;
+            println!("Service '{}' stopped successfully!", name);
+        }
+        None => {
+            service_manager.stop_service(&name)
+                .context(format!("Failed to stop service '{}'", name))?;
+            println!("Stop requested for service '{}'", name);
+        }
+    }
+    Ok(())
+}
 
-    println!("Service '{}' stopped successfully!", name);
+/// 暂停服务
+async fn pause_service(name: String) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    service_manager.pause_service(&name)
+        .context(format!("Failed to pause service '{}'", name))?;
+
+    println!("Service '{}' paused successfully!", name);
+    Ok(())
+}
+
+/// 继续运行服务
+async fn continue_service(name: String) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    service_manager.continue_service(&name)
+        .context(format!("Failed to continue service '{}'", name))?;
+
+    println!("Service '{}' continued successfully!", name);
+    Ok(())
+}
+
+/// 禁用服务
+async fn disable_service(name: String) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    let prior = service_manager.disable_service(&name)
+        .context(format!("Failed to disable service '{}'", name))?;
+
+    println!(
+        "Service '{}' disabled (previous start type: {})",
+        name,
+        service_manager::start_type_name(prior)
+    );
+    Ok(())
+}
+
+/// 启用服务
+async fn enable_service(name: String, start: bool) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    let start_type = service_manager.enable_service(&name)
+        .context(format!("Failed to enable service '{}'", name))?;
+
+    println!(
+        "Service '{}' enabled (start type: {})",
+        name,
+        service_manager::start_type_name(start_type)
+    );
+
+    if start {
+        start_service(name, None).await?;
+    }
+    Ok(())
+}
+
+/// 修改服务的启动类型
+async fn set_start_type(name: String, start_type: ServiceStartType) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    service_manager.set_start_type(&name, start_type)
+        .context(format!("Failed to change start type of service '{}'", name))?;
+
+    println!("Service '{}' start type set to {}", name, start_type.as_str());
+    Ok(())
+}
+
+/// 导出服务配置
+async fn export_service(name: String, output: PathBuf) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    let config = service_manager.get_service_config(&name)
+        .context(format!("Failed to read configuration of service '{}'", name))?;
+    let binary_path = service_manager.binary_path(&name).unwrap_or_default();
+
+    let text = format!(
+        "# Exported from service '{}'\n# Host command line: {}\n\n{}",
+        name,
+        binary_path,
+        config_file::ServiceConfigFile::from_service_config(&config).to_toml()?
+    );
+
+    if output.as_os_str() == "-" {
+        print!("{}", text);
+    } else {
+        std::fs::write(&output, text).context(format!("Failed to write {}", output.display()))?;
+        println!("Configuration of service '{}' exported to {}", name, output.display());
+    }
+    Ok(())
+}
+
+/// 替换服务的依赖列表
+async fn set_dependencies(name: String, dependencies: Vec<String>) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    service_manager.set_dependencies(&name, &dependencies)
+        .context(format!("Failed to change dependencies of service '{}'", name))?;
+
+    if dependencies.is_empty() {
+        println!("Service '{}' no longer depends on other services", name);
+    } else {
+        println!("Service '{}' now depends on: {}", name, dependencies.join(", "));
+    }
     Ok(())
 }
 
 /// 重启服务
-async fn restart_service(name: String) -> Result<()> {
+async fn restart_service(name: String, stop_timeout: Duration, start_timeout: Option<Duration>) -> Result<()> {
     let service_manager = ServiceManager::new()
         .context("Failed to create service manager")?;
 
-    service_manager.restart_service(&name)
+    service_manager.restart_service(&name, stop_timeout, start_timeout)
         .context(format!("Failed to restart service '{}'", name))?;
 
     println!("Service '{}' restarted successfully!", name);
@@ -157,35 +1042,807 @@ async fn restart_service(name: String) -> Result<()> {
 }
 
 /// 获取服务状态
-async fn get_service_status(name: String) -> Result<()> {
+async fn get_service_status(name: String, output: OutputFormat) -> Result<()> {
     let service_manager = ServiceManager::new()
         .context("Failed to create service manager")?;
 
+    if output != OutputFormat::Text {
+        let info = service_manager.service_info(&name)
+            .context(format!("Failed to get service status '{}'", name))?;
+        if output == OutputFormat::Json {
+            return print_json(&info);
+        }
+        print_service_table(&[info]);
+        return Ok(());
+    }
+
     let status = service_manager.get_service_status(&name)
         .context(format!("Failed to get service status '{}'", name))?;
 
-    let status_name = match status {
-        1 => "STOPPED",
-        2 => "START_PENDING",
-        3 => "STOP_PENDING",
-        4 => "RUNNING",
-        5 => "CONTINUE_PENDING",
-        6 => "PAUSE_PENDING",
-        7 => "PAUSED",
-        _ => "UNKNOWN",
+    println!("Service '{}': {}", name, service_manager::service_state_name(status));
+
+    if let Ok(protection) = service_manager::read_parameter_string(&name, "LaunchProtection") {
+        println!("Launch protection: {}", protection);
+    }
+
+    if let Ok((start_type, delayed)) = service_manager.start_type(&name) {
+        let start_type = service_manager::start_type_name(start_type);
+        let delayed = if delayed && start_type == "auto" { " (delayed)" } else { "" };
+        println!("Start type: {}{}", start_type, delayed);
+    }
+
+    if let Ok(config) = service_manager.get_service_config(&name) {
+        let account = config.service_account.unwrap_or(ServiceAccount::LocalSystem);
+        println!("Account: {}", account.display_name());
+        if !config.dependencies.is_empty() {
+            println!("Dependencies: {}", config.dependencies.join(", "));
+        }
+    }
+
+    if let Ok(recovery) = service_manager.recovery_actions(&name) {
+        if !recovery.actions.is_empty() {
+            let actions: Vec<String> = recovery
+                .actions
+                .iter()
+                .map(|action| format!("{} after {}ms", action.action, action.delay_ms))
+                .collect();
+            println!("Recovery: {} (reset after {}s)", actions.join(", "), recovery.reset_period_secs);
+        }
+    }
+
+    // 服务运行中时从服务宿主查询子进程状态
+    if status == 4 {
+        if let Ok(runtime) = status_pipe::query(&name, STATUS_PIPE_TIMEOUT) {
+            if service_manager::read_parameter_string(&name, "ActiveExecutable").is_ok_and(|active| active == "fallback") {
+                let fallback = service_manager::read_parameter_string(&name, "FallbackExecutable").unwrap_or_default();
+                println!("Child process: running fallback executable {}", fallback);
+            }
+            if runtime.waiting_for_restart_window {
+                let window = service_manager::read_parameter_string(&name, "RestartWindow").unwrap_or_default();
+                println!("Child process: waiting for restart window ({})", window);
+            }
+        }
+    }
+
+    // 服务处于停止状态时，显示SCM记录的最近一次启动失败原因
+    if status == 1 {
+        match service_manager.last_start_failure(&name) {
+            Ok(Some(failure)) => print_start_failure(&failure),
+            Ok(None) => {}
+            Err(e) => error!("Failed to query last start failure: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+/// `status --all` 表格中的一行
+#[derive(Debug, Serialize)]
+struct ServiceStatusRow {
+    name: String,
+    state: &'static str,
+    pid: Option<u32>,
+    restarts: Option<u32>,
+    uptime_secs: Option<u64>,
+    last_exit_code: Option<i32>,
+}
+
+impl ServiceStatusRow {
+    fn new(name: String, state: u32, runtime: Option<RuntimeStatus>) -> Self {
+        Self {
+            name,
+            state: match &runtime {
+                Some(runtime) if runtime.waiting_for_restart_window => "WAITING_FOR_WINDOW",
+                _ => service_manager::service_state_name(state),
+            },
+            pid: runtime.as_ref().and_then(|r| r.child_pid),
+            restarts: runtime.as_ref().map(|r| r.restarts),
+            uptime_secs: runtime.as_ref().and_then(|r| r.uptime_secs()),
+            last_exit_code: runtime.as_ref().and_then(|r| r.last_exit_code),
+        }
+    }
+}
+
+/// 查看所有由 rust-nssm 管理的服务状态
+///
+/// 运行中的服务无法查询状态管道时视为失败：默认继续并在最后汇总，`fail_fast` 时在第一个失败处停止。
+/// 返回不健康的服务数量，供监控脚本通过退出码判断。
+async fn get_all_service_status(json: bool, quiet: bool, pending_healthy: bool, fail_fast: bool) -> Result<usize> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    // 只枚举一次SCM，随后并发检查注册表并查询各服务宿主的状态管道
+    let services = service_manager.list_services_with_state()
+        .context("Failed to list services")?;
+
+    let scm_semaphore = scm_semaphore();
+    let semaphore = Arc::new(Semaphore::new(STATUS_QUERY_CONCURRENCY));
+    let tasks: Vec<_> = services
+        .into_iter()
+        .map(|(name, state)| {
+            let scm_semaphore = scm_semaphore.clone();
+            let semaphore = semaphore.clone();
+            tokio::spawn(async move {
+                let managed = {
+                    let _permit = scm_semaphore.acquire_owned().await.ok();
+                    let registry_service = name.clone();
+                    tokio::task::spawn_blocking(move || service_manager::is_managed_service(&registry_service))
+                        .await
+                        .unwrap_or(false)
+                };
+                if !managed {
+                    return None;
+                }
+
+                let (runtime, failure) = if state != 1 {
+                    let _permit = semaphore.acquire_owned().await.ok();
+                    let pipe_service = name.clone();
+                    let query = tokio::task::spawn_blocking(move || {
+                        status_pipe::query(&pipe_service, STATUS_PIPE_TIMEOUT)
+                    });
+                    let result = match tokio::time::timeout(STATUS_PIPE_TIMEOUT, query).await {
+                        Ok(Ok(Ok(runtime))) => Ok(runtime),
+                        Ok(Ok(Err(e))) => Err(format!("{:#}", e)),
+                        Ok(Err(e)) => Err(e.to_string()),
+                        Err(_) => Err("status pipe query timed out".to_string()),
+                    };
+                    match result {
+                        Ok(runtime) => (Some(runtime), None),
+                        // 启动/停止过程中宿主可能尚未创建或已关闭管道，只有运行中的服务才算失败
+                        Err(e) => (None, (state == 4).then_some(e)),
+                    }
+                } else {
+                    (None, None)
+                };
+                Some((ServiceStatusRow::new(name, state, runtime), failure))
+            })
+        })
+        .collect();
+
+    let mut rows = Vec::new();
+    let mut failures = Vec::new();
+    let mut unhealthy = 0;
+    let mut tasks = tasks.into_iter();
+    while let Some(task) = tasks.next() {
+        let Some((row, failure)) = task.await? else {
+            continue;
+        };
+
+        if !is_healthy(row.state, failure.is_none(), pending_healthy) {
+            unhealthy += 1;
+        }
+
+        if let Some(failure) = failure {
+            failures.push(format!("{}: {}", row.name, failure));
+            if fail_fast {
+                rows.push(row);
+                tasks.for_each(|task| task.abort());
+                break;
+            }
+        }
+        rows.push(row);
+    }
+
+    if quiet {
+        // 只通过退出码报告
+    } else if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else if rows.is_empty() {
+        println!("No managed services found.");
+    } else {
+        print_status_table(&rows);
+    }
+
+    if fail_fast {
+        if let Some(failure) = failures.first() {
+            return Err(anyhow::anyhow!(
+                "Stopped at first failure after {} service(s): {}",
+                rows.len() - 1,
+                failure
+            ));
+        }
+    } else if !failures.is_empty() && !quiet {
+        eprintln!("{} service(s) could not be queried:", failures.len());
+        for failure in &failures {
+            eprintln!("  {}", failure);
+        }
+    }
+
+    Ok(unhealthy)
+}
+
+/// 判断 `status --all` 中的一行是否健康：服务运行中且宿主响应状态查询
+///
+/// 等待重启时间窗口的服务没有运行子进程，视为不健康；`pending_healthy` 时过渡状态视为健康。
+fn is_healthy(state: &str, queried: bool, pending_healthy: bool) -> bool {
+    match state {
+        "RUNNING" => queried,
+        pending if pending.ends_with("_PENDING") => pending_healthy,
+        _ => false,
+    }
+}
+
+/// 批量命令共享的SCM操作信号量
+///
+/// SCM数据库在内部串行处理请求，批量命令同时对几十个服务发起SCM/注册表调用时只会排队，
+/// 严重时返回 `ERROR_SERVICE_DATABASE_LOCKED`。所有 `--all` 类命令的SCM/注册表调用都需先获取许可，
+/// 上限默认为 4，可通过环境变量 `RUST_NSSM_SCM_CONCURRENCY` 调整。
+fn scm_semaphore() -> Arc<Semaphore> {
+    static SEMAPHORE: OnceLock<Arc<Semaphore>> = OnceLock::new();
+    SEMAPHORE
+        .get_or_init(|| {
+            let limit = parse_concurrency_limit(std::env::var(SCM_CONCURRENCY_ENV).ok().as_deref());
+            Arc::new(Semaphore::new(limit))
+        })
+        .clone()
+}
+
+/// 解析并发上限，无效或为0时使用默认值
+fn parse_concurrency_limit(value: Option<&str>) -> usize {
+    value
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|&limit| limit > 0)
+        .unwrap_or(DEFAULT_SCM_CONCURRENCY)
+}
+
+/// 打印对齐的状态表格
+fn print_status_table(rows: &[ServiceStatusRow]) {
+    let cells: Vec<[String; 6]> = rows
+        .iter()
+        .map(|row| {
+            [
+                row.name.clone(),
+                row.state.to_string(),
+                row.pid.map_or("-".to_string(), |pid| pid.to_string()),
+                row.restarts.map_or("-".to_string(), |restarts| restarts.to_string()),
+                row.uptime_secs.map_or("-".to_string(), format_uptime),
+                row.last_exit_code.map_or("-".to_string(), format_exit_code),
+            ]
+        })
+        .collect();
+
+    print_table(["NAME", "STATE", "PID", "RESTARTS", "UPTIME", "LAST EXIT"], &cells);
+}
+
+/// 打印左对齐的表格
+fn print_table<const N: usize>(header: [&str; N], cells: &[[String; N]]) {
+    let mut widths = header.map(str::len);
+    for row in cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_line = |cells: &[&str]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_line(&header));
+    for row in cells {
+        let row: Vec<&str> = row.iter().map(String::as_str).collect();
+        println!("{}", format_line(&row));
+    }
+}
+
+/// 将运行时长格式化为紧凑形式，如 `2d 3h`、`5m 7s`
+fn format_uptime(secs: u64) -> String {
+    let (days, hours, minutes, seconds) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// 显示子进程最近的退出记录：服务运行中时从服务宿主查询，否则读取上次保存的记录
+fn show_exit_history(name: &str) -> Result<()> {
+    let history: Vec<ExitRecord> = match status_pipe::query(name, STATUS_PIPE_TIMEOUT) {
+        Ok(runtime) => runtime.exit_history.into(),
+        Err(_) => service_manager::read_parameter_string(name, "ExitHistory")
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
     };
 
-    println!("Service '{}': {}", name, status_name);
+    if history.is_empty() {
+        println!("No child process exits recorded for service '{}'", name);
+        return Ok(());
+    }
+
+    let cells: Vec<[String; 3]> = history
+        .iter()
+        .rev()
+        .map(|record| {
+            [
+                chrono::DateTime::from_timestamp(record.exited_at, 0)
+                    .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                record.exit_code.map_or("-".to_string(), format_exit_code),
+                record.uptime_secs.map_or("-".to_string(), format_uptime),
+            ]
+        })
+        .collect();
+
+    print_table(["EXITED AT", "EXIT CODE", "UPTIME"], &cells);
     Ok(())
 }
 
+/// 检测服务宿主是否响应并打印往返耗时
+fn ping_service(name: &str, timeout: Duration) -> Result<()> {
+    let latency = status_pipe::ping(name, timeout)
+        .context(format!("Service host '{}' is not responsive", name))?;
+    println!("Service host '{}' responded in {} ms", name, latency.as_millis());
+    Ok(())
+}
+
+/// `policy` 命令输出的服务生命周期策略
+#[derive(Debug, Serialize)]
+struct PolicyReport {
+    name: String,
+    start_type: &'static str,
+    delayed_auto_start: bool,
+    restart_delay_secs: u64,
+    restart_jitter_secs: Option<u64>,
+    max_start_attempts: Option<u32>,
+    start_retry_delays_secs: Vec<u64>,
+    fallback_executable: Option<String>,
+    prefer_primary: bool,
+    max_restarts_per_window: Option<u32>,
+    restart_rate_window_secs: u64,
+    max_consecutive_restarts: Option<u32>,
+    stable_window_secs: u64,
+    restart_window: Option<String>,
+    update_check_interval_secs: Option<u64>,
+    restart_on_binary_change: bool,
+    cpu_stall_timeout_secs: Option<u64>,
+    cpu_stall_threshold: Option<f64>,
+    health_check: Option<HealthCheckConfig>,
+    restart_patterns: Vec<String>,
+    restart_pattern_stream: &'static str,
+    exit_actions: Vec<String>,
+    stop_timeout_secs: Option<u64>,
+    wait_for_tree_secs: Option<u64>,
+    detached: bool,
+    scm_recovery: RecoveryActions,
+}
+
+impl PolicyReport {
+    fn new(config: &ServiceConfig, start_type: u32, delayed_auto_start: bool, scm_recovery: RecoveryActions) -> Self {
+        Self {
+            name: config.name.clone(),
+            start_type: service_manager::start_type_name(start_type),
+            delayed_auto_start,
+            restart_delay_secs: service_host::restart_delay(config).as_secs(),
+            restart_jitter_secs: config.restart_jitter.filter(|jitter| *jitter > 0),
+            max_start_attempts: service_host::max_start_attempts(config),
+            start_retry_delays_secs: start_retry_delays(config),
+            fallback_executable: config.fallback_executable.as_ref().map(|path| path.to_string_lossy().to_string()),
+            prefer_primary: config.prefer_primary,
+            max_restarts_per_window: config.max_restarts_per_window,
+            restart_rate_window_secs: config
+                .restart_rate_window
+                .unwrap_or(service_host::DEFAULT_RESTART_RATE_WINDOW.as_secs()),
+            max_consecutive_restarts: config.max_restarts.filter(|max| *max > 0),
+            stable_window_secs: config.stable_window.unwrap_or(service_host::DEFAULT_STABLE_WINDOW.as_secs()),
+            restart_window: config.restart_window.map(|window| window.to_string()),
+            update_check_interval_secs: config.update_check_interval,
+            restart_on_binary_change: config.restart_on_binary_change,
+            cpu_stall_timeout_secs: config.cpu_stall_timeout,
+            cpu_stall_threshold: config
+                .cpu_stall_timeout
+                .map(|_| config.cpu_stall_threshold.unwrap_or(service_host::DEFAULT_CPU_STALL_THRESHOLD)),
+            health_check: config.health_check.clone(),
+            restart_patterns: config.restart_patterns.clone(),
+            restart_pattern_stream: config.restart_pattern_stream.as_str(),
+            exit_actions: if config.exit_actions.is_default() { Vec::new() } else { config.exit_actions.to_rules() },
+            stop_timeout_secs: service_host::console_stop(config).then(|| service_host::stop_timeout(config).as_secs()).filter(|secs| *secs > 0),
+            wait_for_tree_secs: config.wait_for_tree.then(|| service_host::TREE_STOP_TIMEOUT.as_secs()),
+            detached: config.detached,
+            scm_recovery,
+        }
+    }
+
+    fn print(&self) {
+        println!("Lifecycle policy of service '{}'", self.name);
+
+        println!();
+        println!("Startup:");
+        let delayed = if self.delayed_auto_start && self.start_type == "auto" { " (delayed)" } else { "" };
+        println!("  Start type: {}{}", self.start_type, delayed);
+
+        println!();
+        println!("When the child process exits:");
+        if !self.exit_actions.is_empty() {
+            println!("  Exit code actions: {} (only the restart action is subject to the rules below)", self.exit_actions.join(", "));
+        }
+        match self.max_restarts_per_window {
+            Some(max) => println!(
+                "  Restarted after {}s; more than {} restarts within {}s stops the service with a failure exit code",
+                self.restart_delay_secs, max, self.restart_rate_window_secs
+            ),
+            None => println!("  Restarted after {}s, without limit", self.restart_delay_secs),
+        }
+        if let Some(jitter) = self.restart_jitter_secs {
+            println!("  Each restart after an exit waits up to {}s longer at random", jitter);
+        }
+        if let Some(max) = self.max_consecutive_restarts {
+            println!(
+                "  More than {} consecutive restarts stops the service with a failure exit code (reset after {}s of uptime)",
+                max, self.stable_window_secs
+            );
+        }
+        match &self.restart_window {
+            Some(window) => println!("  Restarts only happen within {}; outside it the host waits for the window to open", window),
+            None => println!("  Restarts may happen at any time of day"),
+        }
+
+        println!();
+        println!("When the child process fails to start:");
+        let delays: Vec<String> = self.start_retry_delays_secs.iter().map(|secs| format!("{}s", secs)).collect();
+        let retries = self.max_start_attempts.map_or(usize::MAX, |max| max.saturating_sub(1) as usize);
+        match self.start_retry_delays_secs.last() {
+            Some(last) if retries > delays.len() => println!("  Retried after {}, then every {}s", delays.join(", "), last),
+            Some(_) => println!("  Retried after {}", delays.join(", ")),
+            None => println!("  Not retried"),
+        }
+        match (self.max_start_attempts, &self.fallback_executable) {
+            (None, _) => println!("  The host keeps retrying without giving up"),
+            (Some(max_start_attempts), Some(fallback)) => {
+                println!(
+                    "  After {} consecutive failures the host switches to the fallback executable {}",
+                    max_start_attempts, fallback
+                );
+                if self.prefer_primary {
+                    println!("  While the fallback runs, the primary is checked every minute and restored once launchable");
+                }
+                println!(
+                    "  If the fallback also fails {} times, the host gives up and the service stops with a failure exit code",
+                    max_start_attempts
+                );
+            }
+            (Some(max_start_attempts), None) => println!(
+                "  After {} consecutive failures the host gives up and the service stops with a failure exit code",
+                max_start_attempts
+            ),
+        }
+
+        println!();
+        println!("While the child process runs:");
+        match (self.update_check_interval_secs, self.restart_on_binary_change) {
+            (Some(secs), true) => println!("  Restarted when the executable changes on disk (checked every {}s)", secs),
+            (Some(secs), false) => println!("  Executable changes are logged but not acted on (checked every {}s)", secs),
+            (None, _) => println!("  Executable changes are not watched"),
+        }
+        match (self.cpu_stall_timeout_secs, self.cpu_stall_threshold) {
+            (Some(secs), Some(threshold)) => {
+                println!("  Restarted when its process tree uses less than {}% CPU for {}s", threshold, secs)
+            }
+            _ => println!("  CPU stall watchdog disabled"),
+        }
+        match &self.health_check {
+            Some(health_check) => println!(
+                "  Restarted after {} consecutive failed health checks of {} (checked every {}s)",
+                health_check.failure_threshold, health_check.target, health_check.interval_secs
+            ),
+            None => println!("  Health check disabled"),
+        }
+        for pattern in &self.restart_patterns {
+            println!("  Restarted when a {} line matches /{}/", self.restart_pattern_stream, pattern);
+        }
+
+        println!();
+        println!("When the service is stopped:");
+        match self.stop_timeout_secs {
+            Some(secs) => println!("  Child sent CTRL_BREAK and given up to {}s to exit before it is killed", secs),
+            None => println!("  Child killed immediately"),
+        }
+        match self.wait_for_tree_secs {
+            Some(secs) => println!("  Then up to {}s for the rest of the process tree before it is terminated", secs),
+            None => println!("  Then the rest of the process tree is terminated immediately"),
+        }
+        if self.detached {
+            println!("  Child runs without a console (detached), so it never receives console Ctrl events");
+        }
+
+        println!();
+        println!("SCM recovery actions (when the service host fails):");
+        let recovery = &self.scm_recovery;
+        if recovery.actions.is_empty() {
+            println!("  None configured");
+        } else {
+            for (index, action) in recovery.actions.iter().enumerate() {
+                let failure = match index {
+                    0 => "1st failure".to_string(),
+                    1 => "2nd failure".to_string(),
+                    _ if index + 1 == recovery.actions.len() => "Later failures".to_string(),
+                    _ => format!("Failure {}", index + 1),
+                };
+                println!("  {}: {} after {}ms", failure, action.action, action.delay_ms);
+            }
+            println!("  Failure count reset after {}s without failures", recovery.reset_period_secs);
+            if let Some(command) = &recovery.command {
+                println!("  Command: {}", command);
+            }
+            if !recovery.on_non_crash_failures {
+                println!("  Note: only applied when the host crashes, not when it gives up and stops with a failure exit code");
+            }
+        }
+    }
+}
+
+/// 启动失败后依次的重试等待时间（秒），达到上限后不再列出重复的值
+fn start_retry_delays(config: &ServiceConfig) -> Vec<u64> {
+    let retries = service_host::max_start_attempts(config).map_or(u32::MAX, |max| max.saturating_sub(1));
+    let mut delays: Vec<u64> = Vec::new();
+    for attempt in 1..=retries {
+        let delay = service_host::start_retry_delay(config, attempt).as_secs();
+        if delays.last() == Some(&delay) {
+            break;
+        }
+        delays.push(delay);
+    }
+    delays
+}
+
+/// 汇总显示服务的生命周期策略
+fn show_policy(name: &str, json: bool) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    let config = service_host::load_service_config(name)
+        .context(format!("Failed to load configuration of service '{}'", name))?;
+    let (start_type, delayed_auto_start) = service_manager.start_type(name)
+        .context(format!("Failed to query start type of service '{}'", name))?;
+    let recovery = service_manager.recovery_actions(name)
+        .context(format!("Failed to query recovery actions of service '{}'", name))?;
+
+    let report = PolicyReport::new(&config, start_type, delayed_auto_start, recovery);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        report.print();
+    }
+    Ok(())
+}
+
+/// 通过服务的IPC管道向子进程发送一行命令
+fn send_ipc_command(name: &str, message: &str) -> Result<()> {
+    let pipe = service_manager::read_parameter_string(name, "IpcPipe")
+        .map_err(|_| anyhow::anyhow!("Service '{}' has no IPC pipe (install it with --ipc-pipe-name)", name))?;
+
+    ipc_pipe::send(&pipe, message, IPC_PIPE_TIMEOUT)
+        .context(format!("Failed to send command to service '{}'", name))?;
+
+    println!("Command sent to service '{}'.", name);
+    Ok(())
+}
+
+/// 打印服务的全部有效配置
+fn dump_service(name: &str) -> Result<()> {
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    let config = service_manager.get_service_config(name)
+        .context(format!("Failed to read configuration of service '{}'", name))?;
+
+    let mut entries = vec![DumpEntry::set(
+        "State",
+        service_manager
+            .get_service_status(name)
+            .map_or("unknown", service_manager::service_state_name)
+            .to_string(),
+    )];
+    if let Ok(binary_path) = service_manager.binary_path(name) {
+        entries.push(DumpEntry::set("Host command line", binary_path));
+    }
+    entries.extend(dump_entries(&config));
+
+    let width = entries.iter().map(|entry| entry.key.len()).max().unwrap_or(0);
+    println!("Service '{}'", name);
+    for entry in &entries {
+        let marker = if entry.explicit { "" } else { "  (default)" };
+        println!("  {:<width$}  {}{}", entry.key, entry.value, marker, width = width);
+    }
+    Ok(())
+}
+
+/// `dump` 输出的一项设置
+struct DumpEntry {
+    key: &'static str,
+    value: String,
+    /// 是否为安装时显式指定的值（否则为默认值）
+    explicit: bool,
+}
+
+impl DumpEntry {
+    fn set(key: &'static str, value: String) -> Self {
+        Self { key, value, explicit: true }
+    }
+
+    fn new(key: &'static str, value: String, explicit: bool) -> Self {
+        Self { key, value, explicit }
+    }
+}
+
+/// 把服务配置展开为 `dump` 的各项设置
+fn dump_entries(config: &ServiceConfig) -> Vec<DumpEntry> {
+    fn list(values: &[String]) -> String {
+        if values.is_empty() { "(none)".to_string() } else { values.join(" ") }
+    }
+    fn path(path: &Option<std::path::PathBuf>, default: &str) -> String {
+        path.as_ref().map_or(default.to_string(), |path| path.display().to_string())
+    }
+    fn secs(value: Option<u64>, default: Duration) -> String {
+        format!("{}s", value.unwrap_or(default.as_secs()))
+    }
+
+    let log_rotation = match config.log_rotation {
+        LogRotation::None => "none".to_string(),
+        LogRotation::BySize { max_bytes, keep } => format!("at {} bytes, keep {}", max_bytes, keep),
+        LogRotation::ByDate { interval, keep, max_bytes: None } => format!("{}, keep {}", interval.as_str(), keep),
+        LogRotation::ByDate { interval, keep, max_bytes: Some(max_bytes) } => {
+            format!("{} or at {} bytes, keep {}", interval.as_str(), max_bytes, keep)
+        }
+    };
+    let max_start_attempts = match service_host::max_start_attempts(config) {
+        Some(max) => max.to_string(),
+        None => "unlimited".to_string(),
+    };
+    let recovery = config.recovery_actions.as_ref().map_or("(none)".to_string(), |recovery| {
+        let actions: Vec<String> = recovery.to_actions().iter().map(ToString::to_string).collect();
+        format!("{} (reset after {}s)", actions.join(", "), recovery.reset_period_secs)
+    });
+
+    vec![
+        DumpEntry::set("Display name", config.display_name.clone()),
+        DumpEntry::set("Description", config.description.clone()),
+        DumpEntry::set("Executable", config.executable_path.display().to_string()),
+        DumpEntry::new("Arguments", list(&config.arguments), !config.arguments.is_empty()),
+        DumpEntry::new(
+            "Working directory",
+            path(&config.working_directory, "(service default, %SystemRoot%\\System32)"),
+            config.working_directory.is_some(),
+        ),
+        DumpEntry::new("Stdout", path(&config.stdout_path, "(not redirected)"), config.stdout_path.is_some()),
+        DumpEntry::new("Stderr", path(&config.stderr_path, "(not redirected)"), config.stderr_path.is_some()),
+        DumpEntry::new("PID file", path(&config.pid_file, "(none)"), config.pid_file.is_some()),
+        DumpEntry::new("Log rotation", log_rotation, config.log_rotation != LogRotation::None),
+        DumpEntry::new(
+            "Host log",
+            service_host::host_log_path(config).map_or("(unavailable)".to_string(), |path| path.display().to_string()),
+            config.host_log_path.is_some(),
+        ),
+        DumpEntry::new(
+            "Environment",
+            format!("{} variable(s){}", config.environment.len(), if config.env_clear { ", host environment cleared" } else { "" }),
+            !config.environment.is_empty() || config.env_clear,
+        ),
+        DumpEntry::new("Env file", path(&config.env_file, "(none)"), config.env_file.is_some()),
+        DumpEntry::new(
+            "Start type",
+            config.start_type.as_str().to_string(),
+            config.start_type != ServiceStartType::default(),
+        ),
+        DumpEntry::new(
+            "Account",
+            config.service_account.as_ref().map_or("LocalSystem".to_string(), ServiceAccount::display_name),
+            config.service_account.is_some(),
+        ),
+        DumpEntry::new("Dependencies", list(&config.dependencies), !config.dependencies.is_empty()),
+        DumpEntry::new(
+            "Priority",
+            config.priority_class.map_or("normal", ProcessPriorityClass::as_str).to_string(),
+            config.priority_class.is_some(),
+        ),
+        DumpEntry::new(
+            "CPU affinity",
+            config.cpu_affinity.map_or("(all CPUs)".to_string(), |mask| format!("{:#x}", mask)),
+            config.cpu_affinity.is_some(),
+        ),
+        DumpEntry::new("Restart delay", secs(config.restart_delay, service_host::RESTART_DELAY), config.restart_delay.is_some()),
+        DumpEntry::new("Restart jitter", secs(config.restart_jitter, Duration::ZERO), config.restart_jitter.is_some()),
+        DumpEntry::new(
+            "Exit actions",
+            config.exit_actions.to_rules().join(", "),
+            !config.exit_actions.is_default(),
+        ),
+        DumpEntry::new(
+            "Max restarts",
+            config.max_restarts.filter(|max| *max > 0).map_or("unlimited".to_string(), |max| max.to_string()),
+            config.max_restarts.is_some(),
+        ),
+        DumpEntry::new(
+            "Stable window",
+            secs(config.stable_window, service_host::DEFAULT_STABLE_WINDOW),
+            config.stable_window.is_some(),
+        ),
+        DumpEntry::new("Max start attempts", max_start_attempts, config.max_start_attempts.is_some()),
+        DumpEntry::new(
+            "Start retry delay",
+            format!(
+                "{} doubling, up to {}",
+                secs(config.start_retry_delay, service_host::START_RETRY_DELAY),
+                secs(config.start_retry_delay_max, service_host::START_RETRY_DELAY_MAX)
+            ),
+            config.start_retry_delay.is_some() || config.start_retry_delay_max.is_some(),
+        ),
+        DumpEntry::new("Stop method", config.stop_method.as_str().to_string(), config.stop_method != StopMethod::default()),
+        DumpEntry::new(
+            "Stop timeout",
+            secs(config.stop_timeout, service_host::DEFAULT_STOP_TIMEOUT),
+            config.stop_timeout.is_some(),
+        ),
+        DumpEntry::new("SCM recovery", recovery, config.recovery_actions.is_some()),
+        DumpEntry::new(
+            "Event log",
+            if config.event_log_enabled { "enabled" } else { "disabled" }.to_string(),
+            !config.event_log_enabled,
+        ),
+    ]
+}
+
+/// 打印服务最近一次启动失败的信息
+fn print_start_failure(failure: &service_manager::StartFailure) {
+    println!(
+        "Last start failure: Win32 exit code {} ({})",
+        failure.win32_exit_code,
+        std::io::Error::from_raw_os_error(failure.win32_exit_code as i32)
+    );
+    if failure.service_specific_exit_code != 0 {
+        println!("  Service-specific exit code: {}", failure.service_specific_exit_code);
+    }
+
+    match &failure.event {
+        Some(event) => {
+            println!(
+                "  [{}] Event {}: {}",
+                event.time.format("%Y-%m-%d %H:%M:%S"),
+                event.event_id,
+                event.description
+            );
+            for detail in &event.details {
+                println!("    {}", detail);
+            }
+        }
+        None => println!("  No matching Service Control Manager event found in the System event log"),
+    }
+}
+
 /// 列出服务
-async fn list_services() -> Result<()> {
+async fn list_services(output: OutputFormat, managed: bool) -> Result<()> {
     let service_manager = ServiceManager::new()
         .context("Failed to create service manager")?;
 
-    let services = service_manager.list_services()
+    if output != OutputFormat::Text {
+        // 没有服务时JSON输出为 `[]`，表格只有表头
+        let mut services = service_manager.list_service_info()
+            .context("Failed to list services")?;
+        if managed {
+            services.retain(|service| service_manager::is_managed_service(&service.name));
+        }
+        if output == OutputFormat::Json {
+            return print_json(&services);
+        }
+        print_service_table(&services);
+        return Ok(());
+    }
+
+    let mut services = service_manager.list_services()
         .context("Failed to list services")?;
+    if managed {
+        services.retain(|service| service_manager::is_managed_service(service));
+    }
 
     if services.is_empty() {
         println!("No services found.");
@@ -200,6 +1857,204 @@ async fn list_services() -> Result<()> {
     Ok(())
 }
 
+/// 以单行JSON输出到标准输出
+fn print_json(value: &impl Serialize) -> Result<()> {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// 以表格输出服务信息
+fn print_service_table(services: &[ServiceInfo]) {
+    let cells: Vec<[String; 4]> = services
+        .iter()
+        .map(|service| {
+            [
+                service.name.clone(),
+                service.display_name.clone(),
+                service.status_name.to_string(),
+                service.pid.map_or("-".to_string(), |pid| pid.to_string()),
+            ]
+        })
+        .collect();
+    print_table(["NAME", "DISPLAY NAME", "STATUS", "PID"], &cells);
+}
+
+/// 把新版本 rust-nssm 复制到当前程序旁，并让所有托管服务的SCM命令行改用新程序
+///
+/// 服务宿主运行时其程序文件被占用无法覆盖，所以新程序使用带版本号的文件名并排存放。
+/// 任一服务更新失败时，把已更新的服务改回原来的命令行并删除复制的程序。
+async fn upgrade_host_binary(new_binary: &Path, restart: bool, fail_fast: bool) -> Result<()> {
+    if !new_binary.is_file() {
+        return Err(anyhow::anyhow!("New binary {:?} does not exist", new_binary));
+    }
+
+    let current_exe = std::env::current_exe().context("Failed to get current executable path")?;
+    let install_dir = current_exe.parent()
+        .context("Current executable has no parent directory")?;
+
+    let version_output = std::process::Command::new(new_binary)
+        .arg("--version")
+        .output()
+        .context(format!("Failed to run {:?} --version", new_binary))?;
+    let file_name = versioned_binary_name(&String::from_utf8_lossy(&version_output.stdout))
+        .unwrap_or_else(|| format!("rust-nssm-{}.exe", chrono::Local::now().format("%Y%m%d%H%M%S")));
+
+    let mut target = install_dir.join(&file_name);
+    let copied = if !target.exists() {
+        true
+    } else if std::fs::read(&target)? == std::fs::read(new_binary)? {
+        false
+    } else {
+        // 同名文件内容不同（如重新编译的同一版本），另起一个文件名，不覆盖可能仍在运行的程序
+        let stem = file_name.trim_end_matches(".exe");
+        target = install_dir.join(format!("{}-{}.exe", stem, chrono::Local::now().format("%Y%m%d%H%M%S")));
+        true
+    };
+    if copied {
+        std::fs::copy(new_binary, &target)
+            .context(format!("Failed to copy {:?} to {:?}", new_binary, target))?;
+    }
+    println!("New host binary: {}", target.display());
+
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+    let services: Vec<String> = service_manager.list_services()
+        .context("Failed to list services")?
+        .into_iter()
+        .filter(|name| service_manager::is_managed_service(name))
+        .collect();
+
+    let mut updated = Vec::new();
+    for name in &services {
+        let result = service_manager.binary_path(name).and_then(|old_path| {
+            service_manager.set_binary_path(name, &service_manager::host_command_line(&target, name))?;
+            Ok(old_path)
+        });
+
+        match result {
+            Ok(old_path) => updated.push((name, old_path)),
+            Err(e) => {
+                for (rolled_back, old_path) in updated.iter().rev() {
+                    if let Err(rollback_error) = service_manager.set_binary_path(rolled_back, old_path) {
+                        error!("Failed to restore binary path of '{}': {:#}", rolled_back, rollback_error);
+                    }
+                }
+                if copied {
+                    let _ = std::fs::remove_file(&target);
+                }
+                return Err(e.context(format!(
+                    "Failed to update service '{}'; {} already updated service(s) were rolled back",
+                    name,
+                    updated.len()
+                )));
+            }
+        }
+    }
+
+    println!("Updated {} managed service(s) to the new host binary.", updated.len());
+    if !restart {
+        println!("Running services switch to the new binary the next time they restart.");
+        return Ok(());
+    }
+
+    // 逐个重启，同一时间最多只有一个服务处于停止状态
+    let mut failures = Vec::new();
+    for name in &services {
+        if service_manager.get_service_status(name)? != 4 {
+            continue;
+        }
+        match service_manager.restart_service(name, service_manager::DEFAULT_STOP_WAIT, None) {
+            Ok(()) => println!("Restarted '{}'", name),
+            Err(e) if fail_fast => return Err(e.context(format!("Failed to restart service '{}'", name))),
+            Err(e) => failures.push(format!("{}: {:#}", name, e)),
+        }
+    }
+
+    if !failures.is_empty() {
+        eprintln!("{} service(s) could not be restarted:", failures.len());
+        for failure in &failures {
+            eprintln!("  {}", failure);
+        }
+    }
+    Ok(())
+}
+
+/// 根据 `--version` 的输出（如 `rust-nssm 0.2.0`）生成带版本号的程序文件名
+fn versioned_binary_name(version_output: &str) -> Option<String> {
+    let version: String = version_output
+        .split_whitespace()
+        .last()?
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect();
+    (!version.is_empty()).then(|| format!("rust-nssm-{}.exe", version))
+}
+
+/// 将由NSSM创建的服务迁移为由 rust-nssm 托管
+async fn import_nssm_service(name: String, dry_run: bool) -> Result<()> {
+    if service_manager::is_managed_service(&name) {
+        return Err(anyhow::anyhow!("Service '{}' is already managed by rust-nssm", name));
+    }
+
+    let import = nssm::read_nssm_service(&name)
+        .context(format!("Failed to read NSSM configuration of '{}'", name))?;
+
+    if dry_run {
+        return preview_nssm_import(&name, &import);
+    }
+
+    let service_manager = ServiceManager::new()
+        .context("Failed to create service manager")?;
+
+    service_manager.adopt_service(&import.config)
+        .context(format!("Failed to migrate service '{}'", name))?;
+
+    println!("Service '{}' migrated to rust-nssm.", name);
+
+    let unsupported: Vec<_> = import.unsupported().collect();
+    if !unsupported.is_empty() {
+        println!("The following NSSM settings have no rust-nssm equivalent and were not migrated:");
+        for setting in unsupported {
+            if let nssm::Translation::Unsupported(reason) = &setting.translation {
+                println!("  - {} = {} ({})", setting.name, setting.value, reason);
+            }
+        }
+    }
+
+    println!("Restart the service to switch it to the rust-nssm host. The original NSSM values are left in the registry.");
+    Ok(())
+}
+
+/// 逐项显示NSSM设置如何转换为 rust-nssm 设置，不修改服务
+fn preview_nssm_import(name: &str, import: &nssm::NssmImport) -> Result<()> {
+    println!("NSSM settings of service '{}' (dry run, nothing changed):", name);
+
+    let name_width = import.settings.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for setting in &import.settings {
+        let result = match &setting.translation {
+            nssm::Translation::Mapped(target) => target.clone(),
+            nssm::Translation::Default => "matches rust-nssm default behavior".to_string(),
+            nssm::Translation::Unsupported(reason) => format!("UNSUPPORTED: {}", reason),
+        };
+        println!("  {:<width$}  {}  ->  {}", setting.name, setting.value, result, width = name_width);
+    }
+
+    let unsupported = import.unsupported().count();
+    if unsupported > 0 {
+        return Err(anyhow::anyhow!(
+            "{} NSSM setting(s) cannot be represented in rust-nssm and would be dropped",
+            unsupported
+        ));
+    }
+
+    println!("All NSSM settings can be migrated.");
+    Ok(())
+}
+
 /// 运行服务主机
 async fn run_service_host(name: String) -> Result<()> {
     info!("Starting service host for: {}", name);
@@ -216,18 +2071,304 @@ async fn run_service_host(name: String) -> Result<()> {
     Ok(())
 }
 
-/// 初始化文件日志
+/// 初始化文件日志：写入宿主诊断日志目录中的 `rust-nssm.log`
 fn init_file_logging() -> Result<()> {
-    use std::fs::OpenOptions;
     use std::io::Write;
 
-    let log_file = "D:\\dev\\Rust\\rust-nssm\\rust-nssm.log";
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_file)?;
+    let mut file = log_file::open_host_log("rust-nssm.log")?;
 
     writeln!(file, "[{}] Service host starting...", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args_lines() {
+        let content = "# comment\r\n--port\r\n8080\r\n\r\n--separator\n , \n";
+        let args = parse_args_lines(content);
+        assert_eq!(args, vec!["--port", "8080", "--separator", " , "]);
+    }
+
+    #[test]
+    fn test_dump_entries_mark_defaults() {
+        let config = ServiceConfig {
+            executable_path: "C:\\app\\server.exe".into(),
+            restart_delay: Some(5),
+            ..Default::default()
+        };
+        let entries = dump_entries(&config);
+        let entry = |key: &str| entries.iter().find(|entry| entry.key == key).unwrap();
+
+        assert_eq!(entry("Restart delay").value, "5s");
+        assert!(entry("Restart delay").explicit);
+        assert_eq!(entry("Stop timeout").value, "10s");
+        assert!(!entry("Stop timeout").explicit);
+        assert_eq!(entry("Account").value, "LocalSystem");
+        assert!(!entry("Account").explicit);
+        assert_eq!(entry("Exit actions").value, "default=restart");
+    }
+
+    /// 以 `edit web <args>` 修改 `base`
+    fn edit_args(base: &ServiceConfig, args: &[&str]) -> ServiceConfig {
+        let args = ["rust-nssm", "edit", "web"].iter().chain(args);
+        let Commands::Edit(args) = Cli::try_parse_from(args).unwrap().command else { unreachable!() };
+        service_config_from_args(args, Some(base)).unwrap()
+    }
+
+    fn edit_base() -> ServiceConfig {
+        ServiceConfig {
+            name: "web".to_string(),
+            display_name: "Web".to_string(),
+            description: "Web server".to_string(),
+            executable_path: "C:\\app\\web.exe".into(),
+            environment: vec![("A".to_string(), "1".to_string())],
+            stdout_path: Some("C:\\logs\\web.log".into()),
+            detached: true,
+            max_restarts: Some(5),
+            stable_window: Some(120),
+            fallback_executable: Some("fallback.exe".into()),
+            fallback_arguments: vec!["--safe".to_string()],
+            restart_patterns: vec!["FATAL".to_string()],
+            provision_command: Some("mkdir C:\\data".to_string()),
+            event_log_enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_edit_config_keeps_unmentioned_settings() {
+        let base = edit_base();
+        let path = std::env::temp_dir().join("rust_nssm_edit_partial_test.toml");
+        std::fs::write(&path, "restart_delay = 9\ndetached = false\n\n[environment]\nB = \"2\"\n").unwrap();
+        let config = edit_args(&base, &["--config", path.to_str().unwrap(), "--max-restarts", "3"]);
+        let _ = std::fs::remove_file(&path);
+
+        // 文件覆盖原配置，命令行覆盖两者
+        assert_eq!(config.restart_delay, Some(9));
+        assert!(!config.detached);
+        assert_eq!(config.max_restarts, Some(3));
+        assert_eq!(
+            config.environment,
+            [("A".to_string(), "1".to_string()), ("B".to_string(), "2".to_string())]
+        );
+
+        // 文件和命令行都未提及的设置保持不变（包括安装时保存的准备命令）
+        let unchanged = ServiceConfig {
+            restart_delay: base.restart_delay,
+            detached: base.detached,
+            max_restarts: base.max_restarts,
+            environment: base.environment.clone(),
+            ..config
+        };
+        assert_eq!(unchanged, base);
+    }
+
+    #[test]
+    fn test_edit_switches_and_clear() {
+        let base = edit_base();
+
+        let config = edit_args(&base, &["--no-detached", "--no-event-log", "--clear", "fallback", "--clear", "stdout"]);
+        assert!(!config.detached);
+        assert!(!config.event_log_enabled);
+        assert_eq!(config.fallback_executable, None);
+        assert!(config.fallback_arguments.is_empty());
+        assert_eq!(config.stdout_path, None);
+        assert_eq!(config.restart_patterns, ["FATAL"]);
+
+        // 变量名不区分大小写
+        let config = edit_args(&base, &["--unset-env", "a", "--env", "C=3"]);
+        assert_eq!(config.environment, [("C".to_string(), "3".to_string())]);
+        assert!(config.detached);
+
+        let disabled = ServiceConfig { event_log_enabled: false, ..base.clone() };
+        assert!(edit_args(&disabled, &["--event-log"]).event_log_enabled);
+        assert!(!edit_args(&disabled, &[]).event_log_enabled);
+    }
+
+    #[test]
+    fn test_config_file_roundtrip() {
+        // 每个设置都取非默认值：导出后再导入必须得到相同的配置
+        let config = ServiceConfig {
+            name: "web".to_string(),
+            display_name: "Web".to_string(),
+            description: "Web server".to_string(),
+            executable_path: "C:\\app\\web.exe".into(),
+            arguments: vec!["--port".to_string(), "8080".to_string()],
+            environment: vec![("A".to_string(), "1".to_string()), ("B".to_string(), "x=y z".to_string())],
+            env_clear: true,
+            env_file: Some("C:\\app\\.env".into()),
+            working_directory: Some("C:\\app".into()),
+            pid_file: Some("C:\\run\\web.pid".into()),
+            stdout_path: Some("C:\\logs\\web.out.log".into()),
+            stderr_path: Some("C:\\logs\\web.err.log".into()),
+            update_check_interval: Some(30),
+            restart_on_binary_change: true,
+            restart_window: Some("22:00-06:00".parse().unwrap()),
+            restart_delay: Some(5),
+            stop_method: StopMethod::Kill,
+            stop_timeout: Some(20),
+            launch_protection: service_manager::LaunchProtection::WindowsLight,
+            recovery_actions: Some(
+                RecoveryConfig::from_actions(
+                    &["restart:10".parse().unwrap(), "reboot".parse().unwrap()],
+                    3600,
+                    Some("Rebooting".to_string()),
+                )
+                .unwrap(),
+            ),
+            start_type: ServiceStartType::AutoDelayedStart,
+            service_account: Some(ServiceAccount::NetworkService),
+            dependencies: vec!["Tcpip".to_string(), "+NetworkProvider".to_string()],
+            pipe_allow_group: Some("Users".to_string()),
+            event_log_enabled: false,
+            host_log_path: Some("C:\\logs\\host.log".into()),
+            ipc_pipe_name: Some("web-ipc".to_string()),
+            io_rate_limit: Some(1048576),
+            max_working_set: Some(268435456),
+            priority_class: Some(ProcessPriorityClass::BelowNormal),
+            cpu_affinity: Some(0x3),
+            log_rotation: LogRotation::ByDate { interval: RotationInterval::Weekly, keep: 4, max_bytes: Some(1048576) },
+            log_timestamps: true,
+            provision_command: Some("mkdir C:\\data".to_string()),
+            cpu_stall_timeout: Some(300),
+            cpu_stall_threshold: Some(1.5),
+            health_check: Some(HealthCheckConfig {
+                target: HealthCheckTarget::Http {
+                    url: "http://127.0.0.1:8080/health".to_string(),
+                    expected_status: 204,
+                    timeout_ms: 500,
+                    body_contains: Some("ok".to_string()),
+                },
+                interval_secs: 15,
+                failure_threshold: 4,
+                success_threshold: 2,
+            }),
+            console_codepage: Some(65001),
+            detached: true,
+            fallback_executable: Some("C:\\app\\safe.exe".into()),
+            fallback_arguments: vec!["--safe".to_string()],
+            prefer_primary: true,
+            max_restarts_per_window: Some(10),
+            max_restarts: Some(20),
+            stable_window: Some(120),
+            max_start_attempts: Some(3),
+            start_retry_delay: Some(4),
+            start_retry_delay_max: Some(64),
+            restart_jitter: Some(7),
+            restart_rate_window: Some(600),
+            quiet_restart_logging: true,
+            restart_log_burst: Some(2),
+            restart_log_window: Some(30),
+            restart_patterns: vec!["FATAL".to_string()],
+            restart_pattern_stream: service_manager::PatternStream::Both,
+            exit_actions: ExitActions::parse_rules(&["0=stop".to_string(), "default=restart".to_string()]).unwrap(),
+            wait_for_tree: true,
+            check_signature: true,
+            require_signature: true,
+            signer_thumbprint: Some("0123456789ABCDEF0123456789ABCDEF01234567".to_string()),
+        };
+
+        let path = std::env::temp_dir().join("rust_nssm_config_roundtrip_test.toml");
+        let text = config_file::ServiceConfigFile::from_service_config(&config).to_toml().unwrap();
+        std::fs::write(&path, text).unwrap();
+        let cli = Cli::try_parse_from(["rust-nssm", "install", "--config", path.to_str().unwrap()]).unwrap();
+        let Commands::Install(args) = cli.command else { unreachable!() };
+        let imported = service_config_from_args(args, None).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(imported, config);
+    }
+
+    #[test]
+    fn test_service_info_json() {
+        assert_eq!(serde_json::to_string(&Vec::<ServiceInfo>::new()).unwrap(), "[]");
+
+        let info = ServiceInfo {
+            name: "app".to_string(),
+            display_name: "My App".to_string(),
+            state: 1,
+            status_name: "STOPPED",
+            pid: None,
+            exit_code: 0,
+        };
+        assert_eq!(
+            serde_json::to_string(&info).unwrap(),
+            r#"{"name":"app","display_name":"My App","state":"STOPPED","state_code":1,"status_name":"STOPPED","pid":null,"exit_code":0}"#
+        );
+    }
+
+    #[test]
+    fn test_service_list_json() {
+        let services = vec![
+            ServiceInfo {
+                name: "api".to_string(),
+                display_name: "API".to_string(),
+                state: 4,
+                status_name: "RUNNING",
+                pid: Some(1234),
+                exit_code: 0,
+            },
+            ServiceInfo {
+                name: "worker".to_string(),
+                display_name: "Worker".to_string(),
+                state: 1,
+                status_name: "STOPPED",
+                pid: None,
+                exit_code: 1067,
+            },
+        ];
+
+        let list: serde_json::Value = serde_json::from_str(&serde_json::to_string(&services).unwrap()).unwrap();
+        let entries = list.as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["name"], "api");
+        assert_eq!(entries[0]["display_name"], "API");
+        assert_eq!(entries[0]["state"], "RUNNING");
+        assert_eq!(entries[1]["display_name"], "Worker");
+        assert_eq!(entries[1]["state"], "STOPPED");
+    }
+
+    #[test]
+    fn test_is_healthy() {
+        assert!(is_healthy("RUNNING", true, false));
+        assert!(!is_healthy("RUNNING", false, false));
+        assert!(!is_healthy("STOPPED", true, true));
+        assert!(!is_healthy("WAITING_FOR_WINDOW", true, true));
+        assert!(!is_healthy("START_PENDING", true, false));
+        assert!(is_healthy("START_PENDING", true, true));
+    }
+
+    #[test]
+    fn test_versioned_binary_name() {
+        assert_eq!(versioned_binary_name("rust-nssm 0.2.0\n").as_deref(), Some("rust-nssm-0.2.0.exe"));
+        assert_eq!(versioned_binary_name("rust-nssm 1.0.0-beta/1").as_deref(), Some("rust-nssm-1.0.0-beta1.exe"));
+        assert_eq!(versioned_binary_name(""), None);
+    }
+
+    #[test]
+    fn test_format_exit_code() {
+        assert_eq!(format_exit_code(1), "1");
+        assert_eq!(format_exit_code(0xC0000005u32 as i32), "0xC0000005");
+        assert_eq!(format_exit_code(-1), "0xFFFFFFFF");
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(7), "7s");
+        assert_eq!(format_uptime(125), "2m 5s");
+        assert_eq!(format_uptime(3 * 3600 + 60), "3h 1m");
+        assert_eq!(format_uptime(2 * 86400 + 5 * 3600), "2d 5h");
+    }
+
+    #[test]
+    fn test_parse_concurrency_limit() {
+        assert_eq!(parse_concurrency_limit(None), DEFAULT_SCM_CONCURRENCY);
+        assert_eq!(parse_concurrency_limit(Some(" 16 ")), 16);
+        assert_eq!(parse_concurrency_limit(Some("0")), DEFAULT_SCM_CONCURRENCY);
+        assert_eq!(parse_concurrency_limit(Some("many")), DEFAULT_SCM_CONCURRENCY);
+    }
+}
@@ -0,0 +1,496 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use windows_sys::Win32::Foundation::*;
+use windows_sys::Win32::System::Registry::*;
+
+use crate::service_manager::{self, ExitAction, ProcessPriorityClass, ServiceConfig};
+
+/// NSSM 注册表值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegValue {
+    String(String),
+    Dword(u32),
+    MultiString(Vec<String>),
+    Binary(usize),
+}
+
+impl std::fmt::Display for RegValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegValue::String(value) => write!(f, "{}", value),
+            RegValue::Dword(value) => write!(f, "{}", value),
+            RegValue::MultiString(values) => write!(f, "{}", values.join("; ")),
+            RegValue::Binary(len) => write!(f, "<{} bytes>", len),
+        }
+    }
+}
+
+/// NSSM 设置转换为 rust-nssm 设置的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Translation {
+    /// 转换为 rust-nssm 的对应设置
+    Mapped(String),
+    /// 与 rust-nssm 的默认行为一致，无需转换
+    Default,
+    /// 没有对应设置，迁移后会丢失
+    Unsupported(&'static str),
+}
+
+/// 单个 NSSM 设置及其转换结果
+#[derive(Debug, Clone)]
+pub struct NssmSetting {
+    pub name: String,
+    pub value: RegValue,
+    pub translation: Translation,
+}
+
+/// 从 NSSM 配置转换得到的 rust-nssm 配置
+#[derive(Debug, Clone)]
+pub struct NssmImport {
+    pub config: ServiceConfig,
+    pub settings: Vec<NssmSetting>,
+}
+
+impl NssmImport {
+    /// 无法转换的设置
+    pub fn unsupported(&self) -> impl Iterator<Item = &NssmSetting> {
+        self.settings
+            .iter()
+            .filter(|setting| matches!(setting.translation, Translation::Unsupported(_)))
+    }
+}
+
+/// 读取由 NSSM 创建的服务配置并转换为 rust-nssm 配置
+pub fn read_nssm_service(service_name: &str) -> Result<NssmImport> {
+    let key_path = service_manager::parameters_key_path(service_name)?;
+    let mut values = read_key_values(&key_path)?;
+
+    // AppExit 子键：默认值为默认退出动作，其余值以退出码命名
+    if let Ok(exit_values) = read_key_values(&format!("{}\\AppExit", key_path)) {
+        for (name, value) in exit_values {
+            let name = if name.is_empty() { "AppExit".to_string() } else { format!("AppExit\\{}", name) };
+            values.push((name, value));
+        }
+    }
+
+    translate(service_name, values)
+}
+
+/// 将 NSSM 的 Parameters 值转换为 rust-nssm 配置
+fn translate(service_name: &str, values: Vec<(String, RegValue)>) -> Result<NssmImport> {
+    let mut config = ServiceConfig {
+        name: service_name.to_string(),
+        display_name: service_name.to_string(),
+        event_log_enabled: true,
+        ..Default::default()
+    };
+    let mut settings = Vec::new();
+
+    for (name, value) in values {
+        let translation = match (name.as_str(), &value) {
+            ("Application", RegValue::String(path)) => {
+                config.executable_path = PathBuf::from(path);
+                Translation::Mapped(format!("executable = {}", path))
+            }
+            ("AppParameters", RegValue::String(parameters)) => {
+                config.arguments = split_command_line(parameters);
+                Translation::Mapped(format!("arguments = {:?}", config.arguments))
+            }
+            ("AppDirectory", RegValue::String(dir)) => {
+                config.working_directory = Some(PathBuf::from(dir));
+                Translation::Mapped(format!("working-directory = {}", dir))
+            }
+            ("AppStdout", RegValue::String(path)) => {
+                config.stdout_path = Some(PathBuf::from(path));
+                Translation::Mapped(format!("stdout = {}", path))
+            }
+            ("AppStderr", RegValue::String(path)) => {
+                config.stderr_path = Some(PathBuf::from(path));
+                Translation::Mapped(format!("stderr = {}", path))
+            }
+            // 默认退出动作；Restart 即 rust-nssm 的默认行为
+            ("AppExit", RegValue::String(action)) => match nssm_exit_action(action) {
+                Some(ExitAction::Restart) => Translation::Default,
+                Some(action) => {
+                    config.exit_actions.default = action;
+                    Translation::Mapped(format!("on-exit = default={}", action.as_str()))
+                }
+                None => Translation::Unsupported("only the Restart, Exit and Suicide exit actions are supported"),
+            },
+            (exit, RegValue::String(action)) if exit.starts_with("AppExit\\") => {
+                match (exit["AppExit\\".len()..].parse::<i32>(), nssm_exit_action(action)) {
+                    (Ok(code), Some(action)) => {
+                        config.exit_actions.codes.insert(code, action);
+                        Translation::Mapped(format!("on-exit = {}={}", code, action.as_str()))
+                    }
+                    _ => Translation::Unsupported("only the Restart, Exit and Suicide exit actions are supported"),
+                }
+            }
+            (exit, _) if exit.starts_with("AppExit") => {
+                Translation::Unsupported("only the Restart, Exit and Suicide exit actions are supported")
+            }
+            // rust-nssm 总是通过作业对象结束整个进程树
+            ("AppKillProcessTree", RegValue::Dword(1)) => Translation::Default,
+            ("AppKillProcessTree", _) => Translation::Unsupported("rust-nssm always kills the whole process tree"),
+            // NSSM 的默认值：追加写入输出文件、不轮转、不跳过停止方法
+            ("AppStdoutCreationDisposition" | "AppStderrCreationDisposition", RegValue::Dword(4)) => Translation::Default,
+            ("AppStdoutCreationDisposition" | "AppStderrCreationDisposition", _) => {
+                Translation::Unsupported("output files are always opened for appending")
+            }
+            ("AppRotateFiles" | "AppRotateOnline" | "AppRotateSeconds" | "AppRotateBytes" | "AppRotateBytesHigh", RegValue::Dword(0)) => {
+                Translation::Default
+            }
+            (rotate, _) if rotate.starts_with("AppRotate") => Translation::Unsupported("output file rotation is not supported"),
+            ("AppStopMethodSkip", RegValue::Dword(0)) => Translation::Default,
+            ("AppStopMethodSkip" | "AppStopMethodConsole" | "AppStopMethodWindow" | "AppStopMethodThreads", _) => {
+                Translation::Unsupported("the application is always sent CTRL_BREAK and then terminated on stop")
+            }
+            // AppKillConsoleDelay 以毫秒为单位，rust-nssm 以秒为单位（向上取整）
+            ("AppKillConsoleDelay", RegValue::Dword(ms)) => {
+                let secs = u64::from(ms.div_ceil(1000));
+                config.stop_timeout = Some(secs);
+                Translation::Mapped(format!("stop-timeout = {}", secs))
+            }
+            ("AppKillConsoleDelay" | "AppKillWindowDelay" | "AppKillThreadsDelay", _) => {
+                Translation::Unsupported("the application is always sent CTRL_BREAK and then terminated on stop")
+            }
+            // AppRestartDelay 以毫秒为单位，rust-nssm 以秒为单位（向上取整）
+            ("AppRestartDelay", RegValue::Dword(ms)) => {
+                let secs = u64::from(ms.div_ceil(1000));
+                config.restart_delay = Some(secs);
+                Translation::Mapped(format!("restart-delay = {}", secs))
+            }
+            // AppThrottle 以毫秒为单位：运行时间短于该值的退出按退避策略延迟重启，与 stable-window 一致
+            ("AppThrottle", RegValue::Dword(ms)) => {
+                let secs = u64::from(ms.div_ceil(1000));
+                config.stable_window = Some(secs);
+                Translation::Mapped(format!("stable-window = {}", secs))
+            }
+            ("AppThrottle" | "AppRestartDelay", _) => {
+                Translation::Unsupported("expected a DWORD value in milliseconds")
+            }
+            // AppEnvironmentExtra 在继承的环境之上追加变量，与 rust-nssm 的环境变量一致
+            ("AppEnvironmentExtra", RegValue::MultiString(entries)) => {
+                match entries.iter().map(|entry| service_manager::parse_env_var(entry)).collect::<Result<Vec<_>>>() {
+                    Ok(environment) => {
+                        let mut merged = std::mem::take(&mut config.environment);
+                        merged.extend(environment);
+                        config.environment = service_manager::dedup_environment(merged);
+                        Translation::Mapped(format!("env = {}", entries.join(" ")))
+                    }
+                    Err(_) => Translation::Unsupported("environment entries must be in the form KEY=VALUE"),
+                }
+            }
+            // AppEnvironment 替换整个环境；同时存在时 AppEnvironmentExtra 中的同名变量优先
+            ("AppEnvironment", RegValue::MultiString(entries)) => {
+                match entries.iter().map(|entry| service_manager::parse_env_var(entry)).collect::<Result<Vec<_>>>() {
+                    Ok(mut environment) => {
+                        environment.append(&mut config.environment);
+                        config.environment = service_manager::dedup_environment(environment);
+                        config.env_clear = true;
+                        Translation::Mapped(format!("env-clear, env = {}", entries.join(" ")))
+                    }
+                    Err(_) => Translation::Unsupported("environment entries must be in the form KEY=VALUE"),
+                }
+            }
+            ("AppEnvironment" | "AppEnvironmentExtra", _) => {
+                Translation::Unsupported("environment entries must be in the form KEY=VALUE")
+            }
+            // AppPriority 保存的就是 Win32 优先级类常量，NORMAL_PRIORITY_CLASS 为默认值
+            ("AppPriority", RegValue::Dword(class)) => match ProcessPriorityClass::from_win32_class(*class) {
+                Some(ProcessPriorityClass::Normal) => Translation::Default,
+                Some(priority) => {
+                    config.priority_class = Some(priority);
+                    Translation::Mapped(format!("priority = {}", priority.as_str()))
+                }
+                None => Translation::Unsupported("unknown priority class"),
+            },
+            ("AppPriority", _) => Translation::Unsupported("unknown priority class"),
+            ("AppAffinity", RegValue::String(cpus)) => match nssm_affinity(cpus) {
+                Some(mask) => {
+                    config.cpu_affinity = Some(mask);
+                    Translation::Mapped(format!("cpu-affinity = {:#x}", mask))
+                }
+                None => Translation::Unsupported("CPU affinity must be a list of CPU numbers such as 0-1,3"),
+            },
+            ("AppAffinity", _) => Translation::Unsupported("CPU affinity must be a list of CPU numbers such as 0-1,3"),
+            ("AppNoConsole", _) => Translation::Unsupported("console settings are not supported"),
+            ("AppStdin", _) => Translation::Unsupported("stdin redirection is not supported"),
+            ("AppTimestampLog", RegValue::Dword(0)) => Translation::Default,
+            ("AppTimestampLog", RegValue::Dword(_)) => {
+                config.log_timestamps = true;
+                Translation::Mapped("log-timestamps".to_string())
+            }
+            ("AppTimestampLog", _) => Translation::Unsupported("AppTimestampLog must be a DWORD"),
+            (hook, _) if hook.starts_with("AppEvents") => Translation::Unsupported("event hooks are not supported"),
+            _ => Translation::Unsupported("unknown NSSM setting"),
+        };
+
+        settings.push(NssmSetting { name, value, translation });
+    }
+
+    if config.executable_path.as_os_str().is_empty() {
+        return Err(anyhow::anyhow!(
+            "Service '{}' has no NSSM Application value; it was not created by NSSM",
+            service_name
+        ));
+    }
+
+    Ok(NssmImport { config, settings })
+}
+
+/// NSSM 退出动作对应的 rust-nssm 操作；`Ignore`（保持服务运行但不再启动程序）没有对应项
+fn nssm_exit_action(action: &str) -> Option<ExitAction> {
+    match action.to_ascii_lowercase().as_str() {
+        "restart" => Some(ExitAction::Restart),
+        "exit" => Some(ExitAction::Stop),
+        "suicide" => Some(ExitAction::StopService),
+        _ => None,
+    }
+}
+
+/// NSSM 的CPU列表（如 `0-1,3`）对应的亲和性掩码
+fn nssm_affinity(cpus: &str) -> Option<usize> {
+    let mut mask = 0usize;
+    for part in cpus.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.trim().parse::<u32>().ok()?, last.trim().parse::<u32>().ok()?),
+            None => {
+                let cpu = part.trim().parse::<u32>().ok()?;
+                (cpu, cpu)
+            }
+        };
+        if first > last || last >= usize::BITS {
+            return None;
+        }
+        for cpu in first..=last {
+            mask |= 1 << cpu;
+        }
+    }
+    (mask != 0).then_some(mask)
+}
+
+/// 按 Windows 命令行规则拆分参数（与 CommandLineToArgvW 的规则一致）
+fn split_command_line(command_line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut in_quotes = false;
+    let mut chars = command_line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                // 反斜杠只有在引号之前才需要转义处理
+                let mut backslashes = 1;
+                while chars.peek() == Some(&'\\') {
+                    chars.next();
+                    backslashes += 1;
+                }
+                if chars.peek() == Some(&'"') {
+                    current.push_str(&"\\".repeat(backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        chars.next();
+                        current.push('"');
+                    }
+                } else {
+                    current.push_str(&"\\".repeat(backslashes));
+                }
+                in_arg = true;
+            }
+            '"' => {
+                // 引号内连续两个引号表示一个字面引号
+                if in_quotes && chars.peek() == Some(&'"') {
+                    chars.next();
+                    current.push('"');
+                } else {
+                    in_quotes = !in_quotes;
+                }
+                in_arg = true;
+            }
+            ' ' | '\t' if !in_quotes => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            _ => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+/// 读取注册表项下的所有值
+fn read_key_values(key_path: &str) -> Result<Vec<(String, RegValue)>> {
+    let key_path_w = key_path.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+
+    let mut hkey = HKEY::default();
+    let result = unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr(), 0, KEY_READ, &mut hkey) };
+    if result != ERROR_SUCCESS {
+        return Err(anyhow::anyhow!("Failed to open registry key {}", key_path));
+    }
+
+    let mut max_name_len = 0u32;
+    let mut max_data_len = 0u32;
+    let result = unsafe {
+        RegQueryInfoKeyW(
+            hkey,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut max_name_len,
+            &mut max_data_len,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+
+    if result != ERROR_SUCCESS {
+        unsafe { RegCloseKey(hkey); }
+        return Err(anyhow::anyhow!("Failed to query registry key {}", key_path));
+    }
+
+    let mut values = Vec::new();
+    let mut name = vec![0u16; max_name_len as usize + 1];
+    let mut data = vec![0u8; max_data_len as usize];
+
+    for index in 0.. {
+        let mut name_len = name.len() as u32;
+        let mut data_len = data.len() as u32;
+        let mut value_type = 0u32;
+        let result = unsafe {
+            RegEnumValueW(
+                hkey,
+                index,
+                name.as_mut_ptr(),
+                &mut name_len,
+                std::ptr::null(),
+                &mut value_type,
+                data.as_mut_ptr(),
+                &mut data_len,
+            )
+        };
+
+        if result != ERROR_SUCCESS {
+            break;
+        }
+
+        let value_name = String::from_utf16_lossy(&name[..name_len as usize]);
+        let bytes = &data[..data_len as usize];
+        let value = match value_type {
+            REG_SZ | REG_EXPAND_SZ => RegValue::String(decode_utf16(bytes)),
+            REG_DWORD if bytes.len() >= 4 => RegValue::Dword(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            REG_MULTI_SZ => RegValue::MultiString(
+                decode_utf16(bytes)
+                    .split('\0')
+                    .filter(|s| !s.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+            _ => RegValue::Binary(bytes.len()),
+        };
+
+        values.push((value_name, value));
+    }
+
+    unsafe { RegCloseKey(hkey); }
+    Ok(values)
+}
+
+/// 解码注册表中的UTF-16数据，去掉末尾的null
+fn decode_utf16(bytes: &[u8]) -> String {
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&wide).trim_end_matches('\0').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_command_line() {
+        assert_eq!(split_command_line("--port 8080"), vec!["--port", "8080"]);
+        assert_eq!(split_command_line(r#"-c "C:\Program Files\app.conf"  -v"#), vec!["-c", r"C:\Program Files\app.conf", "-v"]);
+        assert_eq!(split_command_line(r#"a\\\"b "c\\" """#), vec![r#"a\"b"#, r"c\", ""]);
+        assert_eq!(split_command_line(r#""say ""hi""""#), vec![r#"say "hi""#]);
+        assert!(split_command_line("   ").is_empty());
+    }
+
+    #[test]
+    fn test_nssm_affinity() {
+        assert_eq!(nssm_affinity("0-1,3"), Some(0b1011));
+        assert_eq!(nssm_affinity("2"), Some(0b100));
+        assert_eq!(nssm_affinity("3-1"), None);
+        assert_eq!(nssm_affinity("all"), None);
+    }
+
+    #[test]
+    fn test_translate_nssm_values() {
+        let values = vec![
+            ("Application".to_string(), RegValue::String(r"C:\app\server.exe".to_string())),
+            ("AppParameters".to_string(), RegValue::String("--port 8080".to_string())),
+            ("AppDirectory".to_string(), RegValue::String(r"C:\app".to_string())),
+            ("AppStdout".to_string(), RegValue::String(r"C:\app\out.log".to_string())),
+            ("AppExit".to_string(), RegValue::String("Restart".to_string())),
+            ("AppExit\\0".to_string(), RegValue::String("Exit".to_string())),
+            ("AppExit\\3".to_string(), RegValue::String("Ignore".to_string())),
+            ("AppRotateFiles".to_string(), RegValue::Dword(0)),
+            ("AppThrottle".to_string(), RegValue::Dword(1500)),
+            ("AppKillConsoleDelay".to_string(), RegValue::Dword(1500)),
+            ("AppPriority".to_string(), RegValue::Dword(0x4000)),
+            (
+                "AppEnvironmentExtra".to_string(),
+                RegValue::MultiString(vec!["MODE=prod".to_string(), "OPTS=a=1".to_string()]),
+            ),
+            (
+                "AppEnvironment".to_string(),
+                RegValue::MultiString(vec![r"SystemRoot=C:\Windows".to_string(), "MODE=dev".to_string()]),
+            ),
+        ];
+
+        let import = translate("app", values).unwrap();
+        assert_eq!(import.config.executable_path, PathBuf::from(r"C:\app\server.exe"));
+        assert_eq!(import.config.arguments, vec!["--port", "8080"]);
+        assert_eq!(import.config.working_directory, Some(PathBuf::from(r"C:\app")));
+        assert_eq!(import.config.stdout_path, Some(PathBuf::from(r"C:\app\out.log")));
+        // AppEnvironment 替换整个环境，AppEnvironmentExtra 中的同名变量优先
+        assert!(import.config.env_clear);
+        assert_eq!(
+            import.config.environment,
+            vec![
+                ("SystemRoot".to_string(), r"C:\Windows".to_string()),
+                ("MODE".to_string(), "prod".to_string()),
+                ("OPTS".to_string(), "a=1".to_string()),
+            ]
+        );
+
+        assert_eq!(import.config.stop_timeout, Some(2));
+        assert_eq!(import.config.stable_window, Some(2));
+        assert_eq!(import.config.priority_class, Some(ProcessPriorityClass::BelowNormal));
+        assert_eq!(import.config.exit_actions.to_rules(), vec!["0=stop", "default=restart"]);
+
+        let unsupported: Vec<_> = import.unsupported().map(|s| s.name.as_str()).collect();
+        assert_eq!(unsupported, vec!["AppExit\\3"]);
+    }
+
+    #[test]
+    fn test_translate_requires_application() {
+        let values = vec![("AppDirectory".to_string(), RegValue::String(r"C:\app".to_string()))];
+        assert!(translate("app", values).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// 单次网络探测的超时时间上限
+const NETWORK_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 探测失败后重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// 读取HTTP响应的最大字节数（只需要状态行）
+const MAX_HTTP_RESPONSE: u64 = 4096;
+
+// 健康检查读取HTTP响应的最大字节数（需要检查响应正文）
+const MAX_HTTP_BODY_RESPONSE: u64 = 64 * 1024;
+
+/// 服务健康探测
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Probe {
+    /// 能建立TCP连接即视为健康（`tcp://host:port`）
+    Tcp { address: String },
+    /// `GET` 请求返回 2xx/3xx 即视为健康（`http://host[:port]/path`）
+    Http { address: String, host: String, path: String },
+    /// 命令以退出码0结束即视为健康（其余形式的值，交给 cmd 执行）
+    Command(String),
+}
+
+impl FromStr for Probe {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(anyhow::anyhow!("Probe must not be empty"));
+        }
+
+        if let Some(address) = value.strip_prefix("tcp://") {
+            let address = address.trim_end_matches('/');
+            if !address.contains(':') {
+                return Err(anyhow::anyhow!("TCP probe must be in the form tcp://host:port"));
+            }
+            return Ok(Probe::Tcp { address: address.to_string() });
+        }
+
+        if let Some(rest) = value.strip_prefix("http://") {
+            let (authority, path) = match rest.find('/') {
+                Some(index) => rest.split_at(index),
+                None => (rest, "/"),
+            };
+            if authority.is_empty() {
+                return Err(anyhow::anyhow!("HTTP probe must be in the form http://host[:port]/path"));
+            }
+            let address = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+            return Ok(Probe::Http { address, host: authority.to_string(), path: path.to_string() });
+        }
+
+        if value.starts_with("https://") {
+            return Err(anyhow::anyhow!(
+                "HTTPS probes are not supported, use a command instead (e.g. curl -f {})",
+                value
+            ));
+        }
+
+        Ok(Probe::Command(value.to_string()))
+    }
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Tcp { address } => write!(f, "tcp://{}", address),
+            Probe::Http { host, path, .. } => write!(f, "http://{}{}", host, path),
+            Probe::Command(command) => write!(f, "{}", command),
+        }
+    }
+}
+
+impl Probe {
+    /// 执行一次探测，成功时返回结果描述（命令探测为命令输出）
+    pub fn check(&self, timeout: Duration) -> Result<String> {
+        match self {
+            Probe::Tcp { address } => {
+                connect(address, timeout.min(NETWORK_ATTEMPT_TIMEOUT))?;
+                Ok(format!("connected to {}", address))
+            }
+            Probe::Http { address, host, path } => {
+                let status = http_get_status(address, host, path, timeout.min(NETWORK_ATTEMPT_TIMEOUT))?;
+                if (200..400).contains(&status) {
+                    Ok(format!("HTTP {}", status))
+                } else {
+                    Err(anyhow::anyhow!("HTTP status {}", status))
+                }
+            }
+            Probe::Command(command) => run_command(command, timeout),
+        }
+    }
+}
+
+/// 反复探测直到成功或超时，返回最后一次成功探测的结果描述
+///
+/// 刚启动的服务通常需要一段时间才能正常工作，所以失败后会在超时前持续重试。
+pub fn wait_until_healthy(probe: &Probe, timeout: Duration) -> Result<String> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let error = match probe.check(remaining) {
+            Ok(result) => return Ok(result),
+            Err(e) => e,
+        };
+
+        if Instant::now() + RETRY_INTERVAL >= deadline {
+            return Err(error.context(format!("Probe '{}' did not succeed within {:?}", probe, timeout)));
+        }
+
+        info!("Probe '{}' failed, retrying: {:#}", probe, error);
+        std::thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+/// 连接到任意一个解析出的地址
+fn connect(address: &str, timeout: Duration) -> Result<TcpStream> {
+    let addresses: Vec<SocketAddr> = address
+        .to_socket_addrs()
+        .context(format!("Failed to resolve {}", address))?
+        .collect();
+
+    let mut last_error = None;
+    for socket_address in addresses {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(e).context(format!("Failed to connect to {}", address)),
+        None => Err(anyhow::anyhow!("{} did not resolve to any address", address)),
+    }
+}
+
+/// 对 `http://host[:port]/path` 发送 `GET` 请求，检查状态码以及（可选）响应正文中应包含的文本
+pub fn check_http(url: &str, expected_status: u16, body_contains: Option<&str>, timeout: Duration) -> Result<()> {
+    let Probe::Http { address, host, path } = url.parse()? else {
+        return Err(anyhow::anyhow!("Health check URL must be in the form http://host[:port]/path"));
+    };
+
+    let response = http_get(&address, &host, &path, timeout, MAX_HTTP_BODY_RESPONSE)?;
+    let status = parse_status_line(&response)?;
+    if status != expected_status {
+        return Err(anyhow::anyhow!("HTTP status {} (expected {})", status, expected_status));
+    }
+
+    if let Some(text) = body_contains {
+        let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+        if !body.contains(text) {
+            return Err(anyhow::anyhow!("Response body does not contain {:?}", text));
+        }
+    }
+    Ok(())
+}
+
+/// 发送 `GET` 请求并返回响应状态码
+fn http_get_status(address: &str, host: &str, path: &str, timeout: Duration) -> Result<u16> {
+    parse_status_line(&http_get(address, host, path, timeout, MAX_HTTP_RESPONSE)?)
+}
+
+/// 发送 `GET` 请求，返回最多 `max_len` 字节的响应（含状态行和响应头）
+fn http_get(address: &str, host: &str, path: &str, timeout: Duration, max_len: u64) -> Result<String> {
+    let mut stream = connect(address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host);
+    stream.write_all(request.as_bytes()).context("Failed to send HTTP request")?;
+
+    let mut response = Vec::new();
+    stream
+        .take(max_len)
+        .read_to_end(&mut response)
+        .context("Failed to read HTTP response")?;
+
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+/// 从 `HTTP/1.1 200 OK` 形式的响应中取出状态码
+fn parse_status_line(response: &str) -> Result<u16> {
+    let status_line = response.lines().next().unwrap_or_default();
+    let mut parts = status_line.split_whitespace();
+    match (parts.next(), parts.next().and_then(|code| code.parse().ok())) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/") => Ok(code),
+        _ => Err(anyhow::anyhow!("Invalid HTTP response: {:?}", status_line)),
+    }
+}
+
+/// 通过 cmd 运行命令，超时后结束命令；返回合并后的输出
+fn run_command(command: &str, timeout: Duration) -> Result<String> {
+    use std::os::windows::process::CommandExt;
+
+    // 按原样交给 cmd 解析，保留命令中的引号和重定向
+    let mut child = Command::new("cmd")
+        .arg("/C")
+        .raw_arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to run probe command: {}", command))?;
+
+    // 在后台线程中读取输出，命令的子进程继承管道时也不会阻塞超时处理
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+
+    let collect = |output: Option<std::sync::mpsc::Receiver<Vec<u8>>>| {
+        output
+            .and_then(|rx| rx.recv_timeout(Duration::from_millis(500)).ok())
+            .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+            .unwrap_or_default()
+    };
+    let output = [collect(stdout), collect(stderr)]
+        .into_iter()
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    match status {
+        Some(status) if status.success() => Ok(output),
+        Some(status) => Err(anyhow::anyhow!("Probe command exited with {}: {}", status, output)),
+        None => Err(anyhow::anyhow!("Probe command timed out after {:?}: {}", timeout, output)),
+    }
+}
+
+/// 在后台线程中读取全部内容
+fn read_in_background(mut source: impl Read + Send + 'static) -> std::sync::mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = source.read_to_end(&mut buffer);
+        let _ = tx.send(buffer);
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_probe() {
+        assert_eq!(
+            "tcp://localhost:8080".parse::<Probe>().unwrap(),
+            Probe::Tcp { address: "localhost:8080".to_string() }
+        );
+        assert_eq!(
+            "http://localhost:8080/health".parse::<Probe>().unwrap(),
+            Probe::Http {
+                address: "localhost:8080".to_string(),
+                host: "localhost:8080".to_string(),
+                path: "/health".to_string(),
+            }
+        );
+        assert_eq!(
+            "http://example.com".parse::<Probe>().unwrap(),
+            Probe::Http { address: "example.com:80".to_string(), host: "example.com".to_string(), path: "/".to_string() }
+        );
+        assert_eq!(
+            "check.cmd --quick".parse::<Probe>().unwrap(),
+            Probe::Command("check.cmd --quick".to_string())
+        );
+        assert!("tcp://localhost".parse::<Probe>().is_err());
+        assert!("https://example.com".parse::<Probe>().is_err());
+        assert!("".parse::<Probe>().is_err());
+    }
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(parse_status_line("HTTP/1.1 204 No Content\r\n\r\n").unwrap(), 204);
+        assert!(parse_status_line("garbage").is_err());
+    }
+
+    #[test]
+    fn test_tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let probe = Probe::Tcp { address: listener.local_addr().unwrap().to_string() };
+        assert!(probe.check(Duration::from_secs(1)).is_ok());
+
+        drop(listener);
+        assert!(wait_until_healthy(&probe, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_http_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            for status in ["503 Service Unavailable", "200 OK"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+            }
+        });
+
+        let probe: Probe = format!("http://{}/health", address).parse().unwrap();
+        assert!(probe.check(Duration::from_secs(1)).is_err());
+        assert_eq!(probe.check(Duration::from_secs(1)).unwrap(), "HTTP 200");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_check_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/health", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for (status, body) in [("200 OK", "status: degraded"), ("200 OK", "status: ok"), ("204 No Content", "")] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body);
+            }
+        });
+
+        let timeout = Duration::from_secs(1);
+        assert!(check_http(&url, 200, Some("status: ok"), timeout).is_err());
+        assert!(check_http(&url, 200, Some("status: ok"), timeout).is_ok());
+        assert!(check_http(&url, 200, None, timeout).is_err());
+        server.join().unwrap();
+
+        assert!(check_http("tcp://127.0.0.1:80", 200, None, timeout).is_err());
+    }
+}
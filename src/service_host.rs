@@ -1,347 +1,269 @@
 use anyhow::{Context, Result};
-use log::{error, info};
-use std::ffi::OsString;
-use std::os::windows::ffi::OsStringExt;
-use std::path::PathBuf;
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+use regex::RegexSet;
+use std::collections::VecDeque;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tokio::sync::RwLock;
-use windows_service::service::{ServiceControl, ServiceState, ServiceType, ServiceStatus, ServiceControlAccept, ServiceExitCode};
-use windows_service::service_control_handler::{ServiceStatusHandle, ServiceControlHandlerResult};
-use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use windows_service::service::{ServiceState, ServiceType, ServiceStatus, ServiceControlAccept, ServiceExitCode};
+use windows_service::service_control_handler::ServiceStatusHandle;
+use windows_sys::Win32::Foundation::{
+    CloseHandle, GetLastError, BOOL, ERROR_FAILED_SERVICE_CONTROLLER_CONNECT, ERROR_MORE_DATA, ERROR_SERVICE_CANNOT_ACCEPT_CTRL,
+    ERROR_SUCCESS, HANDLE,
+};
+use windows_sys::Win32::System::Console::{
+    GenerateConsoleCtrlEvent, SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_SHUTDOWN_EVENT,
+};
+use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, Thread32First, Thread32Next, PROCESSENTRY32W, THREADENTRY32,
+    TH32CS_SNAPPROCESS, TH32CS_SNAPTHREAD,
+};
+use windows_sys::Win32::System::JobObjects::*;
+use windows_sys::Win32::System::LibraryLoader::{GetModuleHandleW, GetProcAddress};
 use windows_sys::Win32::System::Registry::*;
-use windows_sys::Win32::System::Services::*;
-
-/// 计算宽字符串长度
-unsafe fn wcslen(s: *const u16) -> usize {
-    let mut len = 0;
-    while *s.offset(len) != 0 {
-        len += 1;
-    }
-    len as usize
-}
-
-/// 服务主机 - 负责管理子进程的生命周期
-pub struct ServiceHost {
-    service_name: String,
-    executable_path: PathBuf,
-    arguments: Vec<String>,
-    working_directory: Option<PathBuf>,
-    stdout_path: Option<PathBuf>,
-    stderr_path: Option<PathBuf>,
-    child_process: Arc<RwLock<Option<Child>>>,
-    status_handle: Option<ServiceStatusHandle>,
-    stop_requested: Arc<RwLock<bool>>,
-}
-
-impl ServiceHost {
-    pub fn new(
-        service_name: String,
-        executable_path: PathBuf,
-        arguments: Vec<String>,
-        working_directory: Option<PathBuf>,
-        stdout_path: Option<PathBuf>,
-        stderr_path: Option<PathBuf>,
-    ) -> Self {
-        Self {
-            service_name,
-            executable_path,
-            arguments,
-            working_directory,
-            stdout_path,
-            stderr_path,
-            child_process: Arc::new(RwLock::new(None)),
-            status_handle: None,
-            stop_requested: Arc::new(RwLock::new(false)),
-        }
-    }
-
-    /// 启动服务
-    pub fn start_service(&mut self) -> Result<()> {
-        info!("Starting service: {}", self.service_name);
-        info!("Executable: {:?}", self.executable_path);
-        info!("Arguments: {:?}", self.arguments);
-        info!("Working directory: {:?}", self.working_directory);
-
-        // 启动子进程
-        self.start_child_process().context("Failed to start child process")?;
-
-        // 启动服务监控任务
-        self.start_monitor_task();
+use crate::event_log::{EventLog, EVENT_CHILD_CRASHED, EVENT_CHILD_STARTED, EVENT_CHILD_STOPPED, EVENT_RESTART_ATTEMPT};
+use crate::ipc_pipe::{self, ChildInput};
+use crate::live_output::{self, LineMatcher, OutputHub};
+use crate::log_file;
+use crate::probe;
+use crate::service_manager::{
+    self, read_reg_dword, read_reg_qword, read_reg_string, ExitAction, ExitActions, HealthCheckConfig,
+    HealthCheckTarget, LogRotation, PatternStream, ProcessPriorityClass, RestartWindow, RotationInterval,
+    ServiceConfig, StopMethod,
+};
+use crate::signature;
+use crate::status_pipe::{self, ExitRecord, RuntimeStatus};
+
+// 当前存活的作业对象句柄，供最后清理时终止
+static ACTIVE_JOBS: Mutex<Vec<HANDLE>> = Mutex::new(Vec::new());
+
+/// 作业对象 - 句柄关闭时终止其中的整个进程树
+///
+/// 设置了 `JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE`，因此即使宿主进程被意外终止，
+/// 操作系统关闭句柄时也会一并结束子进程，不会留下孤儿进程。
+struct JobObject {
+    handle: HANDLE,
+}
 
-        Ok(())
-    }
+impl JobObject {
+    /// 创建作业对象
+    fn new() -> Result<Self> {
+        let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if handle == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to create job object: error {}", error));
+        }
 
-    /// 停止服务
-    pub fn stop_service(&mut self) -> Result<()> {
-        info!("Stopping service: {}", self.service_name);
+        if let Err(e) = set_extended_limits(handle, None) {
+            unsafe { CloseHandle(handle); }
+            return Err(e);
+        }
 
-        // 停止子进程
-        self.stop_child_process().context("Failed to stop child process")?;
+        if let Ok(mut jobs) = ACTIVE_JOBS.lock() {
+            jobs.push(handle);
+        }
 
-        Ok(())
+        Ok(Self { handle })
     }
 
-    /// 启动子进程
-    async fn start_child_process_async(&self) -> Result<Child> {
-        let mut cmd = Command::new(&self.executable_path);
-
-        // 设置工作目录
-        if let Some(work_dir) = &self.working_directory {
-            cmd.current_dir(work_dir);
+    /// 应用服务配置的资源限制，系统不支持或设置失败时只记录警告
+    fn apply_resource_limits(&self, config: &ServiceConfig) {
+        if let Some(max_working_set) = config.max_working_set {
+            if let Err(e) = set_extended_limits(self.handle, Some(max_working_set as usize)) {
+                warn!("Working set limit not applied: {}", e);
+            }
         }
 
-        // 设置参数
-        cmd.args(&self.arguments);
-
-        // 配置标准输入/输出/错误
-        cmd.stdin(Stdio::null());
-
-        // 配置输出重定向
-        if let Some(stdout_path) = &self.stdout_path {
-            let stdout_file = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(stdout_path)
-                .context(format!("Failed to open stdout file: {:?}", stdout_path))?;
-            cmd.stdout(Stdio::from(stdout_file));
-        } else {
-            cmd.stdout(Stdio::null());
+        if let Some(bytes_per_sec) = config.io_rate_limit {
+            if let Err(e) = self.set_io_rate_limit(bytes_per_sec) {
+                warn!("I/O rate limit not applied: {}", e);
+            }
         }
+    }
 
-        if let Some(stderr_path) = &self.stderr_path {
-            let stderr_file = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(stderr_path)
-                .context(format!("Failed to open stderr file: {:?}", stderr_path))?;
-            cmd.stderr(Stdio::from(stderr_file));
-        } else {
-            cmd.stderr(Stdio::null());
+    /// 限制作业内所有进程的I/O带宽（字节/秒，作用于所有卷）
+    fn set_io_rate_limit(&self, bytes_per_sec: u64) -> Result<()> {
+        type SetIoRateControlFn =
+            unsafe extern "system" fn(HANDLE, *const JOBOBJECT_IO_RATE_CONTROL_INFORMATION) -> u32;
+
+        // 该API从 Windows 10 / Windows Server 2016 开始提供，动态加载以免旧系统上宿主无法启动
+        let kernel32 = "kernel32.dll".encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+        let module = unsafe { GetModuleHandleW(kernel32.as_ptr()) };
+        let proc = unsafe { GetProcAddress(module, c"SetIoRateControlInformationJobObject".as_ptr() as *const u8) };
+        let Some(proc) = proc else {
+            return Err(anyhow::anyhow!("I/O rate control requires Windows 10 or Windows Server 2016 and later"));
+        };
+        let set_io_rate_control: SetIoRateControlFn = unsafe { std::mem::transmute(proc) };
+
+        let info = JOBOBJECT_IO_RATE_CONTROL_INFORMATION {
+            MaxIops: 0,
+            MaxBandwidth: bytes_per_sec.min(i64::MAX as u64) as i64,
+            ReservationIops: 0,
+            VolumeName: std::ptr::null(),
+            BaseIoSize: 0,
+            ControlFlags: JOB_OBJECT_IO_RATE_CONTROL_ENABLE,
+        };
+
+        if unsafe { set_io_rate_control(self.handle, &info) } == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to set I/O rate control: error {}", error));
         }
 
-        // 启动进程
-        let child = cmd.spawn()
-            .context(format!("Failed to start process: {:?}", self.executable_path))?;
-
-        info!("Started child process with PID: {}", child.id());
-        Ok(child)
+        Ok(())
     }
 
-    /// 同步启动子进程
-    fn start_child_process(&self) -> Result<()> {
-        let child_process = self.child_process.clone();
-        let executable_path = self.executable_path.clone();
-        let working_directory = self.working_directory.clone();
-        let stdout_path = self.stdout_path.clone();
-        let stderr_path = self.stderr_path.clone();
-        let arguments = self.arguments.clone();
-        let service_name = self.service_name.clone();
-
-        tokio::spawn(async move {
-            info!("Attempting to start child process for service: {}", service_name);
-            info!("Command: {:?} {:?}", executable_path, arguments);
-
-            let mut cmd = Command::new(&executable_path);
-
-            if let Some(work_dir) = &working_directory {
-                info!("Setting working directory to: {:?}", work_dir);
-                cmd.current_dir(work_dir);
-            }
-
-            cmd.args(&arguments);
-            cmd.stdin(Stdio::null());
+    /// 将子进程加入作业对象
+    fn assign(&self, child: &Child) -> Result<()> {
+        let result = unsafe { AssignProcessToJobObject(self.handle, child.as_raw_handle() as HANDLE) };
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to assign process to job object: error {}", error));
+        }
+        Ok(())
+    }
 
-            if let Some(stdout_path) = &stdout_path {
-                info!("Redirecting stdout to: {:?}", stdout_path);
-                match std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(stdout_path)
-                {
-                    Ok(stdout_file) => {
-                        cmd.stdout(Stdio::from(stdout_file));
-                    }
-                    Err(e) => {
-                        error!("Failed to open stdout file: {:?}", e);
-                        cmd.stdout(Stdio::null());
-                    }
-                }
-            } else {
-                cmd.stdout(Stdio::null());
-            }
-
-            if let Some(stderr_path) = &stderr_path {
-                info!("Redirecting stderr to: {:?}", stderr_path);
-                match std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(stderr_path)
-                {
-                    Ok(stderr_file) => {
-                        cmd.stderr(Stdio::from(stderr_file));
-                    }
-                    Err(e) => {
-                        error!("Failed to open stderr file: {:?}", e);
-                        cmd.stderr(Stdio::null());
-                    }
+    /// 列出作业中仍在运行的进程ID
+    fn process_ids(&self) -> Result<Vec<usize>> {
+        // 列表头（两个u32）之后是进程ID数组，缓冲区不足时按实际进程数扩大后重试
+        let header_len = std::mem::size_of::<JOBOBJECT_BASIC_PROCESS_ID_LIST>() / std::mem::size_of::<usize>() - 1;
+        let mut capacity = 16usize;
+
+        loop {
+            let mut buffer = vec![0usize; header_len + capacity];
+            let list = buffer.as_mut_ptr() as *mut JOBOBJECT_BASIC_PROCESS_ID_LIST;
+            let result = unsafe {
+                QueryInformationJobObject(
+                    self.handle,
+                    JobObjectBasicProcessIdList,
+                    list as *mut _,
+                    (buffer.len() * std::mem::size_of::<usize>()) as u32,
+                    std::ptr::null_mut(),
+                )
+            };
+
+            let assigned = unsafe { (*list).NumberOfAssignedProcesses } as usize;
+            if result == 0 {
+                let error = unsafe { GetLastError() };
+                if error == ERROR_MORE_DATA && assigned > capacity {
+                    capacity = assigned;
+                    continue;
                 }
-            } else {
-                cmd.stderr(Stdio::null());
+                return Err(anyhow::anyhow!("Failed to query job object processes: error {}", error));
             }
 
-            match cmd.spawn() {
-                Ok(child) => {
-                    info!("Successfully started child process with PID: {}", child.id());
-                    *child_process.write().await = Some(child);
-                }
+            let count = unsafe { (*list).NumberOfProcessIdsInList } as usize;
+            let ids = unsafe { std::ptr::addr_of!((*list).ProcessIdList) as *const usize };
+            return Ok(unsafe { std::slice::from_raw_parts(ids, count) }.to_vec());
+        }
+    }
+
+    /// 等待作业中的所有进程退出，超时后返回仍在运行的进程ID
+    fn wait_until_empty(&self, timeout: Duration) -> Vec<usize> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let remaining = match self.process_ids() {
+                Ok(ids) => ids,
                 Err(e) => {
-                    error!("Failed to start child process: {}", e);
-                    error!("Command: {:?} {:?}", executable_path, arguments);
+                    warn!("{}", e);
+                    return Vec::new();
                 }
+            };
+
+            if remaining.is_empty() || std::time::Instant::now() >= deadline {
+                return remaining;
             }
-        });
 
-        Ok(())
+            std::thread::sleep(JOB_POLL_INTERVAL);
+        }
     }
+}
 
-    /// 停止子进程
-    fn stop_child_process(&self) -> Result<()> {
-        let child_process = self.child_process.clone();
+/// 停止时等待整个进程树退出的最长时间，超时后关闭作业对象强制结束残留进程
+pub(crate) const TREE_STOP_TIMEOUT: Duration = Duration::from_secs(15);
 
-        // 在异步环境中停止进程
-        tokio::spawn(async move {
-            let mut child_guard = child_process.write().await;
-            if let Some(mut child) = child_guard.take() {
-                info!("Stopping child process with PID: {}", child.id());
+// 等待进程树退出时的检查间隔
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
-                // 尝试优雅关闭
-                if let Err(e) = child.kill() {
-                    error!("Failed to kill child process: {}", e);
-                }
+/// 设置作业的扩展限制：始终在作业关闭时结束所有进程，可选限制每个进程的最大工作集
+fn set_extended_limits(handle: HANDLE, max_working_set: Option<usize>) -> Result<()> {
+    // 启用工作集限制时最小工作集也必须为非零值
+    const MIN_WORKING_SET: usize = 1024 * 1024;
 
-                // 等待进程退出
-                match child.wait() {
-                    Ok(status) => {
-                        info!("Child process exited with status: {}", status);
-                    }
-                    Err(e) => {
-                        error!("Failed to wait for child process: {}", e);
-                    }
-                }
-            }
-        });
+    let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+    limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
 
-        Ok(())
+    if let Some(max_working_set) = max_working_set {
+        limits.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_WORKINGSET;
+        limits.BasicLimitInformation.MinimumWorkingSetSize = MIN_WORKING_SET.min(max_working_set);
+        limits.BasicLimitInformation.MaximumWorkingSetSize = max_working_set;
     }
 
-    /// 启动监控任务
-    fn start_monitor_task(&self) {
-        let child_process = self.child_process.clone();
-        let executable_path = self.executable_path.clone();
-        let working_directory = self.working_directory.clone();
-        let stdout_path = self.stdout_path.clone();
-        let stderr_path = self.stderr_path.clone();
-        let arguments = self.arguments.clone();
-
-        tokio::spawn(async move {
-            loop {
-                // 检查子进程是否还在运行
-                {
-                    let mut child_guard = child_process.write().await;
-                    if let Some(ref mut child) = *child_guard {
-                        match child.try_wait() {
-                            Ok(Some(status)) => {
-                                info!("Child process exited with status: {}, restarting...", status);
-                                *child_guard = None;
-
-                                // 延迟重启
-                                tokio::time::sleep(Duration::from_secs(5)).await;
-
-                                // 重新启动子进程
-                                let mut cmd = Command::new(&executable_path);
-
-                                if let Some(work_dir) = &working_directory {
-                                    cmd.current_dir(work_dir);
-                                }
+    let result = unsafe {
+        SetInformationJobObject(
+            handle,
+            JobObjectExtendedLimitInformation,
+            &limits as *const _ as *const _,
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        )
+    };
 
-                                cmd.args(&arguments);
-                                cmd.stdin(Stdio::null());
+    if result == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to configure job object: error {}", error));
+    }
 
-                                if let Some(stdout_path) = &stdout_path {
-                                    let stdout_file = std::fs::OpenOptions::new()
-                                        .create(true)
-                                        .append(true)
-                                        .open(stdout_path)
-                                        .unwrap();
-                                    cmd.stdout(Stdio::from(stdout_file));
-                                } else {
-                                    cmd.stdout(Stdio::null());
-                                }
+    Ok(())
+}
 
-                                if let Some(stderr_path) = &stderr_path {
-                                    let stderr_file = std::fs::OpenOptions::new()
-                                        .create(true)
-                                        .append(true)
-                                        .open(stderr_path)
-                                        .unwrap();
-                                    cmd.stderr(Stdio::from(stderr_file));
-                                } else {
-                                    cmd.stderr(Stdio::null());
-                                }
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        if let Ok(mut jobs) = ACTIVE_JOBS.lock() {
+            jobs.retain(|&job| job != self.handle);
+        }
+        unsafe { CloseHandle(self.handle); }
+    }
+}
 
-                                match cmd.spawn() {
-                                    Ok(new_child) => {
-                                        info!("Restarted child process with PID: {}", new_child.id());
-                                        *child_guard = Some(new_child);
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to restart child process: {}", e);
-                                        // 等待更长时间后重试
-                                        tokio::time::sleep(Duration::from_secs(30)).await;
-                                    }
-                                }
-                            }
-                            Ok(None) => {
-                                // 进程仍在运行
-                            }
-                            Err(e) => {
-                                error!("Failed to check child process status: {}", e);
-                            }
-                        }
-                    }
-                }
+/// 终止所有存活的作业对象（最后清理）
+fn terminate_active_jobs() {
+    if let Ok(jobs) = ACTIVE_JOBS.lock() {
+        for &job in jobs.iter() {
+            unsafe { TerminateJobObject(job, 1); }
+        }
+    }
+}
 
-                // 等待一段时间再次检查
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
-        });
+/// 控制台控制处理器 - 宿主被关闭或系统关机时先终止子进程
+unsafe extern "system" fn host_console_handler(ctrl_type: u32) -> BOOL {
+    // 注意：不处理 CTRL_LOGOFF_EVENT，服务进程在任意用户注销时都会收到该事件
+    if ctrl_type == CTRL_CLOSE_EVENT || ctrl_type == CTRL_SHUTDOWN_EVENT {
+        log_to_file("Host is being terminated, killing child process tree");
+        terminate_active_jobs();
     }
+    // 返回FALSE，交由后续处理器继续处理
+    0
+}
 
-    /// 处理服务控制请求
-    pub fn handle_service_control(&mut self, control: ServiceControl) -> ServiceControlHandlerResult {
-        match control {
-            ServiceControl::Stop => {
-                info!("Received stop request for service: {}", self.service_name);
-                if let Err(e) = self.stop_service() {
-                    error!("Failed to stop service: {}", e);
-                    return ServiceControlHandlerResult::NoError;
-                }
-                ServiceControlHandlerResult::NoError
-            }
-            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
-            _ => ServiceControlHandlerResult::NotImplemented,
-        }
+/// 注册最后清理处理器
+///
+/// 作业对象保证宿主进程以任何方式退出时子进程都会被操作系统终止；
+/// 控制台处理器则保证在可感知的终止场景下，子进程先于宿主退出。
+fn install_last_chance_cleanup() {
+    let result = unsafe { SetConsoleCtrlHandler(Some(host_console_handler), 1) };
+    if result == 0 {
+        warn!("Failed to register console control handler");
     }
 }
 
 /// 从注册表读取服务配置
-pub fn load_service_config(service_name: &str) -> Result<(PathBuf, Vec<String>, Option<PathBuf>, Option<PathBuf>, Option<PathBuf>)> {
-    use windows_sys::Win32::System::Registry::*;
+pub fn load_service_config(service_name: &str) -> Result<ServiceConfig> {
     use windows_sys::Win32::System::Services::*;
 
-    // 首先从服务配置中获取目标可执行文件路径
+    // 确认服务已注册
     let scm = unsafe { OpenSCManagerW(std::ptr::null(), std::ptr::null(), SC_MANAGER_CONNECT) };
     if scm == 0 {
         return Err(anyhow::anyhow!("Failed to open Service Control Manager"));
@@ -355,47 +277,19 @@ pub fn load_service_config(service_name: &str) -> Result<(PathBuf, Vec<String>,
         return Err(anyhow::anyhow!("Failed to open service: {}", service_name));
     }
 
-    // 查询服务配置
-    let mut bytes_needed = 0u32;
-    unsafe { QueryServiceConfigW(service, std::ptr::null_mut(), 0, &mut bytes_needed); }
-
-    if bytes_needed == 0 {
-        unsafe {
-            CloseServiceHandle(service);
-            CloseServiceHandle(scm);
-        }
-        return Err(anyhow::anyhow!("Failed to query service config size"));
-    }
-
-    let mut buffer = vec![0u8; bytes_needed as usize];
-    let config_ptr = buffer.as_mut_ptr() as *mut QUERY_SERVICE_CONFIGW;
-
-    let result = unsafe { QueryServiceConfigW(service, config_ptr, bytes_needed, &mut bytes_needed) };
-
-    if result == 0 {
-        unsafe {
-            CloseServiceHandle(service);
-            CloseServiceHandle(scm);
-        }
-        return Err(anyhow::anyhow!("Failed to query service config"));
-    }
-
-    // 解析二进制路径和参数
-    let service_config = unsafe { &*config_ptr };
-    let binary_path = unsafe {
-        OsString::from_wide(std::slice::from_raw_parts(
-            service_config.lpBinaryPathName,
-            wcslen(service_config.lpBinaryPathName)
-        )).to_string_lossy().to_string()
-    };
-
     unsafe {
         CloseServiceHandle(service);
         CloseServiceHandle(scm);
     }
 
-    // 现在从Parameters注册表项读取额外的配置
-    let key_path = format!("SYSTEM\\CurrentControlSet\\Services\\{}\\Parameters", service_name);
+    load_parameters(service_name)
+}
+
+/// 从 Parameters 注册表项读取服务配置，调用方负责确认服务已注册
+pub(crate) fn load_parameters(service_name: &str) -> Result<ServiceConfig> {
+    use windows_sys::Win32::System::Registry::*;
+
+    let key_path = service_manager::parameters_key_path(service_name)?;
     let key_path_w = key_path.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
 
     let mut hkey = HKEY::default();
@@ -409,138 +303,294 @@ pub fn load_service_config(service_name: &str) -> Result<(PathBuf, Vec<String>,
         )
     };
 
-    let mut executable_path = PathBuf::new();
-    let mut arguments = Vec::new();
-    let mut working_directory = None;
-    let mut stdout_path = None;
-    let mut stderr_path = None;
+    let mut config = ServiceConfig {
+        name: service_name.to_string(),
+        display_name: service_name.to_string(),
+        event_log_enabled: true,
+        ..Default::default()
+    };
 
     if result == ERROR_SUCCESS {
         // 读取目标可执行文件路径
         if let Ok(target_exe) = read_reg_string(hkey, "TargetExecutable") {
-            executable_path = PathBuf::from(target_exe);
+            config.executable_path = PathBuf::from(target_exe);
         }
 
         // 读取工作目录
         if let Ok(work_dir) = read_reg_string(hkey, "WorkingDirectory") {
-            working_directory = Some(PathBuf::from(work_dir));
+            config.working_directory = Some(PathBuf::from(work_dir));
         }
 
         // 读取输出路径
         if let Ok(stdout) = read_reg_string(hkey, "StdoutPath") {
-            stdout_path = Some(PathBuf::from(stdout));
+            config.stdout_path = Some(PathBuf::from(stdout));
         }
 
         if let Ok(stderr) = read_reg_string(hkey, "StderrPath") {
-            stderr_path = Some(PathBuf::from(stderr));
+            config.stderr_path = Some(PathBuf::from(stderr));
         }
 
         // 读取参数
         if let Ok(args_json) = read_reg_string(hkey, "Arguments") {
             if let Ok(args) = serde_json::from_str::<Vec<String>>(&args_json) {
-                arguments = args;
+                config.arguments = args;
             }
         }
 
-        unsafe { RegCloseKey(hkey); }
-    }
+        // 读取环境变量
+        if let Ok(env_json) = read_reg_string(hkey, "Environment") {
+            if let Ok(environment) = serde_json::from_str::<Vec<(String, String)>>(&env_json) {
+                config.environment = environment;
+            }
+        }
 
-    Ok((executable_path, arguments, working_directory, stdout_path, stderr_path))
-}
+        if let Ok(env_clear) = read_reg_string(hkey, "EnvClear") {
+            config.env_clear = env_clear.trim() == "1";
+        }
 
-/// 读取注册表字符串值
-fn read_reg_string(hkey: HKEY, name: &str) -> Result<String> {
-    use windows_sys::Win32::System::Registry::*;
+        if let Ok(env_file) = read_reg_string(hkey, "EnvFile") {
+            config.env_file = Some(PathBuf::from(env_file));
+        }
 
-    let name_w = name.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+        if let Ok(pid_file) = read_reg_string(hkey, "PidFilePath") {
+            config.pid_file = Some(PathBuf::from(pid_file));
+        }
 
-    let mut buffer_type = 0u32;
-    let mut buffer_size = 0u32;
+        // 读取服务依赖
+        if let Ok(dependencies_json) = read_reg_string(hkey, "Dependencies") {
+            if let Ok(dependencies) = serde_json::from_str::<Vec<String>>(&dependencies_json) {
+                config.dependencies = dependencies;
+            }
+        }
 
-    // 查询缓冲区大小
-    let result = unsafe {
-        RegQueryValueExW(
-            hkey,
-            name_w.as_ptr(),
-            std::ptr::null_mut(),
-            &mut buffer_type,
-            std::ptr::null_mut(),
-            &mut buffer_size,
-        )
-    };
+        // 读取目标程序更新检查设置
+        config.update_check_interval = read_reg_dword(hkey, "UpdateCheckInterval").ok().map(u64::from);
+        config.io_rate_limit = read_reg_qword(hkey, "IoRateLimit").ok();
+        config.max_working_set = read_reg_qword(hkey, "MaxWorkingSet").ok();
 
-    if result != ERROR_SUCCESS || buffer_type != REG_SZ {
-        return Err(anyhow::anyhow!("Failed to query registry value"));
-    }
+        config.cpu_affinity = read_reg_qword(hkey, "CpuAffinity").ok().map(|mask| mask as usize);
 
-    // 读取数据
-    let mut buffer = vec![0u16; (buffer_size / 2) as usize];
-    let result = unsafe {
-        RegQueryValueExW(
-            hkey,
-            name_w.as_ptr(),
-            std::ptr::null_mut(),
-            &mut buffer_type,
-            buffer.as_mut_ptr() as *mut _,
-            &mut buffer_size,
-        )
-    };
+        if let Ok(class) = read_reg_dword(hkey, "Priority") {
+            match ProcessPriorityClass::from_win32_class(class) {
+                Some(priority) => config.priority_class = Some(priority),
+                None => warn!("Ignoring invalid Priority: {:#x}", class),
+            }
+        }
 
-    if result != ERROR_SUCCESS {
-        return Err(anyhow::anyhow!("Failed to read registry value"));
-    }
+        // 读取输出文件轮转设置：LogRotation 表示按日期轮转（同时有 MaxLogSize 时周期内也按大小轮转），否则 MaxLogSize 表示按大小轮转
+        let keep = read_reg_dword(hkey, "MaxLogFiles").unwrap_or(log_file::DEFAULT_MAX_LOG_FILES);
+        if let Ok(interval) = read_reg_string(hkey, "LogRotation") {
+            match RotationInterval::from_str(interval.trim(), true) {
+                Ok(interval) => {
+                    let max_bytes = read_reg_qword(hkey, "MaxLogSize").ok();
+                    config.log_rotation = LogRotation::ByDate { interval, keep, max_bytes };
+                }
+                Err(_) => warn!("Ignoring invalid LogRotation: {}", interval),
+            }
+        } else if let Ok(max_bytes) = read_reg_qword(hkey, "MaxLogSize") {
+            config.log_rotation = LogRotation::BySize { max_bytes, keep };
+        }
 
-    // 移除null终止符并转换为字符串
-    if let Some(null_pos) = buffer.iter().position(|&c| c == 0) {
-        buffer.truncate(null_pos);
-    }
+        if let Ok(group) = read_reg_string(hkey, "PipeAllowGroup") {
+            config.pipe_allow_group = Some(group);
+        }
 
-    Ok(String::from_utf16_lossy(&buffer))
-}
+        if let Ok(pipe) = read_reg_string(hkey, "IpcPipe") {
+            config.ipc_pipe_name = Some(pipe);
+        }
+
+        if let Ok(disabled) = read_reg_string(hkey, "DisableEventLog") {
+            config.event_log_enabled = disabled.trim() != "1";
+        }
+
+        if let Ok(path) = read_reg_string(hkey, "HostLogPath") {
+            config.host_log_path = Some(PathBuf::from(path));
+        }
+
+        if let Ok(restart) = read_reg_string(hkey, "RestartOnBinaryChange") {
+            config.restart_on_binary_change = restart.trim() == "1";
+        }
+
+        if let Ok(window) = read_reg_string(hkey, "RestartWindow") {
+            match window.parse() {
+                Ok(window) => config.restart_window = Some(window),
+                Err(e) => warn!("Ignoring invalid RestartWindow: {}", e),
+            }
+        }
+
+        config.restart_delay = read_reg_dword(hkey, "RestartDelay").ok().map(u64::from);
+        config.stop_timeout = read_reg_dword(hkey, "StopTimeout").ok().map(u64::from);
+
+        if let Ok(method) = read_reg_string(hkey, "StopMethod") {
+            match StopMethod::from_str(method.trim(), true) {
+                Ok(method) => config.stop_method = method,
+                Err(_) => warn!("Ignoring invalid StopMethod: {}", method),
+            }
+        }
+
+        config.cpu_stall_timeout = read_reg_dword(hkey, "CpuStallTimeout").ok().map(u64::from);
+
+        if let Ok(threshold) = read_reg_string(hkey, "CpuStallThreshold") {
+            config.cpu_stall_threshold = threshold.trim().parse().ok();
+        }
+
+        let health_target = if let Some(port) = read_reg_dword(hkey, "HealthCheckPort").ok().and_then(|port| u16::try_from(port).ok()) {
+            Some(HealthCheckTarget::Tcp { port })
+        } else if let Ok(url) = read_reg_string(hkey, "HealthCheckUrl") {
+            Some(HealthCheckTarget::Http {
+                url,
+                expected_status: read_reg_string(hkey, "HealthCheckExpectedStatus")
+                    .ok()
+                    .and_then(|status| status.trim().parse().ok())
+                    .unwrap_or(service_manager::DEFAULT_HEALTH_CHECK_STATUS),
+                timeout_ms: read_reg_string(hkey, "HealthCheckTimeout")
+                    .ok()
+                    .and_then(|timeout| timeout.trim().parse().ok())
+                    .unwrap_or(service_manager::DEFAULT_HEALTH_CHECK_TIMEOUT_MS),
+                body_contains: read_reg_string(hkey, "HealthCheckBodyContains").ok(),
+            })
+        } else {
+            None
+        };
+        if let Some(target) = health_target {
+            let mut health_check = HealthCheckConfig::new(target);
+            if let Ok(interval) = read_reg_dword(hkey, "HealthCheckInterval") {
+                health_check.interval_secs = u64::from(interval.max(1));
+            }
+            if let Ok(failures) = read_reg_dword(hkey, "HealthCheckFailureThreshold") {
+                health_check.failure_threshold = failures.max(1);
+            }
+            if let Ok(successes) = read_reg_dword(hkey, "HealthCheckSuccessThreshold") {
+                health_check.success_threshold = successes.max(1);
+            }
+            config.health_check = Some(health_check);
+        }
+
+        config.console_codepage = read_reg_dword(hkey, "ConsoleCodepage").ok();
+
+        if let Ok(detached) = read_reg_string(hkey, "Detached") {
+            config.detached = detached.trim() == "1";
+        }
+
+        if let Ok(timestamps) = read_reg_string(hkey, "LogTimestamps") {
+            config.log_timestamps = timestamps.trim() == "1";
+        }
+
+        // 读取备用程序设置
+        if let Ok(fallback) = read_reg_string(hkey, "FallbackExecutable") {
+            config.fallback_executable = Some(PathBuf::from(fallback));
+        }
+
+        if let Ok(args_json) = read_reg_string(hkey, "FallbackArguments") {
+            if let Ok(args) = serde_json::from_str::<Vec<String>>(&args_json) {
+                config.fallback_arguments = args;
+            }
+        }
+
+        if let Ok(prefer) = read_reg_string(hkey, "PreferPrimary") {
+            config.prefer_primary = prefer.trim() == "1";
+        }
+
+        if let Ok(patterns_json) = read_reg_string(hkey, "RestartPatterns") {
+            if let Ok(patterns) = serde_json::from_str::<Vec<String>>(&patterns_json) {
+                config.restart_patterns = patterns;
+            }
+        }
+
+        if let Ok(stream) = read_reg_string(hkey, "RestartPatternStream") {
+            match PatternStream::from_str(stream.trim(), true) {
+                Ok(stream) => config.restart_pattern_stream = stream,
+                Err(_) => warn!("Ignoring invalid RestartPatternStream: {}", stream),
+            }
+        }
+
+        if let Ok(rules_json) = read_reg_string(hkey, "ExitActions") {
+            match serde_json::from_str::<Vec<String>>(&rules_json).map_err(anyhow::Error::from).and_then(|rules| ExitActions::parse_rules(&rules)) {
+                Ok(actions) => config.exit_actions = actions,
+                Err(e) => warn!("Ignoring invalid ExitActions: {}", e),
+            }
+        }
+
+        if let Ok(quiet) = read_reg_string(hkey, "QuietRestartLogging") {
+            config.quiet_restart_logging = quiet.trim() == "1";
+        }
+
+        config.max_restarts_per_window = read_reg_dword(hkey, "MaxRestartsPerWindow").ok();
+        config.restart_rate_window = read_reg_dword(hkey, "RestartWindowSeconds").ok().map(u64::from);
+        config.max_restarts = read_reg_dword(hkey, "MaxRestarts").ok();
+        config.stable_window = read_reg_dword(hkey, "StableWindow").ok().map(u64::from);
+        config.max_start_attempts = read_reg_dword(hkey, "MaxStartAttempts").ok();
+        config.start_retry_delay = read_reg_dword(hkey, "StartRetryDelay").ok().map(u64::from);
+        config.start_retry_delay_max = read_reg_dword(hkey, "StartRetryDelayMax").ok().map(u64::from);
+        config.restart_jitter = read_reg_dword(hkey, "RestartJitter").ok().map(u64::from);
+        config.restart_log_burst = read_reg_dword(hkey, "RestartLogBurst").ok();
+        config.restart_log_window = read_reg_dword(hkey, "RestartLogWindow").ok().map(u64::from);
+
+        if let Ok(wait) = read_reg_string(hkey, "WaitForTree") {
+            config.wait_for_tree = wait.trim() == "1";
+        }
+
+        // 读取签名校验设置
+        if let Ok(check) = read_reg_string(hkey, "CheckSignature") {
+            config.check_signature = check.trim() == "1";
+        }
+
+        if let Ok(require) = read_reg_string(hkey, "RequireSignature") {
+            config.require_signature = require.trim() == "1";
+        }
+
+        if let Ok(thumbprint) = read_reg_string(hkey, "SignerThumbprint") {
+            config.signer_thumbprint = Some(thumbprint);
+        }
 
-/// 从服务二进制路径解析出目标可执行文件路径
-fn parse_target_executable_path(_binary_path: &str) -> Result<PathBuf> {
-    // 注意：这个函数现在需要service_name参数，但由于调用结构限制，
-    // 我们将直接在load_service_config中处理路径解析
-    Err(anyhow::anyhow!("此函数已弃用，请在load_service_config中直接处理"))
+        // 只在安装时运行，读回后编辑配置时照原样写回
+        if let Ok(command) = read_reg_string(hkey, "ProvisionCommand") {
+            config.provision_command = Some(command);
+        }
+
+        unsafe { RegCloseKey(hkey); }
+    }
+
+    Ok(config)
 }
 
 /// 启动服务主循环
 pub fn run_service(service_name: &str) -> Result<()> {
     // 从注册表读取配置
-    let (executable_path, arguments, working_directory, stdout_path, stderr_path) = load_service_config(service_name)
+    let config = load_service_config(service_name)
         .context("Failed to load service config")?;
 
     // 验证可执行文件是否存在
-    if !executable_path.exists() {
-        return Err(anyhow::anyhow!("Target executable does not exist: {:?}", executable_path));
+    if !config.executable_path.exists() {
+        return Err(anyhow::anyhow!("Target executable does not exist: {:?}", config.executable_path));
+    }
+
+    info!("Loading service '{}' with executable: {:?}", service_name, config.executable_path);
+
+    // 此后本进程的诊断日志写入该服务自己的文件，多个服务的日志不会交错
+    if let Some(path) = host_log_path(&config) {
+        let _ = HOST_LOG_PATH.set(path);
     }
 
-    info!("Loading service '{}' with executable: {:?}", service_name, executable_path);
+    install_last_chance_cleanup();
 
     // 检查是否在服务环境中运行
     if std::env::var("RUST_NSSM_DEBUG").unwrap_or_default() == "1" {
         info!("Running in debug mode (non-service environment)");
-        run_debug_mode(service_name, executable_path, arguments, working_directory, stdout_path, stderr_path)
+        run_debug_mode(config)
     } else {
         // 使用windows_service crate来正确实现Windows服务
-        run_windows_service(service_name, executable_path, arguments, working_directory, stdout_path, stderr_path)
+        run_windows_service(config)
     }
 }
 
 /// 运行Windows服务 - 使用服务分发器正确实现
-fn run_windows_service(
-    service_name: &str,
-    executable_path: PathBuf,
-    arguments: Vec<String>,
-    working_directory: Option<PathBuf>,
-    stdout_path: Option<PathBuf>,
-    stderr_path: Option<PathBuf>,
-) -> Result<()> {
+fn run_windows_service(config: ServiceConfig) -> Result<()> {
     use windows_service::service_dispatcher;
     use std::ffi::OsString;
     use std::os::windows::ffi::OsStringExt;
 
+    let service_name = config.name.clone();
     log_to_file(&format!("Starting Windows service mode for: {}", service_name));
 
     // 将服务配置转换为可传递给服务主函数的格式
@@ -548,14 +598,7 @@ fn run_windows_service(
 
     // 存储服务配置到全局变量，以便服务主函数可以访问
     // 这里使用线程局部存储或全局状态
-    if let Err(e) = set_service_global_config(
-        service_name.to_string(),
-        executable_path,
-        arguments,
-        working_directory,
-        stdout_path,
-        stderr_path,
-    ) {
+    if let Err(e) = set_service_global_config(config.clone()) {
         let error_msg = format!("Failed to set service global config: {}", e);
         log_to_file(&error_msg);
         return Err(anyhow::anyhow!("{}", error_msg));
@@ -569,6 +612,17 @@ fn run_windows_service(
             log_to_file("Service dispatcher started successfully");
             Ok(())
         }
+        Err(windows_service::Error::Winapi(e))
+            if e.raw_os_error() == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT as i32) =>
+        {
+            // 直接从命令行运行（而非由SCM启动），退回前台调试模式
+            log_to_file("Not started by the Service Control Manager, falling back to foreground mode");
+            eprintln!(
+                "rust-nssm run was not started by the Service Control Manager; running service '{}' in the foreground. Press Ctrl+C to stop.",
+                service_name
+            );
+            run_debug_mode(config)
+        }
         Err(e) => {
             let error_msg = format!("Failed to start service dispatcher: {}", e);
             log_to_file(&error_msg);
@@ -577,51 +631,23 @@ fn run_windows_service(
     }
 }
 
-// 全局服务配置存储
-static mut SERVICE_CONFIG: Option<ServiceConfig> = None;
-
-/// 服务配置结构
-#[derive(Clone)]
-struct ServiceConfig {
-    name: String,
-    executable_path: PathBuf,
-    arguments: Vec<String>,
-    working_directory: Option<PathBuf>,
-    stdout_path: Option<PathBuf>,
-    stderr_path: Option<PathBuf>,
-}
+// 全局服务配置存储：服务分派器启动前设置一次，服务主函数中读取
+static SERVICE_CONFIG: OnceLock<ServiceConfig> = OnceLock::new();
 
-/// 设置服务全局配置
-fn set_service_global_config(
-    name: String,
-    executable_path: PathBuf,
-    arguments: Vec<String>,
-    working_directory: Option<PathBuf>,
-    stdout_path: Option<PathBuf>,
-    stderr_path: Option<PathBuf>,
-) -> Result<()> {
-    unsafe {
-        SERVICE_CONFIG = Some(ServiceConfig {
-            name,
-            executable_path,
-            arguments,
-            working_directory,
-            stdout_path,
-            stderr_path,
-        });
-    }
-    Ok(())
+/// 设置服务全局配置（每个进程只能设置一次）
+fn set_service_global_config(config: ServiceConfig) -> Result<()> {
+    SERVICE_CONFIG
+        .set(config)
+        .map_err(|_| anyhow::anyhow!("Service config already set"))
 }
 
 /// 获取服务全局配置
 fn get_service_global_config() -> Result<ServiceConfig> {
-    unsafe {
-        SERVICE_CONFIG.clone().ok_or_else(|| anyhow::anyhow!("Service config not set"))
-    }
+    SERVICE_CONFIG.get().cloned().ok_or_else(|| anyhow::anyhow!("Service config not set"))
 }
 
 /// FFI服务主函数 - Windows服务入口点
-extern "system" fn ffi_service_main(argc: u32, argv: *mut *mut u16) {
+extern "system" fn ffi_service_main(_argc: u32, _argv: *mut *mut u16) {
     use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
     use windows_service::service::{ServiceControl, ServiceState, ServiceStatus, ServiceType};
 
@@ -638,15 +664,38 @@ extern "system" fn ffi_service_main(argc: u32, argv: *mut *mut u16) {
 
     let service_name = config.name.clone();
 
+    let runtime_status = Arc::new(Mutex::new(RuntimeStatus {
+        exit_history: load_exit_history(&service_name),
+        ..Default::default()
+    }));
+
     // 定义服务控制处理器
     let stop_requested = Arc::new(Mutex::new(false));
     let stop_requested_clone = stop_requested.clone();
     let service_name_clone = service_name.clone();
+    let paused = Arc::new(AtomicBool::new(false));
+    let paused_clone = paused.clone();
+    let runtime_status_for_handler = runtime_status.clone();
+    // 处理器先于状态句柄注册，暂停/继续时通过它报告新状态
+    let status_handle_for_handler: Arc<OnceLock<ServiceStatusHandle>> = Arc::new(OnceLock::new());
+    let status_handle_clone = status_handle_for_handler.clone();
 
     let service_control_handler = move |control| -> ServiceControlHandlerResult {
+        let child_pid = || runtime_status_for_handler.lock().ok().and_then(|status| status.child_pid);
+
         match control {
-            ServiceControl::Stop => {
-                log_to_file(&format!("Received stop request for service: {}", service_name_clone));
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                let request = if control == ServiceControl::Stop { "stop" } else { "shutdown" };
+                log_to_file(&format!("Received {} request for service: {}", request, service_name_clone));
+
+                // 暂停中的子进程无法响应 CTRL_BREAK，先恢复再停止
+                if paused_clone.swap(false, Ordering::SeqCst) {
+                    if let Some(pid) = child_pid() {
+                        if let Err(e) = set_process_tree_suspended(pid, false) {
+                            log_to_file(&format!("Failed to resume child process before stopping: {}", e));
+                        }
+                    }
+                }
 
                 // 设置停止标志
                 if let Ok(mut stop) = stop_requested_clone.lock() {
@@ -655,17 +704,32 @@ extern "system" fn ffi_service_main(argc: u32, argv: *mut *mut u16) {
 
                 ServiceControlHandlerResult::NoError
             }
-            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
-            ServiceControl::Shutdown => {
-                log_to_file(&format!("Received shutdown request for service: {}", service_name_clone));
+            ServiceControl::Pause | ServiceControl::Continue => {
+                let pause = control == ServiceControl::Pause;
+                if let Some(pid) = child_pid() {
+                    match set_process_tree_suspended(pid, pause) {
+                        Ok(threads) => {
+                            let action = if pause { "Suspended" } else { "Resumed" };
+                            log_to_file(&format!("{} {} thread(s) of child process {}", action, threads, pid));
+                        }
+                        Err(e) => {
+                            log_to_file(&format!("Failed to {} child process: {}", if pause { "suspend" } else { "resume" }, e));
+                            return ServiceControlHandlerResult::Other(ERROR_SERVICE_CANNOT_ACCEPT_CTRL);
+                        }
+                    }
+                }
+                paused_clone.store(pause, Ordering::SeqCst);
 
-                // 设置停止标志
-                if let Ok(mut stop) = stop_requested_clone.lock() {
-                    *stop = true;
+                if let Some(status_handle) = status_handle_clone.get() {
+                    let state = if pause { ServiceState::Paused } else { ServiceState::Running };
+                    if let Err(e) = set_service_status_with_retry(status_handle, running_status(state)) {
+                        log_to_file(&format!("Failed to report service state {:?}: {}", state, e));
+                    }
                 }
 
                 ServiceControlHandlerResult::NoError
             }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
             _ => ServiceControlHandlerResult::NotImplemented,
         }
     };
@@ -686,19 +750,34 @@ extern "system" fn ffi_service_main(argc: u32, argv: *mut *mut u16) {
         }
     };
 
-    // 设置服务状态为运行中
-    let status = ServiceStatus {
-        service_type: ServiceType::OWN_PROCESS,
-        current_state: ServiceState::Running,
-        controls_accepted: windows_service::service::ServiceControlAccept::STOP,
-        exit_code: windows_service::service::ServiceExitCode::Win32(0),
-        checkpoint: 0,
-        wait_hint: std::time::Duration::default(),
-        process_id: None,
+    let _ = status_handle_for_handler.set(status_handle);
+
+    // 报告运行中之前创建管道，管道名被其他进程占用时以失败状态停止服务
+    let (output_hub, child_input) = match start_pipe_servers(&config, &runtime_status) {
+        Ok(pipes) => pipes,
+        Err(e) => {
+            log_to_file(&format!("{:#}", e));
+            let reason = StopReason::PipeUnavailable;
+            let status = ServiceStatus {
+                service_type: ServiceType::OWN_PROCESS,
+                current_state: ServiceState::Stopped,
+                controls_accepted: windows_service::service::ServiceControlAccept::empty(),
+                exit_code: reason.exit_code(),
+                checkpoint: 0,
+                wait_hint: std::time::Duration::default(),
+                process_id: None,
+            };
+            log_to_file(&format!("Service '{}' {}", service_name, reason));
+            if let Err(e) = set_service_status_with_retry(&status_handle, status) {
+                log_to_file(&format!("Failed to set service status to stopped: {}", e));
+            }
+            return;
+        }
     };
 
+    // 设置服务状态为运行中
     log_to_file("Setting service status to RUNNING...");
-    if let Err(e) = status_handle.set_service_status(status) {
+    if let Err(e) = set_service_status_with_retry(&status_handle, running_status(ServiceState::Running)) {
         log_to_file(&format!("Failed to set service status to running: {}", e));
         return;
     }
@@ -707,220 +786,1559 @@ extern "system" fn ffi_service_main(argc: u32, argv: *mut *mut u16) {
 
     // 启动子进程管理器
     let stop_requested_clone = stop_requested.clone();
-    let executable_path_clone = config.executable_path.clone();
-    let arguments_clone = config.arguments.clone();
-    let working_directory_clone = config.working_directory.clone();
-    let stdout_path_clone = config.stdout_path.clone();
-    let stderr_path_clone = config.stderr_path.clone();
-    let service_name_clone = service_name.clone();
+    let config_clone = config.clone();
+    let runtime_status_for_history = runtime_status.clone();
 
     log_to_file("Starting child process manager...");
 
     // 在单独的线程中管理子进程
+    let (reason_tx, reason_rx) = std::sync::mpsc::channel();
     std::thread::spawn(move || {
-        manage_child_process(
-            &service_name_clone,
-            &executable_path_clone,
-            &arguments_clone,
-            &working_directory_clone,
-            &stdout_path_clone,
-            &stderr_path_clone,
-            &stop_requested_clone,
-        );
+        let reason = manage_child_process(&config_clone, &stop_requested_clone, &paused, &runtime_status, &output_hub, &child_input);
+        let _ = reason_tx.send(reason);
     });
 
     log_to_file("Entering main service loop...");
 
-    // 主循环 - 等待停止信号
-    loop {
-        std::thread::sleep(std::time::Duration::from_millis(500));
+    // 主循环 - 等待停止信号或子进程管理器结束
+    let reason = wait_for_stop(&stop_requested, &reason_rx);
+    log_to_file(&format!("Service '{}' {}", service_name, reason));
+
+    // 收到停止请求后等待子进程管理器完成清理，必要时给进程树留出退出时间
+    if matches!(reason, StopReason::Requested) {
+        let cleanup_timeout = stop_cleanup_timeout(&config);
+        let status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::StopPending,
+            controls_accepted: windows_service::service::ServiceControlAccept::empty(),
+            exit_code: windows_service::service::ServiceExitCode::Win32(0),
+            checkpoint: 1,
+            wait_hint: cleanup_timeout,
+            process_id: None,
+        };
+        if let Err(e) = set_service_status_with_retry(&status_handle, status) {
+            log_to_file(&format!("Failed to set service status to stop pending: {}", e));
+        }
 
-        // 检查是否收到停止请求
-        if let Ok(stop) = stop_requested.lock() {
-            if *stop {
-                log_to_file("Stop signal received, breaking main loop");
-                break;
-            }
+        if reason_rx.recv_timeout(cleanup_timeout).is_err() {
+            log_to_file("Child process manager did not finish cleanup in time");
         }
     }
 
-    // 更新服务状态为已停止
+    save_exit_history(&service_name, &runtime_status_for_history);
+
+    // 更新服务状态为已停止，退出码反映真实的停止原因
     let status = ServiceStatus {
         service_type: ServiceType::OWN_PROCESS,
         current_state: ServiceState::Stopped,
         controls_accepted: windows_service::service::ServiceControlAccept::empty(),
-        exit_code: windows_service::service::ServiceExitCode::Win32(0),
+        exit_code: reason.exit_code(),
         checkpoint: 0,
         wait_hint: std::time::Duration::default(),
         process_id: None,
     };
 
     log_to_file("Setting service status to STOPPED...");
-    if let Err(e) = status_handle.set_service_status(status) {
+    if let Err(e) = set_service_status_with_retry(&status_handle, status) {
         log_to_file(&format!("Failed to set service status to stopped: {}", e));
     } else {
         log_to_file(&format!("Service '{}' stopped successfully", service_name));
     }
 }
 
-/// 记录到文件
-fn log_to_file(message: &str) {
-    use std::fs::OpenOptions;
-    use std::io::Write;
+/// 创建状态管道、实时输出管道和已配置的IPC管道
+///
+/// 任一管道名已被其他进程占用时返回错误，宿主不在无法确认管道归属的情况下运行。
+fn start_pipe_servers(
+    config: &ServiceConfig,
+    runtime_status: &Arc<Mutex<RuntimeStatus>>,
+) -> Result<(Arc<OutputHub>, Arc<ChildInput>)> {
+    let allow_group = config.pipe_allow_group.as_deref();
+    status_pipe::spawn_server(&config.name, runtime_status.clone(), allow_group)?;
+    let output_hub = Arc::new(OutputHub::default());
+    live_output::spawn_server(&config.name, output_hub.clone(), allow_group)?;
+    let child_input = Arc::new(ChildInput::default());
+    if let Some(pipe) = &config.ipc_pipe_name {
+        ipc_pipe::spawn_server(pipe, child_input.clone(), allow_group)?;
+    }
+    Ok((output_hub, child_input))
+}
 
-    let log_file = "D:\\dev\\Rust\\rust-nssm\\service_detailed.log";
-    if let Ok(mut file) = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_file)
-    {
-        let _ = writeln!(file, "[{}] {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+/// 运行中或已暂停时报告给SCM的状态，两种状态下都接受停止和暂停/继续
+fn running_status(state: ServiceState) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
     }
 }
 
-/// 管理子进程的函数
+/// 停止时等待子进程管理器完成清理的最长时间
+fn stop_cleanup_timeout(config: &ServiceConfig) -> Duration {
+    // 子进程管理器每500毫秒检查一次停止标志，结束子进程本身也需要少量时间
+    const CLEANUP_MARGIN: Duration = Duration::from_secs(5);
+
+    let graceful = if console_stop(config) { stop_timeout(config) } else { Duration::ZERO };
+    if config.wait_for_tree {
+        graceful + TREE_STOP_TIMEOUT + CLEANUP_MARGIN
+    } else {
+        graceful + CLEANUP_MARGIN
+    }
+}
+
+// 设置服务状态失败时的最大尝试次数
+const SET_STATUS_ATTEMPTS: u32 = 3;
+
+// 设置服务状态重试间隔
+const SET_STATUS_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// 设置服务状态，短暂失败（如RPC抖动）时有限次重试
+fn set_service_status_with_retry(status_handle: &ServiceStatusHandle, status: ServiceStatus) -> windows_service::Result<()> {
+    retry_with_delay(SET_STATUS_ATTEMPTS, SET_STATUS_RETRY_DELAY, || {
+        status_handle.set_service_status(status.clone())
+    })
+}
+
+/// 重试操作直至成功或达到最大尝试次数，返回最后一次的错误
+fn retry_with_delay<T, E: std::fmt::Display>(
+    attempts: u32,
+    delay: Duration,
+    mut operation: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < attempts => {
+                log_to_file(&format!("Attempt {}/{} failed: {}, retrying in {:?}", attempt, attempts, e, delay));
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// 本进程所服务的诊断日志路径，加载服务配置后设置一次
+static HOST_LOG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// 服务的诊断日志路径：注册表 `HostLogPath` 指定时使用该路径，否则为日志目录中的 `rust-nssm-<服务名>.log`
+pub(crate) fn host_log_path(config: &ServiceConfig) -> Option<PathBuf> {
+    host_log_path_in(config, log_file::host_log_dir())
+}
+
+fn host_log_path_in(config: &ServiceConfig, log_dir: Option<PathBuf>) -> Option<PathBuf> {
+    config
+        .host_log_path
+        .clone()
+        .or_else(|| log_dir.map(|dir| dir.join(format!("rust-nssm-{}.log", config.name))))
+}
+
+/// 记录到本服务的诊断日志，服务配置加载前写入日志目录中的 `service_detailed.log`
+fn log_to_file(message: &str) {
+    use std::io::Write;
+
+    let file = match HOST_LOG_PATH.get() {
+        Some(path) => log_file::open_host_log_at(path),
+        None => log_file::open_host_log("service_detailed.log"),
+    };
+    if let Ok(mut file) = file {
+        let _ = writeln!(file, "[{}] {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+    }
+}
+
+// 子进程多次重启失败后放弃时报告给SCM的服务特定退出码
+const EXIT_RESTARTS_EXHAUSTED: u32 = 1;
+
+// 子进程管理器意外结束时报告给SCM的服务特定退出码
+const EXIT_MANAGER_LOST: u32 = 2;
+
+// 子进程重启频率超过上限时报告给SCM的服务特定退出码
+const EXIT_RESTART_RATE_EXCEEDED: u32 = 3;
+
+// 子进程连续重启次数超过上限时报告给SCM的服务特定退出码
+const EXIT_MAX_RESTARTS_REACHED: u32 = 4;
+
+// 子进程退出码对应的操作为 stop-service 时报告给SCM的服务特定退出码
+const EXIT_CHILD_EXIT_ACTION: u32 = 5;
+
+// 管道名已被其他进程占用、无法创建宿主的命名管道时报告给SCM的服务特定退出码
+const EXIT_PIPE_UNAVAILABLE: u32 = 6;
+
+/// 服务停止的原因，决定最终报告给SCM的退出码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopReason {
+    /// 操作员请求停止（SCM停止、系统关机或Ctrl+C）
+    Requested,
+    /// 子进程连续多次启动失败，放弃重启
+    RestartsExhausted { attempts: u32 },
+    /// 子进程管理器线程意外结束
+    ManagerLost,
+    /// 时间窗口内子进程重启次数超过上限
+    RestartRateExceeded { restarts: u32, window_secs: u64 },
+    /// 子进程连续重启次数超过上限
+    MaxRestartsReached { restarts: u32 },
+    /// 子进程的退出码对应 `stop`（`failed` 为 `false`）或 `stop-service`（`failed` 为 `true`）操作
+    ChildExited { exit_code: Option<i32>, failed: bool },
+    /// 无法创建宿主的命名管道
+    PipeUnavailable,
+}
+
+impl StopReason {
+    /// 报告给SCM的退出码
+    fn exit_code(self) -> ServiceExitCode {
+        match self {
+            StopReason::Requested => ServiceExitCode::Win32(0),
+            StopReason::RestartsExhausted { .. } => ServiceExitCode::ServiceSpecific(EXIT_RESTARTS_EXHAUSTED),
+            StopReason::ManagerLost => ServiceExitCode::ServiceSpecific(EXIT_MANAGER_LOST),
+            StopReason::RestartRateExceeded { .. } => ServiceExitCode::ServiceSpecific(EXIT_RESTART_RATE_EXCEEDED),
+            StopReason::MaxRestartsReached { .. } => ServiceExitCode::ServiceSpecific(EXIT_MAX_RESTARTS_REACHED),
+            StopReason::ChildExited { failed: false, .. } => ServiceExitCode::Win32(0),
+            StopReason::ChildExited { failed: true, .. } => ServiceExitCode::ServiceSpecific(EXIT_CHILD_EXIT_ACTION),
+            StopReason::PipeUnavailable => ServiceExitCode::ServiceSpecific(EXIT_PIPE_UNAVAILABLE),
+        }
+    }
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Requested => write!(f, "stopped by request"),
+            StopReason::RestartsExhausted { attempts } => {
+                write!(f, "stopped after {} failed restarts", attempts)
+            }
+            StopReason::ManagerLost => write!(f, "stopped because the child process manager exited unexpectedly"),
+            StopReason::RestartRateExceeded { restarts, window_secs } => {
+                write!(f, "stopped because the restart rate limit was exceeded ({} restarts in {}s)", restarts, window_secs)
+            }
+            StopReason::MaxRestartsReached { restarts } => {
+                write!(f, "stopped after {} consecutive restarts", restarts)
+            }
+            StopReason::ChildExited { exit_code, failed } => {
+                let exit_code = exit_code.map_or("unknown".to_string(), service_manager::format_exit_code);
+                let action = if *failed { ExitAction::StopService } else { ExitAction::Stop };
+                write!(f, "stopped because the child process exited with code {} (exit action: {})", exit_code, action.as_str())
+            }
+            StopReason::PipeUnavailable => write!(f, "stopped because its named pipes could not be created"),
+        }
+    }
+}
+
+/// 等待停止请求或子进程管理器结束，返回停止原因
+fn wait_for_stop(stop_requested: &Arc<Mutex<bool>>, reason_rx: &std::sync::mpsc::Receiver<StopReason>) -> StopReason {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    loop {
+        match reason_rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(reason) => return reason,
+            Err(RecvTimeoutError::Disconnected) => return StopReason::ManagerLost,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        // 检查是否收到停止请求
+        if let Ok(stop) = stop_requested.lock() {
+            if *stop {
+                return StopReason::Requested;
+            }
+        }
+    }
+}
+
+// 检测到目标程序变化后，文件需保持不变的时间（避免在复制过程中重启）
+const BINARY_STABLE_PERIOD: Duration = Duration::from_secs(5);
+
+/// CPU停滞检测的默认阈值（占一个CPU核心的百分比）
+pub(crate) const DEFAULT_CPU_STALL_THRESHOLD: f64 = 0.5;
+
+// 子进程启动后不做CPU停滞检测的时间，避免把初始化阶段的等待误判为停滞
+const CPU_STALL_STARTUP_GRACE: Duration = Duration::from_secs(60);
+
+/// CPU停滞看门狗：子进程树在整个检测窗口内的CPU使用率都低于阈值时判定为卡死
+struct CpuWatchdog {
+    timeout: Duration,
+    threshold_percent: f64,
+    started: Instant,
+    /// 当前检测窗口的开始时间和当时的累计CPU时间（100纳秒单位）
+    window: Option<(Instant, u64)>,
+}
+
+impl CpuWatchdog {
+    fn new(timeout: Duration, threshold_percent: f64, started: Instant) -> Self {
+        Self { timeout, threshold_percent, started, window: None }
+    }
+
+    /// 记录一次累计CPU时间采样，判定为停滞时返回 `true`
+    fn poll(&mut self, cpu_time: u64, now: Instant) -> bool {
+        if now.duration_since(self.started) < CPU_STALL_STARTUP_GRACE {
+            return false;
+        }
+
+        let Some((window_start, window_cpu)) = self.window else {
+            self.window = Some((now, cpu_time));
+            return false;
+        };
+
+        let elapsed = now.duration_since(window_start);
+        if elapsed < self.timeout {
+            return false;
+        }
+
+        let used = Duration::from_nanos(cpu_time.saturating_sub(window_cpu).saturating_mul(100));
+        let percent = used.as_secs_f64() / elapsed.as_secs_f64() * 100.0;
+        if percent < self.threshold_percent {
+            return true;
+        }
+
+        // 窗口内有足够的CPU活动，开始下一个窗口
+        self.window = Some((now, cpu_time));
+        false
+    }
+}
+
+// 健康检查单次连接的最长等待时间（不超过检查间隔）
+const HEALTH_CHECK_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 健康检查：按间隔连接子进程监听的端口或请求其HTTP接口，连续失败达到阈值时判定为不健康
+struct HealthMonitor {
+    config: HealthCheckConfig,
+    next_check: Instant,
+    failures: u32,
+    successes: u32,
+}
+
+impl HealthMonitor {
+    /// 子进程启动一个检查间隔后进行第一次检查
+    fn new(config: HealthCheckConfig, now: Instant) -> Self {
+        Self { next_check: now + config.interval(), config, failures: 0, successes: 0 }
+    }
+
+    /// 是否到了下一次检查的时间
+    fn due(&self, now: Instant) -> bool {
+        now >= self.next_check
+    }
+
+    /// 清零计数，一个检查间隔后重新开始检查
+    fn reset(&mut self, now: Instant) {
+        self.next_check = now + self.config.interval();
+        self.failures = 0;
+        self.successes = 0;
+    }
+
+    /// 记录一次检查结果，判定为不健康时返回 `true`
+    fn record(&mut self, healthy: bool, now: Instant) -> bool {
+        self.next_check = now + self.config.interval();
+        if healthy {
+            self.successes += 1;
+            if self.successes >= self.config.success_threshold {
+                self.failures = 0;
+            }
+            return false;
+        }
+
+        self.successes = 0;
+        self.failures += 1;
+        self.failures >= self.config.failure_threshold
+    }
+
+    /// 执行一次检查，失败时返回原因
+    fn check(&self) -> Result<()> {
+        match &self.config.target {
+            HealthCheckTarget::Tcp { port } => {
+                let address = std::net::SocketAddr::from(([127, 0, 0, 1], *port));
+                let timeout = HEALTH_CHECK_CONNECT_TIMEOUT.min(self.config.interval());
+                std::net::TcpStream::connect_timeout(&address, timeout)
+                    .map(|_| ())
+                    .context(format!("cannot connect to {}", address))
+            }
+            HealthCheckTarget::Http { url, expected_status, timeout_ms, body_contains } => probe::check_http(
+                url,
+                *expected_status,
+                body_contains.as_deref(),
+                Duration::from_millis(*timeout_ms),
+            ),
+        }
+    }
+}
+
+/// 子进程树的累计CPU时间（100纳秒单位）
+///
+/// 有作业对象时统计作业内所有进程（包括已退出的），避免包装脚本自身空闲导致误判；
+/// 否则只统计直接子进程。
+fn process_tree_cpu_time(child: &Child, job: Option<&JobObject>) -> Option<u64> {
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::Threading::GetProcessTimes;
+
+    if let Some(job) = job {
+        let mut info: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            QueryInformationJobObject(
+                job.handle,
+                JobObjectBasicAccountingInformation,
+                &mut info as *mut _ as *mut _,
+                std::mem::size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as u32,
+                std::ptr::null_mut(),
+            )
+        };
+        if result != 0 {
+            return Some((info.TotalUserTime + info.TotalKernelTime) as u64);
+        }
+    }
+
+    let mut times: [FILETIME; 4] = unsafe { std::mem::zeroed() };
+    let [creation, exit, kernel, user] = &mut times;
+    if unsafe { GetProcessTimes(child.as_raw_handle() as HANDLE, creation, exit, kernel, user) } == 0 {
+        return None;
+    }
+
+    let to_u64 = |time: &FILETIME| ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64;
+    Some(to_u64(kernel) + to_u64(user))
+}
+
+/// 目标可执行文件指纹（大小 + 修改时间）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BinaryFingerprint {
+    size: u64,
+    modified_nanos: u128,
+}
+
+impl BinaryFingerprint {
+    /// 读取文件当前指纹，文件不存在或被占用时返回 `None`
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?;
+        let modified_nanos = modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_nanos();
+        Some(Self { size: metadata.len(), modified_nanos })
+    }
+
+    /// 序列化为注册表字符串
+    fn to_registry_string(self) -> String {
+        format!("{}:{}", self.size, self.modified_nanos)
+    }
+
+    /// 从注册表字符串解析
+    fn parse(value: &str) -> Option<Self> {
+        let (size, modified) = value.trim().split_once(':')?;
+        Some(Self {
+            size: size.parse().ok()?,
+            modified_nanos: modified.parse().ok()?,
+        })
+    }
+}
+
+/// 目标程序更新检查器 - 按间隔比较文件指纹，变化且稳定后才报告
+struct BinaryWatcher {
+    path: PathBuf,
+    interval: Duration,
+    stable_period: Duration,
+    baseline: Option<BinaryFingerprint>,
+    last_check: Instant,
+    pending: Option<(BinaryFingerprint, Instant)>,
+}
+
+impl BinaryWatcher {
+    fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            stable_period: BINARY_STABLE_PERIOD,
+            baseline: None,
+            last_check: Instant::now(),
+            pending: None,
+        }
+    }
+
+    /// 以当前文件状态作为基线（子进程启动时调用）
+    fn reset(&mut self, baseline: Option<BinaryFingerprint>) {
+        self.baseline = baseline;
+        self.pending = None;
+        self.last_check = Instant::now();
+    }
+
+    /// 检查文件是否已更新，返回新的指纹
+    ///
+    /// 检测到变化后改为每次调用都检查，直到文件在 `stable_period` 内保持不变。
+    fn poll(&mut self) -> Option<BinaryFingerprint> {
+        let now = Instant::now();
+        if self.pending.is_none() && now.duration_since(self.last_check) < self.interval {
+            return None;
+        }
+        self.last_check = now;
+
+        // 替换过程中文件可能暂时不存在
+        let current = match BinaryFingerprint::of(&self.path) {
+            Some(current) => current,
+            None => {
+                self.pending = None;
+                return None;
+            }
+        };
+
+        if Some(current) == self.baseline {
+            self.pending = None;
+            return None;
+        }
+
+        match self.pending {
+            Some((fingerprint, since)) if fingerprint == current => {
+                if now.duration_since(since) >= self.stable_period {
+                    self.pending = None;
+                    Some(current)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.pending = Some((current, now));
+                None
+            }
+        }
+    }
+}
+
+/// 记录本次启动的目标程序指纹，宿主重启后据此判断程序是否在停机期间被替换
+fn record_binary_baseline(service_name: &str, fingerprint: Option<BinaryFingerprint>) {
+    let Some(fingerprint) = fingerprint else {
+        return;
+    };
+
+    if let Ok(previous) = load_binary_baseline(service_name) {
+        if previous != fingerprint {
+            info!("Target executable was replaced since the last launch");
+        }
+    }
+
+    if let Err(e) = service_manager::write_parameter_string(service_name, "BinaryFingerprint", &fingerprint.to_registry_string()) {
+        warn!("Failed to persist target executable fingerprint: {}", e);
+    }
+}
+
+/// 读取上次启动时记录的目标程序指纹
+fn load_binary_baseline(service_name: &str) -> Result<BinaryFingerprint> {
+    let key_path = service_manager::parameters_key_path(service_name)?;
+    let key_path_w = key_path.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+
+    let mut hkey = HKEY::default();
+    let result = unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr(), 0, KEY_READ, &mut hkey) };
+    if result != ERROR_SUCCESS {
+        return Err(anyhow::anyhow!("Failed to open registry key"));
+    }
+
+    let value = read_reg_string(hkey, "BinaryFingerprint");
+    unsafe { RegCloseKey(hkey); }
+
+    BinaryFingerprint::parse(&value?).ok_or_else(|| anyhow::anyhow!("Invalid binary fingerprint"))
+}
+
+/// 管理子进程的函数，返回管理结束的原因
 fn manage_child_process(
-    service_name: &str,
-    executable_path: &PathBuf,
-    arguments: &[String],
-    working_directory: &Option<PathBuf>,
-    stdout_path: &Option<PathBuf>,
-    stderr_path: &Option<PathBuf>,
+    config: &ServiceConfig,
     stop_requested: &Arc<Mutex<bool>>,
-) {
+    paused: &Arc<AtomicBool>,
+    runtime_status: &Arc<Mutex<RuntimeStatus>>,
+    output_hub: &Arc<OutputHub>,
+    child_input: &ChildInput,
+) -> StopReason {
     let mut attempt = 0u32;
-    const MAX_ATTEMPTS: u32 = 5;
-    const INITIAL_DELAY: u64 = 2;
+    let mut launched = false;
+
+    let mut restart_log = config.quiet_restart_logging.then(|| {
+        RestartLogLimiter::new(
+            config.restart_log_burst.unwrap_or(DEFAULT_RESTART_LOG_BURST) as usize,
+            config.restart_log_window.map_or(DEFAULT_RESTART_LOG_WINDOW, Duration::from_secs),
+        )
+    });
+    // 本轮重启相关的日志是否逐条输出
+    let mut log_restart = true;
+
+    let restart_patterns = compile_restart_patterns(&config.restart_patterns);
+
+    // 连续重启次数：子进程稳定运行超过 stable_window 后清零
+    let max_restarts = config.max_restarts.filter(|max| *max > 0);
+    let stable_window = config.stable_window.map_or(DEFAULT_STABLE_WINDOW, Duration::from_secs);
+    let mut consecutive_restarts = 0u32;
+    // 未稳定运行就退出的连续次数，决定重启前的退避时间（只在指定了 stable_window 时启用）
+    let mut unstable_exits = 0u32;
+
+    let mut restart_rate = config.max_restarts_per_window.map(|max_restarts| {
+        let window = config.restart_rate_window.map_or(DEFAULT_RESTART_RATE_WINDOW, Duration::from_secs);
+        (max_restarts, RollingWindow::new(window))
+    });
+
+    // 服务启动时总是先尝试主程序
+    let fallback = fallback_config(config);
+    let mut using_fallback = false;
+    let mut last_primary_check = Instant::now();
+    if fallback.is_some() {
+        record_active_executable(&config.name, false);
+    }
+
+    let mut binary_watcher = config
+        .update_check_interval
+        .map(|secs| BinaryWatcher::new(config.executable_path.clone(), Duration::from_secs(secs)));
+
+    let event_log = EventLog::open(&config.name, config.event_log_enabled);
 
     loop {
         // 检查是否收到停止请求
         if let Ok(stop) = stop_requested.lock() {
             if *stop {
                 info!("Stop requested, exiting child process manager");
-                break;
+                return StopReason::Requested;
+            }
+        }
+
+        // 自动重启只在允许的时间段内进行，服务刚启动时的首次启动不受限制
+        if launched {
+            if let Some(window) = &config.restart_window {
+                if !wait_for_restart_window(window, stop_requested, runtime_status) {
+                    info!("Stop requested while waiting for restart window");
+                    return StopReason::Requested;
+                }
             }
         }
 
+        if launched || attempt > 0 {
+            if max_restarts.is_some_and(|max| consecutive_restarts >= max) {
+                error!("Child process restarted {} times in a row without stabilizing, giving up", consecutive_restarts);
+                return StopReason::MaxRestartsReached { restarts: consecutive_restarts };
+            }
+            consecutive_restarts += 1;
+
+            // 重启频率超过上限时放弃，与连续启动失败的次数上限相互独立
+            if let Some((max_restarts, restarts)) = restart_rate.as_mut() {
+                let count = restarts.record(Instant::now()) as u32;
+                if count > *max_restarts {
+                    let window_secs = restarts.window.as_secs();
+                    error!(
+                        "Restart rate limit exceeded: {} restarts in the last {}s (limit {}), giving up",
+                        count, window_secs, max_restarts
+                    );
+                    return StopReason::RestartRateExceeded { restarts: count, window_secs };
+                }
+            }
+
+            if log_restart {
+                event_log.info(EVENT_RESTART_ATTEMPT, &format!("Restarting child process (restart {} in a row)", consecutive_restarts));
+            }
+        }
+
+        // 服务暂停期间不启动新的子进程
+        if !wait_while_paused(paused, stop_requested) {
+            info!("Stop requested while paused");
+            return StopReason::Requested;
+        }
+
+        let active = match &fallback {
+            Some(fallback) if using_fallback => fallback,
+            _ => config,
+        };
+
         // 尝试启动子进程
-        match start_child_process_once(service_name, executable_path, arguments, working_directory, stdout_path, stderr_path) {
-            Ok(mut child) => {
+        match start_child_process_once(active, output_hub, child_input, restart_patterns.as_ref()) {
+            Ok((mut child, job, output_matched, pid_file)) => {
                 attempt = 0; // 重置尝试计数
+                let started_at = Instant::now();
+
+                if log_restart {
+                    info!("Started child process with PID: {}", child.id());
+                    event_log.info(EVENT_CHILD_STARTED, &format!("Started child process with PID: {}", child.id()));
+                }
+
+                if let Ok(mut status) = runtime_status.lock() {
+                    status.record_start(child.id(), launched);
+                }
+                launched = true;
+
+                if let Some(watcher) = binary_watcher.as_mut() {
+                    let fingerprint = BinaryFingerprint::of(&config.executable_path);
+                    watcher.reset(fingerprint);
+                    record_binary_baseline(&config.name, fingerprint);
+                }
+
+                let mut cpu_watchdog = config.cpu_stall_timeout.map(|secs| {
+                    CpuWatchdog::new(
+                        Duration::from_secs(secs),
+                        config.cpu_stall_threshold.unwrap_or(DEFAULT_CPU_STALL_THRESHOLD),
+                        Instant::now(),
+                    )
+                });
+                let mut health_monitor = config.health_check.clone().map(|health_check| HealthMonitor::new(health_check, Instant::now()));
 
                 // 等待子进程退出
                 loop {
                     match child.try_wait() {
                         Ok(Some(status)) => {
-                            info!("Child process exited with status: {}", status);
-                            break;
+                            log_restart = restart_log.as_mut().is_none_or(|limiter| limiter.record(Instant::now()));
+                            if log_restart {
+                                info!("Child process exited with status: {}", status);
+                                let message = format!("Child process exited with status: {}", status);
+                                if status.success() {
+                                    event_log.info(EVENT_CHILD_STOPPED, &message);
+                                } else {
+                                    event_log.error(EVENT_CHILD_CRASHED, &message);
+                                }
+                            }
+                            if let Ok(mut runtime) = runtime_status.lock() {
+                                runtime.record_exit(status.code());
+                            }
+                            // 按退出码决定重启子进程还是停止服务
+                            let failed = match config.exit_actions.action_for(status.code()) {
+                                ExitAction::Restart => break,
+                                ExitAction::Stop => false,
+                                ExitAction::StopService => true,
+                            };
+                            let reason = StopReason::ChildExited { exit_code: status.code(), failed };
+                            if failed {
+                                error!("Service {}", reason);
+                            } else {
+                                info!("Service {}", reason);
+                            }
+                            return reason;
                         }
                         Ok(None) => {
                             // 进程仍在运行，检查停止信号
                             if let Ok(stop) = stop_requested.lock() {
                                 if *stop {
-                                    info!("Stop requested, killing child process");
-                                    let _ = child.kill();
-                                    let _ = child.wait();
-                                    return;
+                                    info!("Stop requested, stopping child process");
+                                    stop_child_gracefully(&mut child, config);
+                                    event_log.info(EVENT_CHILD_STOPPED, "Child process stopped because the service is stopping");
+                                    if config.wait_for_tree {
+                                        if let Some(job) = &job {
+                                            wait_for_process_tree(job);
+                                        }
+                                    }
+                                    // job 在此处被释放，作业中残留的子孙进程随之终止
+                                    return StopReason::Requested;
+                                }
+                            }
+
+                            // 暂停期间子进程树被挂起，跳过各项检查；CPU停滞检测和健康检查在继续运行后重新开始计时
+                            if paused.load(Ordering::SeqCst) {
+                                if let Some(watchdog) = cpu_watchdog.as_mut() {
+                                    watchdog.window = None;
+                                }
+                                if let Some(monitor) = health_monitor.as_mut() {
+                                    monitor.reset(Instant::now());
+                                }
+                                std::thread::sleep(std::time::Duration::from_secs(1));
+                                continue;
+                            }
+
+                            // 运行备用程序期间，主程序可以启动时切换回主程序
+                            if using_fallback && config.prefer_primary && last_primary_check.elapsed() >= PRIMARY_RECHECK_INTERVAL {
+                                last_primary_check = Instant::now();
+                                if primary_launchable(config) {
+                                    info!("Primary executable is launchable again, switching back from fallback");
+                                    let exit_code = reap_child(&mut child).and_then(|status| status.code());
+                                    if let Ok(mut runtime) = runtime_status.lock() {
+                                        runtime.record_exit(exit_code);
+                                    }
+                                    using_fallback = false;
+                                    record_active_executable(&config.name, false);
+                                    break;
                                 }
                             }
+
+                            // 检查目标程序是否已在磁盘上被替换（运行备用程序时不检查）
+                            if let Some(watcher) = binary_watcher.as_mut().filter(|_| !using_fallback) {
+                                if let Some(fingerprint) = watcher.poll() {
+                                    if config.restart_on_binary_change {
+                                        info!("Target executable changed on disk, restarting child process");
+                                        let exit_code = stop_child_gracefully(&mut child, config).and_then(|status| status.code());
+                                        if let Ok(mut runtime) = runtime_status.lock() {
+                                            runtime.record_exit(exit_code);
+                                        }
+                                        break;
+                                    }
+                                    warn!("Target executable changed on disk; restart the service to run the new version");
+                                    watcher.reset(Some(fingerprint));
+                                }
+                            }
+
+                            // 检查子进程输出是否匹配重启规则
+                            if output_matched.load(Ordering::Relaxed) {
+                                warn!("Child output matched a restart pattern, restarting child process");
+                                let exit_code = stop_child_gracefully(&mut child, config).and_then(|status| status.code());
+                                if let Ok(mut runtime) = runtime_status.lock() {
+                                    runtime.record_exit(exit_code);
+                                }
+                                break;
+                            }
+
+                            // 检查子进程树的CPU使用是否停滞
+                            if let Some(watchdog) = cpu_watchdog.as_mut() {
+                                if let Some(cpu_time) = process_tree_cpu_time(&child, job.as_ref()) {
+                                    if watchdog.poll(cpu_time, Instant::now()) {
+                                        warn!(
+                                            "Child process used less than {}% CPU for {:?}, treating it as hung and restarting",
+                                            watchdog.threshold_percent, watchdog.timeout
+                                        );
+                                        let exit_code = stop_child_gracefully(&mut child, config).and_then(|status| status.code());
+                                        if let Ok(mut runtime) = runtime_status.lock() {
+                                            runtime.record_exit(exit_code);
+                                        }
+                                        break;
+                                    }
+                                }
+                            }
+
+                            // 检查子进程是否仍在受理连接
+                            if let Some(monitor) = health_monitor.as_mut().filter(|monitor| monitor.due(Instant::now())) {
+                                let result = monitor.check();
+                                if let Err(e) = &result {
+                                    warn!(
+                                        "Health check {} failed: {:#} ({}/{})",
+                                        monitor.config.target, e, monitor.failures + 1, monitor.config.failure_threshold
+                                    );
+                                }
+                                let healthy = result.is_ok();
+                                if monitor.record(healthy, Instant::now()) {
+                                    warn!("Child process failed {} consecutive health checks, restarting", monitor.failures);
+                                    let exit_code = stop_child_gracefully(&mut child, config).and_then(|status| status.code());
+                                    if let Ok(mut runtime) = runtime_status.lock() {
+                                        runtime.record_exit(exit_code);
+                                    }
+                                    break;
+                                }
+                            }
+
                             std::thread::sleep(std::time::Duration::from_secs(1));
                         }
                         Err(e) => {
+                            // 无法查询状态时先结束并回收子进程，避免与新启动的子进程同时运行
                             error!("Error waiting for child process: {}", e);
+                            let exit_code = reap_child(&mut child).and_then(|status| status.code());
+                            if let Ok(mut runtime) = runtime_status.lock() {
+                                runtime.record_exit(exit_code);
+                            }
                             break;
                         }
                     }
                 }
+
+                drop(pid_file);
+                let uptime = started_at.elapsed();
+                if uptime >= stable_window {
+                    consecutive_restarts = 0;
+                    unstable_exits = 0;
+                } else if config.stable_window.is_some() {
+                    // 类似NSSM的 AppThrottle：频繁退出的子进程不会每隔 restart_delay 就被重启一次
+                    unstable_exits += 1;
+                    let delay = start_retry_delay(config, unstable_exits);
+                    if log_restart {
+                        info!(
+                            "Child process ran for only {}s (stable window {}s), delaying restart by {}s",
+                            uptime.as_secs(), stable_window.as_secs(), delay.as_secs()
+                        );
+                    }
+                    if !sleep_unless_stopped(delay, stop_requested) {
+                        info!("Stop requested, exiting child process manager");
+                        return StopReason::Requested;
+                    }
+                }
             }
             Err(e) => {
-                error!("Failed to start child process: {}", e);
                 attempt += 1;
 
-                if attempt >= MAX_ATTEMPTS {
+                if max_start_attempts(config).is_some_and(|max| attempt >= max) {
+                    error!("Failed to start child process: {}", e);
+
+                    // 主程序无法启动时改为启动备用程序，而不是放弃
+                    if let Some(fallback) = fallback.as_ref().filter(|_| !using_fallback) {
+                        warn!(
+                            "Primary executable failed to start {} times, switching to fallback {:?}",
+                            attempt, fallback.executable_path
+                        );
+                        using_fallback = true;
+                        attempt = 0;
+                        last_primary_check = Instant::now();
+                        record_active_executable(&config.name, true);
+                        continue;
+                    }
+
                     error!("Max attempts reached, giving up");
-                    break;
+                    return StopReason::RestartsExhausted { attempts: attempt };
                 }
 
-                // 指数退避
-                let delay = INITIAL_DELAY * u64::pow(2, attempt.min(8)); // 最多256秒
-                info!("Retrying in {} seconds (attempt {}/{})", delay, attempt, MAX_ATTEMPTS);
-                std::thread::sleep(std::time::Duration::from_secs(delay));
+                log_restart = restart_log.as_mut().is_none_or(|limiter| limiter.record(Instant::now()));
+                let delay = next_start_delay(config, Some(attempt));
+                if log_restart {
+                    error!("Failed to start child process: {}", e);
+                    match max_start_attempts(config) {
+                        Some(max) => info!("Retrying in {} seconds (attempt {}/{})", delay.as_secs(), attempt, max),
+                        None => info!("Retrying in {} seconds (attempt {})", delay.as_secs(), attempt),
+                    }
+                }
+                // 退避时间可能长达数分钟，等待期间仍及时响应停止请求
+                if !sleep_unless_stopped(delay, stop_requested) {
+                    info!("Stop requested, exiting child process manager");
+                    return StopReason::Requested;
+                }
+                continue;
             }
         }
 
-        // 在下次尝试前等待一下
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        // 子进程退出后在重新启动前等待一下，较长的等待期间仍及时响应停止请求
+        if !sleep_unless_stopped(next_start_delay(config, None) + restart_jitter(config), stop_requested) {
+            info!("Stop requested, exiting child process manager");
+            return StopReason::Requested;
+        }
+    }
+}
+
+/// 编译输出重启规则，没有规则或规则无效时返回 `None`
+fn compile_restart_patterns(patterns: &[String]) -> Option<Arc<RegexSet>> {
+    if patterns.is_empty() {
+        return None;
+    }
+    match RegexSet::new(patterns) {
+        Ok(set) => Some(Arc::new(set)),
+        Err(e) => {
+            error!("Ignoring invalid restart patterns: {}", e);
+            None
+        }
+    }
+}
+
+/// 重启频率上限的默认时间窗口
+pub(crate) const DEFAULT_RESTART_RATE_WINDOW: Duration = Duration::from_secs(300);
+
+/// 运行备用程序期间检查主程序能否启动的间隔
+const PRIMARY_RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 用备用程序替换主程序后的配置，未配置备用程序时返回 `None`
+fn fallback_config(config: &ServiceConfig) -> Option<ServiceConfig> {
+    let executable = config.fallback_executable.as_ref()?;
+    Some(ServiceConfig {
+        executable_path: executable.clone(),
+        arguments: config.fallback_arguments.clone(),
+        ..config.clone()
+    })
+}
+
+/// 判断主程序能否再次启动：文件存在且通过签名校验
+fn primary_launchable(config: &ServiceConfig) -> bool {
+    config.executable_path.is_file() && signature::enforce(config).is_ok()
+}
+
+/// 把当前运行的是主程序还是备用程序记录到注册表，供 `status` 显示
+fn record_active_executable(service_name: &str, fallback: bool) {
+    let value = if fallback { "fallback" } else { "primary" };
+    if let Err(e) = service_manager::write_parameter_string(service_name, "ActiveExecutable", value) {
+        warn!("Failed to record active executable: {}", e);
+    }
+}
+
+/// 重启日志限流默认在时间窗口内逐条记录的重启次数
+const DEFAULT_RESTART_LOG_BURST: u32 = 5;
+
+/// 重启日志限流的默认时间窗口
+const DEFAULT_RESTART_LOG_WINDOW: Duration = Duration::from_secs(60);
+
+/// 滑动时间窗口内的事件计数
+struct RollingWindow {
+    window: Duration,
+    events: VecDeque<Instant>,
+}
+
+impl RollingWindow {
+    fn new(window: Duration) -> Self {
+        Self { window, events: VecDeque::new() }
+    }
+
+    /// 记录一次事件，返回窗口内（含本次）的事件数
+    fn record(&mut self, now: Instant) -> usize {
+        self.events.push_back(now);
+        while self.events.front().is_some_and(|&event| now.duration_since(event) > self.window) {
+            self.events.pop_front();
+        }
+        self.events.len()
     }
 }
 
+/// 重启日志限流器：时间窗口内的重启次数超过阈值后不再逐条记录，改为每个窗口输出一次摘要
+struct RestartLogLimiter {
+    burst: usize,
+    restarts: RollingWindow,
+    suppressed: u32,
+    last_summary: Option<Instant>,
+}
+
+impl RestartLogLimiter {
+    fn new(burst: usize, window: Duration) -> Self {
+        Self { burst, restarts: RollingWindow::new(window), suppressed: 0, last_summary: None }
+    }
+
+    /// 记录一次重启，返回这次重启的日志是否应逐条输出
+    fn record(&mut self, now: Instant) -> bool {
+        let restarts = self.restarts.record(now);
+        let window = self.restarts.window;
+
+        if restarts <= self.burst {
+            if self.suppressed > 0 {
+                info!(
+                    "Child process restart rate is back to normal ({} restart(s) were not logged individually)",
+                    self.suppressed
+                );
+                self.suppressed = 0;
+                self.last_summary = None;
+            }
+            return true;
+        }
+
+        self.suppressed += 1;
+        if self.last_summary.is_none_or(|last| now.duration_since(last) >= window) {
+            warn!(
+                "Child process still flapping: {} restarts in the last {}s, individual restart messages suppressed",
+                restarts,
+                window.as_secs()
+            );
+            self.last_summary = Some(now);
+        }
+        false
+    }
+}
+
+/// 子进程连续启动失败的最大次数，达到后服务宿主以失败状态退出（默认值）
+pub(crate) const MAX_START_ATTEMPTS: u32 = 5;
+
+/// 启动失败重试等待时间的默认基数，第N次失败后等待 基数×2^N
+pub(crate) const START_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// 启动失败重试等待时间的默认上限
+pub(crate) const START_RETRY_DELAY_MAX: Duration = Duration::from_secs(512);
+
+/// 子进程持续运行超过该时间后视为稳定，连续重启次数清零（默认值）
+pub(crate) const DEFAULT_STABLE_WINDOW: Duration = Duration::from_secs(60);
+
+/// 子进程退出后重新启动前的默认等待时间
+pub(crate) const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// 停止服务时等待子进程自行退出的默认时间
+pub(crate) const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 等待子进程自行退出时的检查间隔
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 停止服务时是否先向子进程发送 CTRL_BREAK（脱离控制台的子进程收不到控制台事件）
+pub(crate) fn console_stop(config: &ServiceConfig) -> bool {
+    config.stop_method == StopMethod::Console && !config.detached
+}
+
+/// 停止服务时等待子进程自行退出的时间，0 表示直接结束
+pub(crate) fn stop_timeout(config: &ServiceConfig) -> Duration {
+    config.stop_timeout.map_or(DEFAULT_STOP_TIMEOUT, Duration::from_secs)
+}
+
+/// 子进程退出后重新启动前的等待时间
+pub(crate) fn restart_delay(config: &ServiceConfig) -> Duration {
+    config.restart_delay.map_or(RESTART_DELAY, Duration::from_secs)
+}
+
+/// 下次启动子进程前的等待时间
+///
+/// 第 `failed_attempt` 次启动失败后只按 `start_retry_delay` 退避，子进程退出后才等待 `restart_delay`，两者不叠加。
+pub(crate) fn next_start_delay(config: &ServiceConfig, failed_attempt: Option<u32>) -> Duration {
+    match failed_attempt {
+        Some(attempt) => start_retry_delay(config, attempt),
+        None => restart_delay(config),
+    }
+}
+
+/// 子进程退出后本次重启额外等待的随机时间，均匀分布于 `[0, restart_jitter)`；启动失败的重试不使用
+///
+/// 只用于错开同时崩溃的多个服务，不需要密码学强度的随机数，使用标准库 `RandomState` 的随机种子即可。
+pub(crate) fn restart_jitter(config: &ServiceConfig) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+
+    let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    jitter(config.restart_jitter.map_or(Duration::ZERO, Duration::from_secs), random)
+}
+
+/// 把随机数映射到 `[0, max)` 内的毫秒数
+fn jitter(max: Duration, random: u64) -> Duration {
+    let max_millis = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    if max_millis == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(random % max_millis)
+}
+
+/// 等待指定时间，期间收到停止请求时提前返回 `false`
+fn sleep_unless_stopped(duration: Duration, stop_requested: &Arc<Mutex<bool>>) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if stop_requested.lock().map(|stop| *stop).unwrap_or(false) {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        std::thread::sleep(remaining.min(Duration::from_millis(200)));
+    }
+}
+
+/// 连续启动失败的次数上限，`None` 表示不限制
+pub(crate) fn max_start_attempts(config: &ServiceConfig) -> Option<u32> {
+    match config.max_start_attempts {
+        Some(0) => None,
+        Some(attempts) => Some(attempts),
+        None => Some(MAX_START_ATTEMPTS),
+    }
+}
+
+/// 第 `attempt` 次启动失败后的重试等待时间（指数退避，不超过上限）
+pub(crate) fn start_retry_delay(config: &ServiceConfig, attempt: u32) -> Duration {
+    let initial = config.start_retry_delay.map_or(START_RETRY_DELAY, Duration::from_secs);
+    let max = config.start_retry_delay_max.map_or(START_RETRY_DELAY_MAX, Duration::from_secs);
+    initial.saturating_mul(1 << attempt.min(31)).min(max)
+}
+
+// 宿主退出时保存到注册表的退出记录条数
+const PERSISTED_EXIT_HISTORY: usize = 10;
+
+/// 读取上次宿主退出时保存的子进程退出记录
+fn load_exit_history(service_name: &str) -> VecDeque<ExitRecord> {
+    service_manager::read_parameter_string(service_name, "ExitHistory")
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// 保存最近的子进程退出记录，使其在宿主重启后仍可查看
+fn save_exit_history(service_name: &str, runtime_status: &Mutex<RuntimeStatus>) {
+    let Ok(runtime) = runtime_status.lock() else {
+        return;
+    };
+
+    let skip = runtime.exit_history.len().saturating_sub(PERSISTED_EXIT_HISTORY);
+    let trimmed: Vec<&ExitRecord> = runtime.exit_history.iter().skip(skip).collect();
+    let result = serde_json::to_string(&trimmed)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| service_manager::write_parameter_string(service_name, "ExitHistory", &json));
+    if let Err(e) = result {
+        warn!("Failed to save exit history: {}", e);
+    }
+}
+
+/// 等待进入允许重启的时间段，期间收到停止请求时返回 `false`
+fn wait_for_restart_window(
+    window: &RestartWindow,
+    stop_requested: &Arc<Mutex<bool>>,
+    runtime_status: &Arc<Mutex<RuntimeStatus>>,
+) -> bool {
+    if window.contains(chrono::Local::now().time()) {
+        return true;
+    }
+
+    info!("Outside restart window {}, child process will be restarted at {}", window, window.start_time().format("%H:%M"));
+    if let Ok(mut runtime) = runtime_status.lock() {
+        runtime.waiting_for_restart_window = true;
+    }
+
+    loop {
+        if stop_requested.lock().map(|stop| *stop).unwrap_or(false) {
+            return false;
+        }
+        if window.contains(chrono::Local::now().time()) {
+            info!("Restart window {} opened, restarting child process", window);
+            return true;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// 服务暂停时等待继续，期间收到停止请求时返回 `false`
+fn wait_while_paused(paused: &AtomicBool, stop_requested: &Arc<Mutex<bool>>) -> bool {
+    while paused.load(Ordering::SeqCst) {
+        if stop_requested.lock().map(|stop| *stop).unwrap_or(false) {
+            return false;
+        }
+        std::thread::sleep(STOP_POLL_INTERVAL);
+    }
+    true
+}
+
+/// 挂起或恢复进程及其所有子孙进程的全部线程，返回处理的线程数
+///
+/// 通过 Toolhelp 快照找出进程树和其中的线程；快照之后才创建的线程不受影响。
+fn set_process_tree_suspended(pid: u32, suspend: bool) -> Result<usize> {
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, SuspendThread, THREAD_SUSPEND_RESUME};
+
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS | TH32CS_SNAPTHREAD, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Err(anyhow::anyhow!("Failed to create process snapshot: error {}", unsafe { GetLastError() }));
+    }
+
+    let mut processes = Vec::new();
+    let mut process: PROCESSENTRY32W = unsafe { std::mem::zeroed() };
+    process.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+    let mut more = unsafe { Process32FirstW(snapshot, &mut process) } != 0;
+    while more {
+        processes.push((process.th32ProcessID, process.th32ParentProcessID));
+        more = unsafe { Process32NextW(snapshot, &mut process) } != 0;
+    }
+    let tree = process_tree(pid, &processes);
+
+    let mut threads = 0;
+    let mut thread: THREADENTRY32 = unsafe { std::mem::zeroed() };
+    thread.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+    let mut more = unsafe { Thread32First(snapshot, &mut thread) } != 0;
+    while more {
+        if tree.contains(&thread.th32OwnerProcessID) {
+            // 线程可能已在快照之后退出，打开失败时跳过
+            let handle = unsafe { OpenThread(THREAD_SUSPEND_RESUME, 0, thread.th32ThreadID) };
+            if handle != 0 {
+                let previous = unsafe { if suspend { SuspendThread(handle) } else { ResumeThread(handle) } };
+                if previous != u32::MAX {
+                    threads += 1;
+                }
+                unsafe { CloseHandle(handle) };
+            }
+        }
+        more = unsafe { Thread32Next(snapshot, &mut thread) } != 0;
+    }
+
+    unsafe { CloseHandle(snapshot) };
+
+    if threads == 0 {
+        return Err(anyhow::anyhow!("No threads of process {} could be {}", pid, if suspend { "suspended" } else { "resumed" }));
+    }
+    Ok(threads)
+}
+
+/// 从 `(进程ID, 父进程ID)` 列表中找出 `root` 及其所有子孙进程
+fn process_tree(root: u32, processes: &[(u32, u32)]) -> Vec<u32> {
+    let mut tree = vec![root];
+    let mut index = 0;
+    while index < tree.len() {
+        let parent = tree[index];
+        for &(pid, parent_pid) in processes {
+            // 进程ID可能被复用，已在树中的进程不重复加入，避免形成环
+            if parent_pid == parent && !tree.contains(&pid) {
+                tree.push(pid);
+            }
+        }
+        index += 1;
+    }
+    tree
+}
+
+/// 停止时给子孙进程留出自行退出的时间
+fn wait_for_process_tree(job: &JobObject) {
+    info!("Waiting up to {:?} for the rest of the process tree to exit", TREE_STOP_TIMEOUT);
+    let survivors = job.wait_until_empty(TREE_STOP_TIMEOUT);
+    if survivors.is_empty() {
+        info!("Process tree exited cleanly");
+    } else {
+        warn!("Terminating {} process(es) still running after stop timeout: {:?}", survivors.len(), survivors);
+    }
+}
+
+/// 确保宿主拥有控制台，子进程共享该控制台
+///
+/// 服务进程默认没有控制台，此时分配一个（不可见的）控制台。
+fn ensure_console() -> Result<()> {
+    use windows_sys::Win32::System::Console::{AllocConsole, GetConsoleCP};
+
+    unsafe {
+        if GetConsoleCP() == 0 && AllocConsole() == 0 {
+            return Err(anyhow::anyhow!("Failed to allocate console: error {}", GetLastError()));
+        }
+    }
+
+    Ok(())
+}
+
+/// 设置宿主控制台的代码页，子进程共享宿主的控制台并继承该设置
+fn set_console_codepage(codepage: u32) -> Result<()> {
+    use windows_sys::Win32::System::Console::{SetConsoleCP, SetConsoleOutputCP};
+
+    ensure_console()?;
+
+    unsafe {
+        if SetConsoleCP(codepage) == 0 || SetConsoleOutputCP(codepage) == 0 {
+            return Err(anyhow::anyhow!("Failed to set console code page: error {}", GetLastError()));
+        }
+    }
+
+    Ok(())
+}
+
+/// 结束并回收子进程，确保进程句柄和内核对象被释放
+///
+/// 子进程已退出时 `kill` 会失败，`wait` 仍会立即返回其退出状态。
+fn reap_child(child: &mut Child) -> Option<std::process::ExitStatus> {
+    let _ = child.kill();
+    match child.wait() {
+        Ok(status) => Some(status),
+        Err(e) => {
+            error!("Failed to wait for child process {}: {}", child.id(), e);
+            None
+        }
+    }
+}
+
+/// 按停止方式结束子进程：`console` 先发送 CTRL_BREAK 请求子进程自行退出，超时后再强制结束并回收
+///
+/// `kill` 方式及脱离控制台运行的子进程直接结束。
+fn stop_child_gracefully(child: &mut Child, config: &ServiceConfig) -> Option<std::process::ExitStatus> {
+    let timeout = stop_timeout(config);
+    if !console_stop(config) || timeout.is_zero() {
+        return reap_child(child);
+    }
+
+    // 子进程在独立的进程组中启动，进程组ID即其PID，宿主自身不会收到该事件
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, child.id()) } == 0 {
+        warn!("Failed to send CTRL_BREAK to child process {}: error {}", child.id(), unsafe { GetLastError() });
+        return reap_child(child);
+    }
+
+    info!("Sent CTRL_BREAK to child process {}, waiting up to {:?} for it to exit", child.id(), timeout);
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                info!("Child process exited with status: {}", status);
+                return Some(status);
+            }
+            Ok(None) => std::thread::sleep(STOP_POLL_INTERVAL),
+            Err(_) => break,
+        }
+    }
+
+    warn!("Child process {} did not exit within {:?}, killing it", child.id(), timeout);
+    reap_child(child)
+}
+
+/// 启动的子进程及其作业对象、输出匹配标志和PID文件
+type StartedChild = (std::process::Child, Option<JobObject>, Arc<AtomicBool>, Option<PidFile>);
+
 /// 启动子进程一次
+///
+/// 返回的标志在子进程输出匹配重启规则时被置位。
 fn start_child_process_once(
-    service_name: &str,
-    executable_path: &PathBuf,
-    arguments: &[String],
-    working_directory: &Option<PathBuf>,
-    stdout_path: &Option<PathBuf>,
-    stderr_path: &Option<PathBuf>,
-) -> Result<std::process::Child> {
-    info!("Starting child process for service: {}", service_name);
+    config: &ServiceConfig,
+    output_hub: &Arc<OutputHub>,
+    child_input: &ChildInput,
+    restart_patterns: Option<&Arc<RegexSet>>,
+) -> Result<StartedChild> {
+    debug!("Starting child process for service: {}", config.name);
+
+    // 防止磁盘上被篡改的程序以服务身份运行；持有的句柄在子进程启动前阻止替换目标程序
+    let _image_lock = signature::enforce(config)?;
+
+    // 在启动子进程之前创建作业对象，避免出现未纳入管理的子进程
+    let job = match JobObject::new() {
+        Ok(job) => {
+            job.apply_resource_limits(config);
+            Some(job)
+        }
+        Err(e) => {
+            warn!("Child process tree will not be tied to the host: {}", e);
+            None
+        }
+    };
+
+    // 停止时发送的 CTRL_BREAK 只能发给与宿主共享控制台的进程
+    if console_stop(config) {
+        if let Err(e) = ensure_console() {
+            warn!("Child process will not receive CTRL_BREAK on stop: {}", e);
+        }
+    }
+    // 脱离控制台运行的子进程不共享宿主控制台，代码页设置对其无效
+    if let Some(codepage) = config.console_codepage.filter(|_| !config.detached) {
+        if let Err(e) = set_console_codepage(codepage) {
+            warn!("Console code page {} not applied: {}", codepage, e);
+        }
+    }
 
-    let mut cmd = Command::new(executable_path);
+    let mut cmd = Command::new(&config.executable_path);
+
+    {
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, CREATE_SUSPENDED, DETACHED_PROCESS};
+        // 以挂起状态创建，纳入作业对象后再恢复运行
+        let mut flags = CREATE_SUSPENDED;
+        if config.detached {
+            // 不为子进程分配控制台：省去每个服务的 conhost.exe，但子进程也无法接收控制台控制事件
+            flags |= DETACHED_PROCESS;
+        } else if console_stop(config) {
+            // 子进程成为新进程组的组长，进程组ID即其PID：GenerateConsoleCtrlEvent 按该ID发送的
+            // CTRL_BREAK 只到达子进程及继承该进程组的子孙进程，不会发给宿主自身。
+            // 代价是新进程组默认忽略 CTRL_C，调试模式下在控制台按 Ctrl+C 也不会直接传给子进程，
+            // 而是由宿主收到后按停止流程发送 CTRL_BREAK。
+            flags |= CREATE_NEW_PROCESS_GROUP;
+        }
+        cmd.creation_flags(flags);
+    }
 
     // 设置工作目录
-    if let Some(work_dir) = working_directory {
+    if let Some(work_dir) = &config.working_directory {
         cmd.current_dir(work_dir);
     }
 
-    // 设置参数
-    cmd.args(arguments);
-    cmd.stdin(Stdio::null());
+    // 设置参数和环境变量
+    cmd.args(&config.arguments);
+    if config.env_clear {
+        cmd.env_clear();
+    }
+    // 每次启动时重新读取环境变量文件，修改后重启子进程即可生效；--env 指定的同名变量优先
+    if let Some(env_file) = &config.env_file {
+        cmd.envs(service_manager::read_env_file(env_file)?);
+    }
+    cmd.envs(config.environment.iter().map(|(key, value)| (key, value)));
+    // 配置了IPC管道时标准输入由宿主持有，用于转发 send 命令
+    cmd.stdin(if config.ipc_pipe_name.is_some() { Stdio::piped() } else { Stdio::null() });
+
+    // 输出经由管道转发：写入日志文件（如有），同时分发给实时日志客户端
+    let open_output = |path: &Path, stream: &str| -> Result<Box<dyn std::io::Write + Send>> {
+        let file = log_file::open(path, stream, config.log_rotation)?;
+        Ok(if config.log_timestamps { Box::new(log_file::TimestampWriter::new(file)) } else { file })
+    };
+    let stdout_file = config.stdout_path.as_deref().map(|path| open_output(path, "stdout")).transpose()?;
+    let stderr_file = config.stderr_path.as_deref().map(|path| open_output(path, "stderr")).transpose()?;
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
+    let mut child = cmd.spawn()
+        .context(format!("Failed to start process: {:?}", config.executable_path))?;
+
+    // 子进程仍处于挂起状态，此时纳入作业对象，它启动的任何进程都受 KILL_ON_JOB_CLOSE 管理
+    if let Some(job) = &job {
+        if let Err(e) = job.assign(&child) {
+            warn!("{}", e);
+        }
+    }
 
-    // 配置标准输出
-    if let Some(stdout_path) = stdout_path {
-        let stdout_file = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(stdout_path)
-            .context(format!("Failed to open stdout file: {:?}", stdout_path))?;
-        cmd.stdout(Stdio::from(stdout_file));
-    } else {
-        cmd.stdout(Stdio::null());
+    if let Some(priority) = config.priority_class {
+        set_priority_class(&child, priority);
     }
 
-    // 配置标准错误
-    if let Some(stderr_path) = stderr_path {
-        let stderr_file = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(stderr_path)
-            .context(format!("Failed to open stderr file: {:?}", stderr_path))?;
-        cmd.stderr(Stdio::from(stderr_file));
-    } else {
-        cmd.stderr(Stdio::null());
+    // 亲和性设置失败时不让子进程在未受限制的CPU上运行
+    if let Some(mask) = config.cpu_affinity {
+        if let Err(e) = set_affinity_mask(&child, mask) {
+            reap_child(&mut child);
+            return Err(e);
+        }
+    }
+
+    if let Err(e) = set_process_tree_suspended(child.id(), false) {
+        reap_child(&mut child);
+        return Err(e.context(format!("Failed to resume child process {}", child.id())));
+    }
+
+    // PID文件写入失败不影响子进程运行
+    let pid_file = config.pid_file.as_deref().and_then(|path| match PidFile::create(path, child.id()) {
+        Ok(pid_file) => Some(pid_file),
+        Err(e) => {
+            warn!("{:#}", e);
+            None
+        }
+    });
+
+    if let Some(stdin) = child.stdin.take() {
+        child_input.attach(stdin);
+    }
+
+    let output_matched = Arc::new(AtomicBool::new(false));
+    if let Some(stdout) = child.stdout.take() {
+        let matcher = restart_patterns
+            .filter(|_| config.restart_pattern_stream.includes_stdout())
+            .map(|patterns| LineMatcher::spawn(patterns.clone(), output_matched.clone()));
+        live_output::spawn_copier(stdout, stdout_file, output_hub.clone(), matcher);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let matcher = restart_patterns
+            .filter(|_| config.restart_pattern_stream.includes_stderr())
+            .map(|patterns| LineMatcher::spawn(patterns.clone(), output_matched.clone()));
+        live_output::spawn_copier(stderr, stderr_file, output_hub.clone(), matcher);
+    }
+
+    Ok((child, job, output_matched, pid_file))
+}
+
+/// 子进程的PID文件，离开作用域（子进程退出或被结束）时删除
+struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// 写入 `pid` 和换行，所在目录不存在时先创建
+    fn create(path: &Path, pid: u32) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).context(format!("Failed to create directory for PID file {:?}", path))?;
+        }
+        std::fs::write(path, format!("{}\n", pid)).context(format!("Failed to write PID file {:?}", path))?;
+        Ok(Self { path: path.to_path_buf() })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove PID file {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+/// 设置子进程的优先级类，失败时只记录警告
+///
+/// 子进程创建的进程默认继承该优先级类。
+fn set_priority_class(child: &Child, priority: ProcessPriorityClass) {
+    use windows_sys::Win32::System::Threading::{GetPriorityClass, SetPriorityClass};
+
+    let handle = child.as_raw_handle() as HANDLE;
+    if unsafe { SetPriorityClass(handle, priority.win32_class()) } == 0 {
+        let error = unsafe { GetLastError() };
+        warn!("Failed to set priority class {} for child process {}: error {}", priority.as_str(), child.id(), error);
+        return;
     }
 
-    let child = cmd.spawn()
-        .context(format!("Failed to start process: {:?}", executable_path))?;
+    // 没有 SeIncreaseBasePriorityPrivilege 权限时，系统静默改用 High 而不报错
+    let actual = unsafe { GetPriorityClass(handle) };
+    if actual != priority.win32_class() {
+        let actual = ProcessPriorityClass::from_win32_class(actual).map_or("unknown", ProcessPriorityClass::as_str);
+        warn!(
+            "Child process {} runs with priority class {} instead of {} (the service account may lack SeIncreaseBasePriorityPrivilege)",
+            child.id(), actual, priority.as_str()
+        );
+    }
+}
 
-    info!("Started child process with PID: {}", child.id());
-    Ok(child)
+/// 设置子进程可运行的CPU
+///
+/// 掩码不包含本机任何可用CPU时 `SetProcessAffinityMask` 失败（ERROR_INVALID_PARAMETER）。
+fn set_affinity_mask(child: &Child, mask: usize) -> Result<()> {
+    use windows_sys::Win32::System::Threading::SetProcessAffinityMask;
+
+    if unsafe { SetProcessAffinityMask(child.as_raw_handle() as HANDLE, mask) } == 0 {
+        return Err(std::io::Error::last_os_error()).context(format!(
+            "Failed to set CPU affinity mask {:#x} for child process {} (the mask must include at least one CPU of this system)",
+            mask,
+            child.id()
+        ));
+    }
+    Ok(())
 }
 
 /// 调试模式运行（非服务环境）
-fn run_debug_mode(
-    service_name: &str,
-    executable_path: PathBuf,
-    arguments: Vec<String>,
-    working_directory: Option<PathBuf>,
-    stdout_path: Option<PathBuf>,
-    stderr_path: Option<PathBuf>,
-) -> Result<()> {
+fn run_debug_mode(config: ServiceConfig) -> Result<()> {
+    let service_name = config.name.clone();
     info!("Starting debug mode for service: {}", service_name);
-    info!("Executable: {:?}", executable_path);
-    info!("Arguments: {:?}", arguments);
-    info!("Working directory: {:?}", working_directory);
-    info!("Stdout path: {:?}", stdout_path);
-    info!("Stderr path: {:?}", stderr_path);
+    info!("Executable: {:?}", config.executable_path);
+    info!("Arguments: {:?}", config.arguments);
+    info!("Working directory: {:?}", config.working_directory);
+    info!("Stdout path: {:?}", config.stdout_path);
+    info!("Stderr path: {:?}", config.stderr_path);
 
     // 创建停止标志
     let stop_requested = std::sync::Arc::new(std::sync::Mutex::new(false));
@@ -936,40 +2354,515 @@ fn run_debug_mode(
     }).expect("Error setting Ctrl+C handler");
 
     // 启动子进程管理器
-    let executable_path_clone = executable_path.clone();
-    let arguments_clone = arguments.clone();
-    let working_directory_clone = working_directory.clone();
-    let stdout_path_clone = stdout_path.clone();
-    let stderr_path_clone = stderr_path.clone();
-    let service_name_clone = service_name.to_string();
     let stop_requested_for_child = stop_requested.clone();
-
+    let runtime_status = Arc::new(Mutex::new(RuntimeStatus {
+        exit_history: load_exit_history(&service_name),
+        ..Default::default()
+    }));
+    let runtime_status_for_history = runtime_status.clone();
+    let (output_hub, child_input) = start_pipe_servers(&config, &runtime_status)?;
+
+    let (reason_tx, reason_rx) = std::sync::mpsc::channel();
     std::thread::spawn(move || {
-        manage_child_process(
-            &service_name_clone,
-            &executable_path_clone,
-            &arguments_clone,
-            &working_directory_clone,
-            &stdout_path_clone,
-            &stderr_path_clone,
-            &stop_requested_for_child,
-        );
+        let paused = Arc::new(AtomicBool::new(false));
+        let reason = manage_child_process(&config, &stop_requested_for_child, &paused, &runtime_status, &output_hub, &child_input);
+        let _ = reason_tx.send(reason);
     });
 
     info!("Service '{}' started in debug mode. Press Ctrl+C to stop.", service_name);
 
-    // 主循环 - 等待停止信号
-    loop {
-        std::thread::sleep(std::time::Duration::from_millis(500));
+    // 主循环 - 等待停止信号或子进程管理器结束
+    let reason = wait_for_stop(&stop_requested_for_main, &reason_rx);
+    save_exit_history(&service_name, &runtime_status_for_history);
+    if reason.exit_code() != ServiceExitCode::Win32(0) {
+        return Err(anyhow::anyhow!("Service '{}' {}", service_name, reason));
+    }
 
-        // 检查是否收到停止请求
-        if let Ok(stop) = stop_requested_for_main.lock() {
-            if *stop {
-                break;
-            }
+    info!("Service '{}' {}", service_name, reason);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_object_kills_child_on_close() {
+        let job = JobObject::new().expect("Failed to create job object");
+        let mut child = Command::new("cmd")
+            .args(["/C", "ping -n 30 127.0.0.1 > NUL"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to spawn test child");
+        job.assign(&child).expect("Failed to assign child to job");
+
+        // 模拟宿主进程意外退出：作业句柄被关闭
+        drop(job);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while child.try_wait().expect("Failed to query child").is_none() {
+            assert!(Instant::now() < deadline, "child survived job object close");
+            std::thread::sleep(Duration::from_millis(100));
         }
     }
 
-    info!("Service '{}' stopped", service_name);
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn test_stop_leaves_no_orphans() {
+        use windows_sys::Win32::Foundation::WAIT_OBJECT_0;
+        use windows_sys::Win32::System::Threading::{OpenProcess, WaitForSingleObject, PROCESS_SYNCHRONIZE};
+
+        let config = ServiceConfig { stop_method: StopMethod::Kill, ..Default::default() };
+        let job = JobObject::new().expect("Failed to create job object");
+        // start /B 启动的 ping 脱离 cmd 独立运行，只结束 cmd 时会成为孤儿进程
+        let mut child = Command::new("cmd")
+            .args(["/C", "start \"\" /B ping -n 30 127.0.0.1 > NUL & ping -n 30 127.0.0.1 > NUL"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to spawn test child");
+        job.assign(&child).expect("Failed to assign child to job");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while job.process_ids().expect("Failed to list job processes").len() < 3 {
+            assert!(Instant::now() < deadline, "grandchildren did not start");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        let descendants: Vec<HANDLE> = job
+            .process_ids()
+            .expect("Failed to list job processes")
+            .into_iter()
+            .filter(|&pid| pid != child.id() as usize)
+            .map(|pid| unsafe { OpenProcess(PROCESS_SYNCHRONIZE, 0, pid as u32) })
+            .filter(|&handle| handle != 0)
+            .collect();
+        assert!(!descendants.is_empty());
+
+        // 与停止流程相同：结束直接子进程后释放作业对象
+        stop_child_gracefully(&mut child, &config);
+        drop(job);
+
+        for handle in descendants {
+            assert_eq!(unsafe { WaitForSingleObject(handle, 5000) }, WAIT_OBJECT_0, "descendant survived stop");
+            unsafe { CloseHandle(handle) };
+        }
+    }
+
+    #[test]
+    fn test_job_wait_until_empty() {
+        let job = JobObject::new().expect("Failed to create job object");
+        let mut child = Command::new("cmd")
+            .args(["/C", "ping -n 2 127.0.0.1 > NUL"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to spawn test child");
+        job.assign(&child).expect("Failed to assign child to job");
+
+        assert!(job.process_ids().expect("Failed to list job processes").contains(&(child.id() as usize)));
+        assert!(job.wait_until_empty(Duration::from_secs(10)).is_empty());
+        child.wait().expect("Failed to wait for child");
+    }
+
+    #[test]
+    fn test_restart_cycles_do_not_leak_handles() {
+        use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessHandleCount};
+
+        let config = ServiceConfig {
+            name: "handle-leak-test".to_string(),
+            executable_path: PathBuf::from("cmd"),
+            arguments: vec!["/C".to_string(), "exit 3".to_string()],
+            ..Default::default()
+        };
+
+        let handle_count = || {
+            let mut count = 0u32;
+            unsafe { GetProcessHandleCount(GetCurrentProcess(), &mut count) };
+            count
+        };
+
+        let output_hub = Arc::new(OutputHub::default());
+        let cycle = || {
+            let (mut child, job, _, _) = start_child_process_once(&config, &output_hub, &ChildInput::default(), None).expect("Failed to start child");
+            assert_eq!(reap_child(&mut child).and_then(|status| status.code()), Some(3));
+            drop(job);
+        };
+
+        // 预热一次，排除首次启动时一次性分配的句柄
+        cycle();
+        let before = handle_count();
+        for _ in 0..50 {
+            cycle();
+        }
+        // 留出时间让输出转发线程读到管道结束并退出
+        std::thread::sleep(Duration::from_millis(500));
+        let after = handle_count();
+
+        assert!(after <= before + 5, "handle count grew from {} to {}", before, after);
+    }
+
+    #[test]
+    fn test_detached_child_has_no_conhost() {
+        use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+
+        let config = ServiceConfig {
+            name: "detached-test".to_string(),
+            executable_path: PathBuf::from("cmd"),
+            arguments: vec!["/C".to_string(), "ping -n 5 127.0.0.1 > NUL".to_string()],
+            detached: true,
+            ..Default::default()
+        };
+        let output_hub = Arc::new(OutputHub::default());
+        let (mut child, job, _, _) = start_child_process_once(&config, &output_hub, &ChildInput::default(), None).expect("Failed to start child");
+        // 等待 cmd 启动 ping，控制台宿主（如有）此时已创建
+        std::thread::sleep(Duration::from_millis(500));
+
+        let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+        assert_ne!(snapshot, INVALID_HANDLE_VALUE);
+        let mut processes = Vec::new();
+        let mut process: PROCESSENTRY32W = unsafe { std::mem::zeroed() };
+        process.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+        let mut more = unsafe { Process32FirstW(snapshot, &mut process) } != 0;
+        while more {
+            let len = process.szExeFile.iter().position(|&c| c == 0).unwrap_or(process.szExeFile.len());
+            let name = String::from_utf16_lossy(&process.szExeFile[..len]);
+            processes.push((process.th32ProcessID, process.th32ParentProcessID, name));
+            more = unsafe { Process32NextW(snapshot, &mut process) } != 0;
+        }
+        unsafe { CloseHandle(snapshot) };
+
+        let pairs: Vec<(u32, u32)> = processes.iter().map(|(pid, parent, _)| (*pid, *parent)).collect();
+        let tree = process_tree(child.id(), &pairs);
+        assert!(tree.len() > 1, "ping should be running under the detached cmd");
+        let conhosts: Vec<u32> = processes
+            .iter()
+            .filter(|(pid, _, name)| tree.contains(pid) && name.eq_ignore_ascii_case("conhost.exe"))
+            .map(|(pid, _, _)| *pid)
+            .collect();
+
+        reap_child(&mut child);
+        drop(job);
+        assert!(conhosts.is_empty(), "detached child spawned conhost.exe: {:?}", conhosts);
+    }
+
+    #[test]
+    fn test_cpu_watchdog() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        // 100纳秒单位，1秒 = 10_000_000
+        let cpu_secs = |secs: u64| secs * 10_000_000;
+
+        let mut watchdog = CpuWatchdog::new(Duration::from_secs(30), 1.0, start);
+
+        // 启动宽限期内不检测
+        assert!(!watchdog.poll(0, at(10)));
+
+        // 宽限期后的第一个窗口：30秒内使用了3秒CPU（10%），不是停滞
+        assert!(!watchdog.poll(cpu_secs(1), at(60)));
+        assert!(!watchdog.poll(cpu_secs(2), at(80)));
+        assert!(!watchdog.poll(cpu_secs(4), at(90)));
+
+        // 下一个窗口内CPU几乎没有增长，判定为停滞
+        assert!(!watchdog.poll(cpu_secs(4), at(110)));
+        assert!(watchdog.poll(cpu_secs(4), at(120)));
+    }
+
+    #[test]
+    fn test_host_log_path() {
+        let config = ServiceConfig {
+            name: "my-service".to_string(),
+            host_log_path: Some(PathBuf::from("D:\\logs\\host.log")),
+            ..Default::default()
+        };
+        assert_eq!(host_log_path(&config), Some(PathBuf::from("D:\\logs\\host.log")));
+
+        let config = ServiceConfig { host_log_path: None, ..config };
+        let log_dir = log_file::host_log_dir_from(None, Some("C:\\ProgramData".into()), None);
+        assert_eq!(
+            host_log_path_in(&config, log_dir),
+            Some(PathBuf::from("C:\\ProgramData").join("rust-nssm").join("rust-nssm-my-service.log"))
+        );
+        assert_eq!(host_log_path_in(&config, None), None);
+    }
+
+    #[test]
+    fn test_pid_file_removed_on_drop() {
+        let path = std::env::temp_dir()
+            .join(format!("rust-nssm-pid-test-{}", std::process::id()))
+            .join("app.pid");
+        let pid_file = PidFile::create(&path, 4242).expect("Failed to create PID file");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "4242\n");
+
+        drop(pid_file);
+        assert!(!path.exists());
+        let _ = std::fs::remove_dir(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_health_monitor_thresholds() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let config = HealthCheckConfig {
+            target: HealthCheckTarget::Tcp { port: 8080 },
+            interval_secs: 10,
+            failure_threshold: 3,
+            success_threshold: 2,
+        };
+        let mut monitor = HealthMonitor::new(config, start);
+
+        // 启动一个检查间隔后才开始检查
+        assert!(!monitor.due(at(5)));
+        assert!(monitor.due(at(10)));
+
+        // 两次失败后一次成功不足以清零失败计数，第三次失败即判定为不健康
+        assert!(!monitor.record(false, at(10)));
+        assert!(!monitor.record(false, at(20)));
+        assert!(!monitor.due(at(25)));
+        assert!(!monitor.record(true, at(30)));
+        assert!(monitor.record(false, at(40)));
+
+        // 连续成功达到阈值后清零
+        monitor.reset(at(40));
+        assert!(!monitor.record(false, at(50)));
+        assert!(!monitor.record(true, at(60)));
+        assert!(!monitor.record(true, at(70)));
+        assert!(!monitor.record(false, at(80)));
+        assert!(!monitor.record(false, at(90)));
+        assert!(monitor.record(false, at(100)));
+    }
+
+    #[test]
+    fn test_binary_fingerprint_roundtrip() {
+        let fingerprint = BinaryFingerprint { size: 1024, modified_nanos: 1_700_000_000_123_456_789 };
+        assert_eq!(BinaryFingerprint::parse(&fingerprint.to_registry_string()), Some(fingerprint));
+        assert_eq!(BinaryFingerprint::parse("garbage"), None);
+    }
+
+    #[test]
+    fn test_binary_watcher_waits_for_stable_file() {
+        let path = std::env::temp_dir().join("rust_nssm_binary_watcher_test.exe");
+        std::fs::write(&path, b"v1").unwrap();
+
+        let mut watcher = BinaryWatcher::new(path.clone(), Duration::ZERO);
+        watcher.stable_period = Duration::ZERO;
+        watcher.reset(BinaryFingerprint::of(&path));
+        assert_eq!(watcher.poll(), None);
+
+        std::fs::write(&path, b"version 2").unwrap();
+        // 第一次发现变化只记录，文件保持稳定后才报告
+        assert_eq!(watcher.poll(), None);
+        let changed = watcher.poll();
+        assert_eq!(changed, BinaryFingerprint::of(&path));
+
+        watcher.reset(changed);
+        assert_eq!(watcher.poll(), None);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_stop_reason_exit_code() {
+        assert_eq!(StopReason::Requested.exit_code(), ServiceExitCode::Win32(0));
+        assert_eq!(
+            StopReason::RestartsExhausted { attempts: 5 }.exit_code(),
+            ServiceExitCode::ServiceSpecific(EXIT_RESTARTS_EXHAUSTED)
+        );
+        assert_eq!(StopReason::RestartsExhausted { attempts: 5 }.to_string(), "stopped after 5 failed restarts");
+        assert_eq!(
+            StopReason::RestartRateExceeded { restarts: 11, window_secs: 300 }.exit_code(),
+            ServiceExitCode::ServiceSpecific(EXIT_RESTART_RATE_EXCEEDED)
+        );
+        assert_eq!(
+            StopReason::MaxRestartsReached { restarts: 10 }.exit_code(),
+            ServiceExitCode::ServiceSpecific(EXIT_MAX_RESTARTS_REACHED)
+        );
+        assert_eq!(StopReason::MaxRestartsReached { restarts: 10 }.to_string(), "stopped after 10 consecutive restarts");
+        assert_eq!(StopReason::ChildExited { exit_code: Some(0), failed: false }.exit_code(), ServiceExitCode::Win32(0));
+        assert_eq!(
+            StopReason::ChildExited { exit_code: Some(0xC0000005u32 as i32), failed: true }.exit_code(),
+            ServiceExitCode::ServiceSpecific(EXIT_CHILD_EXIT_ACTION)
+        );
+        assert_eq!(
+            StopReason::ChildExited { exit_code: Some(0), failed: false }.to_string(),
+            "stopped because the child process exited with code 0 (exit action: stop)"
+        );
+        assert_eq!(StopReason::PipeUnavailable.exit_code(), ServiceExitCode::ServiceSpecific(EXIT_PIPE_UNAVAILABLE));
+    }
+
+    #[test]
+    fn test_wait_for_stop_reports_manager_reason() {
+        let stop_requested = Arc::new(Mutex::new(false));
+        let (reason_tx, reason_rx) = std::sync::mpsc::channel();
+        reason_tx.send(StopReason::RestartsExhausted { attempts: 3 }).unwrap();
+        assert_eq!(wait_for_stop(&stop_requested, &reason_rx), StopReason::RestartsExhausted { attempts: 3 });
+
+        drop(reason_tx);
+        assert_eq!(wait_for_stop(&stop_requested, &reason_rx), StopReason::ManagerLost);
+    }
+
+    #[test]
+    fn test_fallback_config() {
+        let mut config = ServiceConfig {
+            executable_path: PathBuf::from("C:\\app\\primary.exe"),
+            arguments: vec!["--serve".to_string()],
+            working_directory: Some(PathBuf::from("C:\\app")),
+            ..Default::default()
+        };
+        assert!(fallback_config(&config).is_none());
+
+        config.fallback_executable = Some(PathBuf::from("C:\\app\\degraded.exe"));
+        config.fallback_arguments = vec!["--read-only".to_string()];
+        let fallback = fallback_config(&config).unwrap();
+        assert_eq!(fallback.executable_path, PathBuf::from("C:\\app\\degraded.exe"));
+        assert_eq!(fallback.arguments, ["--read-only"]);
+        assert_eq!(fallback.working_directory, config.working_directory);
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let start = Instant::now();
+        let mut window = RollingWindow::new(Duration::from_secs(300));
+        assert_eq!(window.record(start), 1);
+        assert_eq!(window.record(start + Duration::from_secs(100)), 2);
+        assert_eq!(window.record(start + Duration::from_secs(300)), 3);
+        assert_eq!(window.record(start + Duration::from_secs(401)), 2);
+    }
+
+    #[test]
+    fn test_restart_log_limiter() {
+        let start = Instant::now();
+        let mut limiter = RestartLogLimiter::new(3, Duration::from_secs(60));
+
+        let logged: Vec<bool> = (0..6).map(|i| limiter.record(start + Duration::from_secs(i))).collect();
+        assert_eq!(logged, [true, true, true, false, false, false]);
+        assert_eq!(limiter.suppressed, 3);
+        assert_eq!(limiter.last_summary, Some(start + Duration::from_secs(3)));
+
+        // 窗口内的摘要只输出一次，下一个窗口再输出
+        assert!(!limiter.record(start + Duration::from_secs(30)));
+        assert_eq!(limiter.last_summary, Some(start + Duration::from_secs(3)));
+        assert!(!limiter.record(start + Duration::from_secs(63)));
+        assert_eq!(limiter.last_summary, Some(start + Duration::from_secs(63)));
+
+        // 恢复稳定后重新逐条记录
+        assert!(limiter.record(start + Duration::from_secs(200)));
+        assert_eq!(limiter.suppressed, 0);
+    }
+
+    #[test]
+    fn test_start_retry_delay() {
+        let mut config = ServiceConfig::default();
+        assert_eq!(start_retry_delay(&config, 1), Duration::from_secs(4));
+        assert_eq!(start_retry_delay(&config, 4), Duration::from_secs(32));
+        assert_eq!(start_retry_delay(&config, 20), Duration::from_secs(512));
+
+        config.start_retry_delay = Some(5);
+        config.start_retry_delay_max = Some(60);
+        assert_eq!(start_retry_delay(&config, 1), Duration::from_secs(10));
+        assert_eq!(start_retry_delay(&config, 3), Duration::from_secs(40));
+        assert_eq!(start_retry_delay(&config, 100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_max_start_attempts() {
+        let mut config = ServiceConfig::default();
+        assert_eq!(max_start_attempts(&config), Some(MAX_START_ATTEMPTS));
+        config.max_start_attempts = Some(0);
+        assert_eq!(max_start_attempts(&config), None);
+        config.max_start_attempts = Some(10);
+        assert_eq!(max_start_attempts(&config), Some(10));
+    }
+
+    #[test]
+    fn test_restart_delay() {
+        let mut config = ServiceConfig::default();
+        assert_eq!(restart_delay(&config), RESTART_DELAY);
+        config.restart_delay = Some(0);
+        assert_eq!(restart_delay(&config), Duration::ZERO);
+        config.restart_delay = Some(30);
+        assert_eq!(restart_delay(&config), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_restart_jitter() {
+        assert_eq!(jitter(Duration::ZERO, 12345), Duration::ZERO);
+        assert_eq!(jitter(Duration::from_secs(5), 4999), Duration::from_millis(4999));
+        assert_eq!(jitter(Duration::from_secs(5), 5000), Duration::ZERO);
+        assert_eq!(jitter(Duration::from_secs(5), u64::MAX), Duration::from_millis(u64::MAX % 5000));
+
+        let mut config = ServiceConfig::default();
+        assert_eq!(restart_jitter(&config), Duration::ZERO);
+        config.restart_jitter = Some(3);
+        for _ in 0..100 {
+            assert!(restart_jitter(&config) < Duration::from_secs(3));
+        }
+    }
+
+    #[test]
+    fn test_process_tree() {
+        // 100 → 200 → 300 → 500，400 是无关进程；最后一项模拟进程ID复用形成的环
+        let processes = [(100, 4), (200, 100), (300, 200), (400, 4), (500, 300), (100, 500)];
+        assert_eq!(process_tree(100, &processes), vec![100, 200, 300, 500]);
+        assert_eq!(process_tree(400, &processes), vec![400]);
+    }
+
+    #[test]
+    fn test_stop_timeout() {
+        let mut config = ServiceConfig::default();
+        assert_eq!(stop_timeout(&config), DEFAULT_STOP_TIMEOUT);
+        assert_eq!(stop_cleanup_timeout(&config), DEFAULT_STOP_TIMEOUT + Duration::from_secs(5));
+
+        config.stop_timeout = Some(0);
+        assert_eq!(stop_timeout(&config), Duration::ZERO);
+        config.stop_timeout = Some(30);
+        config.wait_for_tree = true;
+        assert_eq!(stop_cleanup_timeout(&config), Duration::from_secs(30) + TREE_STOP_TIMEOUT + Duration::from_secs(5));
+
+        // 脱离控制台的子进程收不到 CTRL_BREAK，停止时不等待
+        config.detached = true;
+        assert_eq!(stop_cleanup_timeout(&config), TREE_STOP_TIMEOUT + Duration::from_secs(5));
+
+        config.detached = false;
+        config.stop_method = StopMethod::Kill;
+        assert!(!console_stop(&config));
+        assert_eq!(stop_cleanup_timeout(&config), TREE_STOP_TIMEOUT + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_next_start_delay() {
+        let config = ServiceConfig {
+            restart_delay: Some(7),
+            start_retry_delay: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(next_start_delay(&config, None), Duration::from_secs(7));
+        assert_eq!(next_start_delay(&config, Some(1)), Duration::from_secs(10));
+        assert_eq!(next_start_delay(&config, Some(2)), Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_sleep_unless_stopped() {
+        let stop_requested = Arc::new(Mutex::new(false));
+        assert!(sleep_unless_stopped(Duration::ZERO, &stop_requested));
+
+        *stop_requested.lock().unwrap() = true;
+        let started = Instant::now();
+        assert!(!sleep_unless_stopped(Duration::from_secs(30), &stop_requested));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_retry_with_delay() {
+        let mut calls = 0;
+        let result: Result<u32, String> = retry_with_delay(3, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 { Err("transient".to_string()) } else { Ok(calls) }
+        });
+        assert_eq!(result, Ok(3));
+
+        let mut calls = 0;
+        let result: Result<(), String> = retry_with_delay(3, Duration::ZERO, || {
+            calls += 1;
+            Err(format!("failure {}", calls))
+        });
+        assert_eq!(result, Err("failure 3".to_string()));
+    }
+}
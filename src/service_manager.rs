@@ -1,404 +1,3372 @@
 use anyhow::{Context, Result};
 use log::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use windows_sys::Win32::Foundation::*;
 use windows_sys::Win32::Security::*;
+use windows_sys::Win32::Storage::FileSystem::{GetDriveTypeW, GetVolumePathNameW};
+use windows_sys::Win32::System::EventLog::*;
 use windows_sys::Win32::System::Registry::*;
 use windows_sys::Win32::System::Services::*;
+use windows_sys::Win32::System::SystemServices::EVENTLOG_BACKWARDS_READ;
+use windows_sys::Win32::System::WindowsProgramming::{
+    DRIVE_CDROM, DRIVE_FIXED, DRIVE_NO_ROOT_DIR, DRIVE_RAMDISK, DRIVE_REMOTE, DRIVE_REMOVABLE, DRIVE_UNKNOWN,
+};
 
 /// 服务配置
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ServiceConfig {
     pub name: String,
     pub display_name: String,
     pub description: String,
     pub executable_path: PathBuf,
     pub arguments: Vec<String>,
+    /// 子进程额外的环境变量（在继承的环境之上设置，同名时覆盖）
+    pub environment: Vec<(String, String)>,
+    /// 不继承宿主的环境变量，子进程只获得 `environment` 中的变量
+    pub env_clear: bool,
+    /// 每次启动子进程前重新读取的 dotenv 格式环境变量文件
+    pub env_file: Option<PathBuf>,
     pub working_directory: Option<PathBuf>,
+    /// 子进程运行期间记录其PID的文件
+    pub pid_file: Option<PathBuf>,
     pub stdout_path: Option<PathBuf>,
     pub stderr_path: Option<PathBuf>,
+    /// 检查目标程序是否在磁盘上被替换的间隔（秒）
+    pub update_check_interval: Option<u64>,
+    /// 目标程序被替换后自动重启子进程
+    pub restart_on_binary_change: bool,
+    /// 只在该时间段内自动重启子进程
+    pub restart_window: Option<RestartWindow>,
+    /// 子进程退出后重新启动前的等待时间（秒），0 表示立即重启
+    pub restart_delay: Option<u64>,
+    /// 停止服务时结束子进程的方式
+    pub stop_method: StopMethod,
+    /// 停止服务时发送 CTRL_BREAK 后等待子进程自行退出的时间（秒），0 表示直接结束
+    pub stop_timeout: Option<u64>,
+    /// 服务启动保护级别
+    pub launch_protection: LaunchProtection,
+    /// 安装时写入SCM的失败恢复操作
+    pub recovery_actions: Option<RecoveryConfig>,
+    /// 使用延迟自动启动（系统启动完成后再启动服务）
+    pub start_type: ServiceStartType,
+    /// 服务运行所用的账户，`None` 表示 LocalSystem
+    pub service_account: Option<ServiceAccount>,
+    /// 必须先于本服务启动的服务（以 `+` 开头表示服务组）
+    pub dependencies: Vec<String>,
+    /// 除 Administrators 外允许访问状态管道的组
+    pub pipe_allow_group: Option<String>,
+    /// 把子进程的启动、停止、崩溃和重启写入Windows应用程序事件日志（安装时默认启用）
+    pub event_log_enabled: bool,
+    /// 宿主诊断日志的路径，`None` 表示日志目录中的 `rust-nssm-<服务名>.log`
+    pub host_log_path: Option<PathBuf>,
+    /// IPC管道名称：宿主创建 `\\.\pipe\<名称>`，收到的命令逐行转发到子进程的标准输入
+    pub ipc_pipe_name: Option<String>,
+    /// 子进程树的I/O带宽上限（字节/秒）
+    pub io_rate_limit: Option<u64>,
+    /// 子进程树中每个进程的最大工作集（字节）
+    pub max_working_set: Option<u64>,
+    /// 子进程的优先级类，`None` 表示继承宿主的优先级（Normal）
+    pub priority_class: Option<ProcessPriorityClass>,
+    /// 子进程可运行的CPU（位掩码，第 n 位对应第 n 个逻辑处理器）
+    pub cpu_affinity: Option<usize>,
+    /// 子进程输出文件的轮转方式
+    pub log_rotation: LogRotation,
+    /// 为写入输出文件的每行加上时间戳前缀
+    pub log_timestamps: bool,
+    /// 安装时运行一次的环境准备命令
+    pub provision_command: Option<String>,
+    /// CPU停滞检测时长（秒）：子进程树在这段时间内几乎不使用CPU时视为卡死并重启
+    pub cpu_stall_timeout: Option<u64>,
+    /// CPU停滞阈值（占一个CPU核心的百分比）
+    pub cpu_stall_threshold: Option<f64>,
+    /// 健康检查（TCP端口或HTTP接口）：连续多次失败时结束子进程并按重启规则处理
+    pub health_check: Option<HealthCheckConfig>,
+    /// 子进程控制台的输入/输出代码页（如 65001 表示UTF-8）
+    pub console_codepage: Option<u32>,
+    /// 以 `DETACHED_PROCESS` 启动子进程：不分配控制台，也就没有 conhost.exe
+    pub detached: bool,
+    /// 主程序连续启动失败后改为启动的备用程序
+    pub fallback_executable: Option<PathBuf>,
+    /// 备用程序的命令行参数
+    pub fallback_arguments: Vec<String>,
+    /// 运行备用程序期间定期检查主程序，可以启动时切换回主程序
+    pub prefer_primary: bool,
+    /// 时间窗口内允许的最大重启次数，超过后服务宿主放弃并停止服务
+    pub max_restarts_per_window: Option<u32>,
+    /// 连续重启次数上限，超过后服务宿主放弃并停止服务（0 表示不限制）
+    pub max_restarts: Option<u32>,
+    /// 子进程持续运行超过该时间（秒）后视为稳定，连续重启次数清零；
+    /// 指定时未稳定运行就退出的子进程按指数退避延迟重启
+    pub stable_window: Option<u64>,
+    /// 子进程连续启动失败的次数上限，达到后切换到备用程序或放弃（0 表示不限制）
+    pub max_start_attempts: Option<u32>,
+    /// 启动失败重试等待时间的基数（秒），第N次失败后等待 基数×2^N 秒
+    pub start_retry_delay: Option<u64>,
+    /// 启动失败重试等待时间的上限（秒）
+    pub start_retry_delay_max: Option<u64>,
+    /// 每次重启前额外等待的随机时间上限（秒），避免多个服务同时重启
+    pub restart_jitter: Option<u64>,
+    /// 重启频率上限的时间窗口（秒）
+    pub restart_rate_window: Option<u64>,
+    /// 子进程频繁重启时把逐条重启日志合并为周期性摘要
+    pub quiet_restart_logging: bool,
+    /// 时间窗口内逐条记录的重启次数上限
+    pub restart_log_burst: Option<u32>,
+    /// 重启日志限流的时间窗口（秒）
+    pub restart_log_window: Option<u64>,
+    /// 子进程输出中出现匹配的行时重启子进程（正则表达式）
+    pub restart_patterns: Vec<String>,
+    /// 重启规则匹配的输出流
+    pub restart_pattern_stream: PatternStream,
+    /// 按子进程退出码决定退出后重启子进程还是停止服务
+    pub exit_actions: ExitActions,
+    /// 停止时等待整个进程树退出后再强制结束残留进程
+    pub wait_for_tree: bool,
+    /// 安装时及每次启动前校验目标程序的 Authenticode 签名
+    pub check_signature: bool,
+    /// 签名校验失败时拒绝启动（否则只记录警告）
+    pub require_signature: bool,
+    /// 要求签名证书匹配的SHA1指纹
+    pub signer_thumbprint: Option<String>,
 }
 
-/// 服务管理器
-pub struct ServiceManager {
-    scm: SC_HANDLE,
+/// 服务启动类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ServiceStartType {
+    /// 开机时自动启动
+    #[default]
+    #[value(name = "auto")]
+    AutoStart,
+    /// 手动启动
+    #[value(name = "manual")]
+    ManualStart,
+    /// 禁用
+    Disabled,
+    /// 开机后延迟一段时间自动启动
+    #[value(name = "delayed-auto")]
+    AutoDelayedStart,
 }
 
-impl ServiceManager {
-    /// 创建新的服务管理器
-    pub fn new() -> Result<Self> {
-        let scm = unsafe {
-            OpenSCManagerW(
-                std::ptr::null(),
-                std::ptr::null(),
-                SC_MANAGER_ALL_ACCESS,
-            )
-        };
-
-        if scm == 0 {
-            return Err(anyhow::anyhow!("Failed to open Service Control Manager"));
+impl ServiceStartType {
+    /// 命令行中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ServiceStartType::AutoStart => "auto",
+            ServiceStartType::ManualStart => "manual",
+            ServiceStartType::Disabled => "disabled",
+            ServiceStartType::AutoDelayedStart => "delayed-auto",
         }
+    }
 
-        Ok(Self { scm })
+    /// 对应的 `SERVICE_*_START` 值，延迟自动启动另需设置 `SERVICE_CONFIG_DELAYED_AUTO_START_INFO`
+    fn as_raw(self) -> u32 {
+        match self {
+            ServiceStartType::AutoStart | ServiceStartType::AutoDelayedStart => SERVICE_AUTO_START,
+            ServiceStartType::ManualStart => SERVICE_DEMAND_START,
+            ServiceStartType::Disabled => SERVICE_DISABLED,
+        }
     }
 
-    /// 安装服务
-    pub fn install_service(&self, config: &ServiceConfig) -> Result<()> {
-        let service_name = to_wstring(&config.name);
-        let display_name = to_wstring(&config.display_name);
+    /// 由SCM中的启动类型还原，驱动程序专用的启动类型按手动启动处理
+    fn from_raw(start_type: u32, delayed_auto_start: bool) -> Self {
+        match start_type {
+            SERVICE_AUTO_START if delayed_auto_start => ServiceStartType::AutoDelayedStart,
+            SERVICE_AUTO_START => ServiceStartType::AutoStart,
+            SERVICE_DISABLED => ServiceStartType::Disabled,
+            _ => ServiceStartType::ManualStart,
+        }
+    }
+}
 
-        // 获取当前可执行文件的路径（rust-nssm自身）
-        let current_exe = std::env::current_exe()
-            .context("Failed to get current executable path")?;
+/// 服务运行所用的账户
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceAccount {
+    LocalSystem,
+    LocalService,
+    NetworkService,
+    /// 本地或域用户（`.\user`、`DOMAIN\user` 或 `user@domain`），组托管服务账户（以 `$` 结尾）不需要密码
+    User { username: String, password: Option<String> },
+}
 
-        // 构建服务命令行：rust-nssm.exe run --name <service_name>
-        let mut command_line = OsString::new();
-        command_line.push("\"");
-        command_line.push(&current_exe);
-        command_line.push("\" run --name \"");
-        command_line.push(&config.name);
-        command_line.push("\"");
+impl ServiceAccount {
+    /// 按 `--username` 构造账户，内置账户名（可带 `NT AUTHORITY\` 前缀，不区分大小写）映射为对应的内置账户
+    pub fn from_username(username: &str, password: Option<String>) -> Self {
+        let name = username.trim();
+        let builtin = name
+            .get(..13)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("NT AUTHORITY\\"))
+            .map_or(name, |_| &name[13..]);
+        match builtin.to_ascii_lowercase().as_str() {
+            "localsystem" | "system" => ServiceAccount::LocalSystem,
+            "localservice" | "local service" => ServiceAccount::LocalService,
+            "networkservice" | "network service" => ServiceAccount::NetworkService,
+            _ => ServiceAccount::User { username: name.to_string(), password },
+        }
+    }
 
-        let binary_path = to_wstring(&command_line.to_string_lossy());
+    /// 传给 `CreateServiceW` 的账户名，LocalSystem 为 `None`
+    ///
+    /// 不带域名的用户名按本地账户处理（`.\user`）。
+    fn start_name(&self) -> Option<String> {
+        match self {
+            ServiceAccount::LocalSystem => None,
+            ServiceAccount::LocalService => Some("NT AUTHORITY\\LocalService".to_string()),
+            ServiceAccount::NetworkService => Some("NT AUTHORITY\\NetworkService".to_string()),
+            ServiceAccount::User { username, .. } if username.contains(['\\', '@']) => Some(username.clone()),
+            ServiceAccount::User { username, .. } => Some(format!(".\\{}", username)),
+        }
+    }
 
-        // 创建服务
-        let service = unsafe {
-            CreateServiceW(
-                self.scm,
-                service_name.as_ptr(),
-                display_name.as_ptr(),
-                SERVICE_ALL_ACCESS,
-                SERVICE_WIN32_OWN_PROCESS,
-                SERVICE_AUTO_START,
-                SERVICE_ERROR_NORMAL,
-                binary_path.as_ptr(),
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
-            )
-        };
+    /// 显示用的账户名
+    pub fn display_name(&self) -> String {
+        self.start_name().unwrap_or_else(|| "LocalSystem".to_string())
+    }
 
-        if service == 0 {
-            let error = unsafe { GetLastError() };
-            if error == ERROR_SERVICE_EXISTS {
-                return Err(anyhow::anyhow!("Service already exists"));
+    /// 是否必须提供密码：内置账户、组托管服务账户（以 `$` 结尾）和 `NT SERVICE\` 虚拟账户不需要
+    pub fn requires_password(&self) -> bool {
+        match self {
+            ServiceAccount::User { username, .. } => {
+                let virtual_account = username
+                    .get(..11)
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case("NT SERVICE\\"));
+                !username.ends_with('$') && !virtual_account
             }
-            return Err(anyhow::anyhow!("Failed to create service: error {}", error));
+            _ => false,
         }
+    }
 
-        // 设置服务描述
-        if let Err(e) = self.set_service_description(service, &config.description) {
-            warn!("Failed to set service description: {}", e);
+    /// 传给 `CreateServiceW` 的密码，内置账户和未指定密码时为 `None`
+    fn password(&self) -> Option<&str> {
+        match self {
+            ServiceAccount::User { password, .. } => password.as_deref(),
+            _ => None,
         }
+    }
+}
 
-        // 保存额外配置
-        if let Err(e) = self.save_service_config(config) {
-            warn!("Failed to save service config: {}", e);
-        }
+/// 服务启动保护级别（`SERVICE_CONFIG_LAUNCH_PROTECTED`）
+///
+/// 受保护服务要求服务程序及其加载的所有DLL都具有相应的签名，
+/// `antimalware-light` 还要求已注册的ELAM驱动，绝大多数场景应保持 `none`。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LaunchProtection {
+    #[default]
+    None,
+    Windows,
+    WindowsLight,
+    AntimalwareLight,
+}
 
-        // 关闭服务句柄
-        unsafe { CloseServiceHandle(service); }
+impl LaunchProtection {
+    /// 对应的 `SERVICE_LAUNCH_PROTECTED_*` 值
+    fn as_raw(self) -> u32 {
+        match self {
+            LaunchProtection::None => SERVICE_LAUNCH_PROTECTED_NONE,
+            LaunchProtection::Windows => SERVICE_LAUNCH_PROTECTED_WINDOWS,
+            LaunchProtection::WindowsLight => SERVICE_LAUNCH_PROTECTED_WINDOWS_LIGHT,
+            LaunchProtection::AntimalwareLight => SERVICE_LAUNCH_PROTECTED_ANTIMALWARE_LIGHT,
+        }
+    }
 
-        info!("Service '{}' installed successfully", config.name);
-        Ok(())
+    fn from_raw(protection: u32) -> Self {
+        match protection {
+            SERVICE_LAUNCH_PROTECTED_WINDOWS => LaunchProtection::Windows,
+            SERVICE_LAUNCH_PROTECTED_WINDOWS_LIGHT => LaunchProtection::WindowsLight,
+            SERVICE_LAUNCH_PROTECTED_ANTIMALWARE_LIGHT => LaunchProtection::AntimalwareLight,
+            _ => LaunchProtection::None,
+        }
     }
 
-    /// 卸载服务
-    pub fn uninstall_service(&self, service_name: &str) -> Result<()> {
-        let service = self.open_service(service_name, SERVICE_ALL_ACCESS)?;
+    /// 注册表和命令行中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LaunchProtection::None => "none",
+            LaunchProtection::Windows => "windows",
+            LaunchProtection::WindowsLight => "windows-light",
+            LaunchProtection::AntimalwareLight => "antimalware-light",
+        }
+    }
+}
 
-        // 停止服务
-        self.stop_service_internal(service);
+/// 子进程退出后的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExitAction {
+    /// 重启子进程
+    #[default]
+    Restart,
+    /// 正常停止服务（退出码0，不触发SCM恢复操作）
+    Stop,
+    /// 以失败状态停止服务（服务特定退出码，可触发SCM恢复操作）
+    StopService,
+}
 
-        // 删除服务
-        let result = unsafe { DeleteService(service) };
-        if result == 0 {
-            return Err(anyhow::anyhow!("Failed to delete service"));
+impl ExitAction {
+    /// 注册表和命令行中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExitAction::Restart => "restart",
+            ExitAction::Stop => "stop",
+            ExitAction::StopService => "stop-service",
         }
+    }
+}
 
-        // 关闭服务句柄
-        unsafe { CloseServiceHandle(service); }
+impl std::str::FromStr for ExitAction {
+    type Err = anyhow::Error;
 
-        // 删除注册表配置
-        if let Err(e) = self.delete_service_config(service_name) {
-            warn!("Failed to delete service config: {}", e);
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "restart" => Ok(ExitAction::Restart),
+            "stop" => Ok(ExitAction::Stop),
+            "stop-service" => Ok(ExitAction::StopService),
+            _ => Err(anyhow::anyhow!("Invalid exit action {:?}, expected restart, stop or stop-service", value)),
         }
-
-        info!("Service '{}' uninstalled successfully", service_name);
-        Ok(())
     }
+}
 
-    /// 启动服务
-    pub fn start_service(&self, service_name: &str) -> Result<()> {
-        let service = self.open_service(service_name, SERVICE_ALL_ACCESS)?;
+/// 按退出码决定子进程退出后的处理方式，未列出的退出码使用默认操作
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExitActions {
+    pub codes: BTreeMap<i32, ExitAction>,
+    pub default: ExitAction,
+}
 
-        let result = unsafe { StartServiceW(service, 0, std::ptr::null()) };
-        if result == 0 {
-            return Err(anyhow::anyhow!("Failed to start service"));
+impl ExitActions {
+    /// 解析 `CODE=ACTION` 或 `default=ACTION` 形式的规则，同一退出码以最后一条为准
+    pub fn parse_rules(rules: &[String]) -> Result<Self> {
+        let mut actions = Self::default();
+        for rule in rules {
+            let (code, action) = rule
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid exit action rule {:?}, expected CODE=ACTION", rule))?;
+            let action = action.parse()?;
+            if code.trim().eq_ignore_ascii_case("default") {
+                actions.default = action;
+            } else {
+                actions.codes.insert(parse_exit_code(code)?, action);
+            }
         }
+        Ok(actions)
+    }
 
-        unsafe { CloseServiceHandle(service); }
-        info!("Service '{}' started successfully", service_name);
-        Ok(())
+    /// 转换回规则列表，用于保存到注册表
+    pub fn to_rules(&self) -> Vec<String> {
+        let mut rules: Vec<String> = self
+            .codes
+            .iter()
+            .map(|(code, action)| format!("{}={}", format_exit_code(*code), action.as_str()))
+            .collect();
+        rules.push(format!("default={}", self.default.as_str()));
+        rules
     }
 
-    /// 停止服务
-    pub fn stop_service(&self, service_name: &str) -> Result<()> {
-        let service = self.open_service(service_name, SERVICE_ALL_ACCESS)?;
+    /// 子进程以 `code` 退出后的处理方式（无法获得退出码时使用默认操作）
+    pub fn action_for(&self, code: Option<i32>) -> ExitAction {
+        code.and_then(|code| self.codes.get(&code).copied()).unwrap_or(self.default)
+    }
 
-        self.stop_service_internal(service);
-        unsafe { CloseServiceHandle(service); }
+    /// 是否与默认行为一致（任何退出都重启子进程）
+    pub fn is_default(&self) -> bool {
+        self.codes.values().all(|action| *action == ExitAction::Restart) && self.default == ExitAction::Restart
+    }
+}
 
-        info!("Service '{}' stopped successfully", service_name);
-        Ok(())
+/// `--restart-policy` 提供的常用退出码规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RestartPolicy {
+    /// 无论退出码如何都重启子进程（默认）
+    Always,
+    /// 只在退出码非零时重启，退出码为0时正常停止服务
+    #[value(alias = "on-non-zero-exit")]
+    OnFailure,
+    /// 子进程退出后正常停止服务，适合以服务形式运行的一次性任务
+    Never,
+}
+
+impl RestartPolicy {
+    /// 对应的退出码规则
+    pub fn exit_actions(self) -> ExitActions {
+        let mut actions = ExitActions::default();
+        match self {
+            RestartPolicy::Always => {}
+            RestartPolicy::OnFailure => {
+                actions.codes.insert(0, ExitAction::Stop);
+            }
+            RestartPolicy::Never => actions.default = ExitAction::Stop,
+        }
+        actions
     }
+}
 
-    /// 重启服务
-    pub fn restart_service(&self, service_name: &str) -> Result<()> {
-        self.stop_service(service_name)?;
-        std::thread::sleep(std::time::Duration::from_secs(2));
-        self.start_service(service_name)?;
-        info!("Service '{}' restarted successfully", service_name);
-        Ok(())
+/// 解析退出码：十进制（可为负数）或 `0x` 开头的十六进制（如 `0xC0000005`）
+pub fn parse_exit_code(value: &str) -> Result<i32> {
+    let value = value.trim();
+    let code = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).map(|code| code as i32).ok(),
+        None => value
+            .parse::<i32>()
+            .ok()
+            .or_else(|| value.parse::<u32>().ok().map(|code| code as i32)),
+    };
+    code.ok_or_else(|| anyhow::anyhow!("Invalid exit code {:?}", value))
+}
+
+/// 格式化退出码：NTSTATUS 等大数值以十六进制显示（如 `0xC0000005`）
+pub fn format_exit_code(code: i32) -> String {
+    if (0..=0xFFFF).contains(&code) {
+        code.to_string()
+    } else {
+        format!("0x{:08X}", code as u32)
     }
+}
 
-    /// 获取服务状态
-    pub fn get_service_status(&self, service_name: &str) -> Result<u32> {
-        let service = self.open_service(service_name, SERVICE_QUERY_STATUS)?;
+/// 子进程输出文件的轮转方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    /// 不轮转，一直追加写入同一个文件
+    #[default]
+    None,
+    /// 启动子进程前文件达到 `max_bytes` 时轮转为 `.1`、`.2` 等历史文件，保留 `keep` 个
+    BySize { max_bytes: u64, keep: u32 },
+    /// 写入带日期的文件（如 `service_2024-01-15.log`），跨越日期边界时切换到新文件，保留最近 `keep` 个周期的历史文件
+    ///
+    /// 指定 `max_bytes` 时，同一周期内文件达到该大小也会轮转为 `.1`、`.2` 等历史文件，以先达到的限制为准。
+    ByDate { interval: RotationInterval, keep: u32, max_bytes: Option<u64> },
+}
 
-        let mut status = SERVICE_STATUS {
-            dwServiceType: 0,
-            dwCurrentState: 0,
-            dwControlsAccepted: 0,
-            dwWin32ExitCode: 0,
-            dwServiceSpecificExitCode: 0,
-            dwCheckPoint: 0,
-            dwWaitHint: 0,
-        };
-        let result = unsafe { QueryServiceStatus(service, &mut status) };
+/// 按日期轮转的周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RotationInterval {
+    Daily,
+    Weekly,
+}
 
-        unsafe { CloseServiceHandle(service); }
+impl RotationInterval {
+    /// 注册表和命令行中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RotationInterval::Daily => "daily",
+            RotationInterval::Weekly => "weekly",
+        }
+    }
 
-        if result == 0 {
-            return Err(anyhow::anyhow!("Failed to query service status"));
+    /// `date` 所在周期的第一天（按周轮转时为周一）
+    pub fn period_start(self, date: chrono::NaiveDate) -> chrono::NaiveDate {
+        use chrono::Datelike;
+        match self {
+            RotationInterval::Daily => date,
+            RotationInterval::Weekly => date - chrono::Duration::days(i64::from(date.weekday().num_days_from_monday())),
         }
+    }
 
-        Ok(status.dwCurrentState)
+    /// 一个周期的天数
+    pub fn days(self) -> i64 {
+        match self {
+            RotationInterval::Daily => 1,
+            RotationInterval::Weekly => 7,
+        }
     }
+}
 
-    /// 列出所有服务
-    pub fn list_services(&self) -> Result<Vec<String>> {
-        let mut services = Vec::new();
-        let mut bytes_needed = 0u32;
-        let mut services_returned = 0u32;
-        let mut resume_handle = 0u32;
+/// 停止服务时结束子进程的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum StopMethod {
+    /// 先发送 CTRL_BREAK，等待子进程自行退出，超时后再强制结束
+    #[default]
+    Console,
+    /// 直接强制结束（适用于不处理控制台事件的GUI程序）
+    Kill,
+}
 
-        // 第一次调用获取缓冲区大小
-        unsafe {
-            EnumServicesStatusW(
-                self.scm,
-                SERVICE_WIN32,
-                SERVICE_STATE_ALL,
-                std::ptr::null_mut(),
-                0,
-                &mut bytes_needed,
-                &mut services_returned,
-                &mut resume_handle,
-            );
+impl StopMethod {
+    /// 注册表和命令行中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StopMethod::Console => "console",
+            StopMethod::Kill => "kill",
         }
+    }
+}
 
-        // 分配缓冲区
-        let mut buffer = vec![0u8; bytes_needed as usize];
-        let buffer_ptr = buffer.as_mut_ptr() as *mut ENUM_SERVICE_STATUSW;
+/// 子进程的优先级类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProcessPriorityClass {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+    /// 需要 SeIncreaseBasePriorityPrivilege 权限，否则系统会改用 High
+    Realtime,
+}
 
-        // 获取服务列表
-        let result = unsafe {
-            EnumServicesStatusW(
-                self.scm,
-                SERVICE_WIN32,
-                SERVICE_STATE_ALL,
-                buffer_ptr,
-                bytes_needed,
-                &mut bytes_needed,
-                &mut services_returned,
-                &mut resume_handle,
-            )
+impl ProcessPriorityClass {
+    const ALL: [ProcessPriorityClass; 6] = [
+        ProcessPriorityClass::Idle,
+        ProcessPriorityClass::BelowNormal,
+        ProcessPriorityClass::Normal,
+        ProcessPriorityClass::AboveNormal,
+        ProcessPriorityClass::High,
+        ProcessPriorityClass::Realtime,
+    ];
+
+    /// 命令行中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProcessPriorityClass::Idle => "idle",
+            ProcessPriorityClass::BelowNormal => "below-normal",
+            ProcessPriorityClass::Normal => "normal",
+            ProcessPriorityClass::AboveNormal => "above-normal",
+            ProcessPriorityClass::High => "high",
+            ProcessPriorityClass::Realtime => "realtime",
+        }
+    }
+
+    /// 对应的 Win32 优先级类常量，也是注册表中保存的值（与NSSM的 `AppPriority` 相同）
+    pub fn win32_class(self) -> u32 {
+        use windows_sys::Win32::System::Threading::{
+            ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS, IDLE_PRIORITY_CLASS,
+            NORMAL_PRIORITY_CLASS, REALTIME_PRIORITY_CLASS,
         };
 
-        if result != 0 {
-            let services_slice = unsafe {
-                std::slice::from_raw_parts(buffer_ptr, services_returned as usize)
-            };
+        match self {
+            ProcessPriorityClass::Idle => IDLE_PRIORITY_CLASS,
+            ProcessPriorityClass::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            ProcessPriorityClass::Normal => NORMAL_PRIORITY_CLASS,
+            ProcessPriorityClass::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+            ProcessPriorityClass::High => HIGH_PRIORITY_CLASS,
+            ProcessPriorityClass::Realtime => REALTIME_PRIORITY_CLASS,
+        }
+    }
 
-            for service_info in services_slice {
-                let service_name = unsafe {
-                    OsString::from_wide(std::slice::from_raw_parts(
-                        service_info.lpServiceName,
-                        wcslen(service_info.lpServiceName)
-                    ))
-                    .to_string_lossy()
-                    .to_string()
-                };
-                services.push(service_name);
-            }
+    /// 从 Win32 优先级类常量解析，不是已知的优先级类时返回 `None`
+    pub fn from_win32_class(class: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|priority| priority.win32_class() == class)
+    }
+}
+
+/// 解析CPU亲和性掩码：`0x` 开头的十六进制（如 `0x0F`）或十进制，不能为0
+pub fn parse_affinity_mask(value: &str) -> Result<usize> {
+    let value = value.trim();
+    let mask = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+    match mask {
+        Some(0) => Err(anyhow::anyhow!("CPU affinity mask must include at least one CPU")),
+        Some(mask) => Ok(mask),
+        None => Err(anyhow::anyhow!("Invalid CPU affinity mask {:?}, expected e.g. 0x0F", value)),
+    }
+}
+
+/// 本机可用CPU的亲和性掩码
+pub fn system_affinity_mask() -> Option<usize> {
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessAffinityMask};
+
+    let mut process_mask = 0usize;
+    let mut system_mask = 0usize;
+    let result = unsafe { GetProcessAffinityMask(GetCurrentProcess(), &mut process_mask, &mut system_mask) };
+    (result != 0).then_some(system_mask)
+}
+
+/// 重启规则匹配的子进程输出流
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PatternStream {
+    #[default]
+    Stderr,
+    Stdout,
+    Both,
+}
+
+impl PatternStream {
+    /// 注册表和命令行中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PatternStream::Stderr => "stderr",
+            PatternStream::Stdout => "stdout",
+            PatternStream::Both => "both",
         }
+    }
 
-        Ok(services)
+    /// 是否匹配标准输出
+    pub fn includes_stdout(self) -> bool {
+        self != PatternStream::Stderr
     }
 
-    /// 打开服务
-    fn open_service(&self, service_name: &str, access: u32) -> Result<SC_HANDLE> {
-        let service_name_w = to_wstring(service_name);
-        let service = unsafe {
-            OpenServiceW(self.scm, service_name_w.as_ptr(), access)
-        };
+    /// 是否匹配标准错误
+    pub fn includes_stderr(self) -> bool {
+        self != PatternStream::Stdout
+    }
+}
 
-        if service == 0 {
-            return Err(anyhow::anyhow!("Failed to open service"));
+/// 允许自动重启子进程的每日时间段（本地时间），如 `22:00-06:00`
+///
+/// 结束时间早于开始时间表示跨越午夜；开始与结束相同表示全天。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartWindow {
+    /// 开始时间（自午夜起的分钟数）
+    start: u32,
+    /// 结束时间（自午夜起的分钟数，不含）
+    end: u32,
+}
+
+impl RestartWindow {
+    /// 判断给定时间是否在时间段内
+    pub fn contains(&self, time: chrono::NaiveTime) -> bool {
+        use chrono::Timelike;
+
+        let minute = time.hour() * 60 + time.minute();
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            minute >= self.start && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
         }
+    }
 
-        Ok(service)
+    /// 时间段的开始时间
+    pub fn start_time(&self) -> chrono::NaiveTime {
+        chrono::NaiveTime::from_hms_opt(self.start / 60, self.start % 60, 0).unwrap_or_default()
     }
+}
 
-    /// 停止服务内部实现
-    fn stop_service_internal(&self, service: SC_HANDLE) {
-        let mut status = SERVICE_STATUS {
-            dwServiceType: 0,
-            dwCurrentState: 0,
-            dwControlsAccepted: 0,
-            dwWin32ExitCode: 0,
-            dwServiceSpecificExitCode: 0,
-            dwCheckPoint: 0,
-            dwWaitHint: 0,
+impl std::str::FromStr for RestartWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let parse_time = |time: &str| -> Result<u32> {
+            let time = chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| anyhow::anyhow!("Invalid time '{}' in restart window, expected HH:MM", time.trim()))?;
+            Ok(chrono::Timelike::hour(&time) * 60 + chrono::Timelike::minute(&time))
         };
-        unsafe { ControlService(service, SERVICE_CONTROL_STOP, &mut status); }
+
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("Invalid restart window '{}', expected e.g. 22:00-06:00", value))?;
+        Ok(Self { start: parse_time(start)?, end: parse_time(end)? })
     }
+}
 
-    /// 设置服务描述
-    fn set_service_description(&self, service: SC_HANDLE, description: &str) -> Result<()> {
-        let desc_w = to_wstring(description);
-        let description_info = SERVICE_DESCRIPTIONW {
-            lpDescription: desc_w.as_ptr() as *mut _,
-        };
+impl std::fmt::Display for RestartWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}-{:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+/// SCM中一个服务的基本信息
+///
+/// 序列化为JSON时 `state` 为状态名称（如 `RUNNING`），`state_code` 为状态码，`status_name` 与 `state` 相同。
+#[derive(Debug, Clone)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub display_name: String,
+    /// SCM服务状态（`dwCurrentState`）
+    pub state: u32,
+    /// 服务状态的名称（如 `RUNNING`）
+    pub status_name: &'static str,
+    /// 服务进程ID，服务未运行时为 `None`
+    pub pid: Option<u32>,
+    /// 服务上次停止时报告的退出码，服务特定错误时为服务特定退出码
+    pub exit_code: u32,
+}
+
+impl Serialize for ServiceInfo {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut info = serializer.serialize_struct("ServiceInfo", 7)?;
+        info.serialize_field("name", &self.name)?;
+        info.serialize_field("display_name", &self.display_name)?;
+        info.serialize_field("state", self.status_name)?;
+        info.serialize_field("state_code", &self.state)?;
+        info.serialize_field("status_name", self.status_name)?;
+        info.serialize_field("pid", &self.pid)?;
+        info.serialize_field("exit_code", &self.exit_code)?;
+        info.end()
+    }
+}
+
+/// 服务最近一次启动失败的信息
+#[derive(Debug, Clone)]
+pub struct StartFailure {
+    /// SCM记录的Win32退出码
+    pub win32_exit_code: u32,
+    /// 服务特定退出码（Win32退出码为 ERROR_SERVICE_SPECIFIC_ERROR 时有效）
+    pub service_specific_exit_code: u32,
+    /// 系统事件日志中SCM记录的失败事件
+    pub event: Option<ScmFailureEvent>,
+}
+
+/// SCM写入系统事件日志的服务失败事件
+#[derive(Debug, Clone)]
+pub struct ScmFailureEvent {
+    pub event_id: u32,
+    pub time: chrono::DateTime<chrono::Local>,
+    pub description: &'static str,
+    pub details: Vec<String>,
+}
+
+/// SCM中配置的服务失败恢复操作
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RecoveryActions {
+    /// 失败计数清零前的无失败时间（秒）
+    pub reset_period_secs: u32,
+    /// 恢复操作为运行命令时执行的命令行
+    pub command: Option<String>,
+    /// 服务以非零退出码正常停止时是否也执行恢复操作
+    pub on_non_crash_failures: bool,
+    /// 依次对应第1、2、3次及以后失败的操作
+    pub actions: Vec<RecoveryAction>,
+}
+
+/// 单个恢复操作
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryAction {
+    pub action: &'static str,
+    pub delay_ms: u32,
+}
+
+/// 服务失败后SCM执行的恢复操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureAction {
+    /// 等待指定时间后重启服务
+    RestartService(std::time::Duration),
+    /// 运行程序（程序路径和参数）
+    RunProgram(PathBuf, String),
+    /// 重启计算机（需要 `SeShutdownPrivilege`）
+    Reboot,
+    /// 不执行任何操作
+    None,
+}
+
+impl FailureAction {
+    fn as_sc_action(&self) -> SC_ACTION {
+        let (action_type, delay) = match self {
+            FailureAction::RestartService(delay) => (SC_ACTION_RESTART, delay.as_millis().min(u32::MAX as u128) as u32),
+            FailureAction::RunProgram(..) => (SC_ACTION_RUN_COMMAND, 0),
+            FailureAction::Reboot => (SC_ACTION_REBOOT, 0),
+            FailureAction::None => (SC_ACTION_NONE, 0),
+        };
+        SC_ACTION { Type: action_type, Delay: delay }
+    }
+
+    /// 运行程序时的命令行
+    fn command_line(&self) -> Option<String> {
+        match self {
+            FailureAction::RunProgram(program, args) if args.is_empty() => Some(format!("\"{}\"", program.display())),
+            FailureAction::RunProgram(program, args) => Some(format!("\"{}\" {}", program.display(), args)),
+            _ => None,
+        }
+    }
+
+    /// 由SCM中的恢复操作还原，运行程序的操作使用服务的恢复命令行
+    fn from_sc_action(action: &SC_ACTION, command: Option<&str>) -> Result<Self> {
+        match action.Type {
+            SC_ACTION_RESTART => Ok(FailureAction::RestartService(std::time::Duration::from_millis(action.Delay as u64))),
+            SC_ACTION_RUN_COMMAND => format!("run:{}", command.unwrap_or_default()).parse(),
+            SC_ACTION_REBOOT => Ok(FailureAction::Reboot),
+            SC_ACTION_NONE => Ok(FailureAction::None),
+            other => Err(anyhow::anyhow!("Unsupported recovery action type {}", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for FailureAction {
+    /// 按 `--recovery-action` 的格式输出，不足整秒的重启等待时间以毫秒表示
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureAction::RestartService(delay) if delay.subsec_millis() == 0 => write!(f, "restart:{}", delay.as_secs()),
+            FailureAction::RestartService(delay) => write!(f, "restart:{}ms", delay.as_millis()),
+            FailureAction::RunProgram(..) => write!(f, "run:{}", self.command_line().unwrap_or_default()),
+            FailureAction::Reboot => f.write_str("reboot"),
+            FailureAction::None => f.write_str("none"),
+        }
+    }
+}
+
+impl std::str::FromStr for FailureAction {
+    type Err = anyhow::Error;
+
+    /// 解析 `restart:SECS`（或 `restart:MSms`）、`run:PROGRAM [ARGS]`、`reboot` 或 `none`
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if let Some(delay) = value.strip_prefix("restart:") {
+            let delay = delay.trim();
+            let delay = match delay.strip_suffix("ms") {
+                Some(ms) => ms.parse().map(std::time::Duration::from_millis),
+                None => delay.parse().map(std::time::Duration::from_secs),
+            }
+            .context(format!("Invalid restart delay in {:?}", value))?;
+            return Ok(FailureAction::RestartService(delay));
+        }
+        if let Some(command) = value.strip_prefix("run:") {
+            let command = command.trim();
+            // 带空格的程序路径需用引号括起
+            let (program, args) = match command.strip_prefix('"') {
+                Some(rest) => rest.split_once('"').context(format!("Unterminated quote in {:?}", value))?,
+                None => command.split_once(char::is_whitespace).unwrap_or((command, "")),
+            };
+            if program.is_empty() {
+                return Err(anyhow::anyhow!("Missing program in {:?}", value));
+            }
+            return Ok(FailureAction::RunProgram(PathBuf::from(program), args.trim().to_string()));
+        }
+        match value {
+            "reboot" => Ok(FailureAction::Reboot),
+            "none" => Ok(FailureAction::None),
+            _ => Err(anyhow::anyhow!(
+                "Invalid recovery action {:?}, expected restart:SECS, run:PROGRAM [ARGS], reboot or none",
+                value
+            )),
+        }
+    }
+}
+
+/// 健康检查的默认间隔（秒）
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: u64 = 10;
+/// 判定子进程不健康所需的默认连续失败次数
+pub const DEFAULT_HEALTH_CHECK_FAILURES: u32 = 3;
+/// 失败后恢复健康所需的默认连续成功次数
+pub const DEFAULT_HEALTH_CHECK_SUCCESSES: u32 = 1;
+/// HTTP健康检查默认期望的状态码
+pub const DEFAULT_HEALTH_CHECK_STATUS: u16 = 200;
+/// HTTP健康检查单次请求的默认超时时间（毫秒）
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT_MS: u64 = 2000;
+
+/// 健康检查的对象
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HealthCheckTarget {
+    /// 能连接 `127.0.0.1:<port>` 即视为健康
+    Tcp { port: u16 },
+    /// `GET` 请求返回期望的状态码（且响应正文包含指定文本）即视为健康
+    Http {
+        url: String,
+        expected_status: u16,
+        timeout_ms: u64,
+        body_contains: Option<String>,
+    },
+}
+
+impl std::fmt::Display for HealthCheckTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthCheckTarget::Tcp { port } => write!(f, "tcp://127.0.0.1:{}", port),
+            HealthCheckTarget::Http { url, expected_status, .. } => write!(f, "GET {} (expecting {})", url, expected_status),
+        }
+    }
+}
+
+/// 健康检查配置：按间隔检查子进程，连续失败达到阈值时重启子进程
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthCheckConfig {
+    /// 检查的对象
+    pub target: HealthCheckTarget,
+    /// 两次检查之间的间隔（秒）
+    pub interval_secs: u64,
+    /// 连续失败多少次后结束子进程
+    pub failure_threshold: u32,
+    /// 出现失败后连续成功多少次才清零失败计数
+    pub success_threshold: u32,
+}
+
+impl HealthCheckConfig {
+    /// 使用默认间隔和阈值检查 `target`
+    pub fn new(target: HealthCheckTarget) -> Self {
+        Self {
+            target,
+            interval_secs: DEFAULT_HEALTH_CHECK_INTERVAL,
+            failure_threshold: DEFAULT_HEALTH_CHECK_FAILURES,
+            success_threshold: DEFAULT_HEALTH_CHECK_SUCCESSES,
+        }
+    }
+
+    /// 两次检查之间的间隔
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// 未指定时SCM失败计数清零前的无失败时间（秒）
+pub const DEFAULT_RECOVERY_RESET_PERIOD: u32 = 86400;
+
+/// 安装时写入SCM的失败恢复配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryConfig {
+    /// 第1次失败的操作
+    pub first: FailureAction,
+    /// 第2次失败的操作
+    pub second: FailureAction,
+    /// 第3次及以后失败的操作
+    pub subsequent: FailureAction,
+    /// 失败计数清零前的无失败时间（秒）
+    pub reset_period_secs: u32,
+    /// 重启计算机前向用户广播的消息
+    pub reboot_message: Option<String>,
+}
+
+impl RecoveryConfig {
+    /// 每次失败都在 `delay` 后重启服务
+    pub fn restart_after(delay: std::time::Duration, reset_period_secs: u32) -> Self {
+        Self {
+            first: FailureAction::RestartService(delay),
+            second: FailureAction::RestartService(delay),
+            subsequent: FailureAction::RestartService(delay),
+            reset_period_secs,
+            reboot_message: None,
+        }
+    }
+
+    /// 按顺序对应第1、2、3次及以后的失败，不足三个时最后一个操作用于之后的所有失败
+    pub fn from_actions(actions: &[FailureAction], reset_period_secs: u32, reboot_message: Option<String>) -> Result<Self> {
+        let (first, last) = match actions {
+            [] => return Err(anyhow::anyhow!("At least one recovery action is required")),
+            [first, ..] if actions.len() <= 3 => (first, &actions[actions.len() - 1]),
+            _ => return Err(anyhow::anyhow!("At most three recovery actions can be configured")),
+        };
+        Ok(Self {
+            first: first.clone(),
+            second: actions.get(1).unwrap_or(last).clone(),
+            subsequent: last.clone(),
+            reset_period_secs,
+            reboot_message,
+        })
+    }
+
+    /// `from_actions` 的逆操作：去掉末尾重复的操作
+    pub fn to_actions(&self) -> Vec<FailureAction> {
+        let mut actions = vec![self.first.clone(), self.second.clone(), self.subsequent.clone()];
+        while actions.len() > 1 && actions[actions.len() - 2] == actions[actions.len() - 1] {
+            actions.pop();
+        }
+        actions
+    }
+
+    fn actions(&self) -> [&FailureAction; 3] {
+        [&self.first, &self.second, &self.subsequent]
+    }
+
+    /// SCM只为服务保存一条恢复命令，多个运行程序的操作必须使用同一命令行
+    fn command_line(&self) -> Result<Option<String>> {
+        let mut command: Option<String> = None;
+        for line in self.actions().into_iter().filter_map(FailureAction::command_line) {
+            match &command {
+                Some(existing) if *existing != line => {
+                    return Err(anyhow::anyhow!("All run-program recovery actions must use the same command line"));
+                }
+                _ => command = Some(line),
+            }
+        }
+        Ok(command)
+    }
+}
+
+/// 未指定时等待服务停止的最长时间
+pub const DEFAULT_STOP_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+
+// 等待服务停止时的初始查询间隔和最长间隔
+const STOP_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+const MAX_STOP_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+// 等待服务启动时的最长查询间隔
+const MAX_START_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// 按服务名称排序（SCM中服务名称不区分大小写），保证列表输出顺序稳定
+fn sort_services_by_name(services: &mut [ServiceInfo]) {
+    services.sort_by_cached_key(|service| (service.name.to_lowercase(), service.name.clone()));
+}
+
+/// 等待已发出启动请求的服务进入运行状态
+fn wait_for_running(service: SC_HANDLE, service_name: &str, timeout: std::time::Duration) -> Result<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let status = query_status_process(service)?;
+        match status.dwCurrentState {
+            SERVICE_RUNNING => return Ok(()),
+            SERVICE_STOPPED | SERVICE_STOP_PENDING => {
+                return Err(anyhow::anyhow!(
+                    "Service '{}' stopped during startup (exit code {})",
+                    service_name,
+                    service_exit_code(&status)
+                ));
+            }
+            _ => {}
+        }
+
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(anyhow::anyhow!(
+                "Service '{}' did not start within {}s (state: {})",
+                service_name,
+                timeout.as_secs(),
+                service_state_name(status.dwCurrentState)
+            ));
+        }
+        std::thread::sleep(start_poll_interval(status.dwWaitHint).min(remaining));
+    }
+}
+
+/// 启动中的查询间隔：等待提示的十分之一，限制在100毫秒到10秒之间
+fn start_poll_interval(wait_hint_ms: u32) -> std::time::Duration {
+    std::time::Duration::from_millis(u64::from(wait_hint_ms / 10)).clamp(STOP_POLL_INTERVAL, MAX_START_POLL_INTERVAL)
+}
+
+/// 服务报告的退出码：`ERROR_SERVICE_SPECIFIC_ERROR` 时取服务特定退出码
+fn service_exit_code(status: &SERVICE_STATUS_PROCESS) -> u32 {
+    if status.dwWin32ExitCode == ERROR_SERVICE_SPECIFIC_ERROR {
+        status.dwServiceSpecificExitCode
+    } else {
+        status.dwWin32ExitCode
+    }
+}
+
+/// 反复调用 `query_state` 直到服务进入已停止状态，超过 `timeout` 仍未停止时返回错误
+fn wait_for_stopped(
+    service_name: &str,
+    timeout: std::time::Duration,
+    mut query_state: impl FnMut() -> Result<u32>,
+) -> Result<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    let mut interval = STOP_POLL_INTERVAL;
+    loop {
+        let state = query_state()?;
+        if state == SERVICE_STOPPED {
+            return Ok(());
+        }
+
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(anyhow::anyhow!(
+                "Service '{}' did not stop within {}s (state: {})",
+                service_name,
+                timeout.as_secs(),
+                service_state_name(state)
+            ));
+        }
+        std::thread::sleep(interval.min(remaining));
+        interval = next_poll_interval(interval);
+    }
+}
+
+/// 等待服务停止的下一次查询间隔（指数退避）
+fn next_poll_interval(interval: std::time::Duration) -> std::time::Duration {
+    (interval * 2).min(MAX_STOP_POLL_INTERVAL)
+}
+
+// 查找SCM失败事件时最多回溯的事件条数
+const MAX_SCANNED_EVENTS: usize = 5000;
+
+/// 服务管理器
+pub struct ServiceManager {
+    scm: SC_HANDLE,
+}
+
+impl ServiceManager {
+    /// 创建新的服务管理器
+    pub fn new() -> Result<Self> {
+        let scm = unsafe {
+            OpenSCManagerW(
+                std::ptr::null(),
+                std::ptr::null(),
+                SC_MANAGER_ALL_ACCESS,
+            )
+        };
+
+        if scm == 0 {
+            return Err(anyhow::anyhow!("Failed to open Service Control Manager"));
+        }
+
+        Ok(Self { scm })
+    }
+
+    /// 安装服务
+    pub fn install_service(&self, config: &ServiceConfig) -> Result<()> {
+        validate_service_name(&config.name)?;
+
+        let service_name = to_wstring(&config.name);
+        let display_name = to_wstring(&config.display_name);
+        let binary_path = host_binary_path(&config.name)?;
+
+        // 用户账户需要"作为服务登录"权限，否则服务启动时报 1069 错误
+        let start_name = config.service_account.as_ref().and_then(ServiceAccount::start_name);
+        if let Some(ServiceAccount::User { .. }) = &config.service_account {
+            if let Some(account) = &start_name {
+                grant_service_logon_right(account)?;
+            }
+        }
+        let start_name = start_name.as_deref().map(to_wstring);
+        let password = config.service_account.as_ref().and_then(ServiceAccount::password).map(to_wstring);
+        let dependencies = (!config.dependencies.is_empty()).then(|| dependency_list(&config.dependencies));
+
+        // 创建服务
+        let service = unsafe {
+            CreateServiceW(
+                self.scm,
+                service_name.as_ptr(),
+                display_name.as_ptr(),
+                SERVICE_ALL_ACCESS,
+                SERVICE_WIN32_OWN_PROCESS,
+                config.start_type.as_raw(),
+                SERVICE_ERROR_NORMAL,
+                binary_path.as_ptr(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                dependencies.as_ref().map_or(std::ptr::null(), |dependencies| dependencies.as_ptr()),
+                start_name.as_ref().map_or(std::ptr::null(), |name| name.as_ptr()),
+                password.as_ref().map_or(std::ptr::null(), |password| password.as_ptr()),
+            )
+        };
+
+        if service == 0 {
+            let error = unsafe { GetLastError() };
+            if error == ERROR_SERVICE_EXISTS {
+                return Err(anyhow::anyhow!("Service already exists"));
+            }
+            return Err(anyhow::anyhow!("Failed to create service: error {}", error));
+        }
+
+        // 设置服务描述
+        if let Err(e) = self.set_service_description(service, &config.description) {
+            warn!("Failed to set service description: {}", e);
+        }
+
+        // 设置启动保护，系统拒绝时撤销安装
+        if config.launch_protection != LaunchProtection::None {
+            if let Err(e) = self.set_launch_protection(service, config.launch_protection) {
+                unsafe {
+                    DeleteService(service);
+                    CloseServiceHandle(service);
+                }
+                return Err(e);
+            }
+        }
+
+        if config.start_type == ServiceStartType::AutoDelayedStart {
+            if let Err(e) = self.set_delayed_auto_start(service, true) {
+                warn!("Failed to enable delayed auto-start: {}", e);
+            }
+        }
+
+        if let Some(recovery) = &config.recovery_actions {
+            if let Err(e) = self.set_failure_actions(service, recovery) {
+                warn!("Failed to configure recovery actions: {}", e);
+            }
+        }
+
+        // 保存额外配置
+        if let Err(e) = self.save_service_config(config) {
+            warn!("Failed to save service config: {}", e);
+        }
+
+        self.sync_event_source(config);
+
+        // 关闭服务句柄
+        unsafe { CloseServiceHandle(service); }
+
+        info!("Service '{}' installed successfully", config.name);
+        Ok(())
+    }
+
+    /// 以新配置整体替换已安装服务的配置
+    ///
+    /// 通过 `ChangeServiceConfigW` 更新命令行、显示名称、启动类型、依赖和账户，
+    /// 再更新描述、延迟启动和恢复操作，最后把新配置写入 `Parameters` 注册表项：只写入变化的值，
+    /// 删除新配置不再设置的值，宿主写入的运行记录保持不变。服务不存在时返回错误，不会创建服务。
+    pub fn update_service(&self, config: &ServiceConfig) -> Result<()> {
+        validate_service_name(&config.name)?;
+
+        let service = self.open_service(&config.name, SERVICE_CHANGE_CONFIG | SERVICE_QUERY_CONFIG)?;
+        let result = self.change_service_config(service, config);
+        unsafe { CloseServiceHandle(service); }
+        result?;
+
+        self.save_service_config(config)?;
+        self.sync_event_source(config);
+
+        info!("Service '{}' updated successfully", config.name);
+        Ok(())
+    }
+
+    /// 把配置中保存在SCM的部分写入已打开的服务
+    fn change_service_config(&self, service: SC_HANDLE, config: &ServiceConfig) -> Result<()> {
+        let binary_path = host_binary_path(&config.name)?;
+        let display_name = to_wstring(&config.display_name);
+        let dependencies = dependency_list(&config.dependencies);
+
+        // 改回 LocalSystem 时需要显式指定账户名和空密码
+        let account = config.service_account.clone().unwrap_or(ServiceAccount::LocalSystem);
+        if let (ServiceAccount::User { .. }, Some(name)) = (&account, account.start_name()) {
+            grant_service_logon_right(&name)?;
+        }
+        let start_name = to_wstring(&account.start_name().unwrap_or_else(|| "LocalSystem".to_string()));
+        let password = match &account {
+            ServiceAccount::User { password, .. } => password.as_deref().map(to_wstring),
+            _ => Some(to_wstring("")),
+        };
+
+        let result = unsafe {
+            ChangeServiceConfigW(
+                service,
+                SERVICE_NO_CHANGE,
+                config.start_type.as_raw(),
+                SERVICE_NO_CHANGE,
+                binary_path.as_ptr(),
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                dependencies.as_ptr(),
+                start_name.as_ptr(),
+                password.as_ref().map_or(std::ptr::null(), |password| password.as_ptr()),
+                display_name.as_ptr(),
+            )
+        };
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to change service config: error {}", error));
+        }
+
+        self.set_service_description(service, &config.description)?;
+        self.set_delayed_auto_start(service, config.start_type == ServiceStartType::AutoDelayedStart)?;
+
+        match &config.recovery_actions {
+            Some(recovery) => self.set_failure_actions(service, recovery)?,
+            None => self.clear_failure_actions(service)?,
+        }
+
+        if config.launch_protection != LaunchProtection::None {
+            self.set_launch_protection(service, config.launch_protection)?;
+        }
+
+        Ok(())
+    }
+
+    /// 将已存在的服务（如由NSSM创建的服务）改为由 rust-nssm 托管
+    ///
+    /// 只修改服务的可执行文件路径并写入 rust-nssm 的配置，新配置在服务下次启动时生效。
+    pub fn adopt_service(&self, config: &ServiceConfig) -> Result<()> {
+        validate_service_name(&config.name)?;
+
+        let service = self.open_service(&config.name, SERVICE_CHANGE_CONFIG)?;
+        let result = host_binary_path(&config.name).and_then(|binary_path| change_binary_path(service, &binary_path));
+        unsafe { CloseServiceHandle(service); }
+        result?;
+
+        self.save_service_config(config)?;
+        self.sync_event_source(config);
+
+        info!("Service '{}' is now managed by rust-nssm", config.name);
+        Ok(())
+    }
+
+    /// 卸载服务
+    pub fn uninstall_service(&self, service_name: &str) -> Result<()> {
+        let service = self.open_service(service_name, SERVICE_ALL_ACCESS)?;
+
+        // 停止服务
+        self.stop_service_internal(service);
+
+        // 删除服务
+        let result = unsafe { DeleteService(service) };
+        if result == 0 {
+            return Err(anyhow::anyhow!("Failed to delete service"));
+        }
+
+        // 关闭服务句柄
+        unsafe { CloseServiceHandle(service); }
+
+        // 删除注册表配置
+        if let Err(e) = self.delete_service_config(service_name) {
+            warn!("Failed to delete service config: {}", e);
+        }
+        if let Err(e) = self.unregister_event_source(service_name) {
+            warn!("{}", e);
+        }
+
+        info!("Service '{}' uninstalled successfully", service_name);
+        Ok(())
+    }
+
+    /// 启动服务
+    pub fn start_service(&self, service_name: &str) -> Result<()> {
+        let service = self.open_service(service_name, SERVICE_ALL_ACCESS)?;
+
+        let result = unsafe { StartServiceW(service, 0, std::ptr::null()) };
+        if result == 0 {
+            return Err(anyhow::anyhow!("Failed to start service"));
+        }
+
+        unsafe { CloseServiceHandle(service); }
+        info!("Service '{}' started successfully", service_name);
+        Ok(())
+    }
+
+    /// 停止服务
+    pub fn stop_service(&self, service_name: &str) -> Result<()> {
+        let service = self.open_service(service_name, SERVICE_ALL_ACCESS)?;
+
+        self.stop_service_internal(service);
+        unsafe { CloseServiceHandle(service); }
+
+        info!("Service '{}' stopped successfully", service_name);
+        Ok(())
+    }
+
+    /// 启动服务并等待其进入运行状态，超过 `timeout` 仍未运行或启动期间停止时返回错误
+    ///
+    /// 启动中按服务报告的等待提示（`dwWaitHint` 的十分之一）决定查询间隔。
+    pub fn start_service_and_wait(&self, service_name: &str, timeout: std::time::Duration) -> Result<()> {
+        self.start_service(service_name)?;
+
+        let service = self.open_service(service_name, SERVICE_QUERY_STATUS)?;
+        let result = wait_for_running(service, service_name, timeout);
+        unsafe { CloseServiceHandle(service); }
+        result
+    }
+
+    /// 停止服务并等待其进入已停止状态，超过 `timeout` 仍未停止时返回错误
+    ///
+    /// 查询间隔从100毫秒开始逐次翻倍，最长2秒。
+    pub fn stop_service_and_wait(&self, service_name: &str, timeout: std::time::Duration) -> Result<()> {
+        self.stop_service(service_name)?;
+        wait_for_stopped(service_name, timeout, || self.get_service_status(service_name))
+    }
+
+    /// 暂停服务：服务宿主挂起子进程树的全部线程
+    pub fn pause_service(&self, service_name: &str) -> Result<()> {
+        self.send_pause_control(service_name, SERVICE_CONTROL_PAUSE, SERVICE_PAUSED)?;
+        info!("Service '{}' paused successfully", service_name);
+        Ok(())
+    }
+
+    /// 继续运行已暂停的服务
+    pub fn continue_service(&self, service_name: &str) -> Result<()> {
+        self.send_pause_control(service_name, SERVICE_CONTROL_CONTINUE, SERVICE_RUNNING)?;
+        info!("Service '{}' continued successfully", service_name);
+        Ok(())
+    }
+
+    /// 发送暂停/继续控制码，服务不接受时返回错误，控制完成后服务应处于 `expected_state`
+    fn send_pause_control(&self, service_name: &str, control: u32, expected_state: u32) -> Result<()> {
+        let service = self.open_service(service_name, SERVICE_QUERY_STATUS | SERVICE_PAUSE_CONTINUE)?;
+
+        let result = (|| {
+            let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+            if unsafe { QueryServiceStatus(service, &mut status) } == 0 {
+                return Err(anyhow::anyhow!("Failed to query service status: error {}", unsafe { GetLastError() }));
+            }
+            if status.dwControlsAccepted & SERVICE_ACCEPT_PAUSE_CONTINUE == 0 {
+                return Err(anyhow::anyhow!(
+                    "Service does not accept pause/continue in its current state ({})",
+                    service_state_name(status.dwCurrentState)
+                ));
+            }
+
+            if unsafe { ControlService(service, control, &mut status) } == 0 {
+                return Err(anyhow::anyhow!("Failed to send control to service: error {}", unsafe { GetLastError() }));
+            }
+            if status.dwCurrentState != expected_state {
+                return Err(anyhow::anyhow!("Service is {} instead of {}", service_state_name(status.dwCurrentState), service_state_name(expected_state)));
+            }
+            Ok(())
+        })();
+
+        unsafe { CloseServiceHandle(service); }
+        result
+    }
+
+    /// 重启服务
+    ///
+    /// 指定 `start_timeout` 时等待服务重新进入运行状态。
+    pub fn restart_service(
+        &self,
+        service_name: &str,
+        stop_timeout: std::time::Duration,
+        start_timeout: Option<std::time::Duration>,
+    ) -> Result<()> {
+        self.stop_service_and_wait(service_name, stop_timeout)?;
+        match start_timeout {
+            Some(timeout) => self.start_service_and_wait(service_name, timeout)?,
+            None => self.start_service(service_name)?,
+        }
+        info!("Service '{}' restarted successfully", service_name);
+        Ok(())
+    }
+
+    /// 禁用服务：记录当前启动类型后设为 DISABLED 并停止服务，返回原启动类型
+    pub fn disable_service(&self, service_name: &str) -> Result<u32> {
+        let service = self.open_service(service_name, SERVICE_QUERY_CONFIG | SERVICE_CHANGE_CONFIG | SERVICE_STOP)?;
+
+        let result = (|| {
+            let prior = query_start_type(service)?;
+
+            // 已禁用时保留之前记录的启动类型，避免被 DISABLED 覆盖
+            if prior != SERVICE_DISABLED {
+                write_parameter_dword(service_name, "PriorStartType", prior)?;
+                change_start_type(service, SERVICE_DISABLED)?;
+            }
+
+            self.stop_service_internal(service);
+            Ok(prior)
+        })();
+
+        unsafe { CloseServiceHandle(service); }
+
+        if result.is_ok() {
+            info!("Service '{}' disabled", service_name);
+        }
+        result
+    }
+
+    /// 启用服务：恢复禁用前记录的启动类型，没有记录时设为手动启动，返回恢复后的启动类型
+    pub fn enable_service(&self, service_name: &str) -> Result<u32> {
+        let start_type = match read_parameter_dword(service_name, "PriorStartType") {
+            Ok(start_type) if start_type != SERVICE_DISABLED => start_type,
+            Ok(start_type) => {
+                warn!("Invalid PriorStartType {}, falling back to manual start", start_type);
+                SERVICE_DEMAND_START
+            }
+            Err(e) => {
+                warn!("No prior start type recorded for '{}' ({}), falling back to manual start", service_name, e);
+                SERVICE_DEMAND_START
+            }
+        };
+
+        let service = self.open_service(service_name, SERVICE_CHANGE_CONFIG)?;
+        let result = change_start_type(service, start_type);
+        unsafe { CloseServiceHandle(service); }
+        result?;
+
+        delete_parameter_value(service_name, "PriorStartType");
+
+        info!("Service '{}' enabled with start type {}", service_name, start_type_name(start_type));
+        Ok(start_type)
+    }
+
+    /// 修改服务的启动类型，无需重新安装；同时清除维护模式记录的原启动类型
+    pub fn set_start_type(&self, service_name: &str, start_type: ServiceStartType) -> Result<()> {
+        let service = self.open_service(service_name, SERVICE_CHANGE_CONFIG)?;
+        let result = change_start_type(service, start_type.as_raw())
+            .and_then(|_| self.set_delayed_auto_start(service, start_type == ServiceStartType::AutoDelayedStart));
+        unsafe { CloseServiceHandle(service); }
+        result?;
+
+        delete_parameter_value(service_name, "PriorStartType");
+
+        info!("Service '{}' start type set to {}", service_name, start_type.as_str());
+        Ok(())
+    }
+
+    /// 替换已安装服务的依赖列表，空列表表示取消所有依赖
+    pub fn set_dependencies(&self, service_name: &str, dependencies: &[String]) -> Result<()> {
+        let service = self.open_service(service_name, SERVICE_CHANGE_CONFIG)?;
+        let result = change_dependencies(service, dependencies);
+        unsafe { CloseServiceHandle(service); }
+        result?;
+
+        // 同步 Parameters 中的记录，使 edit / 配置查询看到相同的列表
+        if dependencies.is_empty() {
+            delete_parameter_value(service_name, "Dependencies");
+        } else {
+            write_parameter_string(service_name, "Dependencies", &serde_json::to_string(dependencies)?)?;
+        }
+
+        info!("Service '{}' dependencies set to [{}]", service_name, dependencies.join(", "));
+        Ok(())
+    }
+
+    /// 查询服务的启动类型，以及自动启动时是否延迟启动
+    pub fn start_type(&self, service_name: &str) -> Result<(u32, bool)> {
+        let service = self.open_service(service_name, SERVICE_QUERY_CONFIG)?;
+
+        let result = query_start_type(service).and_then(|start_type| {
+            let buffer = query_service_config2(service, SERVICE_CONFIG_DELAYED_AUTO_START_INFO)?;
+            let info = unsafe { &*(buffer.as_ptr() as *const SERVICE_DELAYED_AUTO_START_INFO) };
+            Ok((start_type, info.fDelayedAutostart != 0))
+        });
+
+        unsafe { CloseServiceHandle(service); }
+        result
+    }
+
+    /// 查询SCM中配置的服务失败恢复操作
+    pub fn recovery_actions(&self, service_name: &str) -> Result<RecoveryActions> {
+        let service = self.open_service(service_name, SERVICE_QUERY_CONFIG)?;
+
+        let result = (|| {
+            let buffer = query_service_config2(service, SERVICE_CONFIG_FAILURE_ACTIONS)?;
+            let info = unsafe { &*(buffer.as_ptr() as *const SERVICE_FAILURE_ACTIONSW) };
+
+            let actions = if info.lpsaActions.is_null() {
+                &[][..]
+            } else {
+                unsafe { std::slice::from_raw_parts(info.lpsaActions, info.cActions as usize) }
+            };
+            let command = (!info.lpCommand.is_null())
+                .then(|| unsafe {
+                    OsString::from_wide(std::slice::from_raw_parts(info.lpCommand, wcslen(info.lpCommand)))
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .filter(|command| !command.is_empty());
+
+            let buffer = query_service_config2(service, SERVICE_CONFIG_FAILURE_ACTIONS_FLAG)?;
+            let flag = unsafe { &*(buffer.as_ptr() as *const SERVICE_FAILURE_ACTIONS_FLAG) };
+
+            Ok(RecoveryActions {
+                reset_period_secs: info.dwResetPeriod,
+                command,
+                on_non_crash_failures: flag.fFailureActionsOnNonCrashFailures != 0,
+                actions: actions
+                    .iter()
+                    .map(|action| RecoveryAction {
+                        action: recovery_action_name(action.Type),
+                        delay_ms: action.Delay,
+                    })
+                    .collect(),
+            })
+        })();
+
+        unsafe { CloseServiceHandle(service); }
+        result
+    }
+
+    /// 从SCM和 Parameters 注册表项读回服务的完整配置
+    ///
+    /// 账户密码无法读回，始终为 `None`。
+    pub fn get_service_config(&self, service_name: &str) -> Result<ServiceConfig> {
+        let service = self.open_service(service_name, SERVICE_QUERY_CONFIG)?;
+        let result = query_service_config(service).and_then(|buffer| {
+            let config = unsafe { &*(buffer.as_ptr() as *const QUERY_SERVICE_CONFIGW) };
+            query_scm_config(service, config)
+        });
+        unsafe { CloseServiceHandle(service); }
+        let scm_config = result?;
+
+        // 上面已确认服务存在，这里只读取 Parameters 注册表项
+        let mut config = crate::service_host::load_parameters(service_name)?;
+        if config.executable_path.as_os_str().is_empty() {
+            return Err(anyhow::anyhow!("Service '{}' is not managed by rust-nssm", service_name));
+        }
+
+        config.display_name = scm_config.display_name;
+        config.description = scm_config.description;
+        config.start_type = scm_config.start_type;
+        config.service_account = scm_config.service_account;
+        config.recovery_actions = scm_config.recovery_actions;
+        config.launch_protection = scm_config.launch_protection;
+        config.dependencies = scm_config.dependencies;
+        Ok(config)
+    }
+
+    /// 查询服务在SCM中登记的命令行
+    pub fn binary_path(&self, service_name: &str) -> Result<String> {
+        let service = self.open_service(service_name, SERVICE_QUERY_CONFIG)?;
+        let result = query_binary_path(service);
+        unsafe { CloseServiceHandle(service); }
+        result
+    }
+
+    /// 修改服务在SCM中登记的命令行
+    pub fn set_binary_path(&self, service_name: &str, command_line: &str) -> Result<()> {
+        let service = self.open_service(service_name, SERVICE_CHANGE_CONFIG)?;
+        let result = change_binary_path(service, &to_wstring(command_line));
+        unsafe { CloseServiceHandle(service); }
+        result
+    }
+
+    /// 获取服务状态
+    pub fn get_service_status(&self, service_name: &str) -> Result<u32> {
+        let service = self.open_service(service_name, SERVICE_QUERY_STATUS)?;
+
+        let mut status = SERVICE_STATUS {
+            dwServiceType: 0,
+            dwCurrentState: 0,
+            dwControlsAccepted: 0,
+            dwWin32ExitCode: 0,
+            dwServiceSpecificExitCode: 0,
+            dwCheckPoint: 0,
+            dwWaitHint: 0,
+        };
+        let result = unsafe { QueryServiceStatus(service, &mut status) };
+
+        unsafe { CloseServiceHandle(service); }
+
+        if result == 0 {
+            return Err(anyhow::anyhow!("Failed to query service status"));
+        }
+
+        Ok(status.dwCurrentState)
+    }
+
+    /// 查询服务最近一次启动失败的原因
+    ///
+    /// 结合 `QueryServiceStatusEx` 的退出码和系统事件日志中SCM记录的失败事件，
+    /// 用于排查只在开机启动时出现、无法交互复现的故障。服务未处于失败停止状态时返回 `None`。
+    pub fn last_start_failure(&self, service_name: &str) -> Result<Option<StartFailure>> {
+        let service = self.open_service(service_name, SERVICE_QUERY_STATUS | SERVICE_QUERY_CONFIG)?;
+
+        let status = query_status_process(service);
+        let display_name = query_display_name(service);
+        unsafe { CloseServiceHandle(service); }
+
+        let status = status?;
+        if status.dwCurrentState != SERVICE_STOPPED || status.dwWin32ExitCode == 0 {
+            return Ok(None);
+        }
+
+        let display_name = display_name.unwrap_or_else(|_| service_name.to_string());
+        let event = match find_scm_failure_event(service_name, &display_name) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Failed to search the System event log: {}", e);
+                None
+            }
+        };
+
+        Ok(Some(StartFailure {
+            win32_exit_code: status.dwWin32ExitCode,
+            service_specific_exit_code: status.dwServiceSpecificExitCode,
+            event,
+        }))
+    }
+
+    /// 列出所有服务
+    pub fn list_services(&self) -> Result<Vec<String>> {
+        Ok(self.enum_services()?.into_iter().map(|service| service.name).collect())
+    }
+
+    /// 列出所有服务及其当前状态（仅枚举一次SCM）
+    pub fn list_services_with_state(&self) -> Result<Vec<(String, u32)>> {
+        Ok(self.enum_services()?.into_iter().map(|service| (service.name, service.state)).collect())
+    }
+
+    /// 列出所有服务的名称、显示名称、状态和进程ID（仅枚举一次SCM）
+    pub fn list_service_info(&self) -> Result<Vec<ServiceInfo>> {
+        self.enum_services()
+    }
+
+    /// 查询单个服务的名称、显示名称、状态和进程ID
+    pub fn service_info(&self, service_name: &str) -> Result<ServiceInfo> {
+        let service = self.open_service(service_name, SERVICE_QUERY_STATUS | SERVICE_QUERY_CONFIG)?;
+
+        let result = query_status_process(service).map(|status| ServiceInfo {
+            name: service_name.to_string(),
+            display_name: query_display_name(service).unwrap_or_else(|_| service_name.to_string()),
+            state: status.dwCurrentState,
+            status_name: service_state_name(status.dwCurrentState),
+            pid: (status.dwProcessId != 0).then_some(status.dwProcessId),
+            exit_code: service_exit_code(&status),
+        });
+
+        unsafe { CloseServiceHandle(service); }
+        result
+    }
+
+    /// 枚举所有Win32服务的名称、显示名称、状态和进程ID
+    fn enum_services(&self) -> Result<Vec<ServiceInfo>> {
+        let mut services = Vec::new();
+        let mut bytes_needed = 0u32;
+        let mut services_returned = 0u32;
+        let mut resume_handle = 0u32;
+
+        // 第一次调用获取缓冲区大小
+        unsafe {
+            EnumServicesStatusExW(
+                self.scm,
+                SC_ENUM_PROCESS_INFO,
+                SERVICE_WIN32,
+                SERVICE_STATE_ALL,
+                std::ptr::null_mut(),
+                0,
+                &mut bytes_needed,
+                &mut services_returned,
+                &mut resume_handle,
+                std::ptr::null(),
+            );
+        }
+
+        // 分配缓冲区（按8字节对齐，结构体中含指针）
+        let mut buffer = vec![0u64; (bytes_needed as usize).div_ceil(8)];
+        let buffer_ptr = buffer.as_mut_ptr() as *mut ENUM_SERVICE_STATUS_PROCESSW;
+
+        // 获取服务列表
+        let result = unsafe {
+            EnumServicesStatusExW(
+                self.scm,
+                SC_ENUM_PROCESS_INFO,
+                SERVICE_WIN32,
+                SERVICE_STATE_ALL,
+                buffer_ptr as *mut u8,
+                (buffer.len() * 8) as u32,
+                &mut bytes_needed,
+                &mut services_returned,
+                &mut resume_handle,
+                std::ptr::null(),
+            )
+        };
+
+        if result != 0 {
+            let services_slice = unsafe {
+                std::slice::from_raw_parts(buffer_ptr, services_returned as usize)
+            };
+
+            let to_string = |s: *const u16| unsafe {
+                OsString::from_wide(std::slice::from_raw_parts(s, wcslen(s))).to_string_lossy().to_string()
+            };
+            for service_info in services_slice {
+                let status = &service_info.ServiceStatusProcess;
+                services.push(ServiceInfo {
+                    name: to_string(service_info.lpServiceName),
+                    display_name: to_string(service_info.lpDisplayName),
+                    state: status.dwCurrentState,
+                    status_name: service_state_name(status.dwCurrentState),
+                    pid: (status.dwProcessId != 0).then_some(status.dwProcessId),
+                    exit_code: service_exit_code(status),
+                });
+            }
+        }
+
+        sort_services_by_name(&mut services);
+        Ok(services)
+    }
+
+    /// 打开服务
+    fn open_service(&self, service_name: &str, access: u32) -> Result<SC_HANDLE> {
+        let service_name_w = to_wstring(service_name);
+        let service = unsafe {
+            OpenServiceW(self.scm, service_name_w.as_ptr(), access)
+        };
+
+        if service == 0 {
+            return Err(anyhow::anyhow!("Failed to open service"));
+        }
+
+        Ok(service)
+    }
+
+    /// 停止服务内部实现
+    fn stop_service_internal(&self, service: SC_HANDLE) {
+        let mut status = SERVICE_STATUS {
+            dwServiceType: 0,
+            dwCurrentState: 0,
+            dwControlsAccepted: 0,
+            dwWin32ExitCode: 0,
+            dwServiceSpecificExitCode: 0,
+            dwCheckPoint: 0,
+            dwWaitHint: 0,
+        };
+        unsafe { ControlService(service, SERVICE_CONTROL_STOP, &mut status); }
+    }
+
+    /// 设置服务描述
+    fn set_service_description(&self, service: SC_HANDLE, description: &str) -> Result<()> {
+        let desc_w = to_wstring(description);
+        let description_info = SERVICE_DESCRIPTIONW {
+            lpDescription: desc_w.as_ptr() as *mut _,
+        };
+
+        let result = unsafe {
+            ChangeServiceConfig2W(
+                service,
+                SERVICE_CONFIG_DESCRIPTION,
+                &description_info as *const _ as *const _,
+            )
+        };
+
+        if result == 0 {
+            return Err(anyhow::anyhow!("Failed to set service description"));
+        }
+
+        Ok(())
+    }
+
+    /// 设置自动启动的服务是否延迟启动
+    fn set_delayed_auto_start(&self, service: SC_HANDLE, delayed: bool) -> Result<()> {
+        let info = SERVICE_DELAYED_AUTO_START_INFO {
+            fDelayedAutostart: delayed as BOOL,
+        };
+
+        let result = unsafe {
+            ChangeServiceConfig2W(service, SERVICE_CONFIG_DELAYED_AUTO_START_INFO, &info as *const _ as *const _)
+        };
+
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to set delayed auto-start: error {}", error));
+        }
+
+        Ok(())
+    }
+
+    /// 删除服务的全部恢复操作
+    fn clear_failure_actions(&self, service: SC_HANDLE) -> Result<()> {
+        // lpsaActions 为空指针表示不修改，删除时需传入非空指针且 cActions 为0
+        let mut no_action = SC_ACTION { Type: SC_ACTION_NONE, Delay: 0 };
+        let info = SERVICE_FAILURE_ACTIONSW {
+            dwResetPeriod: 0,
+            lpRebootMsg: std::ptr::null_mut(),
+            lpCommand: std::ptr::null_mut(),
+            cActions: 0,
+            lpsaActions: &mut no_action,
+        };
+
+        let result = unsafe {
+            ChangeServiceConfig2W(service, SERVICE_CONFIG_FAILURE_ACTIONS, &info as *const _ as *const _)
+        };
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to clear failure actions: error {}", error));
+        }
+        Ok(())
+    }
+
+    /// 设置服务失败后的恢复操作
+    ///
+    /// 同时启用 `fFailureActionsOnNonCrashFailures`：服务宿主放弃重启子进程时以非零退出码停止，
+    /// 而不是崩溃，不启用时SCM不会对这种失败执行恢复操作。
+    fn set_failure_actions(&self, service: SC_HANDLE, recovery: &RecoveryConfig) -> Result<()> {
+        // 配置重启计算机的操作时，SCM要求调用方已启用关机特权
+        if recovery.actions().contains(&&FailureAction::Reboot) {
+            enable_shutdown_privilege()?;
+        }
+
+        let mut command = recovery.command_line()?.map(|command| to_wstring(&command));
+        let mut reboot_message = recovery.reboot_message.as_deref().map(to_wstring);
+        let mut actions: Vec<SC_ACTION> = recovery.actions().iter().map(|action| action.as_sc_action()).collect();
+
+        let info = SERVICE_FAILURE_ACTIONSW {
+            dwResetPeriod: recovery.reset_period_secs,
+            lpRebootMsg: reboot_message.as_mut().map_or(std::ptr::null_mut(), |message| message.as_mut_ptr()),
+            lpCommand: command.as_mut().map_or(std::ptr::null_mut(), |command| command.as_mut_ptr()),
+            cActions: actions.len() as u32,
+            lpsaActions: actions.as_mut_ptr(),
+        };
+
+        let result = unsafe {
+            ChangeServiceConfig2W(service, SERVICE_CONFIG_FAILURE_ACTIONS, &info as *const _ as *const _)
+        };
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to set failure actions: error {}", error));
+        }
+
+        let flag = SERVICE_FAILURE_ACTIONS_FLAG { fFailureActionsOnNonCrashFailures: 1 };
+        let result = unsafe {
+            ChangeServiceConfig2W(service, SERVICE_CONFIG_FAILURE_ACTIONS_FLAG, &flag as *const _ as *const _)
+        };
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!("Failed to enable recovery on non-crash failures: error {}", error));
+        }
+
+        Ok(())
+    }
+
+    /// 设置服务启动保护级别
+    fn set_launch_protection(&self, service: SC_HANDLE, protection: LaunchProtection) -> Result<()> {
+        let protection_info = SERVICE_LAUNCH_PROTECTED_INFO {
+            dwLaunchProtected: protection.as_raw(),
+        };
+
+        let result = unsafe {
+            ChangeServiceConfig2W(
+                service,
+                SERVICE_CONFIG_LAUNCH_PROTECTED,
+                &protection_info as *const _ as *const _,
+            )
+        };
+
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(anyhow::anyhow!(
+                "Windows rejected launch protection '{}': {} (error {}). Protected services require rust-nssm and every DLL it loads to carry a matching signature{}",
+                protection.as_str(),
+                std::io::Error::from_raw_os_error(error as i32),
+                error,
+                if protection == LaunchProtection::AntimalwareLight { ", plus a registered ELAM driver" } else { "" }
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// 保存服务配置到注册表
+    fn save_service_config(&self, config: &ServiceConfig) -> Result<()> {
+        let key_path = parameters_key_path(&config.name)?;
+        let key_path_w = to_wstring(&key_path);
+
+        let mut hkey = HKEY::default();
+        let result = unsafe {
+            RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                key_path_w.as_ptr(),
+                0,
+                std::ptr::null(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_READ | KEY_WRITE,
+                std::ptr::null(),
+                &mut hkey,
+                std::ptr::null_mut(),
+            )
+        };
+
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!("Failed to create registry key"));
+        }
+
+        let result = write_config_values(hkey, config);
+        unsafe { RegCloseKey(hkey); }
+        result
+    }
+
+    /// 在应用程序事件日志中注册以服务名称为来源的事件源
+    ///
+    /// 消息文件使用 .NET Framework 自带的 EventLogMessages.dll，其中每个事件ID的消息都是 `%1`，
+    /// 事件查看器因此直接显示宿主写入的文本，而不是"找不到事件ID的描述"。
+    fn register_event_source(&self, service_name: &str) -> Result<()> {
+        let key_path_w = to_wstring(&event_source_key_path(service_name)?);
+
+        let mut hkey = HKEY::default();
+        let result = unsafe {
+            RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                key_path_w.as_ptr(),
+                0,
+                std::ptr::null(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_READ | KEY_WRITE,
+                std::ptr::null(),
+                &mut hkey,
+                std::ptr::null_mut(),
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!("Failed to create event source registry key: error {}", result));
+        }
+
+        let types = (EVENTLOG_ERROR_TYPE | EVENTLOG_WARNING_TYPE | EVENTLOG_INFORMATION_TYPE) as u32;
+        let result = set_reg_expand_string(hkey, "EventMessageFile", EVENT_MESSAGE_FILE)
+            .and_then(|_| set_reg_dword(hkey, "TypesSupported", types));
+        unsafe { RegCloseKey(hkey); }
+        result
+    }
+
+    /// 删除服务的事件源注册，未注册时视为成功
+    fn unregister_event_source(&self, service_name: &str) -> Result<()> {
+        let key_path_w = to_wstring(&event_source_key_path(service_name)?);
+
+        let result = unsafe { RegDeleteKeyW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr()) };
+        if result != ERROR_SUCCESS && result != ERROR_FILE_NOT_FOUND {
+            return Err(anyhow::anyhow!("Failed to delete event source registry key: error {}", result));
+        }
+        Ok(())
+    }
+
+    /// 按配置注册或删除服务的事件源
+    fn sync_event_source(&self, config: &ServiceConfig) {
+        let result = if config.event_log_enabled {
+            self.register_event_source(&config.name)
+        } else {
+            self.unregister_event_source(&config.name)
+        };
+        if let Err(e) = result {
+            warn!("{}", e);
+        }
+    }
+
+    /// 删除服务配置
+    fn delete_service_config(&self, service_name: &str) -> Result<()> {
+        let key_path = parameters_key_path(service_name)?;
+        let key_path_w = to_wstring(&key_path);
+
+        let result = unsafe { RegDeleteKeyW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr()) };
+        if result != ERROR_SUCCESS {
+            warn!("Failed to delete service config registry key");
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ServiceManager {
+    fn drop(&mut self) {
+        if self.scm != 0 {
+            unsafe { CloseServiceHandle(self.scm); }
+        }
+    }
+}
+
+/// 由 `ServiceConfig` 写入 `Parameters` 的全部值名称；新配置不再设置的值保存时被删除
+const CONFIG_VALUE_NAMES: &[&str] = &[
+    "WorkingDirectory",
+    "StdoutPath",
+    "StderrPath",
+    "TargetExecutable",
+    "Arguments",
+    "Environment",
+    "EnvClear",
+    "EnvFile",
+    "PidFilePath",
+    "Dependencies",
+    "UpdateCheckInterval",
+    "RestartOnBinaryChange",
+    "RestartWindow",
+    "RestartDelay",
+    "StopMethod",
+    "StopTimeout",
+    "IoRateLimit",
+    "MaxWorkingSet",
+    "Priority",
+    "CpuAffinity",
+    "MaxLogSize",
+    "MaxLogFiles",
+    "LogRotation",
+    "PipeAllowGroup",
+    "IpcPipe",
+    "DisableEventLog",
+    "HostLogPath",
+    "WaitForTree",
+    "CpuStallTimeout",
+    "CpuStallThreshold",
+    "HealthCheckPort",
+    "HealthCheckUrl",
+    "HealthCheckExpectedStatus",
+    "HealthCheckTimeout",
+    "HealthCheckBodyContains",
+    "HealthCheckInterval",
+    "HealthCheckFailureThreshold",
+    "HealthCheckSuccessThreshold",
+    "ConsoleCodepage",
+    "Detached",
+    "LogTimestamps",
+    "FallbackExecutable",
+    "FallbackArguments",
+    "PreferPrimary",
+    "RestartPatterns",
+    "RestartPatternStream",
+    "MaxRestartsPerWindow",
+    "RestartWindowSeconds",
+    "ExitActions",
+    "MaxRestarts",
+    "StableWindow",
+    "MaxStartAttempts",
+    "StartRetryDelay",
+    "StartRetryDelayMax",
+    "RestartJitter",
+    "QuietRestartLogging",
+    "RestartLogBurst",
+    "RestartLogWindow",
+    "ProvisionCommand",
+    "CheckSignature",
+    "RequireSignature",
+    "SignerThumbprint",
+    "LaunchProtection",
+];
+
+/// 写入 `Parameters` 注册表项的配置值，并记录本次设置了哪些值
+struct ParameterWriter {
+    hkey: HKEY,
+    written: Vec<&'static str>,
+}
+
+impl ParameterWriter {
+    fn string(&mut self, name: &'static str, value: &str) -> Result<()> {
+        self.written.push(name);
+        set_reg_string(self.hkey, name, value)
+    }
+
+    fn dword(&mut self, name: &'static str, value: u32) -> Result<()> {
+        self.written.push(name);
+        set_reg_dword(self.hkey, name, value)
+    }
+
+    fn qword(&mut self, name: &'static str, value: u64) -> Result<()> {
+        self.written.push(name);
+        set_reg_value(self.hkey, name, REG_QWORD, &value.to_le_bytes())
+    }
+
+    /// 删除本次未设置的配置值，不存在的值忽略
+    fn remove_unset(self) -> Result<()> {
+        for name in CONFIG_VALUE_NAMES.iter().filter(|name| !self.written.contains(name)) {
+            let name_w = to_wstring(name);
+            let result = unsafe { RegDeleteValueW(self.hkey, name_w.as_ptr()) };
+            if result != ERROR_SUCCESS && result != ERROR_FILE_NOT_FOUND {
+                return Err(anyhow::anyhow!("Failed to delete registry value {}: error {}", name, result));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 把服务配置写入已打开的 `Parameters` 注册表项
+///
+/// 与已存储内容相同的值不会重写，新配置不再设置的值被删除；
+/// 宿主写入的运行记录（如 `PriorStartType`、`BinaryFingerprint`、`Provisioned`）不受影响。
+fn write_config_values(hkey: HKEY, config: &ServiceConfig) -> Result<()> {
+    let mut params = ParameterWriter { hkey, written: Vec::new() };
+
+    // 保存工作目录
+    if let Some(work_dir) = &config.working_directory {
+        params.string("WorkingDirectory", &work_dir.to_string_lossy())?;
+    }
+
+    // 保存输出路径
+    if let Some(stdout_path) = &config.stdout_path {
+        params.string("StdoutPath", &stdout_path.to_string_lossy())?;
+    }
+
+    if let Some(stderr_path) = &config.stderr_path {
+        params.string("StderrPath", &stderr_path.to_string_lossy())?;
+    }
+
+    // 保存目标可执行文件路径
+    params.string("TargetExecutable", &config.executable_path.to_string_lossy())?;
+
+    // 保存参数
+    if !config.arguments.is_empty() {
+        let args_json = serde_json::to_string(&config.arguments)?;
+        params.string("Arguments", &args_json)?;
+    }
+
+    // 保存环境变量
+    if !config.environment.is_empty() {
+        let env_json = serde_json::to_string(&config.environment)?;
+        params.string("Environment", &env_json)?;
+    }
+
+    if config.env_clear {
+        params.string("EnvClear", "1")?;
+    }
+
+    if let Some(env_file) = &config.env_file {
+        params.string("EnvFile", &env_file.to_string_lossy())?;
+    }
+
+    if let Some(pid_file) = &config.pid_file {
+        params.string("PidFilePath", &pid_file.to_string_lossy())?;
+    }
+
+    // 保存服务依赖（SCM中已有一份，这里的副本供 rust-nssm 读取配置时使用）
+    if !config.dependencies.is_empty() {
+        let dependencies_json = serde_json::to_string(&config.dependencies)?;
+        params.string("Dependencies", &dependencies_json)?;
+    }
+
+    // 保存目标程序更新检查设置
+    if let Some(interval) = config.update_check_interval {
+        params.dword("UpdateCheckInterval", saturating_dword(interval))?;
+    }
+
+    if config.restart_on_binary_change {
+        params.string("RestartOnBinaryChange", "1")?;
+    }
+
+    if let Some(window) = &config.restart_window {
+        params.string("RestartWindow", &window.to_string())?;
+    }
+
+    if let Some(delay) = config.restart_delay {
+        params.dword("RestartDelay", saturating_dword(delay))?;
+    }
+
+    if config.stop_method != StopMethod::Console {
+        params.string("StopMethod", config.stop_method.as_str())?;
+    }
+
+    if let Some(timeout) = config.stop_timeout {
+        params.dword("StopTimeout", saturating_dword(timeout))?;
+    }
+
+    // 保存资源限制
+    if let Some(limit) = config.io_rate_limit {
+        params.qword("IoRateLimit", limit)?;
+    }
+
+    if let Some(limit) = config.max_working_set {
+        params.qword("MaxWorkingSet", limit)?;
+    }
+
+    if let Some(priority) = config.priority_class {
+        params.dword("Priority", priority.win32_class())?;
+    }
+
+    // 64位系统上的掩码可超过32个CPU，按 REG_QWORD 保存
+    if let Some(mask) = config.cpu_affinity {
+        params.qword("CpuAffinity", mask as u64)?;
+    }
+
+    match config.log_rotation {
+        LogRotation::None => {}
+        LogRotation::BySize { max_bytes, keep } => {
+            params.qword("MaxLogSize", max_bytes)?;
+            params.dword("MaxLogFiles", keep)?;
+        }
+        LogRotation::ByDate { interval, keep, max_bytes } => {
+            params.string("LogRotation", interval.as_str())?;
+            params.dword("MaxLogFiles", keep)?;
+            if let Some(max_bytes) = max_bytes {
+                params.qword("MaxLogSize", max_bytes)?;
+            }
+        }
+    }
+
+    if let Some(group) = &config.pipe_allow_group {
+        params.string("PipeAllowGroup", group)?;
+    }
+
+    if let Some(pipe) = &config.ipc_pipe_name {
+        params.string("IpcPipe", pipe)?;
+    }
+
+    if !config.event_log_enabled {
+        params.string("DisableEventLog", "1")?;
+    }
+
+    if let Some(path) = &config.host_log_path {
+        params.string("HostLogPath", &path.to_string_lossy())?;
+    }
+
+    if config.wait_for_tree {
+        params.string("WaitForTree", "1")?;
+    }
+
+    if let Some(timeout) = config.cpu_stall_timeout {
+        params.dword("CpuStallTimeout", saturating_dword(timeout))?;
+    }
+
+    if let Some(threshold) = config.cpu_stall_threshold {
+        params.string("CpuStallThreshold", &threshold.to_string())?;
+    }
+
+    if let Some(health_check) = &config.health_check {
+        match &health_check.target {
+            HealthCheckTarget::Tcp { port } => params.dword("HealthCheckPort", u32::from(*port))?,
+            HealthCheckTarget::Http { url, expected_status, timeout_ms, body_contains } => {
+                params.string("HealthCheckUrl", url)?;
+                params.string("HealthCheckExpectedStatus", &expected_status.to_string())?;
+                params.string("HealthCheckTimeout", &timeout_ms.to_string())?;
+                if let Some(text) = body_contains {
+                    params.string("HealthCheckBodyContains", text)?;
+                }
+            }
+        }
+        params.dword("HealthCheckInterval", saturating_dword(health_check.interval_secs))?;
+        params.dword("HealthCheckFailureThreshold", health_check.failure_threshold)?;
+        params.dword("HealthCheckSuccessThreshold", health_check.success_threshold)?;
+    }
+
+    if let Some(codepage) = config.console_codepage {
+        params.dword("ConsoleCodepage", codepage)?;
+    }
+
+    if config.detached {
+        params.string("Detached", "1")?;
+    }
+
+    if config.log_timestamps {
+        params.string("LogTimestamps", "1")?;
+    }
+
+    // 保存备用程序设置
+    if let Some(fallback) = &config.fallback_executable {
+        params.string("FallbackExecutable", &fallback.to_string_lossy())?;
+    }
+
+    if !config.fallback_arguments.is_empty() {
+        let args_json = serde_json::to_string(&config.fallback_arguments)?;
+        params.string("FallbackArguments", &args_json)?;
+    }
+
+    if config.prefer_primary {
+        params.string("PreferPrimary", "1")?;
+    }
+
+    if !config.restart_patterns.is_empty() {
+        let patterns_json = serde_json::to_string(&config.restart_patterns)?;
+        params.string("RestartPatterns", &patterns_json)?;
+        params.string("RestartPatternStream", config.restart_pattern_stream.as_str())?;
+    }
+
+    if let Some(max_restarts) = config.max_restarts_per_window {
+        params.dword("MaxRestartsPerWindow", max_restarts)?;
+    }
+
+    if let Some(window) = config.restart_rate_window {
+        params.dword("RestartWindowSeconds", saturating_dword(window))?;
+    }
+
+    if !config.exit_actions.is_default() {
+        let rules_json = serde_json::to_string(&config.exit_actions.to_rules())?;
+        params.string("ExitActions", &rules_json)?;
+    }
+
+    if let Some(max_restarts) = config.max_restarts {
+        params.dword("MaxRestarts", max_restarts)?;
+    }
+
+    if let Some(window) = config.stable_window {
+        params.dword("StableWindow", saturating_dword(window))?;
+    }
+
+    if let Some(attempts) = config.max_start_attempts {
+        params.dword("MaxStartAttempts", attempts)?;
+    }
+
+    if let Some(delay) = config.start_retry_delay {
+        params.dword("StartRetryDelay", saturating_dword(delay))?;
+    }
+
+    if let Some(delay) = config.start_retry_delay_max {
+        params.dword("StartRetryDelayMax", saturating_dword(delay))?;
+    }
+
+    if let Some(jitter) = config.restart_jitter.filter(|jitter| *jitter > 0) {
+        params.dword("RestartJitter", saturating_dword(jitter))?;
+    }
+
+    if config.quiet_restart_logging {
+        params.string("QuietRestartLogging", "1")?;
+    }
+
+    if let Some(burst) = config.restart_log_burst {
+        params.dword("RestartLogBurst", burst)?;
+    }
+
+    if let Some(window) = config.restart_log_window {
+        params.dword("RestartLogWindow", saturating_dword(window))?;
+    }
+
+    if let Some(command) = &config.provision_command {
+        params.string("ProvisionCommand", command)?;
+    }
+
+    // 保存签名校验设置
+    if config.check_signature {
+        params.string("CheckSignature", "1")?;
+    }
+
+    if config.require_signature {
+        params.string("RequireSignature", "1")?;
+    }
+
+    if let Some(thumbprint) = &config.signer_thumbprint {
+        params.string("SignerThumbprint", thumbprint)?;
+    }
+
+    // 保存启动保护级别（仅用于显示）
+    if config.launch_protection != LaunchProtection::None {
+        params.string("LaunchProtection", config.launch_protection.as_str())?;
+    }
+
+    params.remove_unset()
+}
+
+/// 写入注册表字符串值
+fn set_reg_string(hkey: HKEY, name: &str, value: &str) -> Result<()> {
+    set_reg_wide_string(hkey, name, REG_SZ, value)
+}
+
+/// 写入含环境变量引用的注册表字符串值（REG_EXPAND_SZ）
+fn set_reg_expand_string(hkey: HKEY, name: &str, value: &str) -> Result<()> {
+    set_reg_wide_string(hkey, name, REG_EXPAND_SZ, value)
+}
+
+fn set_reg_wide_string(hkey: HKEY, name: &str, value_type: u32, value: &str) -> Result<()> {
+    let value_w = to_wstring(value);
+    let value_bytes = unsafe {
+        std::slice::from_raw_parts(
+            value_w.as_ptr() as *const u8,
+            value_w.len() * 2,
+        )
+    };
+
+    set_reg_value(hkey, name, value_type, value_bytes)
+}
+
+/// 写入注册表 REG_DWORD 值
+fn set_reg_dword(hkey: HKEY, name: &str, value: u32) -> Result<()> {
+    set_reg_value(hkey, name, REG_DWORD, &value.to_le_bytes())
+}
+
+/// 以秒为单位的时长等数值超出 REG_DWORD 范围时取最大值
+fn saturating_dword(value: u64) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+/// 写入注册表值，现有值的类型和内容完全相同时跳过写入
+///
+/// 避免无意义的写入触发注册表变更通知和审计日志。
+fn set_reg_value(hkey: HKEY, name: &str, value_type: u32, value_bytes: &[u8]) -> Result<()> {
+    let name_w = to_wstring(name);
+
+    if read_reg_raw(hkey, &name_w).is_some_and(|(existing_type, existing)| {
+        existing_type == value_type && existing == value_bytes
+    }) {
+        return Ok(());
+    }
+
+    let result = unsafe {
+        RegSetValueExW(
+            hkey,
+            name_w.as_ptr(),
+            0,
+            value_type,
+            value_bytes.as_ptr(),
+            value_bytes.len() as u32,
+        )
+    };
+
+    if result != ERROR_SUCCESS {
+        return Err(anyhow::anyhow!("Failed to set registry value"));
+    }
+
+    Ok(())
+}
+
+/// 读取注册表值的类型和原始内容，值不存在或无法读取时返回 `None`
+fn read_reg_raw(hkey: HKEY, name_w: &[u16]) -> Option<(u32, Vec<u8>)> {
+    let mut value_type = 0u32;
+    let mut size = 0u32;
+    let result = unsafe {
+        RegQueryValueExW(hkey, name_w.as_ptr(), std::ptr::null_mut(), &mut value_type, std::ptr::null_mut(), &mut size)
+    };
+    if result != ERROR_SUCCESS {
+        return None;
+    }
+
+    let mut buffer = vec![0u8; size as usize];
+    let result = unsafe {
+        RegQueryValueExW(hkey, name_w.as_ptr(), std::ptr::null_mut(), &mut value_type, buffer.as_mut_ptr(), &mut size)
+    };
+    if result != ERROR_SUCCESS {
+        return None;
+    }
+
+    buffer.truncate(size as usize);
+    Some((value_type, buffer))
+}
+
+/// 构建服务命令行：rust-nssm.exe run --name <service_name>
+fn host_binary_path(service_name: &str) -> Result<Vec<u16>> {
+    // 获取当前可执行文件的路径（rust-nssm自身）
+    let current_exe = std::env::current_exe()
+        .context("Failed to get current executable path")?;
+
+    Ok(to_wstring(&host_command_line(&current_exe, service_name)))
+}
+
+/// 使用指定的 rust-nssm 程序托管服务时的服务命令行
+pub fn host_command_line(host_exe: &Path, service_name: &str) -> String {
+    format!("\"{}\" run --name \"{}\"", host_exe.display(), service_name)
+}
+
+/// 解析 `KEY=VALUE` 形式的环境变量
+///
+/// 只在第一个 `=` 处分割，值中可以包含 `=`，也可以为空。
+pub fn parse_env_var(value: &str) -> Result<(String, String)> {
+    match value.split_once('=') {
+        Some((key, _)) if key.trim().is_empty() => {
+            Err(anyhow::anyhow!("Invalid environment variable {:?}: name must not be empty", value))
+        }
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(anyhow::anyhow!("Invalid environment variable {:?}: expected KEY=VALUE", value)),
+    }
+}
+
+/// 去掉重复的环境变量，同名（不区分大小写，与Windows一致）时保留最后一个
+pub fn dedup_environment(environment: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut result: Vec<(String, String)> = Vec::with_capacity(environment.len());
+    for (key, value) in environment {
+        result.retain(|(existing, _)| !existing.eq_ignore_ascii_case(&key));
+        result.push((key, value));
+    }
+    result
+}
+
+/// 读取 dotenv 格式的环境变量文件
+pub fn read_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .context(format!("Failed to read environment file: {:?}", path))?;
+    parse_env_file(content.trim_start_matches('\u{feff}'))
+        .context(format!("Invalid environment file: {:?}", path))
+}
+
+/// 解析 dotenv 格式的内容：每行一个 `KEY=VALUE`，忽略空行和 `#` 注释行
+///
+/// 允许 `export` 前缀；值可以用双引号（支持 `\n`、`\"`、`\\` 转义）或单引号（按原样）包围，
+/// 以保留首尾空格、`#` 和 `=`。未加引号的值中空白后的 `#` 开始行尾注释。
+pub fn parse_env_file(content: &str) -> Result<Vec<(String, String)>> {
+    let mut environment = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+        let entry = parse_env_line(line).context(format!("line {}", index + 1))?;
+        environment.push(entry);
+    }
+    Ok(environment)
+}
+
+fn parse_env_line(line: &str) -> Result<(String, String)> {
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected KEY=VALUE, found {:?}", line))?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(anyhow::anyhow!("invalid variable name {:?}", key));
+    }
+
+    let value = value.trim();
+    let value = if let Some(quoted) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = quoted.chars();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some(other) => result.push(other),
+                    None => return Err(anyhow::anyhow!("unterminated quoted value for {}", key)),
+                },
+                Some(c) => result.push(c),
+                None => return Err(anyhow::anyhow!("unterminated quoted value for {}", key)),
+            }
+        }
+        result
+    } else if let Some(quoted) = value.strip_prefix('\'') {
+        let (inner, _) = quoted
+            .split_once('\'')
+            .ok_or_else(|| anyhow::anyhow!("unterminated quoted value for {}", key))?;
+        inner.to_string()
+    } else {
+        let end = value.find(" #").or_else(|| value.find("\t#")).unwrap_or(value.len());
+        value[..end].trim_end().to_string()
+    };
+
+    Ok((key.to_string(), value))
+}
+
+// 服务名称的最大长度（SCM的限制）
+const MAX_SERVICE_NAME_LEN: usize = 256;
+
+/// 校验服务名称
+///
+/// SCM不允许名称包含正斜杠和反斜杠；名称还会被拼接进注册表路径，
+/// 含路径分隔符的名称可能访问到其他注册表项。
+pub fn validate_service_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(anyhow::anyhow!("Service name must not be empty"));
+    }
+    if name.encode_utf16().count() > MAX_SERVICE_NAME_LEN {
+        return Err(anyhow::anyhow!("Service name must not exceed {} characters", MAX_SERVICE_NAME_LEN));
+    }
+    if name.contains(['\\', '/']) || name.chars().any(char::is_control) {
+        return Err(anyhow::anyhow!(
+            "Invalid service name {:?}: slashes, backslashes and control characters are not allowed",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// 构建服务 Parameters 注册表项的路径，名称不合法时返回错误而不访问注册表
+pub(crate) fn parameters_key_path(service_name: &str) -> Result<String> {
+    validate_service_name(service_name)?;
+    Ok(format!("SYSTEM\\CurrentControlSet\\Services\\{}\\Parameters", service_name))
+}
+
+/// 事件源的消息文件（.NET Framework 4 自带，所有事件ID的消息均为原样显示的插入字符串）
+const EVENT_MESSAGE_FILE: &str = "%SystemRoot%\\Microsoft.NET\\Framework\\v4.0.30319\\EventLogMessages.dll";
+
+/// 构建服务在应用程序事件日志中的事件源注册表项路径
+pub(crate) fn event_source_key_path(service_name: &str) -> Result<String> {
+    validate_service_name(service_name)?;
+    Ok(format!("SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\{}", service_name))
+}
+
+/// 判断服务是否由 rust-nssm 安装（Parameters 中记录了目标程序）
+pub fn is_managed_service(service_name: &str) -> bool {
+    let Ok(key_path) = parameters_key_path(service_name) else {
+        return false;
+    };
+    let key_path_w = to_wstring(&key_path);
+    let value_name_w = to_wstring("TargetExecutable");
+
+    let mut hkey = HKEY::default();
+    let result = unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr(), 0, KEY_READ, &mut hkey) };
+    if result != ERROR_SUCCESS {
+        return false;
+    }
+
+    let result = unsafe {
+        RegQueryValueExW(
+            hkey,
+            value_name_w.as_ptr(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    unsafe { RegCloseKey(hkey); }
+
+    result == ERROR_SUCCESS
+}
+
+/// 读取注册表字符串值
+pub(crate) fn read_reg_string(hkey: HKEY, name: &str) -> Result<String> {
+    let name_w = name.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+
+    let mut buffer_type = 0u32;
+    let mut buffer_size = 0u32;
+
+    // 查询缓冲区大小
+    let result = unsafe {
+        RegQueryValueExW(
+            hkey,
+            name_w.as_ptr(),
+            std::ptr::null_mut(),
+            &mut buffer_type,
+            std::ptr::null_mut(),
+            &mut buffer_size,
+        )
+    };
+
+    if result != ERROR_SUCCESS || buffer_type != REG_SZ {
+        return Err(anyhow::anyhow!("Failed to query registry value"));
+    }
+
+    // 读取数据
+    let mut buffer = vec![0u16; (buffer_size / 2) as usize];
+    let result = unsafe {
+        RegQueryValueExW(
+            hkey,
+            name_w.as_ptr(),
+            std::ptr::null_mut(),
+            &mut buffer_type,
+            buffer.as_mut_ptr() as *mut _,
+            &mut buffer_size,
+        )
+    };
+
+    if result != ERROR_SUCCESS {
+        return Err(anyhow::anyhow!("Failed to read registry value"));
+    }
+
+    // 移除null终止符并转换为字符串
+    if let Some(null_pos) = buffer.iter().position(|&c| c == 0) {
+        buffer.truncate(null_pos);
+    }
+
+    Ok(String::from_utf16_lossy(&buffer))
+}
+
+/// 读取 REG_DWORD 数值，兼容旧版本以 REG_SZ 字符串保存的值
+pub(crate) fn read_reg_dword(hkey: HKEY, name: &str) -> Result<u32> {
+    let value = read_reg_number(hkey, name)?;
+    u32::try_from(value).map_err(|_| anyhow::anyhow!("Registry value {} is out of range: {}", name, value))
+}
+
+/// 读取 REG_QWORD 数值，兼容 REG_DWORD 以及旧版本以 REG_SZ 字符串保存的值
+pub(crate) fn read_reg_qword(hkey: HKEY, name: &str) -> Result<u64> {
+    read_reg_number(hkey, name)
+}
+
+/// 按值的实际类型读取数值
+fn read_reg_number(hkey: HKEY, name: &str) -> Result<u64> {
+    let (value_type, bytes) = read_reg_raw(hkey, &to_wstring(name))
+        .ok_or_else(|| anyhow::anyhow!("Failed to query registry value"))?;
+
+    match value_type {
+        REG_DWORD if bytes.len() == 4 => Ok(u32::from_le_bytes(bytes[..4].try_into().unwrap()) as u64),
+        REG_QWORD if bytes.len() == 8 => Ok(u64::from_le_bytes(bytes[..8].try_into().unwrap())),
+        REG_SZ => {
+            let wide: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .take_while(|&c| c != 0)
+                .collect();
+            let text = String::from_utf16_lossy(&wide);
+            text.trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Registry value {} is not a number: '{}'", name, text))
+        }
+        _ => Err(anyhow::anyhow!("Registry value {} has unexpected type {}", name, value_type)),
+    }
+}
+
+/// 读取服务 Parameters 注册表项中的单个字符串值
+pub(crate) fn read_parameter_string(service_name: &str, name: &str) -> Result<String> {
+    let hkey = open_parameters_key(service_name)?;
+    let value = read_reg_string(hkey, name);
+    unsafe { RegCloseKey(hkey); }
+    value
+}
+
+/// 读取服务 Parameters 注册表项中的单个32位数值
+pub(crate) fn read_parameter_dword(service_name: &str, name: &str) -> Result<u32> {
+    let hkey = open_parameters_key(service_name)?;
+    let value = read_reg_dword(hkey, name);
+    unsafe { RegCloseKey(hkey); }
+    value
+}
+
+/// 写入服务 Parameters 注册表项中的单个字符串值（供服务宿主记录运行时信息）
+pub(crate) fn write_parameter_string(service_name: &str, name: &str, value: &str) -> Result<()> {
+    let hkey = create_parameters_key(service_name)?;
+    let result = set_reg_string(hkey, name, value);
+    unsafe { RegCloseKey(hkey); }
+    result
+}
+
+/// 写入服务 Parameters 注册表项中的单个32位数值
+pub(crate) fn write_parameter_dword(service_name: &str, name: &str, value: u32) -> Result<()> {
+    let hkey = create_parameters_key(service_name)?;
+    let result = set_reg_dword(hkey, name, value);
+    unsafe { RegCloseKey(hkey); }
+    result
+}
+
+/// 以只读方式打开服务的 Parameters 注册表项
+fn open_parameters_key(service_name: &str) -> Result<HKEY> {
+    let key_path = parameters_key_path(service_name)?;
+    let key_path_w = to_wstring(&key_path);
+
+    let mut hkey = HKEY::default();
+    let result = unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr(), 0, KEY_READ, &mut hkey) };
+    if result != ERROR_SUCCESS {
+        return Err(anyhow::anyhow!("Failed to open registry key"));
+    }
+    Ok(hkey)
+}
+
+/// 打开服务的 Parameters 注册表项用于写入，不存在时创建
+fn create_parameters_key(service_name: &str) -> Result<HKEY> {
+    let key_path = parameters_key_path(service_name)?;
+    let key_path_w = to_wstring(&key_path);
+
+    let mut hkey = HKEY::default();
+    let result = unsafe {
+        RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            key_path_w.as_ptr(),
+            0,
+            std::ptr::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_READ | KEY_WRITE,
+            std::ptr::null(),
+            &mut hkey,
+            std::ptr::null_mut(),
+        )
+    };
+
+    if result != ERROR_SUCCESS {
+        return Err(anyhow::anyhow!("Failed to create registry key"));
+    }
+    Ok(hkey)
+}
+
+/// 删除服务 Parameters 注册表项中的单个值，值不存在时忽略
+pub(crate) fn delete_parameter_value(service_name: &str, name: &str) {
+    let key_path = match parameters_key_path(service_name) {
+        Ok(key_path) => key_path,
+        Err(e) => {
+            warn!("{}", e);
+            return;
+        }
+    };
+    let key_path_w = to_wstring(&key_path);
+    let name_w = to_wstring(name);
+
+    let result = unsafe { RegDeleteKeyValueW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr(), name_w.as_ptr()) };
+    if result != ERROR_SUCCESS && result != ERROR_FILE_NOT_FOUND {
+        warn!("Failed to delete registry value {}: error {}", name, result);
+    }
+}
+
+/// 读取 `QueryServiceConfigW` 返回的服务配置，缓冲区以 `QUERY_SERVICE_CONFIGW` 开头
+fn query_service_config(service: SC_HANDLE) -> Result<Vec<u64>> {
+    let mut bytes_needed = 0u32;
+    unsafe { QueryServiceConfigW(service, std::ptr::null_mut(), 0, &mut bytes_needed); }
+    if bytes_needed == 0 {
+        return Err(anyhow::anyhow!("Failed to query service config size"));
+    }
+
+    // 使用u64缓冲区保证 QUERY_SERVICE_CONFIGW 的对齐
+    let mut buffer = vec![0u64; (bytes_needed as usize).div_ceil(8)];
+    let config = buffer.as_mut_ptr() as *mut QUERY_SERVICE_CONFIGW;
+    if unsafe { QueryServiceConfigW(service, config, bytes_needed, &mut bytes_needed) } == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to query service config: error {}", error));
+    }
+
+    Ok(buffer)
+}
+
+/// 查询服务的启动类型
+fn query_start_type(service: SC_HANDLE) -> Result<u32> {
+    let buffer = query_service_config(service)?;
+    let config = unsafe { &*(buffer.as_ptr() as *const QUERY_SERVICE_CONFIGW) };
+    Ok(config.dwStartType)
+}
+
+/// 查询服务的命令行
+fn query_binary_path(service: SC_HANDLE) -> Result<String> {
+    let buffer = query_service_config(service)?;
+    let config = unsafe { &*(buffer.as_ptr() as *const QUERY_SERVICE_CONFIGW) };
+
+    let path = config.lpBinaryPathName;
+    if path.is_null() {
+        return Ok(String::new());
+    }
+    Ok(unsafe { OsString::from_wide(std::slice::from_raw_parts(path, wcslen(path))) }
+        .to_string_lossy()
+        .to_string())
+}
+
+/// SCM中保存的服务配置（不在 Parameters 注册表项中的部分）
+struct ScmConfig {
+    display_name: String,
+    description: String,
+    start_type: ServiceStartType,
+    service_account: Option<ServiceAccount>,
+    recovery_actions: Option<RecoveryConfig>,
+    launch_protection: LaunchProtection,
+    dependencies: Vec<String>,
+}
+
+/// 查询服务的显示名称、描述、启动类型、账户、依赖、恢复操作和启动保护级别
+///
+/// `config` 为已通过 [`query_service_config`] 读取的基本配置，只额外查询 `QueryServiceConfig2W` 的各项。
+fn query_scm_config(service: SC_HANDLE, config: &QUERY_SERVICE_CONFIGW) -> Result<ScmConfig> {
+
+    let buffer = query_service_config2(service, SERVICE_CONFIG_DELAYED_AUTO_START_INFO)?;
+    let delayed = unsafe { &*(buffer.as_ptr() as *const SERVICE_DELAYED_AUTO_START_INFO) };
+
+    let buffer = query_service_config2(service, SERVICE_CONFIG_DESCRIPTION)?;
+    let description = unsafe { &*(buffer.as_ptr() as *const SERVICE_DESCRIPTIONW) };
+
+    // 早于 Windows 8.1 的系统不支持启动保护，按未启用处理
+    let launch_protection = query_service_config2(service, SERVICE_CONFIG_LAUNCH_PROTECTED)
+        .map(|buffer| {
+            let info = unsafe { &*(buffer.as_ptr() as *const SERVICE_LAUNCH_PROTECTED_INFO) };
+            LaunchProtection::from_raw(info.dwLaunchProtected)
+        })
+        .unwrap_or_default();
+
+    // LocalSystem 与未指定账户等价，按默认值返回
+    let service_account = unsafe { string_from_wide_ptr(config.lpServiceStartName) }
+        .map(|name| ServiceAccount::from_username(&name, None))
+        .filter(|account| *account != ServiceAccount::LocalSystem);
+
+    Ok(ScmConfig {
+        display_name: unsafe { string_from_wide_ptr(config.lpDisplayName) }.unwrap_or_default(),
+        description: unsafe { string_from_wide_ptr(description.lpDescription) }.unwrap_or_default(),
+        start_type: ServiceStartType::from_raw(config.dwStartType, delayed.fDelayedAutostart != 0),
+        service_account,
+        recovery_actions: query_recovery_config(service)?,
+        launch_protection,
+        dependencies: unsafe { dependencies_from_wide_ptr(config.lpDependencies) },
+    })
+}
+
+/// 查询SCM中的失败恢复操作，未配置时返回 `None`
+fn query_recovery_config(service: SC_HANDLE) -> Result<Option<RecoveryConfig>> {
+    let buffer = query_service_config2(service, SERVICE_CONFIG_FAILURE_ACTIONS)?;
+    let info = unsafe { &*(buffer.as_ptr() as *const SERVICE_FAILURE_ACTIONSW) };
+    if info.lpsaActions.is_null() || info.cActions == 0 {
+        return Ok(None);
+    }
+
+    let command = unsafe { string_from_wide_ptr(info.lpCommand) };
+    let actions = unsafe { std::slice::from_raw_parts(info.lpsaActions, info.cActions as usize) }
+        .iter()
+        .map(|action| FailureAction::from_sc_action(action, command.as_deref()))
+        .collect::<Result<Vec<_>>>()?;
+    let reboot_message = unsafe { string_from_wide_ptr(info.lpRebootMsg) };
+
+    RecoveryConfig::from_actions(&actions, info.dwResetPeriod, reboot_message).map(Some)
+}
+
+/// 当前进程是否以管理员身份运行（令牌中的 Administrators 组已启用）
+///
+/// UAC 过滤后的令牌中 Administrators 组仅用于拒绝，未提升权限时返回 `false`。
+pub fn is_elevated() -> bool {
+    // SECURITY_MAX_SID_SIZE
+    let mut sid = [0u8; 68];
+    let mut sid_size = sid.len() as u32;
+
+    unsafe {
+        if CreateWellKnownSid(WinBuiltinAdministratorsSid, std::ptr::null_mut(), sid.as_mut_ptr() as PSID, &mut sid_size) == 0 {
+            return false;
+        }
+
+        let mut is_member: BOOL = 0;
+        CheckTokenMembership(0, sid.as_mut_ptr() as PSID, &mut is_member) != 0 && is_member != 0
+    }
+}
+
+/// 为当前进程启用 `SeShutdownPrivilege`
+fn enable_shutdown_privilege() -> Result<()> {
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    let name = to_wstring("SeShutdownPrivilege");
+    unsafe {
+        let mut token: HANDLE = 0;
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY, &mut token) == 0 {
+            return Err(anyhow::anyhow!("Failed to open process token: error {}", GetLastError()));
+        }
+
+        let mut privileges: TOKEN_PRIVILEGES = std::mem::zeroed();
+        privileges.PrivilegeCount = 1;
+        privileges.Privileges[0].Attributes = SE_PRIVILEGE_ENABLED;
+
+        // AdjustTokenPrivileges 在进程不持有该特权时同样返回成功，需检查 ERROR_NOT_ALL_ASSIGNED
+        let enabled = LookupPrivilegeValueW(std::ptr::null(), name.as_ptr(), &mut privileges.Privileges[0].Luid) != 0
+            && AdjustTokenPrivileges(token, 0, &privileges, 0, std::ptr::null_mut(), std::ptr::null_mut()) != 0
+            && GetLastError() != ERROR_NOT_ALL_ASSIGNED;
+        let error = GetLastError();
+        CloseHandle(token);
+
+        if !enabled {
+            return Err(anyhow::anyhow!("Failed to enable SeShutdownPrivilege required for reboot actions: error {}", error));
+        }
+    }
+    Ok(())
+}
+
+/// 授予账户"作为服务登录"（`SeServiceLogonRight`）权限，账户已有该权限时不做改变
+fn grant_service_logon_right(account: &str) -> Result<()> {
+    use windows_sys::Win32::Security::Authentication::Identity::{
+        LsaAddAccountRights, LsaClose, LsaNtStatusToWinError, LsaOpenPolicy, POLICY_CREATE_ACCOUNT, POLICY_LOOKUP_NAMES,
+    };
+    use windows_sys::Win32::System::WindowsProgramming::OBJECT_ATTRIBUTES;
+
+    let sid = lookup_account_sid(account)?;
+
+    let mut right: Vec<u16> = "SeServiceLogonRight".encode_utf16().collect();
+    let right = UNICODE_STRING {
+        Length: (right.len() * 2) as u16,
+        MaximumLength: (right.len() * 2) as u16,
+        Buffer: right.as_mut_ptr(),
+    };
+
+    unsafe {
+        let attributes: OBJECT_ATTRIBUTES = std::mem::zeroed();
+        let mut policy = 0;
+        let status = LsaOpenPolicy(std::ptr::null(), &attributes, (POLICY_LOOKUP_NAMES | POLICY_CREATE_ACCOUNT) as u32, &mut policy);
+        if status != 0 {
+            return Err(anyhow::anyhow!("Failed to open local security policy: error {}", LsaNtStatusToWinError(status)));
+        }
+
+        let status = LsaAddAccountRights(policy, sid.as_ptr() as PSID, &right, 1);
+        LsaClose(policy);
+        if status != 0 {
+            return Err(anyhow::anyhow!(
+                "Failed to grant SeServiceLogonRight to '{}': error {}",
+                account,
+                LsaNtStatusToWinError(status)
+            ));
+        }
+    }
+
+    info!("Granted SeServiceLogonRight to '{}'", account);
+    Ok(())
+}
+
+/// 查询账户的SID
+fn lookup_account_sid(account: &str) -> Result<Vec<u8>> {
+    let account_w = to_wstring(account);
+    let mut sid_size = 0u32;
+    let mut domain_len = 0u32;
+    let mut sid_use: SID_NAME_USE = 0;
+
+    // 第一次调用只取所需的缓冲区大小
+    unsafe {
+        LookupAccountNameW(
+            std::ptr::null(),
+            account_w.as_ptr(),
+            std::ptr::null_mut(),
+            &mut sid_size,
+            std::ptr::null_mut(),
+            &mut domain_len,
+            &mut sid_use,
+        );
+    }
+    if sid_size == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to look up account '{}': error {}", account, error));
+    }
+
+    let mut sid = vec![0u8; sid_size as usize];
+    let mut domain = vec![0u16; domain_len as usize];
+    let result = unsafe {
+        LookupAccountNameW(
+            std::ptr::null(),
+            account_w.as_ptr(),
+            sid.as_mut_ptr() as PSID,
+            &mut sid_size,
+            domain.as_mut_ptr(),
+            &mut domain_len,
+            &mut sid_use,
+        )
+    };
+    if result == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to look up account '{}': error {}", account, error));
+    }
+
+    Ok(sid)
+}
+
+/// 修改服务的命令行
+fn change_binary_path(service: SC_HANDLE, binary_path: &[u16]) -> Result<()> {
+    let result = unsafe {
+        ChangeServiceConfigW(
+            service,
+            SERVICE_NO_CHANGE,
+            SERVICE_NO_CHANGE,
+            SERVICE_NO_CHANGE,
+            binary_path.as_ptr(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+
+    if result == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to change service binary path: error {}", error));
+    }
+    Ok(())
+}
+
+/// 修改服务的启动类型
+fn change_start_type(service: SC_HANDLE, start_type: u32) -> Result<()> {
+    let result = unsafe {
+        ChangeServiceConfigW(
+            service,
+            SERVICE_NO_CHANGE,
+            start_type,
+            SERVICE_NO_CHANGE,
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+
+    if result == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to change service start type: error {}", error));
+    }
+    Ok(())
+}
+
+/// 修改服务的依赖列表，其余配置保持不变
+fn change_dependencies(service: SC_HANDLE, dependencies: &[String]) -> Result<()> {
+    // 空列表只含结尾的空字符，SCM据此清除所有依赖
+    let dependencies = dependency_list(dependencies);
+    let result = unsafe {
+        ChangeServiceConfigW(
+            service,
+            SERVICE_NO_CHANGE,
+            SERVICE_NO_CHANGE,
+            SERVICE_NO_CHANGE,
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            dependencies.as_ptr(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+
+    if result == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to change service dependencies: error {}", error));
+    }
+    Ok(())
+}
+
+/// 读取 `QueryServiceConfig2W` 返回的可变长度配置数据
+fn query_service_config2(service: SC_HANDLE, info_level: u32) -> Result<Vec<u64>> {
+    let mut bytes_needed = 0u32;
+    unsafe { QueryServiceConfig2W(service, info_level, std::ptr::null_mut(), 0, &mut bytes_needed); }
+    if bytes_needed == 0 {
+        return Err(anyhow::anyhow!("Failed to query service config size (level {})", info_level));
+    }
+
+    // 使用u64缓冲区保证返回结构体的对齐
+    let mut buffer = vec![0u64; (bytes_needed as usize).div_ceil(8)];
+    let result = unsafe {
+        QueryServiceConfig2W(service, info_level, buffer.as_mut_ptr() as *mut u8, bytes_needed, &mut bytes_needed)
+    };
+    if result == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(anyhow::anyhow!("Failed to query service config (level {}): error {}", info_level, error));
+    }
+
+    Ok(buffer)
+}
+
+/// 恢复操作类型的显示名称
+fn recovery_action_name(action: SC_ACTION_TYPE) -> &'static str {
+    match action {
+        SC_ACTION_NONE => "none",
+        SC_ACTION_RESTART => "restart",
+        SC_ACTION_REBOOT => "reboot",
+        SC_ACTION_RUN_COMMAND => "run-command",
+        SC_ACTION_OWN_RESTART => "own-restart",
+        _ => "unknown",
+    }
+}
+
+/// 启动类型的显示名称
+pub fn start_type_name(start_type: u32) -> &'static str {
+    match start_type {
+        SERVICE_BOOT_START => "boot",
+        SERVICE_SYSTEM_START => "system",
+        SERVICE_AUTO_START => "auto",
+        SERVICE_DEMAND_START => "manual",
+        SERVICE_DISABLED => "disabled",
+        _ => "unknown",
+    }
+}
+
+/// SCM服务状态的名称
+pub fn service_state_name(state: u32) -> &'static str {
+    match state {
+        1 => "STOPPED",
+        2 => "START_PENDING",
+        3 => "STOP_PENDING",
+        4 => "RUNNING",
+        5 => "CONTINUE_PENDING",
+        6 => "PAUSE_PENDING",
+        7 => "PAUSED",
+        _ => "UNKNOWN",
+    }
+}
+
+/// 查询服务的扩展状态（包含进程ID）
+fn query_status_process(service: SC_HANDLE) -> Result<SERVICE_STATUS_PROCESS> {
+    let mut status: SERVICE_STATUS_PROCESS = unsafe { std::mem::zeroed() };
+    let mut bytes_needed = 0u32;
+    let result = unsafe {
+        QueryServiceStatusEx(
+            service,
+            SC_STATUS_PROCESS_INFO,
+            &mut status as *mut _ as *mut u8,
+            std::mem::size_of::<SERVICE_STATUS_PROCESS>() as u32,
+            &mut bytes_needed,
+        )
+    };
+
+    if result == 0 {
+        return Err(anyhow::anyhow!("Failed to query service status"));
+    }
 
-        let result = unsafe {
-            ChangeServiceConfig2W(
-                service,
-                SERVICE_CONFIG_DESCRIPTION,
-                &description_info as *const _ as *const _,
-            )
-        };
+    Ok(status)
+}
 
-        if result == 0 {
-            return Err(anyhow::anyhow!("Failed to set service description"));
-        }
+/// 查询服务的显示名称
+fn query_display_name(service: SC_HANDLE) -> Result<String> {
+    let buffer = query_service_config(service)?;
+    let config = unsafe { &*(buffer.as_ptr() as *const QUERY_SERVICE_CONFIGW) };
+    Ok(unsafe { string_from_wide_ptr(config.lpDisplayName) }.unwrap_or_default())
+}
 
-        Ok(())
+/// 在系统事件日志中查找SCM为该服务记录的最近一次失败事件
+fn find_scm_failure_event(service_name: &str, display_name: &str) -> Result<Option<ScmFailureEvent>> {
+    let log_name = to_wstring("System");
+    let handle = unsafe { OpenEventLogW(std::ptr::null(), log_name.as_ptr()) };
+    if handle == 0 {
+        return Err(anyhow::anyhow!("Failed to open System event log"));
     }
 
-    /// 保存服务配置到注册表
-    fn save_service_config(&self, config: &ServiceConfig) -> Result<()> {
-        let key_path = format!("SYSTEM\\CurrentControlSet\\Services\\{}\\Parameters", config.name);
-        let key_path_w = to_wstring(&key_path);
+    let header_size = std::mem::size_of::<EVENTLOGRECORD>();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut scanned = 0usize;
+    let mut found = None;
 
-        let mut hkey = HKEY::default();
+    'read: loop {
+        let mut bytes_read = 0u32;
+        let mut bytes_needed = 0u32;
         let result = unsafe {
-            RegCreateKeyExW(
-                HKEY_LOCAL_MACHINE,
-                key_path_w.as_ptr(),
+            ReadEventLogW(
+                handle,
+                EVENTLOG_SEQUENTIAL_READ | EVENTLOG_BACKWARDS_READ,
                 0,
-                std::ptr::null(),
-                REG_OPTION_NON_VOLATILE,
-                KEY_WRITE,
-                std::ptr::null(),
-                &mut hkey,
-                std::ptr::null_mut(),
+                buffer.as_mut_ptr() as *mut _,
+                buffer.len() as u32,
+                &mut bytes_read,
+                &mut bytes_needed,
             )
         };
 
-        if result != ERROR_SUCCESS {
-            return Err(anyhow::anyhow!("Failed to create registry key"));
+        if result == 0 {
+            let error = unsafe { GetLastError() };
+            if error == ERROR_INSUFFICIENT_BUFFER {
+                buffer.resize(bytes_needed as usize, 0);
+                continue;
+            }
+            // ERROR_HANDLE_EOF：已读到最早的记录
+            break;
         }
 
-        // 保存工作目录
-        if let Some(work_dir) = &config.working_directory {
-            self.save_reg_string(hkey, "WorkingDirectory", &work_dir.to_string_lossy())?;
-        }
+        let mut offset = 0usize;
+        while offset + header_size <= bytes_read as usize {
+            // 缓冲区只保证字节对齐，按非对齐方式读取记录头
+            let record = unsafe {
+                std::ptr::read_unaligned(buffer.as_ptr().add(offset) as *const EVENTLOGRECORD)
+            };
+            let record_len = record.Length as usize;
+            if record_len < header_size || offset + record_len > bytes_read as usize {
+                break 'read;
+            }
 
-        // 保存输出路径
-        if let Some(stdout_path) = &config.stdout_path {
-            self.save_reg_string(hkey, "StdoutPath", &stdout_path.to_string_lossy())?;
-        }
+            let record_bytes = &buffer[offset..offset + record_len];
+            if let Some(event) = parse_scm_failure_record(&record, record_bytes, service_name, display_name) {
+                found = Some(event);
+                break 'read;
+            }
 
-        if let Some(stderr_path) = &config.stderr_path {
-            self.save_reg_string(hkey, "StderrPath", &stderr_path.to_string_lossy())?;
+            scanned += 1;
+            if scanned >= MAX_SCANNED_EVENTS {
+                break 'read;
+            }
+            offset += record_len;
         }
+    }
 
-        // 保存目标可执行文件路径
-        self.save_reg_string(hkey, "TargetExecutable", &config.executable_path.to_string_lossy())?;
-
-        // 保存参数
-        if !config.arguments.is_empty() {
-            let args_json = serde_json::to_string(&config.arguments)?;
-            self.save_reg_string(hkey, "Arguments", &args_json)?;
-        }
+    unsafe { CloseEventLog(handle); }
+    Ok(found)
+}
 
-        unsafe { RegCloseKey(hkey); }
-        Ok(())
+/// 解析单条事件记录，仅返回与指定服务相关的SCM失败事件
+fn parse_scm_failure_record(
+    record: &EVENTLOGRECORD,
+    record_bytes: &[u8],
+    service_name: &str,
+    display_name: &str,
+) -> Option<ScmFailureEvent> {
+    let event_id = record.EventID & 0xFFFF;
+    let description = scm_failure_description(event_id)?;
+
+    // 事件源名称紧跟在记录头之后
+    let source = decode_wide_strings(&record_bytes[std::mem::size_of::<EVENTLOGRECORD>()..], 1);
+    if source.first().map(String::as_str) != Some("Service Control Manager") {
+        return None;
     }
 
-    /// 保存字符串到注册表
-    fn save_reg_string(&self, hkey: HKEY, name: &str, value: &str) -> Result<()> {
-        let name_w = to_wstring(name);
-        let value_w = to_wstring(value);
-        let value_bytes = unsafe {
-            std::slice::from_raw_parts(
-                value_w.as_ptr() as *const u8,
-                value_w.len() * 2,
-            )
-        };
+    let strings_start = (record.StringOffset as usize).min(record_bytes.len());
+    let details = decode_wide_strings(&record_bytes[strings_start..], record.NumStrings as usize);
 
-        let result = unsafe {
-            RegSetValueExW(
-                hkey,
-                name_w.as_ptr(),
-                0,
-                REG_SZ,
-                value_bytes.as_ptr(),
-                value_bytes.len() as u32,
-            )
-        };
+    // 第一个插入字符串为服务显示名称
+    let subject = details.first()?;
+    if !subject.eq_ignore_ascii_case(display_name) && !subject.eq_ignore_ascii_case(service_name) {
+        return None;
+    }
 
-        if result != ERROR_SUCCESS {
-            return Err(anyhow::anyhow!("Failed to set registry value"));
-        }
+    let time = chrono::DateTime::from_timestamp(record.TimeGenerated as i64, 0)?
+        .with_timezone(&chrono::Local);
 
-        Ok(())
+    Some(ScmFailureEvent {
+        event_id,
+        time,
+        description,
+        details: details.into_iter().skip(1).collect(),
+    })
+}
+
+/// SCM服务失败相关事件ID的说明
+fn scm_failure_description(event_id: u32) -> Option<&'static str> {
+    match event_id {
+        7000 => Some("The service failed to start"),
+        7009 => Some("Timed out waiting for the service to connect"),
+        7011 => Some("Timed out waiting for a transaction response from the service"),
+        7022 => Some("The service hung on starting"),
+        7023 => Some("The service terminated with an error"),
+        7024 => Some("The service terminated with a service-specific error"),
+        7031 => Some("The service terminated unexpectedly and a recovery action was taken"),
+        7034 => Some("The service terminated unexpectedly"),
+        _ => None,
     }
+}
 
-    /// 删除服务配置
-    fn delete_service_config(&self, service_name: &str) -> Result<()> {
-        let key_path = format!("SYSTEM\\CurrentControlSet\\Services\\{}\\Parameters", service_name);
-        let key_path_w = to_wstring(&key_path);
+/// 从字节缓冲区中依次解码以null结尾的UTF-16字符串
+fn decode_wide_strings(bytes: &[u8], count: usize) -> Vec<String> {
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+
+    wide.split(|&c| c == 0)
+        .take(count)
+        .map(String::from_utf16_lossy)
+        .collect()
+}
 
-        let result = unsafe { RegDeleteKeyW(HKEY_LOCAL_MACHINE, key_path_w.as_ptr()) };
-        if result != ERROR_SUCCESS {
-            warn!("Failed to delete service config registry key");
-        }
+/// 查询路径所在卷的驱动器类型（`DRIVE_FIXED`、`DRIVE_REMOTE` 等），无法确定时返回 `None`
+pub fn drive_type_of_path(path: &Path) -> Option<u32> {
+    let path_w = to_wstring(&path.to_string_lossy());
+    let mut volume = vec![0u16; 1024];
 
-        Ok(())
+    // 先取得卷根路径，挂载到文件夹的卷和UNC路径也能正确识别
+    let result = unsafe { GetVolumePathNameW(path_w.as_ptr(), volume.as_mut_ptr(), volume.len() as u32) };
+    if result == 0 {
+        return None;
+    }
+
+    match unsafe { GetDriveTypeW(volume.as_ptr()) } {
+        DRIVE_UNKNOWN | DRIVE_NO_ROOT_DIR => None,
+        drive_type => Some(drive_type),
     }
 }
 
-impl Drop for ServiceManager {
-    fn drop(&mut self) {
-        if self.scm != 0 {
-            unsafe { CloseServiceHandle(self.scm); }
-        }
+/// 驱动器类型的显示名称
+pub fn drive_type_name(drive_type: u32) -> &'static str {
+    match drive_type {
+        DRIVE_REMOVABLE => "removable",
+        DRIVE_FIXED => "fixed",
+        DRIVE_REMOTE => "network",
+        DRIVE_CDROM => "CD-ROM",
+        DRIVE_RAMDISK => "RAM disk",
+        _ => "unknown",
+    }
+}
+
+/// 判断代码页是否已在系统中安装
+pub fn is_code_page_installed(codepage: u32) -> bool {
+    unsafe { windows_sys::Win32::Globalization::IsValidCodePage(codepage) != 0 }
+}
+
+/// 判断目标可执行文件是否为当前运行的 rust-nssm 程序
+pub fn is_self_executable(executable: &Path) -> Result<bool> {
+    let current_exe = std::env::current_exe()
+        .context("Failed to get current executable path")?;
+    Ok(is_same_file_path(executable, &current_exe))
+}
+
+/// 比较两个路径规范化后是否指向同一文件（Windows路径不区分大小写）
+fn is_same_file_path(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a.to_string_lossy().eq_ignore_ascii_case(&b.to_string_lossy()),
+        _ => false,
     }
 }
 
@@ -407,6 +3375,26 @@ fn to_wstring(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
 }
 
+/// 构造 `CreateServiceW` 的 `lpDependencies` 参数：每个名称以空字符结尾，整个列表再以一个空字符结尾
+fn dependency_list(names: &[String]) -> Vec<u16> {
+    let mut list: Vec<u16> = names.iter().flat_map(|name| name.encode_utf16().chain(std::iter::once(0))).collect();
+    list.push(0);
+    list
+}
+
+/// 解析以两个空字符结尾的依赖列表（`dependency_list` 的逆操作），服务组保留 `+` 前缀
+unsafe fn dependencies_from_wide_ptr(mut s: *const u16) -> Vec<String> {
+    let mut names = Vec::new();
+    if s.is_null() {
+        return names;
+    }
+    while let Some(name) = string_from_wide_ptr(s) {
+        s = s.add(wcslen(s) + 1);
+        names.push(name);
+    }
+    names
+}
+
 /// 计算宽字符串长度
 unsafe fn wcslen(s: *const u16) -> usize {
     let mut len = 0;
@@ -416,6 +3404,15 @@ unsafe fn wcslen(s: *const u16) -> usize {
     len as usize
 }
 
+/// 读取以null结尾的宽字符串，空指针和空字符串返回 `None`
+unsafe fn string_from_wide_ptr(s: *const u16) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let value = OsString::from_wide(std::slice::from_raw_parts(s, wcslen(s))).to_string_lossy().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -432,6 +3429,7 @@ mod tests {
             working_directory: Some(PathBuf::from("C:\\test")),
             stdout_path: Some(PathBuf::from("C:\\test\\stdout.log")),
             stderr_path: Some(PathBuf::from("C:\\test\\stderr.log")),
+            ..Default::default()
         };
 
         assert_eq!(config.name, "test_service");
@@ -455,6 +3453,43 @@ mod tests {
         assert_eq!(wide_str.len(), test_str.len() + 1);
     }
 
+    #[test]
+    fn test_is_same_file_path() {
+        let dir = std::env::temp_dir().join("rust_nssm_same_path_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target.exe");
+        let other = dir.join("other.exe");
+        std::fs::write(&target, b"").unwrap();
+        std::fs::write(&other, b"").unwrap();
+
+        // 不同写法的同一路径应被识别为同一文件
+        assert!(is_same_file_path(&target, &dir.join(".").join("target.exe")));
+        assert!(!is_same_file_path(&target, &other));
+        assert!(!is_same_file_path(&target, &dir.join("missing.exe")));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_decode_wide_strings() {
+        let mut bytes = Vec::new();
+        for s in ["Service Control Manager", "my-service", "%%1067"] {
+            for c in to_wstring(s) {
+                bytes.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+
+        assert_eq!(decode_wide_strings(&bytes, 1), vec!["Service Control Manager"]);
+        assert_eq!(decode_wide_strings(&bytes, 3), vec!["Service Control Manager", "my-service", "%%1067"]);
+    }
+
+    #[test]
+    fn test_scm_failure_description() {
+        assert!(scm_failure_description(7000).is_some());
+        assert!(scm_failure_description(7034).is_some());
+        assert!(scm_failure_description(7036).is_none());
+    }
+
     #[test]
     fn test_wcslen() {
         let test_str = "Hello";
@@ -465,4 +3500,551 @@ mod tests {
             assert_eq!(len, test_str.len());
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_launch_protection_names() {
+        use clap::ValueEnum;
+
+        for protection in LaunchProtection::value_variants() {
+            assert_eq!(LaunchProtection::from_str(protection.as_str(), false), Ok(*protection));
+        }
+        assert_eq!(LaunchProtection::AntimalwareLight.as_raw(), SERVICE_LAUNCH_PROTECTED_ANTIMALWARE_LIGHT);
+        assert_eq!(LaunchProtection::default(), LaunchProtection::None);
+    }
+
+    #[test]
+    fn test_restart_window() {
+        let time = |hour, minute| chrono::NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+
+        let overnight: RestartWindow = "22:00-06:00".parse().unwrap();
+        assert!(overnight.contains(time(23, 30)));
+        assert!(overnight.contains(time(5, 59)));
+        assert!(!overnight.contains(time(6, 0)));
+        assert!(!overnight.contains(time(12, 0)));
+        assert_eq!(overnight.to_string(), "22:00-06:00");
+
+        let daytime: RestartWindow = "9:30-17:00".parse().unwrap();
+        assert!(daytime.contains(time(9, 30)));
+        assert!(!daytime.contains(time(17, 0)));
+        assert_eq!(daytime.start_time(), time(9, 30));
+
+        assert!("22:00".parse::<RestartWindow>().is_err());
+        assert!("25:00-06:00".parse::<RestartWindow>().is_err());
+    }
+
+    #[test]
+    fn test_unchanged_registry_value_is_not_rewritten() {
+        let key_path = to_wstring("Software\\rust-nssm-test-idempotent-write");
+        let mut hkey = HKEY::default();
+        let result = unsafe {
+            RegCreateKeyExW(
+                HKEY_CURRENT_USER,
+                key_path.as_ptr(),
+                0,
+                std::ptr::null(),
+                REG_OPTION_VOLATILE,
+                KEY_READ | KEY_WRITE,
+                std::ptr::null(),
+                &mut hkey,
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(result, ERROR_SUCCESS);
+
+        // 键的最后写入时间只在值真正被写入时更新
+        let last_write_time = || {
+            let mut time: FILETIME = unsafe { std::mem::zeroed() };
+            let null = std::ptr::null_mut();
+            unsafe { RegQueryInfoKeyW(hkey, null as _, null, null, null, null, null, null, null, null, null, &mut time) };
+            ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64
+        };
+
+        set_reg_string(hkey, "Value", "first").unwrap();
+        let written = last_write_time();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        set_reg_string(hkey, "Value", "first").unwrap();
+        assert_eq!(last_write_time(), written);
+
+        set_reg_string(hkey, "Value", "second").unwrap();
+        assert!(last_write_time() > written);
+        assert_eq!(read_reg_string(hkey, "Value").unwrap(), "second");
+
+        unsafe {
+            RegCloseKey(hkey);
+            RegDeleteKeyW(HKEY_CURRENT_USER, key_path.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_unchanged_config_is_not_rewritten() {
+        let key_path = to_wstring("Software\\rust-nssm-test-config-values");
+        let mut hkey = HKEY::default();
+        let result = unsafe {
+            RegCreateKeyExW(
+                HKEY_CURRENT_USER,
+                key_path.as_ptr(),
+                0,
+                std::ptr::null(),
+                REG_OPTION_VOLATILE,
+                KEY_READ | KEY_WRITE,
+                std::ptr::null(),
+                &mut hkey,
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(result, ERROR_SUCCESS);
+
+        let last_write_time = || {
+            let mut time: FILETIME = unsafe { std::mem::zeroed() };
+            let null = std::ptr::null_mut();
+            unsafe { RegQueryInfoKeyW(hkey, null as _, null, null, null, null, null, null, null, null, null, &mut time) };
+            ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64
+        };
+
+        let config = ServiceConfig {
+            name: "app".to_string(),
+            executable_path: PathBuf::from("C:\\app\\app.exe"),
+            arguments: vec!["--port".to_string(), "8080".to_string()],
+            stdout_path: Some(PathBuf::from("C:\\logs\\out.log")),
+            restart_delay: Some(5),
+            ..Default::default()
+        };
+        write_config_values(hkey, &config).unwrap();
+        // 宿主写入的运行记录
+        set_reg_dword(hkey, "Provisioned", 1).unwrap();
+        let written = last_write_time();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        // 配置未变化的 edit 不写入任何值
+        write_config_values(hkey, &config).unwrap();
+        assert_eq!(last_write_time(), written);
+
+        // 不再设置的配置值被删除，运行记录保留
+        let config = ServiceConfig { stdout_path: None, ..config };
+        write_config_values(hkey, &config).unwrap();
+        assert!(read_reg_string(hkey, "StdoutPath").is_err());
+        assert_eq!(read_reg_string(hkey, "TargetExecutable").unwrap(), "C:\\app\\app.exe");
+        assert_eq!(read_reg_dword(hkey, "Provisioned").unwrap(), 1);
+
+        unsafe {
+            RegCloseKey(hkey);
+            RegDeleteKeyW(HKEY_CURRENT_USER, key_path.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_config_value_names_cover_all_writes() {
+        let source = include_str!("service_manager.rs");
+        let pattern = regex::Regex::new(r#"params\.(?:string|dword|qword)\("(\w+)""#).unwrap();
+        let mut written: Vec<&str> = pattern.captures_iter(source).map(|c| c.get(1).unwrap().as_str()).collect();
+        written.sort_unstable();
+        written.dedup();
+
+        let mut names = CONFIG_VALUE_NAMES.to_vec();
+        names.sort_unstable();
+        assert_eq!(written, names);
+    }
+
+    #[test]
+    fn test_numeric_registry_values_roundtrip() {
+        let key_path = to_wstring("Software\\rust-nssm-test-numeric-values");
+        let mut hkey = HKEY::default();
+        let result = unsafe {
+            RegCreateKeyExW(
+                HKEY_CURRENT_USER,
+                key_path.as_ptr(),
+                0,
+                std::ptr::null(),
+                REG_OPTION_VOLATILE,
+                KEY_READ | KEY_WRITE,
+                std::ptr::null(),
+                &mut hkey,
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(result, ERROR_SUCCESS);
+
+        set_reg_dword(hkey, "Dword", 65001).unwrap();
+        assert_eq!(read_reg_dword(hkey, "Dword").unwrap(), 65001);
+        assert_eq!(read_reg_qword(hkey, "Dword").unwrap(), 65001);
+
+        let large = 6 * 1024 * 1024 * 1024u64;
+        set_reg_value(hkey, "Qword", REG_QWORD, &large.to_le_bytes()).unwrap();
+        assert_eq!(read_reg_qword(hkey, "Qword").unwrap(), large);
+        assert!(read_reg_dword(hkey, "Qword").is_err());
+
+        // 旧版本以字符串保存的数值仍可读取
+        set_reg_string(hkey, "Legacy", " 300 ").unwrap();
+        assert_eq!(read_reg_dword(hkey, "Legacy").unwrap(), 300);
+        set_reg_string(hkey, "Text", "abc").unwrap();
+        assert!(read_reg_dword(hkey, "Text").is_err());
+        assert!(read_reg_dword(hkey, "Missing").is_err());
+
+        unsafe {
+            RegCloseKey(hkey);
+            RegDeleteKeyW(HKEY_CURRENT_USER, key_path.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_saturating_dword() {
+        assert_eq!(saturating_dword(60), 60);
+        assert_eq!(saturating_dword(u64::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_next_poll_interval() {
+        assert_eq!(next_poll_interval(STOP_POLL_INTERVAL), std::time::Duration::from_millis(200));
+        assert_eq!(next_poll_interval(std::time::Duration::from_millis(1500)), MAX_STOP_POLL_INTERVAL);
+        assert_eq!(next_poll_interval(MAX_STOP_POLL_INTERVAL), MAX_STOP_POLL_INTERVAL);
+
+        assert_eq!(start_poll_interval(0), STOP_POLL_INTERVAL);
+        assert_eq!(start_poll_interval(30_000), std::time::Duration::from_secs(3));
+        assert_eq!(start_poll_interval(u32::MAX), MAX_START_POLL_INTERVAL);
+    }
+
+    #[test]
+    fn test_wait_for_stopped() {
+        let timeout = std::time::Duration::from_secs(5);
+
+        // RUNNING → STOP_PENDING → STOPPED
+        let mut states = vec![SERVICE_RUNNING, SERVICE_STOP_PENDING, SERVICE_STOPPED].into_iter();
+        let mut queries = 0;
+        wait_for_stopped("app", timeout, || {
+            queries += 1;
+            Ok(states.next().unwrap())
+        })
+        .unwrap();
+        assert_eq!(queries, 3);
+
+        let error = wait_for_stopped("app", std::time::Duration::ZERO, || Ok(SERVICE_STOP_PENDING)).unwrap_err();
+        assert_eq!(error.to_string(), "Service 'app' did not stop within 0s (state: STOP_PENDING)");
+
+        assert!(wait_for_stopped("app", timeout, || Err(anyhow::anyhow!("Service not found"))).is_err());
+    }
+
+    #[test]
+    fn test_sort_services_by_name() {
+        let info = |name: &str| ServiceInfo {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            state: SERVICE_STOPPED,
+            status_name: service_state_name(SERVICE_STOPPED),
+            pid: None,
+            exit_code: 0,
+        };
+        let mut services = vec![info("wuauserv"), info("Appinfo"), info("bits"), info("AudioSrv")];
+        sort_services_by_name(&mut services);
+
+        let names: Vec<&str> = services.iter().map(|service| service.name.as_str()).collect();
+        assert_eq!(names, ["Appinfo", "AudioSrv", "bits", "wuauserv"]);
+    }
+
+    #[test]
+    fn test_dependency_list() {
+        let list = dependency_list(&["Tcpip".to_string(), "+NetworkProvider".to_string()]);
+        let expected: Vec<u16> = "Tcpip\0+NetworkProvider\0\0".encode_utf16().collect();
+        assert_eq!(list, expected);
+
+        let names = unsafe { dependencies_from_wide_ptr(list.as_ptr()) };
+        assert_eq!(names, ["Tcpip", "+NetworkProvider"]);
+        assert!(unsafe { dependencies_from_wide_ptr(dependency_list(&[]).as_ptr()) }.is_empty());
+        assert!(unsafe { dependencies_from_wide_ptr(std::ptr::null()) }.is_empty());
+    }
+
+    #[test]
+    fn test_validate_service_name() {
+        assert!(validate_service_name("my-service").is_ok());
+        assert!(validate_service_name("My Service 2").is_ok());
+        assert!(validate_service_name("").is_err());
+        assert!(validate_service_name("a/b").is_err());
+        assert!(validate_service_name("line\nbreak").is_err());
+        assert!(validate_service_name(&"x".repeat(MAX_SERVICE_NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_traversal_name_rejected_before_registry_access() {
+        let name = "..\\..\\..\\SOFTWARE\\Microsoft";
+        assert!(validate_service_name(name).is_err());
+        assert!(parameters_key_path(name).is_err());
+        assert!(read_parameter_string(name, "TargetExecutable").is_err());
+        assert!(write_parameter_string(name, "TargetExecutable", "evil").is_err());
+        assert!(!is_managed_service(name));
+        assert_eq!(
+            parameters_key_path("my-service").unwrap(),
+            "SYSTEM\\CurrentControlSet\\Services\\my-service\\Parameters"
+        );
+        assert!(event_source_key_path(name).is_err());
+        assert_eq!(
+            event_source_key_path("my-service").unwrap(),
+            "SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\my-service"
+        );
+    }
+
+    #[test]
+    fn test_parse_env_var() {
+        assert_eq!(parse_env_var("PORT=8080").unwrap(), ("PORT".to_string(), "8080".to_string()));
+        assert_eq!(parse_env_var("EMPTY=").unwrap(), ("EMPTY".to_string(), String::new()));
+        assert_eq!(parse_env_var("OPTS=a=1;b=2").unwrap(), ("OPTS".to_string(), "a=1;b=2".to_string()));
+        assert!(parse_env_var("=value").is_err());
+        assert!(parse_env_var("NOVALUE").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let content = "# comment\n\nPORT=8080\nexport MODE = prod # trailing\nGREETING=\"hello world\"\nOPTS='a=1 # b'\nESCAPED=\"line1\\nsay \\\"hi\\\"\"\nEMPTY=\n";
+        let environment = parse_env_file(content).unwrap();
+        let pairs: Vec<(&str, &str)> = environment.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(
+            pairs,
+            [
+                ("PORT", "8080"),
+                ("MODE", "prod"),
+                ("GREETING", "hello world"),
+                ("OPTS", "a=1 # b"),
+                ("ESCAPED", "line1\nsay \"hi\""),
+                ("EMPTY", ""),
+            ]
+        );
+
+        assert!(parse_env_file("NOVALUE").is_err());
+        assert!(parse_env_file("BAD KEY=1").is_err());
+        assert!(parse_env_file("OPEN=\"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_dedup_environment() {
+        let environment = vec![
+            ("Path".to_string(), "a".to_string()),
+            ("MODE".to_string(), "dev".to_string()),
+            ("PATH".to_string(), "b".to_string()),
+        ];
+        assert_eq!(
+            dedup_environment(environment),
+            vec![("MODE".to_string(), "dev".to_string()), ("PATH".to_string(), "b".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_host_command_line() {
+        assert_eq!(
+            host_command_line(Path::new("C:\\Tools\\rust-nssm-0.2.0.exe"), "my-service"),
+            "\"C:\\Tools\\rust-nssm-0.2.0.exe\" run --name \"my-service\""
+        );
+    }
+
+    #[test]
+    fn test_recovery_config() {
+        let recovery = RecoveryConfig::restart_after(std::time::Duration::from_secs(30), 86400);
+        let action = recovery.first.as_sc_action();
+        assert_eq!((action.Type, action.Delay), (SC_ACTION_RESTART, 30_000));
+        assert_eq!(recovery.command_line().unwrap(), None);
+
+        let alert = FailureAction::RunProgram(PathBuf::from(r"C:\Tools\alert.exe"), "--service api".to_string());
+        let recovery = RecoveryConfig {
+            second: alert.clone(),
+            subsequent: alert,
+            ..recovery
+        };
+        assert_eq!(
+            recovery.command_line().unwrap().as_deref(),
+            Some(r#""C:\Tools\alert.exe" --service api"#)
+        );
+
+        let recovery = RecoveryConfig {
+            subsequent: FailureAction::RunProgram(PathBuf::from(r"C:\Tools\other.exe"), String::new()),
+            ..recovery
+        };
+        assert!(recovery.command_line().is_err());
+    }
+
+    #[test]
+    fn test_exit_actions() {
+        let rules = ["0=stop".to_string(), "0xC0000005=stop-service".to_string(), "default=restart".to_string()];
+        let actions = ExitActions::parse_rules(&rules).unwrap();
+        assert_eq!(actions.action_for(Some(0)), ExitAction::Stop);
+        assert_eq!(actions.action_for(Some(0xC0000005u32 as i32)), ExitAction::StopService);
+        assert_eq!(actions.action_for(Some(1)), ExitAction::Restart);
+        assert_eq!(actions.action_for(None), ExitAction::Restart);
+        assert!(!actions.is_default());
+        assert_eq!(ExitActions::parse_rules(&actions.to_rules()).unwrap(), actions);
+
+        let actions = ExitActions::parse_rules(&["default=stop".to_string(), "3=restart".to_string()]).unwrap();
+        assert_eq!(actions.action_for(Some(3)), ExitAction::Restart);
+        assert_eq!(actions.action_for(Some(4)), ExitAction::Stop);
+
+        assert!(ExitActions::default().is_default());
+        assert!(ExitActions::parse_rules(&["0".to_string()]).is_err());
+        assert!(ExitActions::parse_rules(&["x=stop".to_string()]).is_err());
+        assert!(ExitActions::parse_rules(&["0=ignore".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_restart_policy_exit_actions() {
+        assert!(RestartPolicy::Always.exit_actions().is_default());
+
+        let on_failure = RestartPolicy::OnFailure.exit_actions();
+        assert_eq!(on_failure.action_for(Some(0)), ExitAction::Stop);
+        assert_eq!(on_failure.action_for(Some(1)), ExitAction::Restart);
+        assert_eq!(on_failure.action_for(None), ExitAction::Restart);
+
+        let never = RestartPolicy::Never.exit_actions();
+        assert_eq!(never.action_for(Some(0)), ExitAction::Stop);
+        assert_eq!(never.action_for(Some(1)), ExitAction::Stop);
+        assert_eq!(never.to_rules(), ["default=stop"]);
+    }
+
+    #[test]
+    fn test_parse_failure_action() {
+        assert_eq!(
+            "restart:60".parse::<FailureAction>().unwrap(),
+            FailureAction::RestartService(std::time::Duration::from_secs(60))
+        );
+        assert_eq!(
+            r#"run:"C:\Program Files\alert.exe" --level high"#.parse::<FailureAction>().unwrap(),
+            FailureAction::RunProgram(PathBuf::from(r"C:\Program Files\alert.exe"), "--level high".to_string())
+        );
+        assert_eq!(
+            "run:notify.cmd".parse::<FailureAction>().unwrap(),
+            FailureAction::RunProgram(PathBuf::from("notify.cmd"), String::new())
+        );
+        assert_eq!("reboot".parse::<FailureAction>().unwrap(), FailureAction::Reboot);
+        assert_eq!("none".parse::<FailureAction>().unwrap(), FailureAction::None);
+        assert_eq!(
+            "restart:1500ms".parse::<FailureAction>().unwrap(),
+            FailureAction::RestartService(std::time::Duration::from_millis(1500))
+        );
+        assert!("restart:soon".parse::<FailureAction>().is_err());
+        assert!("restart:ms".parse::<FailureAction>().is_err());
+
+        for action in ["restart:60", "restart:1500ms", r#"run:"C:\Program Files\alert.exe" --level high"#, "reboot", "none"] {
+            let parsed = action.parse::<FailureAction>().unwrap();
+            assert_eq!(parsed.to_string().parse::<FailureAction>().unwrap(), parsed);
+        }
+        assert!("run:".parse::<FailureAction>().is_err());
+        assert!("shutdown".parse::<FailureAction>().is_err());
+    }
+
+    #[test]
+    fn test_recovery_config_from_actions() {
+        let restart = FailureAction::RestartService(std::time::Duration::from_secs(10));
+        let recovery = RecoveryConfig::from_actions(&[restart.clone(), FailureAction::Reboot], 3600, None).unwrap();
+        assert_eq!(recovery.first, restart);
+        assert_eq!(recovery.second, FailureAction::Reboot);
+        assert_eq!(recovery.subsequent, FailureAction::Reboot);
+        assert_eq!(recovery.to_actions(), [restart.clone(), FailureAction::Reboot]);
+
+        let recovery = RecoveryConfig::from_actions(std::slice::from_ref(&restart), 3600, None).unwrap();
+        assert_eq!(recovery, RecoveryConfig::restart_after(std::time::Duration::from_secs(10), 3600));
+        assert_eq!(recovery.to_actions(), std::slice::from_ref(&restart));
+
+        assert!(RecoveryConfig::from_actions(&[], 3600, None).is_err());
+        assert!(RecoveryConfig::from_actions(&[FailureAction::None, FailureAction::None, FailureAction::None, FailureAction::None], 3600, None).is_err());
+    }
+
+    #[test]
+    fn test_service_start_type_as_raw() {
+        use clap::ValueEnum;
+
+        assert_eq!(ServiceStartType::AutoStart.as_raw(), SERVICE_AUTO_START);
+        assert_eq!(ServiceStartType::AutoDelayedStart.as_raw(), SERVICE_AUTO_START);
+        assert_eq!(ServiceStartType::ManualStart.as_raw(), SERVICE_DEMAND_START);
+        assert_eq!(ServiceStartType::Disabled.as_raw(), SERVICE_DISABLED);
+
+        for start_type in [
+            ServiceStartType::AutoStart,
+            ServiceStartType::AutoDelayedStart,
+            ServiceStartType::ManualStart,
+            ServiceStartType::Disabled,
+        ] {
+            let delayed = start_type == ServiceStartType::AutoDelayedStart;
+            assert_eq!(ServiceStartType::from_raw(start_type.as_raw(), delayed), start_type);
+        }
+        for start_type in [ServiceStartType::AutoStart, ServiceStartType::AutoDelayedStart, ServiceStartType::Disabled] {
+            assert_eq!(ServiceStartType::from_str(start_type.as_str(), false).unwrap(), start_type);
+        }
+        // 只有自动启动才区分是否延迟
+        assert_eq!(ServiceStartType::from_raw(SERVICE_DEMAND_START, true), ServiceStartType::ManualStart);
+    }
+
+    #[test]
+    fn test_failure_action_from_sc_action() {
+        let actions: [FailureAction; 4] = [
+            "restart:30".parse().unwrap(),
+            r#"run:"C:\Program Files\alert.exe" --service web"#.parse().unwrap(),
+            FailureAction::Reboot,
+            FailureAction::None,
+        ];
+        for action in actions {
+            let command = action.command_line();
+            let restored = FailureAction::from_sc_action(&action.as_sc_action(), command.as_deref()).unwrap();
+            assert_eq!(restored, action);
+        }
+
+        let own_restart = SC_ACTION { Type: SC_ACTION_OWN_RESTART, Delay: 0 };
+        assert!(FailureAction::from_sc_action(&own_restart, None).is_err());
+    }
+
+    #[test]
+    fn test_service_account() {
+        assert_eq!(ServiceAccount::from_username("LocalSystem", None), ServiceAccount::LocalSystem);
+        assert_eq!(ServiceAccount::from_username(r"NT AUTHORITY\Network Service", None), ServiceAccount::NetworkService);
+        assert_eq!(ServiceAccount::from_username("localservice", None).start_name().unwrap(), r"NT AUTHORITY\LocalService");
+        assert_eq!(ServiceAccount::LocalSystem.start_name(), None);
+
+        let user = ServiceAccount::from_username("svc-app", Some("secret".to_string()));
+        assert_eq!(user.start_name().unwrap(), r".\svc-app");
+        assert_eq!(user.password(), Some("secret"));
+        assert_eq!(ServiceAccount::from_username(r"CORP\svc-app", None).start_name().unwrap(), r"CORP\svc-app");
+        assert_eq!(ServiceAccount::from_username("svc-app@corp.example", None).start_name().unwrap(), "svc-app@corp.example");
+        assert_eq!(ServiceAccount::from_username(r"CORP\gmsa-app$", None).password(), None);
+
+        assert!(user.requires_password());
+        assert!(!ServiceAccount::from_username(r"CORP\gmsa-app$", None).requires_password());
+        assert!(!ServiceAccount::from_username(r"NT SERVICE\my-service", None).requires_password());
+        assert!(!ServiceAccount::NetworkService.requires_password());
+        assert_eq!(ServiceAccount::LocalSystem.display_name(), "LocalSystem");
+    }
+
+    #[test]
+    fn test_parse_affinity_mask() {
+        assert_eq!(parse_affinity_mask("0x0F").unwrap(), 0x0F);
+        assert_eq!(parse_affinity_mask("0XfF").unwrap(), 0xFF);
+        assert_eq!(parse_affinity_mask("5").unwrap(), 5);
+        assert!(parse_affinity_mask("0x0").is_err());
+        assert!(parse_affinity_mask("0xZZ").is_err());
+        assert!(parse_affinity_mask("").is_err());
+    }
+
+    #[test]
+    fn test_priority_class_roundtrip() {
+        for priority in ProcessPriorityClass::ALL {
+            assert_eq!(ProcessPriorityClass::from_win32_class(priority.win32_class()), Some(priority));
+        }
+        assert_eq!(ProcessPriorityClass::from_win32_class(0x4000), Some(ProcessPriorityClass::BelowNormal));
+        assert_eq!(ProcessPriorityClass::from_win32_class(0), None);
+    }
+
+    #[test]
+    fn test_start_type_name() {
+        assert_eq!(start_type_name(SERVICE_AUTO_START), "auto");
+        assert_eq!(start_type_name(SERVICE_DEMAND_START), "manual");
+        assert_eq!(start_type_name(SERVICE_DISABLED), "disabled");
+        assert_eq!(start_type_name(99), "unknown");
+    }
+
+    #[test]
+    fn test_recovery_action_name() {
+        assert_eq!(recovery_action_name(SC_ACTION_RESTART), "restart");
+        assert_eq!(recovery_action_name(SC_ACTION_RUN_COMMAND), "run-command");
+        assert_eq!(recovery_action_name(99), "unknown");
+    }
+
+    #[test]
+    fn test_drive_type_name() {
+        assert_eq!(drive_type_name(DRIVE_FIXED), "fixed");
+        assert_eq!(drive_type_name(DRIVE_REMOTE), "network");
+        assert_eq!(drive_type_name(DRIVE_REMOVABLE), "removable");
+    }
+}
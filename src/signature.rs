@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::fs::File;
+use std::os::windows::fs::OpenOptionsExt;
+use std::path::Path;
+use windows_sys::Win32::Foundation::{
+    TRUST_E_ACTION_UNKNOWN, TRUST_E_NOSIGNATURE, TRUST_E_PROVIDER_UNKNOWN, TRUST_E_SUBJECT_FORM_UNKNOWN,
+};
+use windows_sys::Win32::Security::Cryptography::{CertGetCertificateContextProperty, CERT_SHA1_HASH_PROP_ID};
+use windows_sys::Win32::Security::WinTrust::*;
+use windows_sys::Win32::Storage::FileSystem::FILE_SHARE_READ;
+
+use crate::service_manager::ServiceConfig;
+
+/// Authenticode 签名校验结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// 签名有效，附带签名证书的SHA1指纹（大写十六进制）
+    Valid { thumbprint: Option<String> },
+    /// 文件没有嵌入签名
+    Unsigned,
+    /// 签名存在但无效（`WinVerifyTrust` 返回的错误码）
+    Invalid(i32),
+}
+
+/// 使用 `WinVerifyTrust` 校验文件的 Authenticode 签名
+///
+/// 只检查文件内嵌签名，通过目录（catalog）签名的系统文件会被视为未签名。
+/// 返回 `Err` 表示系统无法完成校验（如缺少信任提供程序），而不是签名无效。
+pub fn verify_file(path: &Path) -> Result<SignatureStatus> {
+    let path_w: Vec<u16> = path.as_os_str().to_string_lossy().encode_utf16().chain(std::iter::once(0)).collect();
+
+    let mut file_info: WINTRUST_FILE_INFO = unsafe { std::mem::zeroed() };
+    file_info.cbStruct = std::mem::size_of::<WINTRUST_FILE_INFO>() as u32;
+    file_info.pcwszFilePath = path_w.as_ptr();
+
+    let mut data: WINTRUST_DATA = unsafe { std::mem::zeroed() };
+    data.cbStruct = std::mem::size_of::<WINTRUST_DATA>() as u32;
+    data.dwUIChoice = WTD_UI_NONE;
+    data.fdwRevocationChecks = WTD_REVOKE_NONE;
+    data.dwUnionChoice = WTD_CHOICE_FILE;
+    data.Anonymous.pFile = &mut file_info;
+    data.dwStateAction = WTD_STATEACTION_VERIFY;
+    data.dwProvFlags = WTD_CACHE_ONLY_URL_RETRIEVAL;
+
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    let result = unsafe { WinVerifyTrust(0, &mut action, &mut data as *mut _ as *mut _) };
+
+    let status = match result {
+        0 => Ok(SignatureStatus::Valid { thumbprint: signer_thumbprint(data.hWVTStateData) }),
+        TRUST_E_NOSIGNATURE => Ok(SignatureStatus::Unsigned),
+        TRUST_E_PROVIDER_UNKNOWN | TRUST_E_ACTION_UNKNOWN | TRUST_E_SUBJECT_FORM_UNKNOWN => Err(anyhow::anyhow!(
+            "Authenticode verification is not available on this system (0x{:08X})",
+            result as u32
+        )),
+        code => Ok(SignatureStatus::Invalid(code)),
+    };
+
+    // 释放校验过程中分配的状态数据
+    data.dwStateAction = WTD_STATEACTION_CLOSE;
+    unsafe { WinVerifyTrust(0, &mut action, &mut data as *mut _ as *mut _) };
+
+    status
+}
+
+/// 从校验状态中读取签名证书的SHA1指纹
+fn signer_thumbprint(state: isize) -> Option<String> {
+    unsafe {
+        let provider = WTHelperProvDataFromStateData(state);
+        if provider.is_null() {
+            return None;
+        }
+
+        let signer = WTHelperGetProvSignerFromChain(provider, 0, 0, 0);
+        if signer.is_null() || (*signer).csCertChain == 0 || (*signer).pasCertChain.is_null() {
+            return None;
+        }
+
+        let cert = (*(*signer).pasCertChain).pCert;
+        if cert.is_null() {
+            return None;
+        }
+
+        let mut hash = [0u8; 20];
+        let mut size = hash.len() as u32;
+        if CertGetCertificateContextProperty(cert, CERT_SHA1_HASH_PROP_ID, hash.as_mut_ptr() as *mut _, &mut size) == 0 {
+            return None;
+        }
+
+        Some(hash[..size as usize].iter().map(|byte| format!("{:02X}", byte)).collect())
+    }
+}
+
+/// 规范化证书指纹：去掉空格和冒号并转为大写
+pub fn normalize_thumbprint(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+/// 按服务配置校验目标程序签名
+///
+/// 校验失败时，启用 `require_signature` 则返回错误，否则只记录警告。
+/// 返回的文件句柄只允许共享读取：调用者应持有它直到子进程启动，
+/// 防止目标程序在校验之后、启动之前被改写、替换或删除。
+pub fn enforce(config: &ServiceConfig) -> Result<Option<File>> {
+    if !config.check_signature {
+        return Ok(None);
+    }
+
+    let path = &config.executable_path;
+    let (image, failure) = match lock_image(path) {
+        Ok(image) => {
+            let failure = match verify_file(path) {
+                Ok(status) => check_status(&status, config.signer_thumbprint.as_deref()),
+                Err(e) => Some(e.to_string()),
+            };
+            (Some(image), failure)
+        }
+        Err(e) => (None, Some(format!("{:#}", e))),
+    };
+
+    match failure {
+        None => {
+            info!("Authenticode signature of {:?} verified", path);
+            Ok(image)
+        }
+        Some(reason) if config.require_signature => {
+            Err(anyhow::anyhow!("Refusing to launch {:?}: {}", path, reason))
+        }
+        Some(reason) => {
+            warn!("Signature check of {:?} failed: {}", path, reason);
+            Ok(image)
+        }
+    }
+}
+
+/// 以只允许共享读取的方式打开目标程序，句柄关闭前其他进程无法写入、重命名或删除该文件
+fn lock_image(path: &Path) -> Result<File> {
+    File::options()
+        .read(true)
+        .share_mode(FILE_SHARE_READ)
+        .open(path)
+        .context(format!("Failed to open {:?} for signature verification", path))
+}
+
+/// 判断校验结果是否满足要求，不满足时返回原因
+fn check_status(status: &SignatureStatus, expected_thumbprint: Option<&str>) -> Option<String> {
+    match status {
+        SignatureStatus::Unsigned => Some("executable is not signed".to_string()),
+        SignatureStatus::Invalid(code) => Some(format!("signature is not trusted (0x{:08X})", *code as u32)),
+        SignatureStatus::Valid { thumbprint } => {
+            let expected = normalize_thumbprint(expected_thumbprint?);
+            match thumbprint {
+                Some(actual) if *actual == expected => None,
+                Some(actual) => Some(format!("signer thumbprint {} does not match {}", actual, expected)),
+                None => Some("signer thumbprint could not be read".to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_thumbprint() {
+        assert_eq!(normalize_thumbprint("ab cd:ef 01"), "ABCDEF01");
+    }
+
+    #[test]
+    fn test_check_status() {
+        let valid = SignatureStatus::Valid { thumbprint: Some("ABCDEF".to_string()) };
+        assert_eq!(check_status(&valid, None), None);
+        assert_eq!(check_status(&valid, Some("ab:cd:ef")), None);
+        assert!(check_status(&valid, Some("012345")).is_some());
+        assert!(check_status(&SignatureStatus::Unsigned, None).is_some());
+        assert!(check_status(&SignatureStatus::Invalid(-1), None).is_some());
+    }
+
+    #[test]
+    fn test_lock_image_blocks_replacement() {
+        let path = std::env::temp_dir().join(format!("rust-nssm-lock-test-{}.exe", std::process::id()));
+        std::fs::write(&path, b"MZ").unwrap();
+
+        let image = lock_image(&path).unwrap();
+        assert!(std::fs::read(&path).is_ok());
+        assert!(File::options().write(true).open(&path).is_err());
+        assert!(std::fs::remove_file(&path).is_err());
+
+        drop(image);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,496 @@
+use anyhow::{Context, Result};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use windows_sys::Win32::Foundation::{GetLastError, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE, INVALID_HANDLE_VALUE, PSID};
+use windows_sys::Win32::Security::Authorization::{
+    ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, ConvertStringSidToSidW, SDDL_REVISION_1,
+};
+use windows_sys::Win32::Security::{LookupAccountNameW, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+use windows_sys::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_OUTBOUND};
+use windows_sys::Win32::System::Memory::LocalFree;
+use windows_sys::Win32::System::Pipes::*;
+
+/// 子进程退出历史保留的条数
+pub const EXIT_HISTORY_LEN: usize = 20;
+
+/// 一次子进程退出的记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExitRecord {
+    /// 退出时间（Unix时间戳，秒）
+    pub exited_at: i64,
+    /// 退出码
+    pub exit_code: Option<i32>,
+    /// 退出前已运行的秒数
+    pub uptime_secs: Option<u64>,
+}
+
+/// 服务宿主的运行时状态（通过命名管道提供给 `status` 命令）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuntimeStatus {
+    /// 当前子进程ID
+    pub child_pid: Option<u32>,
+    /// 子进程被重新启动的次数（不含首次启动）
+    pub restarts: u32,
+    /// 当前子进程的启动时间（Unix时间戳，秒）
+    pub child_started_at: Option<i64>,
+    /// 上一个子进程的退出码
+    pub last_exit_code: Option<i32>,
+    /// 子进程已退出，正在等待允许重启的时间段
+    #[serde(default)]
+    pub waiting_for_restart_window: bool,
+    /// 最近的子进程退出记录（最早的在前）
+    #[serde(default)]
+    pub exit_history: VecDeque<ExitRecord>,
+}
+
+impl RuntimeStatus {
+    /// 记录子进程启动
+    pub fn record_start(&mut self, pid: u32, is_restart: bool) {
+        if is_restart {
+            self.restarts += 1;
+        }
+        self.child_pid = Some(pid);
+        self.child_started_at = Some(chrono::Utc::now().timestamp());
+        self.waiting_for_restart_window = false;
+    }
+
+    /// 记录子进程退出
+    pub fn record_exit(&mut self, exit_code: Option<i32>) {
+        if self.exit_history.len() == EXIT_HISTORY_LEN {
+            self.exit_history.pop_front();
+        }
+        self.exit_history.push_back(ExitRecord {
+            exited_at: chrono::Utc::now().timestamp(),
+            exit_code,
+            uptime_secs: self.uptime_secs(),
+        });
+
+        self.child_pid = None;
+        self.child_started_at = None;
+        self.last_exit_code = exit_code;
+    }
+
+    /// 当前子进程已运行的秒数
+    pub fn uptime_secs(&self) -> Option<u64> {
+        self.child_started_at
+            .map(|started| (chrono::Utc::now().timestamp() - started).max(0) as u64)
+    }
+}
+
+/// 服务状态管道名称
+pub fn pipe_name(service_name: &str) -> String {
+    format!("\\\\.\\pipe\\rust-nssm-{}", service_name)
+}
+
+/// 状态管道的SDDL：仅 SYSTEM、Administrators 和管道所有者可访问，可额外授予一个组只读权限
+pub fn pipe_sddl(allow_group_sid: Option<&str>) -> String {
+    let mut sddl = String::from("D:P(A;;GA;;;SY)(A;;GA;;;BA)(A;;GA;;;OW)");
+    if let Some(sid) = allow_group_sid {
+        sddl.push_str(&format!("(A;;GR;;;{})", sid));
+    }
+    sddl
+}
+
+/// 将账户或组名称解析为字符串形式的SID
+///
+/// 已是SID字符串时经系统解析后重新格式化，结果会拼入SDDL，不能含有SID以外的内容。
+pub fn resolve_account_sid(account: &str) -> Result<String> {
+    let account_w: Vec<u16> = account.encode_utf16().chain(std::iter::once(0)).collect();
+
+    if account.starts_with("S-1-") {
+        let mut sid = std::ptr::null_mut();
+        if unsafe { ConvertStringSidToSidW(account_w.as_ptr(), &mut sid) } == 0 {
+            return Err(anyhow::anyhow!("Invalid SID '{}': error {}", account, unsafe { GetLastError() }));
+        }
+        let value = sid_to_string(sid);
+        unsafe { LocalFree(sid as isize) };
+        return value;
+    }
+
+    let mut sid_size = 0u32;
+    let mut domain_size = 0u32;
+    let mut sid_use = 0;
+
+    // 第一次调用获取缓冲区大小
+    unsafe {
+        LookupAccountNameW(
+            std::ptr::null(),
+            account_w.as_ptr(),
+            std::ptr::null_mut(),
+            &mut sid_size,
+            std::ptr::null_mut(),
+            &mut domain_size,
+            &mut sid_use,
+        );
+    }
+
+    if sid_size == 0 {
+        return Err(anyhow::anyhow!("Account '{}' not found: error {}", account, unsafe { GetLastError() }));
+    }
+
+    let mut sid = vec![0u8; sid_size as usize];
+    let mut domain = vec![0u16; domain_size as usize];
+    let result = unsafe {
+        LookupAccountNameW(
+            std::ptr::null(),
+            account_w.as_ptr(),
+            sid.as_mut_ptr() as *mut _,
+            &mut sid_size,
+            domain.as_mut_ptr(),
+            &mut domain_size,
+            &mut sid_use,
+        )
+    };
+
+    if result == 0 {
+        return Err(anyhow::anyhow!("Failed to look up account '{}': error {}", account, unsafe { GetLastError() }));
+    }
+
+    sid_to_string(sid.as_mut_ptr() as *mut _)
+}
+
+/// SID的字符串形式
+fn sid_to_string(sid: PSID) -> Result<String> {
+    let mut sid_string = std::ptr::null_mut();
+    if unsafe { ConvertSidToStringSidW(sid, &mut sid_string) } == 0 {
+        return Err(anyhow::anyhow!("Failed to convert SID: error {}", unsafe { GetLastError() }));
+    }
+
+    let len = (0..).take_while(|&i| unsafe { *sid_string.add(i) } != 0).count();
+    let value = String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(sid_string, len) });
+    unsafe { LocalFree(sid_string as isize) };
+
+    Ok(value)
+}
+
+/// 由SDDL构造的安全描述符，离开作用域时释放
+pub(crate) struct PipeSecurity {
+    descriptor: PSECURITY_DESCRIPTOR,
+}
+
+impl PipeSecurity {
+    pub(crate) fn new(sddl: &str) -> Result<Self> {
+        let sddl_w: Vec<u16> = sddl.encode_utf16().chain(std::iter::once(0)).collect();
+        let mut descriptor = std::ptr::null_mut();
+
+        let result = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl_w.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                std::ptr::null_mut(),
+            )
+        };
+
+        if result == 0 {
+            return Err(anyhow::anyhow!("Failed to build security descriptor: error {}", unsafe { GetLastError() }));
+        }
+
+        Ok(Self { descriptor })
+    }
+
+    fn attributes(&self) -> SECURITY_ATTRIBUTES {
+        SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.descriptor,
+            bInheritHandle: 0,
+        }
+    }
+}
+
+impl Drop for PipeSecurity {
+    fn drop(&mut self) {
+        unsafe { LocalFree(self.descriptor as isize) };
+    }
+}
+
+/// 创建一个带访问控制的管道实例
+///
+/// `first` 为 `true` 时附加 `FILE_FLAG_FIRST_PIPE_INSTANCE`，同名管道已存在（如被其他进程抢先创建）时失败。
+fn create_pipe_instance(name_w: &[u16], security: &PipeSecurity, open_mode: u32, first: bool) -> Result<HANDLE> {
+    let open_mode = if first { open_mode | FILE_FLAG_FIRST_PIPE_INSTANCE } else { open_mode };
+    let attributes = security.attributes();
+    let pipe = unsafe {
+        CreateNamedPipeW(
+            name_w.as_ptr(),
+            open_mode,
+            PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            4096,
+            0,
+            0,
+            &attributes,
+        )
+    };
+
+    if pipe == INVALID_HANDLE_VALUE {
+        return Err(anyhow::anyhow!("Failed to create named pipe: error {}", unsafe { GetLastError() }));
+    }
+
+    Ok(pipe)
+}
+
+/// 在后台线程中创建管道并循环处理连接，每个实例交给 `serve` 处理（状态、实时输出和IPC管道共用）
+///
+/// 第一个实例创建完成后才返回；同名管道已被其他进程创建时返回错误，
+/// 避免客户端连接到冒充服务宿主的进程。
+pub(crate) fn spawn_pipe_server(
+    name: &str,
+    allow_group: Option<&str>,
+    open_mode: u32,
+    mut serve: impl FnMut(HANDLE) + Send + 'static,
+) -> Result<()> {
+    let name_w: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let sddl = access_sddl(allow_group);
+    let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel(1);
+
+    std::thread::spawn(move || {
+        let security = match PipeSecurity::new(&sddl) {
+            Ok(security) => security,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+
+        let mut first = true;
+        loop {
+            let pipe = match create_pipe_instance(&name_w, &security, open_mode, first) {
+                Ok(pipe) => pipe,
+                Err(e) if first => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    return;
+                }
+            };
+            if first {
+                first = false;
+                let _ = ready_tx.send(Ok(()));
+            }
+
+            serve(pipe);
+        }
+    });
+
+    ready_rx
+        .recv()
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Pipe server thread exited unexpectedly")))
+        .context(format!("Failed to create pipe {}", name))
+}
+
+/// 按服务配置的访问组生成管道的SDDL，组无法解析时只记录错误并使用默认权限
+pub(crate) fn access_sddl(allow_group: Option<&str>) -> String {
+    let group_sid = allow_group.and_then(|group| match resolve_account_sid(group) {
+        Ok(sid) => Some(sid),
+        Err(e) => {
+            error!("Ignoring pipe access group '{}': {}", group, e);
+            None
+        }
+    });
+    pipe_sddl(group_sid.as_deref())
+}
+
+/// 在后台线程中启动状态管道服务端，每个连接写入一次JSON状态后断开
+///
+/// 管道默认只允许 Administrators 访问，`allow_group` 可额外授予一个组只读权限；
+/// 组无法解析时仍按默认权限创建管道。管道名已被占用时返回错误。
+pub fn spawn_server(service_name: &str, status: Arc<Mutex<RuntimeStatus>>, allow_group: Option<&str>) -> Result<()> {
+    spawn_pipe_server(&pipe_name(service_name), allow_group, PIPE_ACCESS_OUTBOUND, move |pipe| {
+        serve_client(pipe, &status)
+    })
+}
+
+/// 等待一个客户端连接并写入当前状态
+fn serve_client(pipe: HANDLE, status: &Mutex<RuntimeStatus>) {
+    // 管道句柄交由 File 管理，离开作用域时关闭
+    let mut file = unsafe { File::from_raw_handle(pipe as _) };
+
+    let connected = unsafe { ConnectNamedPipe(pipe, std::ptr::null_mut()) } != 0
+        || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+    if !connected {
+        return;
+    }
+
+    let snapshot = match status.lock() {
+        Ok(status) => status.clone(),
+        Err(_) => RuntimeStatus::default(),
+    };
+
+    match serde_json::to_vec(&snapshot) {
+        Ok(payload) => {
+            if let Err(e) = file.write_all(&payload).and_then(|_| file.sync_all()) {
+                warn!("Failed to write status pipe: {}", e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize runtime status: {}", e),
+    }
+
+    unsafe { DisconnectNamedPipe(file.as_raw_handle() as _) };
+}
+
+/// 通过状态管道查询服务宿主的运行时状态（阻塞，超时后返回错误）
+pub fn query(service_name: &str, timeout: Duration) -> Result<RuntimeStatus> {
+    let name = pipe_name(service_name);
+    let deadline = Instant::now() + timeout;
+
+    // 所有管道实例都忙时短暂重试
+    let mut file = loop {
+        match File::open(&name) {
+            Ok(file) => break file,
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) && Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Err(e) => return Err(e).context(format!("Failed to open status pipe {}", name)),
+        }
+    };
+
+    let mut payload = Vec::new();
+    file.read_to_end(&mut payload)
+        .context("Failed to read status pipe")?;
+
+    serde_json::from_slice(&payload).context("Invalid status pipe response")
+}
+
+/// 检测服务宿主本身是否响应：通过状态管道完成一次往返，返回耗时
+///
+/// 与 `query` 不同，读取响应也受超时限制，内部卡住的宿主会在超时后报告失败。
+pub fn ping(service_name: &str, timeout: Duration) -> Result<Duration> {
+    let started = Instant::now();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let name = service_name.to_string();
+    std::thread::spawn(move || {
+        let _ = tx.send(query(&name, timeout));
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result.map(|_| started.elapsed()),
+        Err(_) => Err(anyhow::anyhow!(
+            "Service host '{}' did not respond within {} ms",
+            service_name,
+            timeout.as_millis()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_status_restarts() {
+        let mut status = RuntimeStatus::default();
+        status.record_start(100, false);
+        status.record_exit(Some(1));
+        status.record_start(200, true);
+
+        assert_eq!(status.child_pid, Some(200));
+        assert_eq!(status.restarts, 1);
+        assert_eq!(status.last_exit_code, Some(1));
+        assert!(status.uptime_secs().is_some());
+    }
+
+    #[test]
+    fn test_exit_history_is_bounded() {
+        let mut status = RuntimeStatus::default();
+        for code in 0..(EXIT_HISTORY_LEN as i32 + 5) {
+            status.record_start(100, code > 0);
+            status.record_exit(Some(code));
+        }
+
+        assert_eq!(status.exit_history.len(), EXIT_HISTORY_LEN);
+        assert_eq!(status.exit_history.front().unwrap().exit_code, Some(5));
+        assert_eq!(status.exit_history.back().unwrap().exit_code, Some(EXIT_HISTORY_LEN as i32 + 4));
+        assert_eq!(status.exit_history.back().unwrap().uptime_secs, Some(0));
+    }
+
+    #[test]
+    fn test_ping_without_host_fails() {
+        assert!(ping("rust-nssm-test-no-such-service", Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn test_pipe_name() {
+        assert_eq!(pipe_name("MyService"), "\\\\.\\pipe\\rust-nssm-MyService");
+    }
+
+    #[test]
+    fn test_pipe_sddl() {
+        assert_eq!(pipe_sddl(None), "D:P(A;;GA;;;SY)(A;;GA;;;BA)(A;;GA;;;OW)");
+        assert!(pipe_sddl(Some("S-1-5-32-558")).ends_with("(A;;GR;;;S-1-5-32-558)"));
+    }
+
+    #[test]
+    fn test_resolve_account_sid_rejects_sddl_injection() {
+        assert_eq!(resolve_account_sid("S-1-5-32-558").unwrap(), "S-1-5-32-558");
+        assert!(resolve_account_sid("S-1-1-0)(A;;GA;;;WD").is_err());
+        assert!(resolve_account_sid("S-1-").is_err());
+        assert_eq!(access_sddl(Some("S-1-1-0)(A;;GA;;;WD")), pipe_sddl(None));
+    }
+
+    #[test]
+    fn test_pipe_dacl_applied() {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::Security::Authorization::{
+            ConvertSecurityDescriptorToStringSecurityDescriptorW, GetSecurityInfo, SE_KERNEL_OBJECT,
+        };
+        use windows_sys::Win32::Security::DACL_SECURITY_INFORMATION;
+
+        let name_w: Vec<u16> = pipe_name(&format!("dacl-test-{}", std::process::id()))
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+        let security = PipeSecurity::new(&pipe_sddl(Some("S-1-5-32-558"))).unwrap();
+        let pipe = create_pipe_instance(&name_w, &security, PIPE_ACCESS_OUTBOUND, true).unwrap();
+
+        let mut descriptor = std::ptr::null_mut();
+        let result = unsafe {
+            GetSecurityInfo(
+                pipe,
+                SE_KERNEL_OBJECT,
+                DACL_SECURITY_INFORMATION,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut descriptor,
+            )
+        };
+        assert_eq!(result, 0);
+
+        let mut sddl = std::ptr::null_mut();
+        let mut sddl_len = 0u32;
+        let converted = unsafe {
+            ConvertSecurityDescriptorToStringSecurityDescriptorW(
+                descriptor,
+                SDDL_REVISION_1,
+                DACL_SECURITY_INFORMATION,
+                &mut sddl,
+                &mut sddl_len,
+            )
+        };
+        assert_ne!(converted, 0);
+
+        let len = (0..).take_while(|&i| unsafe { *sddl.add(i) } != 0).count();
+        let applied = String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(sddl, len) });
+        unsafe {
+            LocalFree(sddl as isize);
+            LocalFree(descriptor as isize);
+            CloseHandle(pipe);
+        }
+
+        // 受保护的DACL，不含 Everyone 等继承的访问权限
+        assert!(applied.starts_with("D:P"));
+        assert!(applied.contains(";;;BA)"));
+        assert!(applied.contains(";;;S-1-5-32-558)"));
+        assert!(!applied.contains(";;;WD)"));
+    }
+}